@@ -1,22 +1,50 @@
 use std::fmt::Display;
 
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 pub struct Args {
+    /// Run as if started in this directory instead of the current one.
+    /// Only taken before the command, as in git: `diff -C` finds copies.
+    #[arg(short = 'C', value_name = "PATH")]
+    pub directories: Vec<String>,
+    #[arg(long, global = true, value_name = "PATH")]
+    pub git_dir: Option<String>,
+    #[arg(long, global = true, value_name = "PATH")]
+    pub work_tree: Option<String>,
     #[clap(subcommand)]
     pub command: Command,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
-    Init,
+    Init(InitArgs),
     CatFile(CatFileArgs),
     HashObject(HashObjectArgs),
     LsTree(LsTreeArgs),
     WriteTree,
     CommitTree(CommitTreeArgs),
+    Commit(CommitArgs),
     Clone(CloneArgs),
+    Submodule(SubmoduleArgs),
+    Add(AddArgs),
+    Status(StatusArgs),
+    CheckIgnore(CheckIgnoreArgs),
+    CheckAttr(CheckAttrArgs),
+    Config(ConfigArgs),
+    Checkout(CheckoutArgs),
+    Switch(SwitchArgs),
+    Branch(BranchArgs),
+    Tag(TagArgs),
+    SymbolicRef(SymbolicRefArgs),
+    ShowRef(ShowRefArgs),
+    UpdateRef(UpdateRefArgs),
+    Reflog(ReflogArgs),
+    RevParse(RevParseArgs),
+    Log(LogArgs),
+    RevList(RevListArgs),
+    MergeBase(MergeBaseArgs),
+    Diff(DiffArgs),
 }
 
 impl Display for Command {
@@ -25,6 +53,13 @@ impl Display for Command {
     }
 }
 
+#[derive(Parser, Debug)]
+pub struct InitArgs {
+    #[arg(long)]
+    pub bare: bool,
+    pub directory: Option<String>,
+}
+
 #[derive(Parser, Debug)]
 pub struct CatFileArgs {
     #[arg(short = 'p')]
@@ -35,6 +70,8 @@ pub struct CatFileArgs {
 pub struct HashObjectArgs {
     #[arg(short = 'w')]
     pub write: bool,
+    #[arg(long)]
+    pub no_filters: bool,
     pub file_path: String,
 }
 
@@ -49,9 +86,34 @@ pub struct LsTreeArgs {
 pub struct CommitTreeArgs {
     pub tree_name: String,
     #[arg(short = 'm')]
-    pub message: String,
+    pub messages: Vec<String>,
+    #[arg(short = 'F')]
+    pub files: Vec<String>,
     #[arg(short = 'p')]
-    pub parent_tree: Option<String>,
+    pub parents: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct CommitArgs {
+    #[arg(
+        short = 'm',
+        long = "message",
+        conflicts_with = "file",
+        allow_hyphen_values = true
+    )]
+    pub messages: Vec<String>,
+    #[arg(short = 'F', long = "file")]
+    pub file: Option<String>,
+    #[arg(long)]
+    pub amend: bool,
+    #[arg(long)]
+    pub allow_empty: bool,
+    #[arg(short = 'a', long)]
+    pub all: bool,
+    #[arg(long)]
+    pub author: Option<String>,
+    #[arg(long)]
+    pub date: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -59,3 +121,499 @@ pub struct CloneArgs {
     pub origin: String,
     pub directory: String,
 }
+
+#[derive(Parser, Debug)]
+pub struct SubmoduleArgs {
+    #[clap(subcommand)]
+    pub command: SubmoduleCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SubmoduleCommand {
+    Init(SubmodulePathArgs),
+    Update(SubmoduleUpdateArgs),
+    Status(SubmodulePathArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct SubmodulePathArgs {
+    pub paths: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SubmoduleUpdateArgs {
+    #[arg(long)]
+    pub init: bool,
+    pub paths: Vec<String>,
+}
+
+/// `reflog [<ref>]` is short for `reflog show [<ref>]`.
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct ReflogArgs {
+    #[clap(subcommand)]
+    pub command: Option<ReflogCommand>,
+    pub reference: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReflogCommand {
+    Show(ReflogShowArgs),
+    Expire(ReflogExpireArgs),
+    Delete(ReflogDeleteArgs),
+    Exists(ReflogExistsArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct ReflogShowArgs {
+    pub reference: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ReflogExpireArgs {
+    #[arg(long, value_name = "TIME")]
+    pub expire: Option<String>,
+    #[arg(long, value_name = "TIME")]
+    pub expire_unreachable: Option<String>,
+    #[arg(long)]
+    pub all: bool,
+    pub references: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ReflogDeleteArgs {
+    #[arg(long)]
+    pub rewrite: bool,
+    #[arg(required = true)]
+    pub entries: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ReflogExistsArgs {
+    pub reference: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct AddArgs {
+    #[arg(short, long)]
+    pub force: bool,
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct StatusArgs {
+    #[arg(short, long)]
+    pub short: bool,
+    #[arg(short, long)]
+    pub branch: bool,
+    #[arg(long, num_args = 0..=1, default_missing_value = "v1", require_equals = true)]
+    pub porcelain: Option<String>,
+    #[arg(long)]
+    pub long: bool,
+    /// Show staged renames of files at least this similar (50% by default).
+    #[arg(short = 'M', long, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    pub find_renames: Option<String>,
+    #[arg(long)]
+    pub no_renames: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct CheckIgnoreArgs {
+    #[arg(short, long)]
+    pub verbose: bool,
+    #[arg(short, long)]
+    pub non_matching: bool,
+    #[arg(long)]
+    pub no_index: bool,
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct CheckAttrArgs {
+    #[arg(short, long)]
+    pub all: bool,
+    pub args: Vec<String>,
+    #[arg(last = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct CheckoutArgs {
+    #[arg(short = 'b')]
+    pub new_branch: Option<String>,
+    #[arg(short, long)]
+    pub force: bool,
+    #[arg(long)]
+    pub detach: bool,
+    pub target: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SwitchArgs {
+    #[arg(short = 'c', long = "create")]
+    pub new_branch: Option<String>,
+    #[arg(short, long, alias = "discard-changes")]
+    pub force: bool,
+    #[arg(short, long)]
+    pub detach: bool,
+    pub target: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct BranchArgs {
+    #[arg(short, long)]
+    pub all: bool,
+    #[arg(short, long)]
+    pub remotes: bool,
+    /// Once to show each branch's commit and how it compares to its
+    /// upstream, twice to also name the upstream.
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Only list branches that contain this commit (HEAD by default).
+    #[arg(long, num_args = 0..=1, default_missing_value = "HEAD")]
+    pub contains: Option<String>,
+    /// Only list branches merged into this commit (HEAD by default).
+    #[arg(long, num_args = 0..=1, default_missing_value = "HEAD")]
+    pub merged: Option<String>,
+    #[arg(short, long)]
+    pub delete: bool,
+    /// Delete even branches that aren't merged.
+    #[arg(short = 'D')]
+    pub force_delete: bool,
+    #[arg(short = 'm', long = "move")]
+    pub rename: bool,
+    /// Rename even over an existing branch.
+    #[arg(short = 'M')]
+    pub force_rename: bool,
+    #[arg(short, long)]
+    pub force: bool,
+    #[arg(short, long)]
+    pub track: bool,
+    #[arg(short = 'u', long)]
+    pub set_upstream_to: Option<String>,
+    #[arg(long)]
+    pub unset_upstream: bool,
+    /// The branch to create and its start point, the branches to delete, or
+    /// `[<old>] <new>` for a rename.
+    pub names: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct TagArgs {
+    #[arg(short, long)]
+    pub list: bool,
+    #[arg(short, long)]
+    pub delete: bool,
+    #[arg(short, long)]
+    pub force: bool,
+    #[arg(short, long)]
+    pub annotate: bool,
+    #[arg(short = 'm', long = "message", conflicts_with = "file")]
+    pub messages: Vec<String>,
+    #[arg(short = 'F', long = "file")]
+    pub file: Option<String>,
+    /// Sort by `refname`, `version:refname`, `creatordate`, `taggerdate`,
+    /// `committerdate` or `objectname`, descending with a leading `-`.
+    #[arg(long)]
+    pub sort: Vec<String>,
+    /// Only list tags of this object (HEAD by default).
+    #[arg(long, num_args = 0..=1, default_missing_value = "HEAD")]
+    pub points_at: Option<String>,
+    /// Only list tags that contain this commit (HEAD by default).
+    #[arg(long, num_args = 0..=1, default_missing_value = "HEAD")]
+    pub contains: Option<String>,
+    /// The tag to create and the object it names, the tags to delete, or
+    /// patterns to list.
+    pub args: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SymbolicRefArgs {
+    #[arg(short, long)]
+    pub quiet: bool,
+    #[arg(short, long)]
+    pub delete: bool,
+    #[arg(long)]
+    pub short: bool,
+    #[arg(short = 'm')]
+    pub message: Option<String>,
+    pub name: String,
+    pub target: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ShowRefArgs {
+    #[arg(long)]
+    pub head: bool,
+    #[arg(long)]
+    pub heads: bool,
+    #[arg(long)]
+    pub tags: bool,
+    #[arg(short, long)]
+    pub dereference: bool,
+    /// Only print the object ids, abbreviated to the given length if any.
+    #[arg(short = 's', long = "hash", num_args = 0..=1, require_equals = true, default_missing_value = "40")]
+    pub hash: Option<usize>,
+    #[arg(long)]
+    pub verify: bool,
+    #[arg(short, long)]
+    pub quiet: bool,
+    pub patterns: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct UpdateRefArgs {
+    #[arg(short = 'm')]
+    pub message: Option<String>,
+    #[arg(short, long = "delete")]
+    pub delete: bool,
+    #[arg(long)]
+    pub no_deref: bool,
+    /// Read update, create, delete and verify commands from stdin and apply
+    /// them as a single transaction.
+    #[arg(long, conflicts_with = "delete")]
+    pub stdin: bool,
+    /// With `--stdin`, fields end in NUL rather than lines in newlines.
+    #[arg(short = 'z', requires = "stdin")]
+    pub nul_terminated: bool,
+    #[arg(required_unless_present = "stdin", conflicts_with = "stdin")]
+    pub name: Option<String>,
+    /// `<new-value> [<old-value>]`, or only `[<old-value>]` with `-d`.
+    pub values: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct RevParseArgs {
+    /// Require exactly one argument naming an object.
+    #[arg(long)]
+    pub verify: bool,
+    /// With `--verify`, fail silently instead of with a message.
+    #[arg(short, long)]
+    pub quiet: bool,
+    /// Abbreviate the object id to the given length (7 by default), or
+    /// longer if needed to keep it unique. Implies `--verify`.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "7")]
+    pub short: Option<usize>,
+    /// Print the short name of the ref each argument names instead.
+    #[arg(long)]
+    pub abbrev_ref: bool,
+    #[arg(long)]
+    pub show_toplevel: bool,
+    pub revisions: Vec<String>,
+}
+
+/// Which commits a history walk shows, for `log` and `rev-list`.
+#[derive(Parser, Debug)]
+pub struct WalkArgs {
+    #[arg(short = 'n', long)]
+    pub max_count: Option<usize>,
+    /// Only commits whose author matches this regex.
+    #[arg(long)]
+    pub author: Vec<String>,
+    /// Only commits whose message matches this regex.
+    #[arg(long)]
+    pub grep: Vec<String>,
+    #[arg(long, visible_alias = "after")]
+    pub since: Option<String>,
+    #[arg(long, visible_alias = "before")]
+    pub until: Option<String>,
+    #[arg(long)]
+    pub first_parent: bool,
+    #[arg(long, conflicts_with = "no_merges")]
+    pub merges: bool,
+    #[arg(long)]
+    pub no_merges: bool,
+    #[arg(long)]
+    pub topo_order: bool,
+    /// Revisions to start from (HEAD by default), then paths to limit to.
+    pub revisions: Vec<String>,
+    /// Paths to limit to, after `--`.
+    #[arg(last = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct LogArgs {
+    #[command(flatten)]
+    pub walk: WalkArgs,
+    /// `--pretty=oneline --abbrev-commit`.
+    #[arg(long)]
+    pub oneline: bool,
+    /// `oneline`, `short`, `medium`, `full`, `fuller` or a `format:` template.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "medium")]
+    pub pretty: Option<String>,
+    #[arg(long, conflicts_with = "pretty")]
+    pub format: Option<String>,
+    #[arg(long)]
+    pub abbrev_commit: bool,
+    /// How to show dates: `default`, `iso`, `iso-strict`, `rfc`, `short`,
+    /// `raw`, `unix` or `relative`.
+    #[arg(long)]
+    pub date: Option<String>,
+    /// Draw the history as a graph beside the commits.
+    #[arg(long)]
+    pub graph: bool,
+    /// Keep following the single path given across renames.
+    #[arg(long)]
+    pub follow: bool,
+    /// What to show of each commit's changes, which is nothing by default.
+    #[command(flatten)]
+    pub diff: DiffFormatArgs,
+}
+
+#[derive(Parser, Debug)]
+pub struct RevListArgs {
+    #[command(flatten)]
+    pub walk: WalkArgs,
+    /// Print how many commits there are instead of listing them.
+    #[arg(long)]
+    pub count: bool,
+    /// Mark which side of a symmetric difference each commit is on.
+    #[arg(long)]
+    pub left_right: bool,
+    /// Only commits that are both descendants of an excluded commit and
+    /// ancestors of an included one.
+    #[arg(long)]
+    pub ancestry_path: bool,
+    /// Also list the trees and blobs the commits use.
+    #[arg(long)]
+    pub objects: bool,
+    /// Leave some objects out of `--objects`: `blob:none`,
+    /// `blob:limit=<n>[kmg]`, `tree:<depth>` or `object:type=<type>`.
+    #[arg(long)]
+    pub filter: Option<String>,
+    /// Also list the excluded commits right behind the ones listed.
+    #[arg(long)]
+    pub boundary: bool,
+    #[arg(long)]
+    pub reverse: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct MergeBaseArgs {
+    /// Print every best common ancestor rather than just one.
+    #[arg(short, long)]
+    pub all: bool,
+    /// The merge bases for merging all the commits at once.
+    #[arg(long, group = "mode")]
+    pub octopus: bool,
+    /// List the commits none of the others can reach.
+    #[arg(long, group = "mode")]
+    pub independent: bool,
+    /// Succeed if the first commit is an ancestor of the second.
+    #[arg(long, group = "mode")]
+    pub is_ancestor: bool,
+    /// Where a commit (HEAD by default) forked off the history recorded in
+    /// a ref's reflog.
+    #[arg(long, group = "mode")]
+    pub fork_point: bool,
+    pub commits: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct DiffArgs {
+    /// Compare a commit (HEAD by default) with the index rather than the
+    /// index with the working tree.
+    #[arg(long, visible_alias = "staged")]
+    pub cached: bool,
+    /// Exit with status 1 if there are differences, 0 if there are none.
+    #[arg(long)]
+    pub exit_code: bool,
+    /// Print nothing; implies `--exit-code`.
+    #[arg(long)]
+    pub quiet: bool,
+    #[command(flatten)]
+    pub format: DiffFormatArgs,
+    /// Revisions to compare, then paths to limit to.
+    pub revisions: Vec<String>,
+    /// Paths to limit to, after `--`.
+    #[arg(last = true)]
+    pub paths: Vec<String>,
+}
+
+/// How `diff` and `log` compare files and what they print about them.
+#[derive(Parser, Debug)]
+pub struct DiffFormatArgs {
+    /// Show a patch, with this many lines of context around each change.
+    #[arg(short = 'U', long)]
+    pub unified: Option<usize>,
+    /// Show a patch, which is the default when no other format is asked for.
+    #[arg(short, long, short_alias = 'u')]
+    pub patch: bool,
+    /// Show a graph of how many lines changed in each file.
+    #[arg(long)]
+    pub stat: bool,
+    /// Show how many lines were added and deleted in each file.
+    #[arg(long)]
+    pub numstat: bool,
+    #[arg(long, conflicts_with = "name_status")]
+    pub name_only: bool,
+    #[arg(long)]
+    pub name_status: bool,
+    /// Show the modes, blob ids and status of each changed file.
+    #[arg(long)]
+    pub raw: bool,
+    /// `myers` (the default), `minimal`, `patience` or `histogram`.
+    #[arg(long)]
+    pub diff_algorithm: Option<String>,
+    #[arg(short = 'b', long)]
+    pub ignore_space_change: bool,
+    #[arg(short = 'w', long)]
+    pub ignore_all_space: bool,
+    #[arg(long)]
+    pub ignore_blank_lines: bool,
+    /// Place changes by how the lines around them are indented.
+    #[arg(long, overrides_with = "no_indent_heuristic")]
+    pub indent_heuristic: bool,
+    #[arg(long, overrides_with = "indent_heuristic")]
+    pub no_indent_heuristic: bool,
+    /// Pair deleted files with added ones at least this similar (50% by
+    /// default) as renames.
+    #[arg(short = 'M', long, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    pub find_renames: Option<String>,
+    /// Also pair added files with changed ones they were copied from. Given
+    /// twice, the same as `--find-copies-harder`.
+    #[arg(short = 'C', long, num_args = 0..=1, require_equals = true, default_missing_value = "", action = ArgAction::Append)]
+    pub find_copies: Vec<String>,
+    /// Look for copies of unchanged files as well.
+    #[arg(long)]
+    pub find_copies_harder: bool,
+    #[arg(long)]
+    pub no_renames: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigArgs {
+    #[arg(long, group = "scope")]
+    pub global: bool,
+    #[arg(long, group = "scope")]
+    pub system: bool,
+    #[arg(long, group = "scope")]
+    pub local: bool,
+    #[arg(long, group = "scope")]
+    pub worktree: bool,
+    #[arg(short = 'f', long, group = "scope")]
+    pub file: Option<String>,
+    #[arg(long, group = "action")]
+    pub get: bool,
+    #[arg(long, group = "action")]
+    pub get_all: bool,
+    #[arg(long, group = "action")]
+    pub set: bool,
+    #[arg(long, group = "action")]
+    pub unset: bool,
+    #[arg(short = 'l', long, group = "action")]
+    pub list: bool,
+    #[arg(long)]
+    pub show_origin: bool,
+    #[arg(long = "type", value_parser = ["bool", "int", "bool-or-int", "path"])]
+    pub value_type: Option<String>,
+    #[arg(long, conflicts_with = "value_type")]
+    pub bool: bool,
+    #[arg(long, conflicts_with_all = ["value_type", "bool"])]
+    pub int: bool,
+    #[arg(allow_negative_numbers = true)]
+    pub args: Vec<String>,
+}
@@ -0,0 +1,858 @@
+use clap::{error::ErrorKind, ArgMatches, CommandFactory, FromArgMatches};
+use std::{
+    env,
+    io::{self, Write},
+    process,
+};
+
+use crate::{
+    arg_parse::{Args, Command, DiffFormatArgs, ReflogCommand, SubmoduleCommand, WalkArgs},
+    commands::{ConfigAction, Paragraph},
+    git_config::Scope,
+    git_date,
+    git_diff::DiffOptions,
+    git_rename::{self, Detection},
+    git_revwalk::WalkOptions,
+    git_xdiff::Algorithm,
+    Error, Repository,
+};
+
+/// Runs the `git` command line on the process arguments and exits with
+/// git's status for the outcome.
+pub fn run() {
+    let arguments = expand_rename_scores(expand_not(expand_count_shorthand(env::args().collect())));
+    let matches = Args::command()
+        .try_get_matches_from(&arguments)
+        .unwrap_or_else(|err| usage_error(err, &arguments));
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| usage_error(err, &arguments));
+    for directory in args.directories.iter().filter(|d| !d.is_empty()) {
+        if let Err(err) = env::set_current_dir(directory) {
+            fail(Error::from(format!(
+                "fatal: cannot change to '{directory}': {err}"
+            )));
+        }
+    }
+    let repository = || Repository::discover(args.git_dir.as_deref(), args.work_tree.as_deref());
+    match args.command {
+        Command::Init(init_args) => {
+            let result = crate::commands::init(&init_args.directory, init_args.bare);
+            if result.is_ok() {
+                println!("Initialized git directory");
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::CatFile(cat_file_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::cat_file(&repository, &cat_file_args.object_name)
+            });
+            if result.is_ok() {
+                io::stdout().write_all(&result.unwrap()).unwrap();
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::HashObject(hash_object_args) => {
+            let result = crate::commands::hash_object(
+                repository().ok().as_ref(),
+                &hash_object_args.file_path,
+                hash_object_args.write,
+                hash_object_args.no_filters,
+            );
+            if result.is_ok() {
+                println!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::LsTree(ls_tree_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::ls_tree(
+                    &repository,
+                    &ls_tree_args.object_name,
+                    ls_tree_args.name_only,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::WriteTree => {
+            let result =
+                repository().and_then(|repository| crate::commands::write_tree(&repository));
+            if result.is_ok() {
+                println!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::CommitTree(commit_tree_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::commit_tree(
+                    &repository,
+                    &commit_tree_paragraphs(&matches),
+                    &commit_tree_args.tree_name,
+                    &commit_tree_args.parents,
+                )
+            });
+            if result.is_ok() {
+                println!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Commit(commit_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::commit(
+                    &repository,
+                    &commit_args.messages,
+                    &commit_args.file,
+                    commit_args.amend,
+                    commit_args.allow_empty,
+                    commit_args.all,
+                    &commit_args.author,
+                    &commit_args.date,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Clone(clone_args) => {
+            let result = crate::commands::clone(&clone_args.origin, &clone_args.directory);
+            if result.is_ok() {
+                println!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Submodule(submodule_args) => {
+            let result = repository().and_then(|repository| match submodule_args.command {
+                SubmoduleCommand::Init(init_args) => {
+                    crate::commands::submodule_init(&repository, &init_args.paths)
+                }
+                SubmoduleCommand::Update(update_args) => crate::commands::submodule_update(
+                    &repository,
+                    &update_args.paths,
+                    update_args.init,
+                ),
+                SubmoduleCommand::Status(status_args) => {
+                    crate::commands::submodule_status(&repository, &status_args.paths)
+                }
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Add(add_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::add(&repository, &add_args.paths, add_args.force)
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Status(status_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::status(
+                    &repository,
+                    status_args.short && !status_args.long,
+                    &status_args.porcelain,
+                    status_args.branch,
+                    match status_args.no_renames {
+                        true => Some(Detection::Off),
+                        false => status_args
+                            .find_renames
+                            .as_ref()
+                            .map(|_| Detection::Renames),
+                    },
+                    rename_score(&status_args.find_renames, "-M")?,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::CheckIgnore(check_ignore_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::check_ignore(
+                    &repository,
+                    &check_ignore_args.paths,
+                    check_ignore_args.verbose,
+                    check_ignore_args.non_matching,
+                    check_ignore_args.no_index,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::CheckAttr(check_attr_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::check_attr(
+                    &repository,
+                    check_attr_args.all,
+                    &check_attr_args.args,
+                    &check_attr_args.paths,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Config(config_args) => {
+            let name = config_args.args.first().cloned().unwrap_or_default();
+            let action = match config_args.args.len() {
+                _ if config_args.list => Ok(ConfigAction::List),
+                1 if config_args.get => Ok(ConfigAction::Get(name)),
+                1 if config_args.get_all => Ok(ConfigAction::GetAll(name)),
+                1 if config_args.unset => Ok(ConfigAction::Unset(name)),
+                2 if config_args.set => Ok(ConfigAction::Set(name, config_args.args[1].clone())),
+                1 if !config_args.set => Ok(ConfigAction::Get(name)),
+                2 if !config_args.get && !config_args.get_all && !config_args.unset => {
+                    Ok(ConfigAction::Set(name, config_args.args[1].clone()))
+                }
+                _ => Err(Error::Exit {
+                    message: "error: wrong number of arguments".to_string(),
+                    code: 129,
+                }),
+            };
+            let scope = if config_args.global {
+                Some(Scope::Global)
+            } else if config_args.system {
+                Some(Scope::System)
+            } else if config_args.local {
+                Some(Scope::Local)
+            } else if config_args.worktree {
+                Some(Scope::Worktree)
+            } else {
+                None
+            };
+            let value_type = if config_args.bool {
+                Some("bool".to_string())
+            } else if config_args.int {
+                Some("int".to_string())
+            } else {
+                config_args.value_type.clone()
+            };
+            let repository = repository().ok();
+            let result = action.and_then(|action| {
+                crate::commands::config(
+                    repository.as_ref(),
+                    &action,
+                    scope,
+                    &config_args.file,
+                    &value_type,
+                    config_args.show_origin,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Checkout(checkout_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::checkout(
+                    &repository,
+                    &checkout_args.target,
+                    &checkout_args.new_branch,
+                    checkout_args.force,
+                    checkout_args.detach,
+                    false,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Switch(switch_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::checkout(
+                    &repository,
+                    &switch_args.target,
+                    &switch_args.new_branch,
+                    switch_args.force,
+                    switch_args.detach,
+                    true,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Branch(branch_args) => {
+            let names = &branch_args.names;
+            let result = repository().and_then(|repository| {
+                if branch_args.delete || branch_args.force_delete {
+                    crate::commands::branch_delete(
+                        &repository,
+                        names,
+                        branch_args.remotes,
+                        branch_args.force || branch_args.force_delete,
+                    )
+                } else if branch_args.rename || branch_args.force_rename {
+                    crate::commands::branch_rename(
+                        &repository,
+                        names,
+                        branch_args.force || branch_args.force_rename,
+                    )
+                } else if let Some(upstream) = &branch_args.set_upstream_to {
+                    crate::commands::branch_set_upstream(
+                        &repository,
+                        upstream,
+                        &names.first().cloned(),
+                    )
+                } else if branch_args.unset_upstream {
+                    crate::commands::branch_unset_upstream(&repository, &names.first().cloned())
+                } else if names.is_empty()
+                    || branch_args.contains.is_some()
+                    || branch_args.merged.is_some()
+                {
+                    crate::commands::branch_list(
+                        &repository,
+                        branch_args.all,
+                        branch_args.remotes,
+                        branch_args.verbose,
+                        &branch_args.contains,
+                        &branch_args.merged,
+                    )
+                } else {
+                    crate::commands::branch_create(
+                        &repository,
+                        &names[0],
+                        &names.get(1).cloned(),
+                        branch_args.force,
+                        branch_args.track,
+                    )
+                }
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Tag(tag_args) => {
+            let args = &tag_args.args;
+            let result = repository().and_then(|repository| {
+                if tag_args.delete {
+                    crate::commands::tag_delete(&repository, args)
+                } else if tag_args.list
+                    || args.is_empty()
+                    || !tag_args.sort.is_empty()
+                    || tag_args.points_at.is_some()
+                    || tag_args.contains.is_some()
+                {
+                    crate::commands::tag_list(
+                        &repository,
+                        args,
+                        &tag_args.sort,
+                        &tag_args.points_at,
+                        &tag_args.contains,
+                    )
+                } else {
+                    crate::commands::tag_create(
+                        &repository,
+                        &args[0],
+                        &args.get(1).cloned(),
+                        tag_args.annotate,
+                        &tag_args.messages,
+                        &tag_args.file,
+                        tag_args.force,
+                    )
+                }
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::SymbolicRef(symbolic_ref_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::symbolic_ref(
+                    &repository,
+                    &symbolic_ref_args.name,
+                    &symbolic_ref_args.target,
+                    symbolic_ref_args.delete,
+                    symbolic_ref_args.quiet,
+                    symbolic_ref_args.short,
+                    &symbolic_ref_args.message,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::ShowRef(show_ref_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::show_ref(
+                    &repository,
+                    &show_ref_args.patterns,
+                    show_ref_args.head,
+                    show_ref_args.heads,
+                    show_ref_args.tags,
+                    show_ref_args.dereference,
+                    show_ref_args.hash,
+                    show_ref_args.verify,
+                    show_ref_args.quiet,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::UpdateRef(update_ref_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::update_ref(
+                    &repository,
+                    &update_ref_args.name,
+                    &update_ref_args.values,
+                    update_ref_args.delete,
+                    update_ref_args.no_deref,
+                    update_ref_args.stdin,
+                    update_ref_args.nul_terminated,
+                    &update_ref_args.message,
+                )
+            });
+            if result.is_err() {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Reflog(reflog_args) => {
+            let result = repository().and_then(|repository| match reflog_args.command {
+                None => crate::commands::reflog_show(&repository, &reflog_args.reference),
+                Some(ReflogCommand::Show(show_args)) => {
+                    crate::commands::reflog_show(&repository, &show_args.reference)
+                }
+                Some(ReflogCommand::Expire(expire_args)) => crate::commands::reflog_expire(
+                    &repository,
+                    &expire_args.references,
+                    expire_args.all,
+                    &expire_args.expire,
+                    &expire_args.expire_unreachable,
+                ),
+                Some(ReflogCommand::Delete(delete_args)) => crate::commands::reflog_delete(
+                    &repository,
+                    &delete_args.entries,
+                    delete_args.rewrite,
+                ),
+                Some(ReflogCommand::Exists(exists_args)) => {
+                    crate::commands::reflog_exists(&repository, &exists_args.reference)
+                }
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::RevParse(rev_parse_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::rev_parse(
+                    &repository,
+                    &rev_parse_args.revisions,
+                    rev_parse_args.verify,
+                    rev_parse_args.quiet,
+                    rev_parse_args.short,
+                    rev_parse_args.abbrev_ref,
+                    rev_parse_args.show_toplevel,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Log(log_args) => {
+            let result = repository().and_then(|repository| {
+                let mut options = walk_options(&log_args.walk)?;
+                let diff_options = diff_options(&log_args.diff, false)?;
+                if log_args.follow {
+                    options.follow = Some(diff_options.rename_options(&repository)?);
+                }
+                crate::commands::log(
+                    &repository,
+                    &log_args.walk.revisions,
+                    &log_args.walk.paths,
+                    &options,
+                    &diff_options,
+                    &log_args.format.clone().or(log_args.pretty.clone()),
+                    log_args.oneline,
+                    log_args.abbrev_commit,
+                    &log_args.date,
+                    log_args.graph,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::RevList(rev_list_args) => {
+            let result = repository().and_then(|repository| {
+                let mut options = walk_options(&rev_list_args.walk)?;
+                options.ancestry_path = rev_list_args.ancestry_path;
+                options.boundary = rev_list_args.boundary;
+                crate::commands::rev_list(
+                    &repository,
+                    &rev_list_args.walk.revisions,
+                    &rev_list_args.walk.paths,
+                    &options,
+                    rev_list_args.count,
+                    rev_list_args.left_right,
+                    rev_list_args.objects,
+                    &rev_list_args.filter,
+                    rev_list_args.reverse,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::MergeBase(merge_base_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::merge_base(
+                    &repository,
+                    &merge_base_args.commits,
+                    merge_base_args.all,
+                    merge_base_args.octopus,
+                    merge_base_args.independent,
+                    merge_base_args.is_ancestor,
+                    merge_base_args.fork_point,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+        Command::Diff(diff_args) => {
+            let result = repository().and_then(|repository| {
+                crate::commands::diff(
+                    &repository,
+                    &diff_args.revisions,
+                    &diff_args.paths,
+                    diff_args.cached,
+                    diff_args.exit_code || diff_args.quiet,
+                    diff_args.quiet,
+                    &diff_options(&diff_args.format, true)?,
+                )
+            });
+            if result.is_ok() {
+                print!("{}", result.unwrap());
+            } else {
+                fail(result.unwrap_err());
+            }
+        }
+    }
+}
+
+/// `commit-tree`'s `-m` messages and `-F` files in the order they were
+/// given, as each one is a paragraph of the message.
+fn commit_tree_paragraphs(matches: &ArgMatches) -> Vec<Paragraph> {
+    let matches = match matches.subcommand_matches("commit-tree") {
+        Some(matches) => matches,
+        None => return Vec::new(),
+    };
+    let mut paragraphs = Vec::new();
+    for id in ["messages", "files"] {
+        if let (Some(indices), Some(values)) =
+            (matches.indices_of(id), matches.get_many::<String>(id))
+        {
+            paragraphs.extend(indices.zip(values.map(|value| match id {
+                "messages" => Paragraph::Message(value.clone()),
+                _ => Paragraph::File(value.clone()),
+            })));
+        }
+    }
+    paragraphs.sort_by_key(|(index, _)| *index);
+    return paragraphs
+        .into_iter()
+        .map(|(_, paragraph)| paragraph)
+        .collect();
+}
+
+/// The position of the command in `arguments`, past the global options
+/// that come before it.
+fn command_position(arguments: &[String]) -> Option<usize> {
+    let mut index = 1;
+    while let Some(argument) = arguments.get(index) {
+        if matches!(argument.as_str(), "-C" | "--git-dir" | "--work-tree") {
+            index += 2;
+        } else if argument.starts_with('-') {
+            index += 1;
+        } else {
+            return Some(index);
+        }
+    }
+    return None;
+}
+
+/// The position of the command if it is `log` or `rev-list`, the commands
+/// whose arguments need rewriting before clap sees them.
+fn walk_command_position(arguments: &[String]) -> Option<usize> {
+    return command_position(arguments)
+        .filter(|&index| matches!(arguments[index].as_str(), "log" | "rev-list"));
+}
+
+/// Rewrites the `-<n>` shorthand of `log` and `rev-list` into
+/// `--max-count=<n>`, which clap can't express, up to any `--`.
+fn expand_count_shorthand(arguments: Vec<String>) -> Vec<String> {
+    let command = match walk_command_position(&arguments) {
+        Some(command) => command,
+        None => return arguments,
+    };
+    let mut expanded = Vec::new();
+    let mut options_ended = false;
+    for (index, argument) in arguments.into_iter().enumerate() {
+        options_ended |= argument == "--";
+        match argument.strip_prefix('-') {
+            Some(count) if index > command && !options_ended && count.parse::<usize>().is_ok() => {
+                expanded.push(format!("--max-count={count}"))
+            }
+            _ => expanded.push(argument),
+        }
+    }
+    return expanded;
+}
+
+/// Rewrites the score given to `-M` and `-C` in the same argument, as in
+/// `-M90%`, into `-M=90%` for clap, once past the command: before it `-C`
+/// takes a directory.
+fn expand_rename_scores(arguments: Vec<String>) -> Vec<String> {
+    let mut expanded = Vec::new();
+    let mut command_seen = false;
+    let mut options_ended = false;
+    for argument in arguments {
+        options_ended |= argument == "--";
+        let score = argument
+            .strip_prefix("-M")
+            .or_else(|| argument.strip_prefix("-C"));
+        match score {
+            Some(score) if command_seen && !options_ended && !score.is_empty() => {
+                if score.starts_with('=') {
+                    expanded.push(argument);
+                } else {
+                    expanded.push(format!("{}={score}", &argument[..2]));
+                }
+            }
+            _ => {
+                let previous = expanded.last().map(String::as_str);
+                command_seen |= !expanded.is_empty()
+                    && !argument.starts_with('-')
+                    && !matches!(previous, Some("-C" | "--git-dir" | "--work-tree"));
+                expanded.push(argument);
+            }
+        }
+    }
+    return expanded;
+}
+
+/// Rewrites `--not` for `log` and `rev-list`, which flips whether the
+/// revisions after it are excluded, by flipping their `^` prefixes instead:
+/// clap can't tell where among the revisions an option was given.
+fn expand_not(arguments: Vec<String>) -> Vec<String> {
+    // the options whose value may be the next argument
+    const TAKES_VALUE: &[&str] = &[
+        "-n",
+        "--max-count",
+        "--author",
+        "--grep",
+        "--since",
+        "--after",
+        "--until",
+        "--before",
+        "--format",
+        "--date",
+        "--filter",
+        "-U",
+        "--unified",
+        "--diff-algorithm",
+    ];
+    let command = match walk_command_position(&arguments) {
+        Some(command) => command,
+        None => return arguments,
+    };
+    let mut expanded = Vec::new();
+    let mut negated = false;
+    let mut options_ended = false;
+    let mut is_value = false;
+    for (index, argument) in arguments.into_iter().enumerate() {
+        options_ended |= argument == "--";
+        if index <= command || options_ended || is_value || argument.starts_with('-') {
+            is_value = TAKES_VALUE.contains(&argument.as_str());
+            if options_ended || argument != "--not" {
+                expanded.push(argument);
+            } else {
+                negated = !negated;
+            }
+            continue;
+        }
+        expanded.push(match (negated, argument.strip_prefix('^')) {
+            (false, _) => argument,
+            (true, Some(revision)) => revision.to_string(),
+            (true, None) => format!("^{argument}"),
+        });
+    }
+    return expanded;
+}
+
+/// The history walk `args` ask for, minus the revisions and paths.
+fn walk_options(args: &WalkArgs) -> Result<WalkOptions, Error> {
+    let since = match &args.since {
+        Some(date) => Some(git_date::parse(date)?.0),
+        None => None,
+    };
+    let until = match &args.until {
+        Some(date) => Some(git_date::parse(date)?.0),
+        None => None,
+    };
+    return Ok(WalkOptions {
+        topo_order: args.topo_order,
+        first_parent: args.first_parent,
+        max_count: args.max_count,
+        min_parents: if args.merges { 2 } else { 0 },
+        max_parents: if args.no_merges { Some(1) } else { None },
+        authors: args.author.clone(),
+        grep: args.grep.clone(),
+        since,
+        until,
+        ..Default::default()
+    });
+}
+
+/// The score given to `-M` or `-C`, if one was.
+fn rename_score(value: &Option<String>, option: &str) -> Result<Option<u32>, Error> {
+    return match value.as_deref() {
+        None | Some("") => Ok(None),
+        Some(value) => match git_rename::parse_score(value) {
+            Some(score) => Ok(Some(score)),
+            None => Err(Error::Exit {
+                message: format!("error: invalid argument to {option}"),
+                code: 129,
+            }),
+        },
+    };
+}
+
+/// What `diff` and `log` print: a patch if asked for, or for `diff` unless
+/// only other formats are. Names alone leave no room for anything else, as
+/// in git.
+fn diff_options(args: &DiffFormatArgs, patch_by_default: bool) -> Result<DiffOptions, Error> {
+    let algorithm = match &args.diff_algorithm {
+        Some(name) => Some(Algorithm::parse(name).ok_or_else(|| Error::Exit {
+            message: "error: option diff-algorithm accepts \"myers\", \"minimal\", \"patience\" and \"histogram\"".to_string(),
+            code: 129,
+        })?),
+        None => None,
+    };
+    let indent_heuristic = match (args.indent_heuristic, args.no_indent_heuristic) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    };
+    let (detection, rename_score) = if args.no_renames {
+        (Some(Detection::Off), None)
+    } else if !args.find_copies.is_empty() {
+        // like git, the last score given counts
+        let mut score = None;
+        for value in args.find_copies.iter() {
+            score = rename_score(&Some(value.clone()), "-C")?.or(score);
+        }
+        (Some(Detection::Copies), score)
+    } else if args.find_renames.is_some() {
+        (
+            Some(Detection::Renames),
+            rename_score(&args.find_renames, "-M")?,
+        )
+    } else {
+        (None, None)
+    };
+    let names = args.name_only || args.name_status;
+    let other_format = names || args.stat || args.numstat || args.raw;
+    return Ok(DiffOptions {
+        context: args.unified.unwrap_or(3),
+        patch: !names
+            && (args.patch || args.unified.is_some() || (patch_by_default && !other_format)),
+        stat: !names && args.stat,
+        numstat: !names && args.numstat,
+        shortstat: false,
+        summary: false,
+        name_only: args.name_only,
+        name_status: args.name_status,
+        raw: !names && args.raw,
+        algorithm,
+        indent_heuristic,
+        ignore_space_change: args.ignore_space_change,
+        ignore_all_space: args.ignore_all_space,
+        ignore_blank_lines: args.ignore_blank_lines,
+        detection,
+        rename_score,
+        find_copies_harder: args.find_copies_harder || args.find_copies.len() > 1,
+    });
+}
+
+/// Reports a command line clap rejects with git's exit status for bad
+/// usage: 128 for `log` and `rev-list`, which die on arguments they don't
+/// know, and 129 for the other commands. Asking for help isn't an error.
+fn usage_error(err: clap::Error, arguments: &[String]) -> ! {
+    if matches!(
+        err.kind(),
+        ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+    ) {
+        err.exit();
+    }
+    let _ = err.print();
+    process::exit(match walk_command_position(arguments) {
+        Some(_) => 128,
+        None => 129,
+    });
+}
+
+/// Reports `err` the way git does and exits with its status.
+fn fail(err: Error) -> ! {
+    match &err {
+        // the command's answer, not a complaint
+        Error::CheckFailed { output } => {
+            print!("{output}");
+            io::stdout().flush().unwrap();
+        }
+        _ => {
+            let message = err.to_string();
+            if !message.is_empty() {
+                eprintln!("{message}");
+            }
+        }
+    }
+    process::exit(err.exit_code());
+}
@@ -1,243 +1,3123 @@
 use core::str;
 use std::{
-    env::set_current_dir,
+    cmp::Ordering,
+    collections::BTreeMap,
+    env,
     fs::{self, File},
-    io::Read,
-    time::{SystemTime, UNIX_EPOCH},
+    io::{self, BufRead, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
 };
 
-use chrono::Local;
-use reqwest::{blocking::Client, header::CONTENT_TYPE, Method, StatusCode};
+use crate::{
+    git, git_attributes,
+    git_config::{self, Config, Scope},
+    git_date,
+    git_diff::{self, DiffOptions},
+    git_graph::Graph,
+    git_ignore::{self, IgnoreRules},
+    git_index::{self, IndexEntry},
+    git_merge_base,
+    git_object::{self, Commit, Object, ObjectType, Tag, Tree},
+    git_pretty::{self, Format},
+    git_reflog,
+    git_refs::{self, RefValue},
+    git_rename::{self, Detection, RenameOptions, Skipped},
+    git_revision::{self, Revision},
+    git_revwalk::{self, ObjectFilter, WalkOptions, WalkedCommit},
+    git_status, git_submodule, Error, ObjectId, Repository,
+};
+
+pub fn init(directory: &Option<String>, bare: bool) -> Result<(), Error> {
+    let directory = directory.as_deref().unwrap_or(".");
+    Repository::init(Path::new(directory), bare)?;
+    Ok(())
+}
+
+pub fn cat_file(repository: &Repository, object_name: &str) -> Result<Vec<u8>, Error> {
+    let id = repository.resolve_revision(object_name)?;
+    // commits and tags are shown exactly as stored, which parsing them
+    // wouldn't preserve for unknown headers or other encodings than UTF-8
+    return Ok(match git_object::read_raw(repository, &id)? {
+        (ObjectType::Tree, _) => stringify_tree(&repository.read_tree(&id)?, false).into_bytes(),
+        (_, data) => data,
+    });
+}
+
+/// Hashes the file at `file_path`. Outside of a repository there are no
+/// attributes to apply and nowhere to write the object to.
+pub fn hash_object(
+    repository: Option<&Repository>,
+    file_path: &str,
+    write: bool,
+    no_filters: bool,
+) -> Result<String, Error> {
+    let mut file = File::open(file_path).map_err(|err| Error::io("error opening file", err))?;
+    let mut file_contents: Vec<u8> = Vec::new();
+    file.read_to_end(&mut file_contents)
+        .map_err(|err| Error::io("error reading file contents", err))?;
+    if let (Some(repository), false) = (repository, no_filters) {
+        // files outside the working tree can still be hashed, they just
+        // won't match any attributes
+        let attribute_path = repository
+            .path_from_cwd(file_path)
+            .unwrap_or(file_path.to_string());
+        file_contents = git_attributes::convert_to_git(repository, &attribute_path, file_contents)?;
+    }
+    let size = file_contents.len();
+    let mut blob_contents: Vec<u8> = format!("blob {size}\0").bytes().collect();
+    blob_contents.append(&mut file_contents);
+
+    let hash = match (write, repository) {
+        (true, Some(repository)) => git_object::write_object(repository, &blob_contents)?,
+        (true, None) => return Err(Error::NotARepository),
+        (false, _) => ObjectId::hash(&blob_contents),
+    };
+
+    return Ok(hash.to_hex());
+}
+
+pub fn ls_tree(
+    repository: &Repository,
+    object_name: &str,
+    name_only: bool,
+) -> Result<String, Error> {
+    let id = repository.resolve_revision(object_name)?;
+    let tree = match repository.read_object(&id)? {
+        Object::Tree(tree) => tree,
+        Object::Commit(commit) => repository.read_tree(&commit.tree)?,
+        _ => return Err(format!("{object_name} is not a tree object").into()),
+    };
+
+    return Ok(stringify_tree(&tree, name_only));
+}
+
+pub fn write_tree(repository: &Repository) -> Result<String, Error> {
+    let mut ignore_rules = IgnoreRules::load(repository)?;
+    let hash = git_object::write_tree_from_directory(repository, "", &mut ignore_rules)?;
+    return Ok(hash.to_hex());
+}
+
+/// A paragraph of a `commit-tree` message: an `-m` value or an `-F` file.
+pub enum Paragraph {
+    Message(String),
+    File(String),
+}
+
+pub fn commit_tree(
+    repository: &Repository,
+    paragraphs: &Vec<Paragraph>,
+    tree_name: &str,
+    parent_names: &Vec<String>,
+) -> Result<String, Error> {
+    let tree = repository.resolve_revision(tree_name)?;
+    if repository.object_type(&tree)? != ObjectType::Tree {
+        return Err(format!("fatal: {tree} is not a valid 'tree' object").into());
+    }
+
+    let mut parents: Vec<ObjectId> = Vec::new();
+    for parent_name in parent_names {
+        let parent = git::resolve_commit(repository, parent_name)?;
+        if parents.contains(&parent) {
+            eprintln!("error: duplicate parent {parent} ignored");
+            continue;
+        }
+        parents.push(parent);
+    }
+
+    // each -m is its own paragraph, while files and stdin are used verbatim
+    let mut message = String::new();
+    for paragraph in paragraphs {
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        message += match paragraph {
+            Paragraph::Message(text) => format!("{text}\n"),
+            Paragraph::File(path) => read_message_file(path)?,
+        }
+        .as_str();
+    }
+    if paragraphs.is_empty() {
+        message = read_message_file("-")?;
+    }
+
+    let commit = Commit {
+        tree,
+        parents,
+        author: git::author_signature(repository)?,
+        committer: git::committer_signature(repository)?,
+        encoding: None,
+        extra_headers: Vec::new(),
+        gpgsig: None,
+        message,
+    };
+
+    let hash = git_object::write_commit(repository, &mut commit.serialize())?;
+
+    return Ok(hash.to_hex());
+}
+
+pub fn commit(
+    repository: &Repository,
+    messages: &[String],
+    message_file: &Option<String>,
+    amend: bool,
+    allow_empty: bool,
+    all: bool,
+    author: &Option<String>,
+    date: &Option<String>,
+) -> Result<String, Error> {
+    repository.work_tree()?;
+    if all {
+        stage_tracked_changes(repository)?;
+    }
+    let entries = git_index::read(repository)?;
+    if entries.iter().any(|entry| entry.stage != 0) {
+        return Err(
+            "error: Committing is not possible because you have unmerged files.\nfatal: Exiting because of an unresolved conflict."
+                .into(),
+        );
+    }
+    let tree = git_index::write_tree(repository, &entries)?;
+
+    let head = git::head_commit(repository)?;
+    let amended = match (amend, &head) {
+        (true, Some(head)) => Some(git_object::read_commit(repository, head)?),
+        (true, None) => return Err("fatal: You have nothing to amend.".into()),
+        (false, _) => None,
+    };
+    let parents = match &amended {
+        Some(amended) => amended.parents.clone(),
+        None => head.iter().cloned().collect(),
+    };
+
+    let message = if let Some(message_file) = message_file {
+        read_message_file(message_file)?
+    } else if !messages.is_empty() {
+        messages.join("\n\n")
+    } else if let Some(amended) = &amended {
+        amended.message.clone()
+    } else {
+        String::new()
+    };
+    let message = cleanup_message(&message);
+    if message.is_empty() {
+        return Err("Aborting commit due to empty commit message.".into());
+    }
+
+    let parent_tree = match parents.first() {
+        Some(parent) => git_object::read_commit(repository, parent)?.tree,
+        None => ObjectId::from_hex(git_object::EMPTY_TREE_HASH)?,
+    };
+    if tree == parent_tree && !allow_empty {
+        match &amended {
+            // amending an already empty commit keeps it empty, which is fine
+            Some(amended) if amended.tree == tree => {}
+            Some(_) => {
+                return Err(Error::Exit {
+                    message: "You asked to amend the most recent commit, but doing so would make\nit empty. You can repeat your command with --allow-empty, or you can\nremove the commit entirely with \"git reset HEAD^\".".to_string(),
+                    code: 1,
+                });
+            }
+            None => {
+                return Err(Error::CheckFailed {
+                    output: status_long(
+                        repository,
+                        &git_status::status(
+                            repository,
+                            &RenameOptions::configured(repository, "status")?,
+                        )?,
+                    ),
+                })
+            }
+        }
+    }
+
+    // amending keeps the original author unless it's overridden
+    let mut author_signature = match &amended {
+        Some(amended) => amended.author.clone(),
+        None => git::author_signature(repository)?,
+    };
+    if let Some(author) = author {
+        let (name, email) = author
+            .split_once('<')
+            .and_then(|(name, rest)| Some((name.trim(), rest.strip_suffix('>')?)))
+            .ok_or_else(|| format!("fatal: --author '{author}' is not 'Name <email>'"))?;
+        author_signature.name = name.to_string();
+        author_signature.email = email.to_string();
+    }
+    if let Some(date) = date {
+        (author_signature.timestamp, author_signature.timezone_offset) = git_date::parse(date)?;
+    }
+
+    let commit = Commit {
+        tree,
+        parents,
+        author: author_signature,
+        committer: git::committer_signature(repository)?,
+        encoding: None,
+        extra_headers: Vec::new(),
+        gpgsig: None,
+        message,
+    };
+    let hash = git_object::write_commit(repository, &mut commit.serialize())?;
+
+    let reflog_kind = if amend {
+        "commit (amend)"
+    } else if commit.parents.is_empty() {
+        "commit (initial)"
+    } else if commit.parents.len() > 1 {
+        "commit (merge)"
+    } else {
+        "commit"
+    };
+    git::update_head(
+        repository,
+        &hash,
+        &format!("{reflog_kind}: {}", commit.subject()),
+    )?;
+
+    let location = match git::current_branch(repository)? {
+        Some(branch) => branch,
+        None => "detached HEAD".to_string(),
+    };
+    let root = if commit.parents.is_empty() {
+        " (root-commit)"
+    } else {
+        ""
+    };
+    let mut output = format!("[{location}{root} {}] {}\n", hash.short(), commit.subject());
+    output += &commit_summary(repository, &commit)?;
+    return Ok(output);
+}
+
+/// The totals and file summary `commit` prints for the changes since the
+/// first parent, with renames found as by default. Merges show nothing.
+fn commit_summary(repository: &Repository, commit: &Commit) -> Result<String, Error> {
+    let parent_tree = match commit.parents.as_slice() {
+        [] => None,
+        [parent] => Some(git_object::read_commit(repository, parent)?.tree),
+        _ => return Ok(String::new()),
+    };
+    let pairs = git_diff::diff_trees(
+        repository,
+        parent_tree.as_ref(),
+        Some(&commit.tree),
+        &[],
+        false,
+    )?;
+    let rename_options = RenameOptions {
+        detection: Detection::Renames,
+        ..RenameOptions::configured(repository, "diff")?
+    };
+    let pairs =
+        git_rename::detect_renames(repository, pairs, &rename_options, &mut Skipped::default())?;
+    let options = DiffOptions {
+        shortstat: true,
+        summary: true,
+        ..DiffOptions::default()
+    };
+    return git_diff::format(repository, &pairs, &options);
+}
+
+/// Reads a commit message from a file, or from stdin when the name is `-`.
+fn read_message_file(message_file: &str) -> Result<String, Error> {
+    let mut contents = Vec::new();
+    let read_result = if message_file == "-" {
+        io::stdin().read_to_end(&mut contents)
+    } else {
+        File::open(message_file).and_then(|mut file| file.read_to_end(&mut contents))
+    };
+    read_result.map_err(|err| {
+        Error::io(
+            format!("fatal: could not read log file '{message_file}'"),
+            err,
+        )
+    })?;
+    return Ok(String::from_utf8_lossy(&contents).to_string());
+}
+
+/// Updates the index with the working tree state of every tracked file, as
+/// `commit -a` does. Untracked files are left alone.
+fn stage_tracked_changes(repository: &Repository) -> Result<(), Error> {
+    let index_mtime = git_index::index_mtime(repository);
+    let mut entries: Vec<IndexEntry> = Vec::new();
+    for entry in git_index::read(repository)? {
+        let file_path = repository.work_path(&entry.path);
+        let metadata = match fs::symlink_metadata(&file_path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(format!("error reading {}: {err}", entry.path).into()),
+        };
+        if entry.stage != 0 || entry.stat_matches(&metadata, index_mtime) {
+            entries.push(entry);
+            continue;
+        }
+        let hash = if entry.is_gitlink() {
+            if !file_path.join(".git").exists() {
+                entries.push(entry);
+                continue;
+            }
+            git_object::submodule_head(&file_path)?
+        } else {
+            git_object::write_blob_from_file(repository, &entry.path)?
+        };
+        let mode = git_index::mode_for_metadata(&metadata);
+        entries.push(IndexEntry::from_metadata(
+            &entry.path,
+            &hash,
+            mode,
+            &metadata,
+        ));
+    }
+    return git_index::write(repository, &mut entries);
+}
+
+/// Strips trailing whitespace and leading and trailing blank lines, and
+/// collapses runs of blank lines, like git's default cleanup of `-m` messages.
+fn cleanup_message(message: &str) -> String {
+    let mut result = String::new();
+    let mut pending_blank = false;
+    for line in message.lines().map(|line| line.trim_end()) {
+        if line.is_empty() {
+            pending_blank = !result.is_empty();
+            continue;
+        }
+        if pending_blank {
+            result.push('\n');
+            pending_blank = false;
+        }
+        result += format!("{line}\n").as_str();
+    }
+    return result;
+}
+
+pub fn clone(remote: &str, directory: &str) -> Result<String, Error> {
+    Repository::clone(remote, Path::new(directory))?;
+    return Ok(format!(
+        "cloned remote {} to {directory}",
+        remote.strip_suffix('/').unwrap_or(remote)
+    ));
+}
+
+pub fn checkout(
+    repository: &Repository,
+    target: &Option<String>,
+    new_branch: &Option<String>,
+    force: bool,
+    detach: bool,
+    require_branch: bool,
+) -> Result<String, Error> {
+    repository.work_tree()?;
+    let moving_from = format!(
+        "checkout: moving from {}",
+        git::head_description(repository)?
+    );
+    if let Some(new_branch) = new_branch {
+        if git::branch_exists(repository, new_branch) {
+            return Err(format!("fatal: a branch named '{new_branch}' already exists").into());
+        }
+        let start =
+            git::resolve_commit(repository, target.as_ref().unwrap_or(&"HEAD".to_string()))?;
+        let carried_over = git::switch_worktree(repository, &start, force)?;
+        git::make_branch(
+            repository,
+            new_branch,
+            &start,
+            &format!(
+                "branch: Created from {}",
+                target.as_deref().unwrap_or("HEAD")
+            ),
+        )?;
+        git::set_head_to_branch(
+            repository,
+            new_branch,
+            &format!("{moving_from} to {new_branch}"),
+        )?;
+        return Ok(format!(
+            "{}Switched to a new branch '{new_branch}'\n",
+            format_carried_over(repository, &carried_over)
+        ));
+    }
+
+    let target = target
+        .as_ref()
+        .ok_or("fatal: missing branch or commit argument".to_string())?;
+    if !detach && git::branch_exists(repository, target) {
+        let hash = git::resolve_commit(repository, &format!("refs/heads/{target}"))?;
+        let already_on = git::current_branch(repository)?.as_ref() == Some(target);
+        let carried_over = git::switch_worktree(repository, &hash, force)?;
+        git::set_head_to_branch(repository, target, &format!("{moving_from} to {target}"))?;
+        return Ok(format!(
+            "{}{}\n",
+            format_carried_over(repository, &carried_over),
+            if already_on {
+                format!("Already on '{target}'")
+            } else {
+                format!("Switched to branch '{target}'")
+            }
+        ));
+    }
+
+    let hash = git::resolve_commit(repository, target)?;
+    if require_branch && !detach {
+        return Err(format!(
+            "fatal: a branch is expected, got commit '{target}'\nhint: If you want to detach HEAD at the commit, try again with the --detach option."
+        ).into());
+    }
+    let carried_over = git::switch_worktree(repository, &hash, force)?;
+    git::set_head_detached(repository, &hash, &format!("{moving_from} to {target}"))?;
+    return Ok(format!(
+        "{}HEAD is now at {} {}\n",
+        format_carried_over(repository, &carried_over),
+        hash.short(),
+        git_object::read_commit(repository, &hash)?.subject()
+    ));
+}
+
+/// Lists branches like `git branch`: the local ones, the remote-tracking ones
+/// with `remotes` or both with `all`, optionally only those that contain the
+/// commit `contains` or are merged into `merged`. `verbose` adds each tip and
+/// how local branches compare to their upstream, named from `-vv` on.
+pub fn branch_list(
+    repository: &Repository,
+    all: bool,
+    remotes: bool,
+    verbose: u8,
+    contains: &Option<String>,
+    merged: &Option<String>,
+) -> Result<String, Error> {
+    let contains = filter_commit(repository, contains)?;
+    let merged = filter_commit(repository, merged)?;
+
+    // (label, ref name, tip, current)
+    let mut rows: Vec<(String, Option<String>, ObjectId, bool)> = Vec::new();
+    let current = git::current_branch(repository)?;
+    if !remotes || all {
+        if let (None, Some(head)) = (&current, git::head_commit(repository)?) {
+            rows.push((
+                git::detached_head_description(repository)?,
+                None,
+                head,
+                true,
+            ));
+        }
+        for reference in git_refs::list(repository, "refs/heads/")? {
+            let label = git_refs::shorten(&reference.name).to_string();
+            let is_current = current.as_ref() == Some(&label);
+            rows.push((label, Some(reference.name), reference.id, is_current));
+        }
+    }
+    if remotes || all {
+        for reference in git_refs::list(repository, "refs/remotes/")? {
+            let label = match all {
+                true => reference.name.strip_prefix("refs/").unwrap().to_string(),
+                false => git_refs::shorten(&reference.name).to_string(),
+            };
+            rows.push((label, Some(reference.name), reference.id, false));
+        }
+    }
+
+    let mut shown = Vec::new();
+    for row in rows {
+        let tip = &row.2;
+        if let Some(commit) = &contains {
+            if !git_merge_base::is_ancestor(repository, commit, tip)? {
+                continue;
+            }
+        }
+        if let Some(merged) = &merged {
+            if !git_merge_base::is_ancestor(repository, tip, merged)? {
+                continue;
+            }
+        }
+        shown.push(row);
+    }
+    let width = shown
+        .iter()
+        .map(|(label, ..)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut output = String::new();
+    for (label, name, tip, is_current) in shown {
+        let marker = if is_current { "* " } else { "  " };
+        let symbolic = match &name {
+            Some(name) => match git_refs::read(repository, name)? {
+                Some(RefValue::Symbolic(target)) => Some(target),
+                _ => None,
+            },
+            None => None,
+        };
+        if let Some(target) = symbolic {
+            let target = git_refs::shorten(&target);
+            output += &match verbose {
+                0 => format!("{marker}{label} -> {target}\n"),
+                _ => format!("{marker}{label:<width$} -> {target}\n"),
+            };
+            continue;
+        }
+        if verbose == 0 {
+            output += &format!("{marker}{label}\n");
+            continue;
+        }
+        let tracking = match name
+            .as_deref()
+            .and_then(|name| name.strip_prefix("refs/heads/"))
+        {
+            Some(branch) => branch_tracking(repository, branch, &tip, verbose > 1)?,
+            None => String::new(),
+        };
+        output += &format!(
+            "{marker}{label:<width$} {} {tracking}{}\n",
+            git_object::abbreviate(repository, &tip, 7)?,
+            git_object::read_commit(repository, &tip)?.subject()
+        );
+    }
+    return Ok(output);
+}
+
+/// The commit a `--contains` or `--merged` argument names.
+fn filter_commit(
+    repository: &Repository,
+    name: &Option<String>,
+) -> Result<Option<ObjectId>, Error> {
+    return match filter_object(repository, name)? {
+        Some(id) => git_revision::peel_to(repository, &id, ObjectType::Commit)
+            .map(Some)
+            .map_err(|_| malformed_object_name(name.as_deref().unwrap())),
+        None => Ok(None),
+    };
+}
+
+/// The object a `--points-at` argument names.
+fn filter_object(
+    repository: &Repository,
+    name: &Option<String>,
+) -> Result<Option<ObjectId>, Error> {
+    return match name {
+        Some(name) => git_revision::resolve(repository, name)
+            .map(Some)
+            .map_err(|_| malformed_object_name(name)),
+        None => Ok(None),
+    };
+}
+
+fn malformed_object_name(name: &str) -> Error {
+    return Error::Exit {
+        message: format!("error: malformed object name {name}"),
+        code: 129,
+    };
+}
+
+/// `[ahead 1, behind 2] ` for `git branch -v`, with the upstream's name in
+/// front when `show_name` is set.
+fn branch_tracking(
+    repository: &Repository,
+    branch: &str,
+    tip: &ObjectId,
+    show_name: bool,
+) -> Result<String, Error> {
+    let upstream = match git_status::upstream(repository, branch, &Some(*tip))? {
+        Some(upstream) => upstream,
+        None => return Ok(String::new()),
+    };
+    let mut counts = Vec::new();
+    match upstream.ahead_behind {
+        None => counts.push("gone".to_string()),
+        Some((ahead, behind)) => {
+            if ahead > 0 {
+                counts.push(format!("ahead {ahead}"));
+            }
+            if behind > 0 {
+                counts.push(format!("behind {behind}"));
+            }
+        }
+    }
+    let counts = counts.join(", ");
+    return Ok(match (show_name, counts.is_empty()) {
+        (true, true) => format!("[{}] ", upstream.name),
+        (true, false) => format!("[{}: {counts}] ", upstream.name),
+        (false, true) => String::new(),
+        (false, false) => format!("[{counts}] "),
+    });
+}
+
+/// Creates the branch `name` at `start` (HEAD by default), replacing an
+/// existing one with `force`. Branches started from a remote-tracking branch
+/// track it, as do those started from a local branch with `track`, unless
+/// `branch.autoSetupMerge` says otherwise.
+pub fn branch_create(
+    repository: &Repository,
+    name: &str,
+    start: &Option<String>,
+    force: bool,
+    track: bool,
+) -> Result<String, Error> {
+    if !git_refs::check_format(&format!("refs/heads/{name}"), false) {
+        return Err(format!("fatal: '{name}' is not a valid branch name").into());
+    }
+    let exists = git::branch_exists(repository, name);
+    if exists && !force {
+        return Err(format!("fatal: a branch named '{name}' already exists").into());
+    }
+    if exists && git::current_branch(repository)?.as_deref() == Some(name) {
+        return Err(format!(
+            "fatal: cannot force update the branch '{name}' checked out at '{}'",
+            checked_out_at(repository)
+        )
+        .into());
+    }
+
+    // like git, name the branch HEAD is on in the log
+    let start_name = match start {
+        Some(start) => start.clone(),
+        None => git::current_branch(repository)?.unwrap_or("HEAD".to_string()),
+    };
+    let start_name = start_name.as_str();
+    let start_id = git_revision::resolve(repository, start_name)
+        .map_err(|_| format!("fatal: not a valid object name: '{start_name}'"))?;
+    let commit = match git_revision::peel_to(repository, &start_id, ObjectType::Commit) {
+        Ok(commit) => commit,
+        Err(_) => {
+            let object_type = git_object::get_type(repository, &start_id)?;
+            return Err(format!(
+                "error: object {start_id} is a {object_type}, not a commit\nfatal: not a valid branch point: '{start_name}'"
+            )
+            .into());
+        }
+    };
+    let message = if exists {
+        format!("branch: Reset to {start_name}")
+    } else {
+        format!("branch: Created from {start_name}")
+    };
+    git::make_branch(repository, name, &commit, &message)?;
+
+    let upstream = match start {
+        Some(start) => git_refs::expand(repository, start)?,
+        None => None,
+    };
+    // whether branches started from remote-tracking and local branches
+    // track them without being told to
+    let (auto_remote, auto_local) =
+        match git_config::get(repository, "branch.autosetupmerge")?.as_deref() {
+            Some("always") => (true, true),
+            Some("inherit" | "simple") | None => (true, false),
+            value => (
+                git_config::parse_bool(value, "branch.autosetupmerge")?,
+                false,
+            ),
+        };
+    let should_track = match upstream.as_deref() {
+        Some(upstream) if upstream.starts_with("refs/remotes/") => track || auto_remote,
+        Some(upstream) if upstream.starts_with("refs/heads/") => track || auto_local,
+        _ => false,
+    };
+    if !should_track {
+        return Ok(String::new());
+    }
+    let upstream = upstream.unwrap();
+    git::set_upstream(repository, name, &upstream)?;
+    return Ok(format!(
+        "branch '{name}' set up to track '{}'.\n",
+        git_refs::shorten(&upstream)
+    ));
+}
+
+/// Deletes the branches `names`, or remote-tracking branches with `remotes`.
+/// Unless `force` is set, local branches have to be merged into their
+/// upstream, or HEAD if they have none. Failures are reported as they
+/// happen without stopping the others.
+pub fn branch_delete(
+    repository: &Repository,
+    names: &Vec<String>,
+    remotes: bool,
+    force: bool,
+) -> Result<String, Error> {
+    if names.is_empty() {
+        return Err("fatal: branch name required".into());
+    }
+    let current = git::current_branch(repository)?;
+    let head = git::head_commit(repository)?;
+    let (prefix, kind) = match remotes {
+        true => ("refs/remotes/", "remote-tracking branch"),
+        false => ("refs/heads/", "branch"),
+    };
+
+    let mut output = String::new();
+    let mut failed = false;
+    for name in names {
+        let reference = format!("{prefix}{name}");
+        let tip = match git_refs::resolve(repository, &reference)? {
+            Some(tip) => tip,
+            None => {
+                eprintln!("error: {kind} '{name}' not found.");
+                failed = true;
+                continue;
+            }
+        };
+        if !remotes && current.as_ref() == Some(name) {
+            eprintln!(
+                "error: Cannot delete branch '{name}' checked out at '{}'",
+                checked_out_at(repository)
+            );
+            failed = true;
+            continue;
+        }
+        if !remotes && !force {
+            let upstream = match git::upstream_ref(repository, name)? {
+                Some(upstream) => {
+                    git_refs::resolve(repository, &upstream)?.map(|id| (upstream, id))
+                }
+                None => None,
+            };
+            let merged_into_head = match &head {
+                Some(head) => git_merge_base::is_ancestor(repository, &tip, head)?,
+                None => false,
+            };
+            let merged = match &upstream {
+                Some((upstream, id)) => {
+                    let merged = git_merge_base::is_ancestor(repository, &tip, id)?;
+                    if merged && !merged_into_head {
+                        eprintln!(
+                            "warning: deleting branch '{name}' that has been merged to\n         '{upstream}', but not yet merged to HEAD."
+                        );
+                    }
+                    merged
+                }
+                None => merged_into_head,
+            };
+            if !merged {
+                eprintln!(
+                    "error: The branch '{name}' is not fully merged.\nIf you are sure you want to delete it, run 'git branch -D {name}'."
+                );
+                failed = true;
+                continue;
+            }
+        }
+
+        git_refs::delete(repository, &reference)?;
+        if !remotes {
+            git_config::rename_section(
+                repository.git_path("config"),
+                &format!("branch.{name}"),
+                None,
+            )?;
+            repository.forget_config();
+        }
+        output += &format!(
+            "Deleted {kind} {name} (was {}).\n",
+            git_object::abbreviate(repository, &tip, 7)?
+        );
+    }
+    if failed {
+        return Err(Error::CheckFailed { output });
+    }
+    return Ok(output);
+}
+
+/// Renames `[<old>] <new>`, the current branch if `<old>` is left out,
+/// along with its log and configuration. `force` allows replacing an
+/// existing branch.
+pub fn branch_rename(
+    repository: &Repository,
+    names: &Vec<String>,
+    force: bool,
+) -> Result<String, Error> {
+    let current = git::current_branch(repository)?;
+    let (old, new) = match names.as_slice() {
+        [new] => (
+            current.clone().ok_or_else(|| {
+                "fatal: cannot rename the current branch while not on any.".to_string()
+            })?,
+            new,
+        ),
+        [old, new] => (old.clone(), new),
+        [] => return Err("fatal: branch name required".into()),
+        _ => return Err("fatal: too many arguments for a rename operation".into()),
+    };
+    let is_current = current.as_ref() == Some(&old);
+    let old_ref = format!("refs/heads/{old}");
+    let exists = git::branch_exists(repository, &old);
+    // the current branch may not have any commits yet
+    if !exists && !is_current {
+        return Err(format!("fatal: No branch named '{old}'.").into());
+    }
+    if !git_refs::check_format(&format!("refs/heads/{new}"), false) {
+        return Err(format!("fatal: '{new}' is not a valid branch name").into());
+    }
+    if old != *new && git::branch_exists(repository, new) {
+        if !force {
+            return Err(format!("fatal: a branch named '{new}' already exists").into());
+        }
+        if current.as_ref() == Some(new) {
+            return Err(format!(
+                "fatal: cannot force update the branch '{new}' checked out at '{}'",
+                checked_out_at(repository)
+            )
+            .into());
+        }
+    }
+
+    let new_ref = format!("refs/heads/{new}");
+    if exists {
+        git_refs::rename(
+            repository,
+            &old_ref,
+            &new_ref,
+            &format!("Branch: renamed {old_ref} to {new_ref}"),
+        )?;
+    } else {
+        git::set_head_to_branch(repository, new, "")?;
+    }
+    let config_path = repository.git_path("config");
+    if old != *new {
+        git_config::rename_section(&config_path, &format!("branch.{new}"), None)?;
+    }
+    git_config::rename_section(
+        &config_path,
+        &format!("branch.{old}"),
+        Some(&format!("branch.{new}")),
+    )?;
+    repository.forget_config();
+    return Ok(String::new());
+}
+
+/// Makes `branch` (the current one by default) track `upstream`.
+pub fn branch_set_upstream(
+    repository: &Repository,
+    upstream: &str,
+    branch: &Option<String>,
+) -> Result<String, Error> {
+    let branch = match branch {
+        Some(branch) => branch.clone(),
+        None => git::current_branch(repository)?.ok_or_else(|| {
+            format!("fatal: could not set upstream of HEAD to {upstream} when it does not point to any branch.")
+        })?,
+    };
+    if !git::branch_exists(repository, &branch) {
+        return Err(format!("fatal: branch '{branch}' does not exist").into());
+    }
+    let upstream_ref = git_refs::expand(repository, upstream)?
+        .filter(|name| name.starts_with("refs/heads/") || name.starts_with("refs/remotes/"))
+        .filter(|name| git_refs::resolve(repository, name).is_ok_and(|id| id.is_some()))
+        .ok_or_else(|| {
+            format!(
+                "fatal: the requested upstream branch '{upstream}' does not exist\nhint: \nhint: If you are planning on basing your work on an upstream\nhint: branch that already exists at the remote, you may need to\nhint: run \"git fetch\" to retrieve it.\nhint: \nhint: If you are planning to push out a new local branch that\nhint: will track its remote counterpart, you may want to use\nhint: \"git push -u\" to set the upstream config as you push.\nhint: Disable this message with \"git config advice.setUpstreamFailure false\""
+            )
+        })?;
+    git::set_upstream(repository, &branch, &upstream_ref)?;
+    return Ok(format!(
+        "branch '{branch}' set up to track '{}'.\n",
+        git_refs::shorten(&upstream_ref)
+    ));
+}
+
+/// Removes the upstream configuration of `branch`, the current one by
+/// default.
+pub fn branch_unset_upstream(
+    repository: &Repository,
+    branch: &Option<String>,
+) -> Result<String, Error> {
+    let branch = match branch {
+        Some(branch) => branch.clone(),
+        None => git::current_branch(repository)?.ok_or_else(|| {
+            "fatal: could not unset upstream of HEAD when it does not point to any branch."
+                .to_string()
+        })?,
+    };
+    if !git::branch_exists(repository, &branch) {
+        return Err(format!("fatal: branch '{branch}' does not exist").into());
+    }
+    let config_path = repository.git_path("config");
+    let removed = git_config::unset(&config_path, &format!("branch.{branch}.remote"))?
+        + git_config::unset(&config_path, &format!("branch.{branch}.merge"))?;
+    repository.forget_config();
+    if removed == 0 {
+        return Err(format!("fatal: Branch '{branch}' has no upstream information").into());
+    }
+    return Ok(String::new());
+}
+
+/// Where the current branch is checked out, for errors about it.
+fn checked_out_at(repository: &Repository) -> String {
+    return match &repository.work_tree {
+        Some(work_tree) => work_tree.display().to_string(),
+        None => repository.git_dir.display().to_string(),
+    };
+}
+
+/// Lists the tags matching any of `patterns`, or all of them, sorted by the
+/// `sort` keys with the last one deciding first (`tag.sort` if there are
+/// none). `points_at` keeps tags naming that object directly or through a
+/// tag object, `contains` those whose commit has that commit in its history.
+pub fn tag_list(
+    repository: &Repository,
+    patterns: &[String],
+    sort: &[String],
+    points_at: &Option<String>,
+    contains: &Option<String>,
+) -> Result<String, Error> {
+    let points_at = filter_object(repository, points_at)?;
+    let contains = filter_commit(repository, contains)?;
+    let sort = match (sort.is_empty(), git_config::get(repository, "tag.sort")?) {
+        (true, Some(configured)) => vec![configured],
+        _ => sort.to_vec(),
+    };
+    let mut keys = Vec::new();
+    for key in sort.iter() {
+        let (key, reverse) = match key.strip_prefix('-') {
+            Some(key) => (key, true),
+            None => (key.as_str(), false),
+        };
+        let key = match key {
+            "refname" | "objectname" | "creatordate" | "taggerdate" | "committerdate" => key,
+            "version:refname" | "v:refname" => "version:refname",
+            _ => return Err(format!("fatal: unknown field name: {key}").into()),
+        };
+        keys.push((key, reverse));
+    }
+    let needs_dates = keys.iter().any(|(key, _)| key.ends_with("date"));
+
+    // (name, id, tagger date, committer date)
+    let mut tags: Vec<(String, ObjectId, Option<i64>, Option<i64>)> = Vec::new();
+    for reference in git_refs::list(repository, "refs/tags/")? {
+        let name = git_refs::shorten(&reference.name).to_string();
+        if !patterns.is_empty()
+            && !patterns.iter().any(|pattern| {
+                git_ignore::wildmatch_across_slashes(pattern.as_bytes(), name.as_bytes())
+            })
+        {
+            continue;
+        }
+        // only read and peel the tag when a filter or the sort needs it
+        let tag = match points_at.is_some() || needs_dates {
+            true => match git_object::read_object(repository, &reference.id)? {
+                Object::Tag(tag) => Some(tag),
+                _ => None,
+            },
+            false => None,
+        };
+        if let Some(points_at) = &points_at {
+            if reference.id != *points_at && tag.as_ref().map(|tag| tag.object) != Some(*points_at)
+            {
+                continue;
+            }
+        }
+        if contains.is_none() && !needs_dates {
+            tags.push((name, reference.id, None, None));
+            continue;
+        }
+        let peeled = git_refs::peel(repository, &reference.id)?;
+        let is_commit = git_object::get_type(repository, &peeled)? == ObjectType::Commit;
+        if let Some(contains) = &contains {
+            if !is_commit || !git_merge_base::is_ancestor(repository, contains, &peeled)? {
+                continue;
+            }
+        }
+        let tagger_date = tag
+            .and_then(|tag| tag.tagger)
+            .map(|tagger| tagger.timestamp);
+        // only lightweight tags of commits have a committer date
+        let committer_date = match needs_dates && is_commit && reference.id == peeled {
+            true => Some(
+                git_object::read_commit(repository, &peeled)?
+                    .committer
+                    .timestamp,
+            ),
+            false => None,
+        };
+        tags.push((name, reference.id, tagger_date, committer_date));
+    }
+
+    tags.sort_by(|a, b| {
+        for (key, reverse) in keys.iter().rev() {
+            let ordering = match *key {
+                "refname" => a.0.cmp(&b.0),
+                "version:refname" => compare_versions(&a.0, &b.0),
+                "objectname" => a.1.cmp(&b.1),
+                "taggerdate" => a.2.cmp(&b.2),
+                "committerdate" => a.3.cmp(&b.3),
+                _ => a.2.or(a.3).cmp(&b.2.or(b.3)),
+            };
+            let ordering = if *reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        return a.0.cmp(&b.0);
+    });
+    return Ok(tags.into_iter().map(|(name, ..)| name + "\n").collect());
+}
+
+/// Orders names with embedded numbers by value, so `v1.9` comes before
+/// `v1.10`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        let (x, y) = match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (*x, *y),
+        };
+        if !x.is_ascii_digit() || !y.is_ascii_digit() {
+            if x != y {
+                return x.cmp(&y);
+            }
+            a = &a[1..];
+            b = &b[1..];
+            continue;
+        }
+        let digits = |bytes: &[u8]| bytes.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (a_end, b_end) = (digits(a), digits(b));
+        let trim = |number: &[u8]| -> Vec<u8> {
+            number.iter().skip_while(|c| **c == b'0').copied().collect()
+        };
+        let (a_number, b_number) = (trim(&a[..a_end]), trim(&b[..b_end]));
+        let ordering = a_number
+            .len()
+            .cmp(&b_number.len())
+            .then_with(|| a_number.cmp(&b_number));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        a = &a[a_end..];
+        b = &b[b_end..];
+    }
+}
+
+/// Creates the tag `name` for `target` (HEAD by default). With a message, or
+/// with `annotate`, that is a tag object recording the tagger, otherwise a
+/// lightweight tag. `force` replaces an existing tag.
+pub fn tag_create(
+    repository: &Repository,
+    name: &str,
+    target: &Option<String>,
+    annotate: bool,
+    messages: &[String],
+    message_file: &Option<String>,
+    force: bool,
+) -> Result<String, Error> {
+    let reference = format!("refs/tags/{name}");
+    if !git_refs::check_format(&reference, false) {
+        return Err(format!("fatal: '{name}' is not a valid tag name.").into());
+    }
+    let target = target.as_deref().unwrap_or("HEAD");
+    let object = git_revision::resolve(repository, target)
+        .map_err(|_| format!("fatal: Failed to resolve '{target}' as a valid ref."))?;
+    let previous = git_refs::resolve(repository, &reference)?;
+    if previous.is_some() && !force {
+        return Err(format!("fatal: tag '{name}' already exists").into());
+    }
+
+    let message = if let Some(message_file) = message_file {
+        Some(read_message_file(message_file)?)
+    } else if !messages.is_empty() {
+        Some(messages.join("\n\n"))
+    } else if annotate {
+        return Err("fatal: no tag message?".into());
+    } else {
+        None
+    };
+    let id = match message {
+        Some(message) => {
+            let object_type = git_object::get_type(repository, &object)?;
+            if object_type == ObjectType::Tag {
+                eprintln!(
+                    "hint: You have created a nested tag. The object referred to by your new tag is\nhint: already a tag. If you meant to tag the object that it points to, use:\nhint: \nhint: \tgit tag -f {name} {target}^{{}}\nhint: Disable this message with \"git config advice.nestedTag false\""
+                );
+            }
+            let tag = Tag {
+                object,
+                object_type,
+                tag: name.to_string(),
+                tagger: Some(git::committer_signature(repository)?),
+                extra_headers: Vec::new(),
+                message: cleanup_message(&message),
+            };
+            git_object::write_tag(repository, &mut tag.serialize())?
+        }
+        None => object,
+    };
+    git_refs::write(repository, &reference, &id, "")?;
+
+    return Ok(match previous {
+        Some(previous) if previous != id => format!(
+            "Updated tag '{name}' (was {})\n",
+            git_object::abbreviate(repository, &previous, 7)?
+        ),
+        _ => String::new(),
+    });
+}
+
+/// Deletes the tags `names`, reporting missing ones without stopping.
+pub fn tag_delete(repository: &Repository, names: &Vec<String>) -> Result<String, Error> {
+    let mut output = String::new();
+    let mut failed = false;
+    for name in names {
+        let reference = format!("refs/tags/{name}");
+        let id = match git_refs::resolve(repository, &reference)? {
+            Some(id) => id,
+            None => {
+                eprintln!("error: tag '{name}' not found.");
+                failed = true;
+                continue;
+            }
+        };
+        git_refs::delete(repository, &reference)?;
+        output += &format!(
+            "Deleted tag '{name}' (was {})\n",
+            git_object::abbreviate(repository, &id, 7)?
+        );
+    }
+    if failed {
+        return Err(Error::CheckFailed { output });
+    }
+    return Ok(output);
+}
+
+/// Lists the local changes a checkout kept, like git's `M\tpath` lines.
+fn format_carried_over(repository: &Repository, carried_over: &Vec<String>) -> String {
+    let mut result = String::new();
+    for path in carried_over {
+        let code = if repository.work_path(path).symlink_metadata().is_err() {
+            'D'
+        } else {
+            'M'
+        };
+        result += format!("{code}\t{path}\n").as_str();
+    }
+    return result;
+}
+
+pub fn add(repository: &Repository, paths: &Vec<String>, force: bool) -> Result<String, Error> {
+    repository.work_tree()?;
+    let mut entries: BTreeMap<String, IndexEntry> = git_index::read(repository)?
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+    let index_mtime = git_index::index_mtime(repository);
+    let mut ignore_rules = if force {
+        IgnoreRules::empty()
+    } else {
+        IgnoreRules::load(repository)?
+    };
+
+    for pathspec in paths {
+        let prefix = repository.path_from_cwd(pathspec)?;
+        let in_pathspec = |path: &String| {
+            prefix.is_empty() || path == &prefix || path.starts_with(&format!("{prefix}/"))
+        };
+
+        let mut files: Vec<String> = Vec::new();
+        let prefix_path = repository.work_path(&prefix);
+        if prefix.is_empty() || (prefix_path.is_dir() && !prefix_path.join(".git").exists()) {
+            files = git::list_worktree_files(repository, &prefix, &mut ignore_rules)?;
+            // tracked files stay tracked even if they match an ignore rule
+            for path in entries.keys().filter(|path| in_pathspec(path)) {
+                if repository.work_path(path).symlink_metadata().is_ok() && !files.contains(path) {
+                    files.push(path.clone());
+                }
+            }
+        } else if prefix_path.symlink_metadata().is_ok() {
+            if !entries.contains_key(&prefix)
+                && ignore_rules.is_ignored(&prefix, prefix_path.is_dir())?
+            {
+                return Err(format!(
+                    "The following paths are ignored by one of your .gitignore files:\n{prefix}\nhint: Use -f if you really want to add them."
+                ).into());
+            }
+            files.push(prefix.clone());
+        }
+
+        let removed: Vec<String> = entries
+            .keys()
+            .filter(|path| {
+                in_pathspec(path) && repository.work_path(path).symlink_metadata().is_err()
+            })
+            .cloned()
+            .collect();
+        if files.is_empty() && removed.is_empty() {
+            return Err(format!("fatal: pathspec '{pathspec}' did not match any files").into());
+        }
+        for path in removed {
+            entries.remove(&path);
+        }
+
+        for path in files {
+            let file_path = repository.work_path(&path);
+            let metadata = fs::symlink_metadata(&file_path)
+                .map_err(|err| Error::io(format!("error reading metadata of {path}"), err))?;
+            let mode = git_index::mode_for_metadata(&metadata);
+            if entries
+                .get(&path)
+                .is_some_and(|entry| entry.stat_matches(&metadata, index_mtime))
+            {
+                continue;
+            }
+            let hash = if metadata.is_dir() {
+                git_object::submodule_head(&file_path)?
+            } else {
+                git_object::write_blob_from_file(repository, &path)?
+            };
+            entries.insert(
+                path.clone(),
+                IndexEntry::from_metadata(&path, &hash, mode, &metadata),
+            );
+        }
+    }
+
+    git_index::write(repository, &mut entries.into_values().collect::<Vec<_>>())?;
+    return Ok(String::new());
+}
+
+pub fn status(
+    repository: &Repository,
+    short: bool,
+    porcelain: &Option<String>,
+    show_branch: bool,
+    detection: Option<Detection>,
+    rename_score: Option<u32>,
+) -> Result<String, Error> {
+    let mut renames = RenameOptions::configured(repository, "status")?;
+    if let Some(detection) = detection {
+        renames.detection = detection;
+    }
+    if let Some(score) = rename_score {
+        renames.min_score = score;
+    }
+    let status = git_status::status(repository, &renames)?;
+    return match porcelain.as_deref() {
+        Some("v2") | Some("2") => Ok(status_porcelain_v2(&status, show_branch)),
+        Some("v1") | Some("1") => Ok(status_short(None, &status, show_branch)),
+        Some(version) => Err(format!("fatal: unsupported porcelain version '{version}'").into()),
+        None if short => Ok(status_short(Some(repository), &status, show_branch)),
+        None => Ok(status_long(repository, &status)),
+    };
+}
+
+fn status_long(repository: &Repository, status: &git_status::Status) -> String {
+    let mut result = String::new();
+    match (&status.branch, &status.head) {
+        (Some(branch), _) => result += format!("On branch {branch}\n").as_str(),
+        (None, Some(head)) => result += format!("HEAD detached at {}\n", head.short()).as_str(),
+        (None, None) => {}
+    }
+    // every block below ends with a blank line, separating it from the next
+    if status.head.is_none() {
+        result += "\nNo commits yet\n\n";
+    } else if let Some(upstream) = &status.upstream {
+        let name = &upstream.name;
+        result += match upstream.ahead_behind {
+            None => format!(
+                "Your branch is based on '{name}', but the upstream is gone.\n  (use \"git branch --unset-upstream\" to fixup)\n"
+            ),
+            Some((0, 0)) => format!("Your branch is up to date with '{name}'.\n"),
+            Some((ahead, 0)) => format!(
+                "Your branch is ahead of '{name}' by {ahead} {}.\n  (use \"git push\" to publish your local commits)\n",
+                plural(ahead, "commit")
+            ),
+            Some((0, behind)) => format!(
+                "Your branch is behind '{name}' by {behind} {}, and can be fast-forwarded.\n  (use \"git pull\" to update your local branch)\n",
+                plural(behind, "commit")
+            ),
+            Some((ahead, behind)) => format!(
+                "Your branch and '{name}' have diverged,\nand have {ahead} and {behind} different commits each, respectively.\n  (use \"git pull\" if you want to integrate the remote branch with yours)\n"
+            ),
+        }
+        .as_str();
+        result += "\n";
+    }
+
+    let staged: Vec<&git_status::StatusEntry> = status
+        .entries
+        .iter()
+        .filter(|e| e.staged.is_some())
+        .collect();
+    let unmerged: Vec<&git_status::StatusEntry> = status
+        .entries
+        .iter()
+        .filter(|e| e.conflict.is_some())
+        .collect();
+    let unstaged: Vec<&git_status::StatusEntry> = status
+        .entries
+        .iter()
+        .filter(|e| e.unstaged.is_some())
+        .collect();
+
+    if status.merging {
+        if !unmerged.is_empty() {
+            result += "You have unmerged paths.\n  (fix conflicts and run \"git commit\")\n  (use \"git merge --abort\" to abort the merge)\n\n";
+        } else {
+            result += "All conflicts fixed but you are still merging.\n  (use \"git commit\" to conclude merge)\n\n";
+        }
+    }
+    // during a merge there is nothing to unstage back to
+    let unstage_hint = match (status.merging, status.head.is_some()) {
+        (true, _) => "",
+        (false, true) => "  (use \"git restore --staged <file>...\" to unstage)\n",
+        (false, false) => "  (use \"git rm --cached <file>...\" to unstage)\n",
+    };
+    if !staged.is_empty() {
+        result += "Changes to be committed:\n";
+        result += unstage_hint;
+        for entry in staged.iter() {
+            let label = entry.staged.unwrap().long_label();
+            let mut path = repository.path_for_display(&entry.path);
+            if let Some(origin) = &entry.origin {
+                path = format!("{} -> {path}", repository.path_for_display(&origin.path));
+            }
+            result += format!("\t{label:<12}{path}\n").as_str();
+        }
+        result += "\n";
+    }
+    if !unmerged.is_empty() {
+        let conflicts: Vec<&str> = unmerged
+            .iter()
+            .map(|e| e.conflict.unwrap().short_code())
+            .collect();
+        let both_deleted = conflicts.contains(&"DD");
+        let delete_modify = conflicts.iter().any(|code| *code == "UD" || *code == "DU");
+        let not_deleted = conflicts
+            .iter()
+            .any(|code| !["DD", "UD", "DU"].contains(code));
+        result += "Unmerged paths:\n";
+        result += unstage_hint;
+        result += if !both_deleted && !delete_modify {
+            "  (use \"git add <file>...\" to mark resolution)\n"
+        } else if both_deleted && !delete_modify && !not_deleted {
+            "  (use \"git rm <file>...\" to mark resolution)\n"
+        } else {
+            "  (use \"git add/rm <file>...\" as appropriate to mark resolution)\n"
+        };
+        for entry in unmerged.iter() {
+            let label = entry.conflict.unwrap().long_label();
+            let path = repository.path_for_display(&entry.path);
+            result += format!("\t{label:<17}{path}\n").as_str();
+        }
+        result += "\n";
+    }
+    if !unstaged.is_empty() {
+        result += "Changes not staged for commit:\n";
+        if unstaged
+            .iter()
+            .any(|e| e.unstaged == Some(git_status::Change::Deleted))
+        {
+            result += "  (use \"git add/rm <file>...\" to update what will be committed)\n";
+        } else {
+            result += "  (use \"git add <file>...\" to update what will be committed)\n";
+        }
+        result += "  (use \"git restore <file>...\" to discard changes in working directory)\n";
+        for entry in unstaged.iter() {
+            let label = entry.unstaged.unwrap().long_label();
+            let path = repository.path_for_display(&entry.path);
+            result += format!("\t{label:<12}{path}\n").as_str();
+        }
+        result += "\n";
+    }
+    if !status.untracked.is_empty() {
+        result += "Untracked files:\n";
+        result += "  (use \"git add <file>...\" to include in what will be committed)\n";
+        for path in status.untracked.iter() {
+            result += format!("\t{}\n", repository.path_for_display(path)).as_str();
+        }
+        result += "\n";
+    }
+
+    if staged.is_empty() {
+        if !unstaged.is_empty() || !unmerged.is_empty() {
+            result += "no changes added to commit (use \"git add\" and/or \"git commit -a\")\n";
+        } else if !status.untracked.is_empty() {
+            result +=
+                "nothing added to commit but untracked files present (use \"git add\" to track)\n";
+        } else if status.head.is_none() {
+            result += "nothing to commit (create/copy files and use \"git add\" to track)\n";
+        } else {
+            result += "nothing to commit, working tree clean\n";
+        }
+    }
+    return result;
+}
+
+/// The short format shows paths relative to the current directory, while the
+/// porcelain format (with no `repository`) keeps them relative to the root.
+fn status_short(
+    repository: Option<&Repository>,
+    status: &git_status::Status,
+    show_branch: bool,
+) -> String {
+    let display = |path: &String| match repository {
+        Some(repository) => repository.path_for_display(path),
+        None => path.clone(),
+    };
+    let mut result = String::new();
+    if show_branch {
+        result += "## ";
+        match (&status.branch, &status.head) {
+            (Some(branch), None) => result += format!("No commits yet on {branch}").as_str(),
+            (Some(branch), Some(_)) => result += branch.as_str(),
+            (None, _) => result += "HEAD (no branch)",
+        }
+        if let Some(upstream) = &status.upstream {
+            result += format!("...{}", upstream.name).as_str();
+            match upstream.ahead_behind {
+                None => result += " [gone]",
+                Some((0, 0)) => {}
+                Some((ahead, 0)) => result += format!(" [ahead {ahead}]").as_str(),
+                Some((0, behind)) => result += format!(" [behind {behind}]").as_str(),
+                Some((ahead, behind)) => {
+                    result += format!(" [ahead {ahead}, behind {behind}]").as_str()
+                }
+            }
+        }
+        result += "\n";
+    }
+    for entry in status.entries.iter() {
+        let mut path = display(&entry.path);
+        if let Some(origin) = &entry.origin {
+            path = format!("{} -> {path}", display(&origin.path));
+        }
+        if let Some(conflict) = &entry.conflict {
+            result += format!("{} {path}\n", conflict.short_code()).as_str();
+            continue;
+        }
+        result += format!(
+            "{}{} {path}\n",
+            entry.staged.map_or(' ', |c| c.short_code()),
+            entry.unstaged.map_or(' ', |c| c.short_code()),
+        )
+        .as_str();
+    }
+    for path in status.untracked.iter() {
+        result += format!("?? {}\n", display(path)).as_str();
+    }
+    return result;
+}
+
+fn status_porcelain_v2(status: &git_status::Status, show_branch: bool) -> String {
+    let mut result = String::new();
+    if show_branch {
+        result += format!(
+            "# branch.oid {}\n",
+            status
+                .head
+                .map_or("(initial)".to_string(), |head| head.to_hex())
+        )
+        .as_str();
+        result += format!(
+            "# branch.head {}\n",
+            status.branch.as_deref().unwrap_or("(detached)")
+        )
+        .as_str();
+        if let Some(upstream) = &status.upstream {
+            result += format!("# branch.upstream {}\n", upstream.name).as_str();
+            if let Some((ahead, behind)) = upstream.ahead_behind {
+                result += format!("# branch.ab +{ahead} -{behind}\n").as_str();
+            }
+        }
+    }
+    // unmerged paths come after all the other changes
+    let entries = status
+        .entries
+        .iter()
+        .filter(|entry| entry.conflict.is_none())
+        .chain(
+            status
+                .entries
+                .iter()
+                .filter(|entry| entry.conflict.is_some()),
+        );
+    for entry in entries {
+        let is_submodule = [&entry.head, &entry.index].iter().any(|side| {
+            side.as_ref()
+                .is_some_and(|(mode, _)| *mode == git_object::GITLINK_MODE)
+        });
+        let submodule_state = if !is_submodule {
+            "N..."
+        } else if entry.unstaged.is_some() {
+            "SC.."
+        } else {
+            "S..."
+        };
+        let mode = |side: &Option<(u64, ObjectId)>| side.as_ref().map_or(0, |(mode, _)| *mode);
+        let hash = |side: &Option<(u64, ObjectId)>| {
+            side.as_ref().map_or(ObjectId::NULL, |(_, hash)| *hash)
+        };
+        if let Some(conflict) = &entry.conflict {
+            let [base, ours, theirs] = &conflict.stages;
+            result += format!(
+                "u {} {submodule_state} {:06} {:06} {:06} {:06} {} {} {} {}\n",
+                conflict.short_code(),
+                mode(base),
+                mode(ours),
+                mode(theirs),
+                entry.worktree_mode.unwrap_or(0),
+                hash(base),
+                hash(ours),
+                hash(theirs),
+                entry.path,
+            )
+            .as_str();
+            continue;
+        }
+        // renames and copies name their score and where they come from
+        let (kind, score, path) = match &entry.origin {
+            Some(origin) => (
+                '2',
+                format!(
+                    " {}{}",
+                    entry.staged.unwrap().short_code(),
+                    origin.similarity()
+                ),
+                format!("{}\t{}", entry.path, origin.path),
+            ),
+            None => ('1', String::new(), entry.path.clone()),
+        };
+        result += format!(
+            "{kind} {}{} {submodule_state} {:06} {:06} {:06} {} {}{score} {path}\n",
+            entry.staged.map_or('.', |c| c.short_code()),
+            entry.unstaged.map_or('.', |c| c.short_code()),
+            mode(&entry.head),
+            mode(&entry.index),
+            entry.worktree_mode.unwrap_or(0),
+            hash(&entry.head),
+            hash(&entry.index),
+        )
+        .as_str();
+    }
+    for path in status.untracked.iter() {
+        result += format!("? {path}\n").as_str();
+    }
+    return result;
+}
+
+fn plural(count: usize, word: &str) -> String {
+    return if count == 1 {
+        word.to_string()
+    } else {
+        format!("{word}s")
+    };
+}
+
+pub fn submodule_init(repository: &Repository, paths: &[String]) -> Result<String, Error> {
+    let submodules = git_submodule::filter_by_paths(
+        git_submodule::read_gitmodules(repository)?,
+        &paths_from_cwd(repository, paths)?,
+    );
+    let mut result = String::new();
+    for submodule in submodules {
+        let url_key = format!("submodule.{}.url", submodule.name);
+        if git_config::get(repository, &url_key)?.is_some() {
+            continue;
+        }
+        let url = git_submodule::resolve_url(repository, &submodule.url)?;
+        let config_path = repository.git_path("config");
+        git_config::set(
+            &config_path,
+            &format!("submodule.{}.active", submodule.name),
+            "true",
+        )?;
+        git_config::set(&config_path, &url_key, &url)?;
+        repository.forget_config();
+        result += format!(
+            "Submodule '{}' ({url}) registered for path '{}'\n",
+            submodule.name, submodule.path
+        )
+        .as_str();
+    }
+    return Ok(result);
+}
+
+pub fn submodule_update(
+    repository: &Repository,
+    paths: &[String],
+    init: bool,
+) -> Result<String, Error> {
+    let mut result = String::new();
+    if init {
+        result += submodule_init(repository, paths)?.as_str();
+    }
+
+    let tree_hash = head_tree_hash(repository)?;
+    let submodules = git_submodule::filter_by_paths(
+        git_submodule::read_gitmodules(repository)?,
+        &paths_from_cwd(repository, paths)?,
+    );
+    for submodule in submodules {
+        let url = git_config::get(repository, &format!("submodule.{}.url", submodule.name))?;
+        if url.is_none() {
+            // not initialized, so the user doesn't want it populated
+            continue;
+        }
+        let url = url.unwrap();
+        let commit = recorded_submodule_commit(repository, &tree_hash, &submodule)?;
+
+        let submodule_path = repository.work_path(&submodule.path);
+        if !submodule_path.join(".git").exists() {
+            result += format!("Cloning into '{}'...\n", submodule_path.display()).as_str();
+            clone(&url, submodule_path.to_string_lossy().as_ref())?;
+        }
+
+        let checkout_result = Repository::open_work_tree(&submodule_path).and_then(|submodule| {
+            if submodule.object_type(&commit).is_err() {
+                submodule.fetch(&url, &[commit])?;
+            }
+            return git::checkout_detached(&submodule, &commit);
+        });
+        checkout_result.map_err(|err| {
+            format!(
+                "unable to checkout '{commit}' in submodule path '{}': {err}",
+                submodule.path
+            )
+        })?;
+        result += format!(
+            "Submodule path '{}': checked out '{commit}'\n",
+            submodule.path
+        )
+        .as_str();
+    }
+    return Ok(result);
+}
+
+pub fn submodule_status(repository: &Repository, paths: &[String]) -> Result<String, Error> {
+    let tree_hash = head_tree_hash(repository)?;
+    let submodules = git_submodule::filter_by_paths(
+        git_submodule::read_gitmodules(repository)?,
+        &paths_from_cwd(repository, paths)?,
+    );
+    let mut result = String::new();
+    for submodule in submodules {
+        let commit = recorded_submodule_commit(repository, &tree_hash, &submodule)?;
+        let submodule_path = repository.work_path(&submodule.path);
+        if !submodule_path.join(".git").exists() {
+            result += format!("-{commit} {}\n", submodule.path).as_str();
+            continue;
+        }
+        let checked_out = git::head_commit(&Repository::open_work_tree(&submodule_path)?)?;
+        match checked_out {
+            Some(checked_out) if checked_out != commit => {
+                result += format!("+{checked_out} {}\n", submodule.path).as_str()
+            }
+            _ => result += format!(" {commit} {}\n", submodule.path).as_str(),
+        }
+    }
+    return Ok(result);
+}
+
+fn head_tree_hash(repository: &Repository) -> Result<ObjectId, Error> {
+    let head =
+        git::head_commit(repository)?.ok_or("HEAD does not point to a commit".to_string())?;
+    return Ok(git_object::read_commit(repository, &head)?.tree);
+}
+
+fn recorded_submodule_commit(
+    repository: &Repository,
+    tree_hash: &ObjectId,
+    submodule: &git_submodule::Submodule,
+) -> Result<ObjectId, Error> {
+    return match git::find_tree_entry(repository, tree_hash, &submodule.path)? {
+        Some(node) if node.is_gitlink() => Ok(node.hash),
+        _ => Err(format!("no submodule commit recorded for path '{}'", submodule.path).into()),
+    };
+}
 
-use crate::{
-    git,
-    git_object::{self, ObjectType},
-    git_pack, reader_utils,
-};
+/// Converts paths given on the command line to paths relative to the
+/// working tree root.
+fn paths_from_cwd(repository: &Repository, paths: &[String]) -> Result<Vec<String>, Error> {
+    return paths
+        .iter()
+        .map(|path| repository.path_from_cwd(path))
+        .collect();
+}
 
-pub fn init() -> Result<(), String> {
-    fs::create_dir(".git").map_err(|err| format!("error creating .git directory: {err}"))?;
-    fs::create_dir(".git/objects")
-        .map_err(|err| format!("error creating objects directory: {err}"))?;
-    fs::create_dir(".git/refs").map_err(|err| format!("error creating refs directory: {err}"))?;
-    fs::write(".git/HEAD", "ref: refs/heads/main\n")
-        .map_err(|err| format!("error writing HEAD file: {err}"))?;
-    Ok(())
+pub fn check_ignore(
+    repository: &Repository,
+    paths: &Vec<String>,
+    verbose: bool,
+    non_matching: bool,
+    no_index: bool,
+) -> Result<String, Error> {
+    let tracked: Vec<String> = if no_index {
+        Vec::new()
+    } else {
+        git_index::read(repository)?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect()
+    };
+    let mut ignore_rules = IgnoreRules::load(repository)?;
+    let mut result = String::new();
+    let mut matched = 0;
+    for path in paths {
+        let normalized = repository.path_from_cwd(path)?;
+        let is_dir = path.ends_with('/') || repository.work_path(&normalized).is_dir();
+        let pattern = if tracked.contains(&normalized) {
+            None
+        } else {
+            ignore_rules.matching_pattern(&normalized, is_dir)?
+        };
+        // -v shows negated patterns too, and they count as matches then
+        if pattern.as_ref().is_some_and(|p| verbose || !p.is_negated()) {
+            matched += 1;
+        }
+        match pattern {
+            Some(pattern) if verbose => {
+                result += format!(
+                    "{}:{}:{}\t{path}\n",
+                    pattern.source, pattern.line_number, pattern.original
+                )
+                .as_str()
+            }
+            Some(pattern) if !pattern.is_negated() => result += format!("{path}\n").as_str(),
+            _ if verbose && non_matching => result += format!("::\t{path}\n").as_str(),
+            _ => {}
+        }
+    }
+    if matched == 0 {
+        return Err(Error::CheckFailed { output: result });
+    }
+    return Ok(result);
 }
 
-pub fn cat_file(object_name: &String) -> Result<String, String> {
-    let mut reader = git_object::reader(object_name)?;
-    let (object_type, size) =
-        git_object::identify_header(&reader_utils::read_to_next_null_byte(&mut reader)?)?;
+pub enum ConfigAction {
+    Get(String),
+    GetAll(String),
+    Set(String, String),
+    Unset(String),
+    List,
+}
+
+/// Reads or writes config. Without a `repository` only the files outside of
+/// one are available.
+pub fn config(
+    repository: Option<&Repository>,
+    action: &ConfigAction,
+    scope: Option<Scope>,
+    file: &Option<String>,
+    value_type: &Option<String>,
+    show_origin: bool,
+) -> Result<String, Error> {
+    let write_path = match (file, scope) {
+        (Some(file), _) => PathBuf::from(file),
+        (None, Some(scope)) => scope
+            .paths(repository)
+            .pop()
+            .ok_or("fatal: not in a git directory".to_string())?,
+        (None, None) => match repository {
+            Some(repository) => repository.git_path("config"),
+            None => PathBuf::new(),
+        },
+    };
+    let read = || match (file, scope) {
+        (Some(file), _) => Config::load_file(file, repository),
+        (None, Some(scope)) => Config::load_scope(scope, repository),
+        (None, None) => Config::load(repository),
+    };
+    let writable = || {
+        if write_path.as_os_str().is_empty() {
+            return Err(Error::from("fatal: not in a git directory"));
+        }
+        return Ok(());
+    };
+    let origin_prefix = |entry: &git_config::ConfigEntry| {
+        if show_origin {
+            format!("file:{}\t", entry.origin)
+        } else {
+            String::new()
+        }
+    };
 
-    match object_type {
-        ObjectType::Commit | ObjectType::Blob => {
-            let file_contents = reader_utils::read_n_bytes(size, &mut reader)?;
-            return Ok(str::from_utf8(&file_contents)
-                .map_err(|err| format!("error reading object file: {err}"))?
-                .to_string());
+    let mut result = String::new();
+    match action {
+        ConfigAction::Get(name) | ConfigAction::GetAll(name) => {
+            let config = read()?;
+            let matching = config.matching(name)?;
+            if matching.is_empty() {
+                return Err(Error::Exit {
+                    message: String::new(),
+                    code: 1,
+                });
+            }
+            let shown = match action {
+                ConfigAction::Get(_) => matching.len().saturating_sub(1),
+                _ => 0,
+            };
+            for entry in &matching[shown..] {
+                let value = format_config_value(entry.value.as_deref(), value_type, name)?;
+                result += format!("{}{value}\n", origin_prefix(entry)).as_str();
+            }
+        }
+        ConfigAction::Set(name, value) => {
+            writable()?;
+            let existing = Config {
+                entries: git_config::read_file(&write_path)?,
+            };
+            if existing.matching(name)?.len() > 1 {
+                return Err(Error::Exit {
+                    message: format!("warning: {name} has multiple values\nerror: cannot overwrite multiple values with a single value\n       Use a regexp, --add or --replace-all to change {name}."),
+                    code: 5,
+                });
+            }
+            let value = match value_type {
+                Some(_) => format_config_value(Some(value), value_type, name)?,
+                None => value.clone(),
+            };
+            git_config::set(&write_path, name, &value)?;
+        }
+        ConfigAction::Unset(name) => {
+            writable()?;
+            let existing = Config {
+                entries: git_config::read_file(&write_path)?,
+            };
+            if existing.matching(name)?.len() > 1 {
+                return Err(Error::Exit {
+                    message: format!("warning: {name} has multiple values"),
+                    code: 5,
+                });
+            }
+            if git_config::unset(&write_path, name)? == 0 {
+                return Err(Error::Exit {
+                    message: String::new(),
+                    code: 5,
+                });
+            }
+        }
+        ConfigAction::List => {
+            for entry in read()?.entries.iter() {
+                let name = entry.name();
+                result += match &entry.value {
+                    Some(_) => format!(
+                        "{}{name}={}\n",
+                        origin_prefix(entry),
+                        format_config_value(entry.value.as_deref(), value_type, &name)?
+                    ),
+                    None => format!("{}{name}\n", origin_prefix(entry)),
+                }
+                .as_str();
+            }
         }
-        ObjectType::Tree => return stringify_tree(&mut reader, size, false),
     }
+    return Ok(result);
 }
 
-pub fn hash_object(file_path: &String, write: bool) -> Result<String, String> {
-    let mut file = File::open(&file_path).map_err(|err| format!("error opening file: {err}"))?;
-    let mut file_contents: Vec<u8> = Vec::new();
-    let size = file
-        .read_to_end(&mut file_contents)
-        .map_err(|err| format!("error reading file contents: {err}"))?;
-    let mut blob_contents: Vec<u8> = format!("blob {size}\0").bytes().collect();
-    blob_contents.append(&mut file_contents);
+/// Canonicalizes a value for `--type`, e.g. `yes` becomes `true` and `1k`
+/// becomes `1024`.
+fn format_config_value(
+    value: Option<&str>,
+    value_type: &Option<String>,
+    name: &str,
+) -> Result<String, Error> {
+    return match value_type.as_deref() {
+        Some("bool") => Ok(git_config::parse_bool(value, name)?.to_string()),
+        Some("int") => Ok(git_config::parse_int(value.unwrap_or(""), name)?.to_string()),
+        Some("bool-or-int") => match value.map(|value| git_config::parse_int(value, name)) {
+            Some(Ok(number)) => Ok(number.to_string()),
+            _ => Ok(git_config::parse_bool(value, name)?.to_string()),
+        },
+        Some("path") => {
+            let value = value.unwrap_or("");
+            Ok(match (value.strip_prefix("~/"), env::var("HOME")) {
+                (Some(rest), Ok(home)) => format!("{home}/{rest}"),
+                _ => value.to_string(),
+            })
+        }
+        Some(other) => Err(Error::Failed(format!(
+            "unrecognized --type argument, {other}"
+        ))),
+        None => Ok(value.unwrap_or("true").to_string()),
+    };
+}
 
-    let hash = if write {
-        git_object::write_object(&blob_contents)?
+pub fn check_attr(
+    repository: &Repository,
+    all: bool,
+    args: &[String],
+    paths_after_separator: &[String],
+) -> Result<String, Error> {
+    let (attribute_names, paths): (Vec<String>, Vec<String>) = if all {
+        (Vec::new(), [args, paths_after_separator].concat())
+    } else if !paths_after_separator.is_empty() {
+        (args.to_vec(), paths_after_separator.to_vec())
+    } else if args.len() >= 2 {
+        (vec![args[0].clone()], args[1..].to_vec())
     } else {
-        git_object::hash_data(&blob_contents)
+        return Err("usage: check-attr [-a | <attr>...] [--] <pathname>...".into());
     };
 
-    let hash_string = hex::encode(&hash);
-    return Ok(hash_string);
+    let attributes = repository.attributes()?;
+    let mut result = String::new();
+    for path in paths {
+        let normalized = repository.path_from_cwd(&path)?;
+        if all {
+            for (name, state) in attributes.all(&normalized)? {
+                result += format!("{path}: {name}: {}\n", state.describe()).as_str();
+            }
+            continue;
+        }
+        for name in attribute_names.iter() {
+            let state = attributes.get(&normalized, name)?;
+            result += format!("{path}: {name}: {}\n", state.describe()).as_str();
+        }
+    }
+    return Ok(result);
+}
+
+pub fn symbolic_ref(
+    repository: &Repository,
+    name: &str,
+    target: &Option<String>,
+    delete: bool,
+    quiet: bool,
+    short: bool,
+    message: &Option<String>,
+) -> Result<String, Error> {
+    if delete {
+        if name == "HEAD" {
+            return Err("fatal: deleting 'HEAD' is not allowed".into());
+        }
+        if !matches!(
+            git_refs::read(repository, name)?,
+            Some(RefValue::Symbolic(_))
+        ) {
+            return Err(format!("fatal: Cannot delete {name}, not a symbolic ref").into());
+        }
+        git_refs::delete(repository, name)?;
+        return Ok(String::new());
+    }
+    if let Some(target) = target {
+        if name == "HEAD" && !target.starts_with("refs/") {
+            return Err("fatal: Refusing to point HEAD outside of refs/".into());
+        }
+        if !git_refs::check_format(target, true) {
+            return Err(
+                format!("fatal: Refusing to set '{name}' to invalid ref '{target}'").into(),
+            );
+        }
+        git_refs::write_symbolic(repository, name, target, message.as_deref().unwrap_or(""))?;
+        return Ok(String::new());
+    }
+
+    let resolved = git_refs::resolve_name(repository, name)?;
+    if resolved == *name {
+        if quiet {
+            return Err(Error::Exit {
+                message: String::new(),
+                code: 1,
+            });
+        }
+        return Err(format!("fatal: ref {name} is not a symbolic ref").into());
+    }
+    if short {
+        return Ok(format!("{}\n", git_refs::shorten(&resolved)));
+    }
+    return Ok(format!("{resolved}\n"));
+}
+
+pub fn show_ref(
+    repository: &Repository,
+    patterns: &Vec<String>,
+    head: bool,
+    heads: bool,
+    tags: bool,
+    dereference: bool,
+    hash_length: Option<usize>,
+    verify: bool,
+    quiet: bool,
+) -> Result<String, Error> {
+    let mut refs = Vec::new();
+    if verify {
+        for pattern in patterns {
+            let id = if (pattern.starts_with("refs/") || pattern == "HEAD")
+                && git_refs::check_format(pattern, true)
+            {
+                git_refs::resolve(repository, pattern)?
+            } else {
+                None
+            };
+            let id = match id {
+                Some(id) => id,
+                None if quiet => {
+                    return Err(Error::Exit {
+                        message: String::new(),
+                        code: 1,
+                    })
+                }
+                None => return Err(format!("fatal: '{pattern}' - not a valid ref").into()),
+            };
+            refs.push(git_refs::Ref {
+                name: pattern.clone(),
+                id,
+                peeled: None,
+            });
+        }
+    } else {
+        if head {
+            if let Some(id) = git_refs::resolve(repository, "HEAD")? {
+                refs.push(git_refs::Ref {
+                    name: "HEAD".to_string(),
+                    id,
+                    peeled: None,
+                });
+            }
+        }
+        for reference in git_refs::list(repository, "refs/")? {
+            let name = reference.name.as_str();
+            if (heads || tags)
+                && !(heads && name.starts_with("refs/heads/")
+                    || tags && name.starts_with("refs/tags/"))
+            {
+                continue;
+            }
+            // a pattern matches whole trailing components of the name
+            if !patterns.is_empty()
+                && !patterns
+                    .iter()
+                    .any(|pattern| name == pattern || name.ends_with(&format!("/{pattern}")))
+            {
+                continue;
+            }
+            refs.push(reference);
+        }
+    }
+
+    let format_ref = |id: &ObjectId, name: &str| match hash_length {
+        Some(length) => format!(
+            "{}\n",
+            &id.to_hex()[..length.clamp(4, ObjectId::HEX_LENGTH)]
+        ),
+        None => format!("{id} {name}\n"),
+    };
+    let mut result = String::new();
+    for reference in refs.iter() {
+        result += format_ref(&reference.id, &reference.name).as_str();
+        if !dereference {
+            continue;
+        }
+        let peeled = match reference.peeled {
+            Some(peeled) => peeled,
+            None => git_refs::peel(repository, &reference.id)?,
+        };
+        if peeled != reference.id {
+            result += format_ref(&peeled, &format!("{}^{{}}", reference.name)).as_str();
+        }
+    }
+    if quiet {
+        result.clear();
+    }
+    if refs.is_empty() {
+        return Err(Error::CheckFailed { output: result });
+    }
+    return Ok(result);
 }
 
-pub fn ls_tree(object_name: &String, name_only: bool) -> Result<String, String> {
-    let mut reader = git_object::reader(object_name)?;
-    let (object_type, size) =
-        git_object::identify_header(&reader_utils::read_to_next_null_byte(&mut reader)?)?;
-    if object_type != ObjectType::Tree {
-        return Err(format!("{object_name} is not a tree object"));
+const UPDATE_REF_USAGE: &str = "usage: git update-ref [<options>] -d <refname> [<old-val>]
+   or: git update-ref [<options>]    <refname> <new-val> [<old-val>]
+   or: git update-ref [<options>] --stdin";
+
+pub fn update_ref(
+    repository: &Repository,
+    name: &Option<String>,
+    values: &[String],
+    delete: bool,
+    no_deref: bool,
+    stdin: bool,
+    nul_terminated: bool,
+    message: &Option<String>,
+) -> Result<(), Error> {
+    let message = message.as_deref().unwrap_or("");
+    if stdin {
+        return update_ref_stdin(repository, no_deref, nul_terminated, message);
+    }
+    let usage = || Error::Exit {
+        message: UPDATE_REF_USAGE.to_string(),
+        code: 129,
+    };
+    let name = name.as_ref().ok_or_else(usage)?;
+    if values.len() > if delete { 1 } else { 2 } || (!delete && values.is_empty()) {
+        return Err(usage());
+    }
+    if !git_refs::check_format(name, true) {
+        return Err(format!(
+            "fatal: update_ref failed for ref '{name}': refusing to update ref with bad name '{name}'"
+        )
+        .into());
     }
+    let (new_value, old_value) = if delete {
+        (None, values.first())
+    } else {
+        (values.first(), values.get(1))
+    };
+    let parse = |value: &String| {
+        git_revision::resolve(repository, value).map_err(|err| match err {
+            Error::ObjectNotFound(_) | Error::AmbiguousPrefix(_) => {
+                Error::from(format!("fatal: {value}: not a valid SHA1"))
+            }
+            err => err,
+        })
+    };
+    let new = match new_value {
+        Some(value) => parse(value)?,
+        None => ObjectId::NULL,
+    };
+    let old = old_value.map(parse).transpose()?;
 
-    return stringify_tree(&mut reader, size, name_only);
+    let target = if no_deref {
+        name.clone()
+    } else {
+        git_refs::resolve_name(repository, name)?
+    };
+    if let Some(reason) = non_commit_branch_update(repository, &target, &new)? {
+        return Err(format!("fatal: update_ref failed for ref '{name}': {reason}").into());
+    }
+    let update = git_refs::RefUpdate {
+        name: name.clone(),
+        new: Some(new),
+        old,
+        deref: !no_deref,
+        message: message.to_string(),
+    };
+    return git_refs::apply(repository, &[update]).map_err(|err| {
+        let reason = match err {
+            Error::RefRejected { reason, .. } => reason,
+            Error::LockConflict { .. } => err.to_string().replacen("fatal: ", "", 1),
+            err => return err,
+        };
+        let message = format!("cannot lock ref '{target}': {reason}");
+        if delete {
+            return Error::Failed(message);
+        }
+        return format!("fatal: update_ref failed for ref '{name}': {message}").into();
+    });
 }
 
-pub fn write_tree() -> Result<String, String> {
-    return Ok(hex::encode(git_object::write_tree_from_directory("./")?));
+/// Where an `update-ref --stdin` transaction is, as git tracks it. Updates
+/// given before any `start` are committed at the end of the input; an
+/// explicit transaction that isn't committed by then is aborted.
+#[derive(Clone, Copy, PartialEq)]
+enum StdinState {
+    Open,
+    Started,
+    Prepared,
+    Closed,
 }
 
-pub fn commit_tree(
-    message: &String,
-    tree_name: &String,
-    parent_name: &Option<String>,
-) -> Result<String, String> {
-    if git_object::get_type(tree_name)? != ObjectType::Tree {
-        return Err("provided hash isn't a tree".to_string());
-    }
-    if parent_name.is_some()
-        && git_object::get_type(parent_name.as_ref().unwrap())? != ObjectType::Commit
-    {
-        return Err("provided parent hash isn't a commit".to_string());
-    }
-
-    let full_tree_name = git_object::full_hash(tree_name)?;
-    let mut commit_byte_buffer: Vec<u8> = Vec::new();
-    commit_byte_buffer.append(&mut format!("tree {full_tree_name}\n").bytes().collect());
-    if parent_name.is_some() {
-        let full_parent_hash = git_object::full_hash(parent_name.as_ref().unwrap())?;
-
-        commit_byte_buffer.append(&mut format!("parent {}\n", full_parent_hash).bytes().collect());
-    }
-    let current_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|err| format!("error getting timestamp: {err}"))?
-        .as_secs();
-    let timezone = Local::now().offset().to_string();
-    commit_byte_buffer.append(
-        &mut format!("author 123abc <123abc@example.com> {current_time} {timezone}\n")
-            .bytes()
-            .collect(),
-    );
-    commit_byte_buffer.append(
-        &mut format!("committer 123abc <123abc@example.com> {current_time} {timezone}\n\n")
-            .bytes()
-            .collect(),
-    );
-    commit_byte_buffer.append(&mut format!("{message}\n").bytes().collect());
+/// Runs the commands read from stdin: `update`, `create`, `delete` and
+/// `verify` queue updates, `option no-deref` applies to the next one, and
+/// `start`, `prepare`, `commit` and `abort` control the transaction,
+/// answering `<command>: ok` as soon as they are done. With `-z` every
+/// field ends in a NUL and an empty old value means there is none.
+fn update_ref_stdin(
+    repository: &Repository,
+    no_deref: bool,
+    nul_terminated: bool,
+    message: &str,
+) -> Result<(), Error> {
+    let terminator = if nul_terminated { b'\0' } else { b'\n' };
+    let mut input = io::stdin().lock();
+    let mut read_field = || -> Result<Option<String>, Error> {
+        let mut field = Vec::new();
+        let read = input
+            .read_until(terminator, &mut field)
+            .map_err(|err| Error::io("fatal: could not read from stdin", err))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if field.last() == Some(&terminator) {
+            field.pop();
+        }
+        return Ok(Some(String::from_utf8_lossy(&field).to_string()));
+    };
+
+    let mut state = StdinState::Open;
+    let mut updates = Vec::new();
+    let mut prepared = None;
+    let mut deref = !no_deref;
+    while let Some(line) = read_field()? {
+        let (command, rest) = line.split_once(' ').unwrap_or((&line, ""));
+        let next_state = match command {
+            "start" => StdinState::Started,
+            "prepare" => StdinState::Prepared,
+            "commit" | "abort" => StdinState::Closed,
+            _ => StdinState::Open,
+        };
+        match (state, next_state) {
+            (StdinState::Started, StdinState::Started) => {
+                return Err("fatal: cannot restart ongoing transaction".into())
+            }
+            (StdinState::Prepared, next) if next != StdinState::Closed => {
+                return Err("fatal: prepared transactions can only be closed".into())
+            }
+            (StdinState::Closed, next) if next != StdinState::Started => {
+                return Err("fatal: transaction is closed".into())
+            }
+            _ => {}
+        }
+        if next_state != StdinState::Open {
+            if !rest.is_empty() {
+                return Err(format!("fatal: {command}: extra input: {rest}").into());
+            }
+            let failed = |err: Error| match err {
+                Error::RefRejected { name, reason } => Error::from(format!(
+                    "fatal: {command}: cannot lock ref '{name}': {reason}"
+                )),
+                Error::Message(message) => match message.strip_prefix("fatal: ") {
+                    Some(reason) => Error::from(format!("fatal: {command}: {reason}")),
+                    None => Error::Message(message),
+                },
+                err => err,
+            };
+            match command {
+                "start" => {}
+                "prepare" => {
+                    prepared = Some(git_refs::prepare(repository, &updates).map_err(failed)?);
+                }
+                "commit" => {
+                    let transaction = match prepared.take() {
+                        Some(transaction) => transaction,
+                        None => git_refs::prepare(repository, &updates).map_err(failed)?,
+                    };
+                    transaction.commit().map_err(failed)?;
+                    updates.clear();
+                }
+                _ => {
+                    prepared = None;
+                    updates.clear();
+                }
+            }
+            println!("{command}: ok");
+            io::stdout()
+                .flush()
+                .map_err(|err| Error::io("fatal: could not write to stdout", err))?;
+            state = next_state;
+            continue;
+        }
+        if state == StdinState::Closed {
+            return Err("fatal: transaction is closed".into());
+        }
 
-    let hash = git_object::write_commit(&mut commit_byte_buffer)?;
+        if command == "option" {
+            if rest != "no-deref" {
+                return Err(format!("fatal: option unknown: {rest}").into());
+            }
+            deref = false;
+            continue;
+        }
+        let value_names: &[&str] = match command {
+            "update" => &["newvalue", "oldvalue"],
+            "create" => &["newvalue"],
+            "delete" | "verify" => &["oldvalue"],
+            _ => return Err(format!("fatal: unknown command: {line}").into()),
+        };
+        let max_values = value_names.len();
+        let (name, values) = match nul_terminated {
+            true => {
+                let mut values = Vec::new();
+                for what in value_names {
+                    match read_field()? {
+                        Some(value) => values.push(value),
+                        None => {
+                            return Err(format!(
+                                "fatal: {command} {rest}: unexpected end of input when reading <{what}>"
+                            )
+                            .into())
+                        }
+                    }
+                }
+                // an empty old value is a missing one
+                if command != "create" && values.last().is_some_and(|value| value.is_empty()) {
+                    values.pop();
+                }
+                if command == "update" && values.first().is_some_and(|value| value.is_empty()) {
+                    eprintln!("warning: update {rest}: missing <newvalue>, treating as zero");
+                }
+                (rest, values)
+            }
+            false => {
+                let mut fields = rest.split(' ');
+                let name = fields.next().unwrap_or("");
+                let values: Vec<String> = fields.map(|value| value.to_string()).collect();
+                if values.len() > max_values {
+                    return Err(format!(
+                        "fatal: {command} {name}: extra input:  {}",
+                        values[max_values..].join(" ")
+                    )
+                    .into());
+                }
+                (name, values)
+            }
+        };
+        if name.is_empty() {
+            return Err(format!("fatal: {command}: missing <ref>").into());
+        }
+        if !git_refs::check_format(name, true) {
+            return Err(format!("fatal: invalid ref format: {name}").into());
+        }
+        let parse = |index: usize, what: &str| -> Result<Option<ObjectId>, Error> {
+            return match values.get(index) {
+                // -z spells a zero new value as an empty one
+                Some(value) if value.is_empty() => Ok(Some(ObjectId::NULL)),
+                Some(value) => git_revision::resolve(repository, value)
+                    .map(Some)
+                    .map_err(|err| match err {
+                        Error::ObjectNotFound(_) | Error::AmbiguousPrefix(_) => {
+                            format!("fatal: {command} {name}: invalid <{what}>: {value}").into()
+                        }
+                        err => err,
+                    }),
+                None => Ok(None),
+            };
+        };
+        let missing_new = || format!("fatal: {command} {name}: missing <newvalue>");
+        let (new, old) = match command {
+            "update" => (
+                Some(parse(0, "newvalue")?.ok_or_else(missing_new)?),
+                parse(1, "oldvalue")?,
+            ),
+            "create" => {
+                let new = parse(0, "newvalue")?.ok_or_else(missing_new)?;
+                if new.is_null() {
+                    return Err(format!("fatal: create {name}: zero <newvalue>").into());
+                }
+                (Some(new), Some(ObjectId::NULL))
+            }
+            "delete" => (Some(ObjectId::NULL), parse(0, "oldvalue")?),
+            _ => (None, Some(parse(0, "oldvalue")?.unwrap_or(ObjectId::NULL))),
+        };
 
-    return Ok(hex::encode(hash));
+        if let Some(new) = new {
+            let target = if deref {
+                git_refs::resolve_name(repository, name)?
+            } else {
+                name.to_string()
+            };
+            if let Some(reason) = non_commit_branch_update(repository, &target, &new)? {
+                return Err(format!("fatal: {reason}").into());
+            }
+        }
+        updates.push(git_refs::RefUpdate {
+            name: name.to_string(),
+            new,
+            old,
+            deref,
+            message: message.to_string(),
+        });
+        deref = !no_deref;
+    }
+    // a transaction that was started but never committed is dropped
+    if state == StdinState::Open {
+        return git_refs::apply(repository, &updates);
+    }
+    return Ok(());
 }
 
-pub fn clone(remote: &String, directory: &String) -> Result<String, String> {
-    let mut remote_url = remote.clone();
-    if remote_url.ends_with('/') {
-        remote_url.pop();
+/// Why storing `id` in `target` is refused: branches may only point at
+/// commits.
+fn non_commit_branch_update(
+    repository: &Repository,
+    target: &str,
+    id: &ObjectId,
+) -> Result<Option<String>, Error> {
+    if id.is_null()
+        || !target.starts_with("refs/heads/")
+        || git_object::get_type(repository, id)? == ObjectType::Commit
+    {
+        return Ok(None);
     }
+    return Ok(Some(format!(
+        "cannot update ref '{target}': trying to write non-commit object {id} to branch '{target}'"
+    )));
+}
 
-    let client = Client::new();
-    let mut discovery_response = client
-        .request(
-            Method::GET,
-            format!("{remote_url}/info/refs?service=git-upload-pack"),
+pub fn reflog_show(repository: &Repository, reference: &Option<String>) -> Result<String, Error> {
+    let reference = reference.as_deref().unwrap_or("HEAD");
+    let name = reflog_ref_name(repository, reference)?;
+    let mut result = String::new();
+    for (index, entry) in git_reflog::read(repository, &name)?
+        .iter()
+        .rev()
+        .enumerate()
+    {
+        result += format!(
+            "{} {reference}@{{{index}}}: {}\n",
+            entry.new.short(),
+            entry.message
         )
-        .send()
-        .map_err(|err| format!("error sending discovery request: {err}"))?;
-    if discovery_response.status() != StatusCode::OK {
-        return Err(format!("discovery status: {}", discovery_response.status()));
+        .as_str();
+    }
+    return Ok(result);
+}
+
+pub fn reflog_expire(
+    repository: &Repository,
+    references: &[String],
+    all: bool,
+    expire: &Option<String>,
+    expire_unreachable: &Option<String>,
+) -> Result<String, Error> {
+    let expiry = |value: &Option<String>, key: &str, default: &str| -> Result<i64, Error> {
+        let value = match value {
+            Some(value) => value.clone(),
+            None => git_config::get(repository, key)?.unwrap_or(default.to_string()),
+        };
+        return Ok(match value.as_str() {
+            "never" | "false" => i64::MIN,
+            "all" | "now" => i64::MAX,
+            _ => git_date::parse(&value)?.0,
+        });
+    };
+    let expire = expiry(expire, "gc.reflogexpire", "90.days.ago")?;
+    let expire_unreachable = expiry(
+        expire_unreachable,
+        "gc.reflogexpireunreachable",
+        "30.days.ago",
+    )?;
+
+    let names = if all {
+        git_reflog::list(repository)?
+    } else {
+        references
+            .iter()
+            .map(|reference| reflog_ref_name(repository, reference))
+            .collect::<Result<Vec<String>, Error>>()?
+    };
+    for name in names {
+        git_reflog::expire(repository, &name, expire, expire_unreachable)?;
     }
+    return Ok(String::new());
+}
 
-    // junk lines before ref data
-    let mut data = reader_utils::read_git_pack_line(&mut discovery_response)?;
-    if data.is_some() {
-        reader_utils::read_git_pack_line(&mut discovery_response)?;
+pub fn reflog_delete(
+    repository: &Repository,
+    entries: &Vec<String>,
+    rewrite: bool,
+) -> Result<String, Error> {
+    for entry in entries {
+        let (reference, index) = entry
+            .strip_suffix('}')
+            .and_then(|entry| entry.split_once("@{"))
+            .and_then(|(reference, index)| Some((reference, index.parse::<usize>().ok()?)))
+            .ok_or_else(|| Error::Failed(format!("not a reflog: {entry}")))?;
+        let name = reflog_ref_name(repository, reference)?;
+        git_reflog::delete_entry(repository, &name, index, rewrite)?;
     }
+    return Ok(String::new());
+}
 
-    // first ref has trailing capabilities
-    data = reader_utils::read_git_pack_line(&mut discovery_response)?;
-    if data.is_none() {
-        return Err("no data found where refs should have started".to_string());
+pub fn reflog_exists(repository: &Repository, reference: &str) -> Result<String, Error> {
+    if !git_reflog::exists(repository, reference) {
+        return Err(Error::CheckFailed {
+            output: String::new(),
+        });
     }
+    return Ok(String::new());
+}
+
+/// The full name of the ref whose log `reference` refers to.
+fn reflog_ref_name(repository: &Repository, reference: &str) -> Result<String, Error> {
+    return git_refs::expand(repository, reference)?.ok_or_else(|| ambiguous_argument(reference));
+}
 
-    let ref_parts = str::from_utf8(data.as_ref().unwrap())
-        .map_err(|err| format!("error converting pack data to string: {err}"))?
-        .split("\x00")
-        .collect::<Vec<&str>>()[0]
-        .split(" ")
-        .collect::<Vec<&str>>();
-    if ref_parts.get(1) != Some(&"HEAD") {
-        return Err("no HEAD ref advertized".to_string());
+/// Prints the object ids `revisions` name, one per line and prefixed with
+/// `^` for those a range excludes. `--verify` (implied by `--short`) wants
+/// exactly one object, `--abbrev-ref` prints ref names instead of ids.
+pub fn rev_parse(
+    repository: &Repository,
+    revisions: &Vec<String>,
+    verify: bool,
+    quiet: bool,
+    short: Option<usize>,
+    abbrev_ref: bool,
+    show_toplevel: bool,
+) -> Result<String, Error> {
+    let mut output = String::new();
+    if show_toplevel {
+        output += &format!("{}\n", repository.work_tree()?.display());
     }
-    let head_hash = ref_parts[0];
 
-    let mut head_ref: Option<String> = None;
-    loop {
-        let data = reader_utils::read_git_pack_line(&mut discovery_response)?;
-        if data.is_none() {
-            break;
+    if verify || short.is_some() {
+        let id = match revisions.as_slice() {
+            [revision] => match git_revision::resolve(repository, revision) {
+                Ok(id) => Some(id),
+                // a broken repository is worth more than "no such revision"
+                Err(err @ (Error::Io { .. } | Error::CorruptObject { .. })) => return Err(err),
+                Err(_) => None,
+            },
+            _ => None,
+        };
+        let id = match (id, quiet) {
+            (Some(id), _) => id,
+            (None, true) => {
+                return Err(Error::Exit {
+                    message: String::new(),
+                    code: 1,
+                })
+            }
+            (None, false) => return Err("fatal: Needed a single revision".into()),
+        };
+        return Ok(match short {
+            Some(length) => format!(
+                "{output}{}\n",
+                git_object::abbreviate(repository, &id, length)?
+            ),
+            None => format!("{output}{id}\n"),
+        });
+    }
+
+    for revision in revisions {
+        let found = git_revision::resolve_range(repository, revision).map_err(|err| match err {
+            Error::ObjectNotFound(_) | Error::AmbiguousPrefix(_) => ambiguous_argument(revision),
+            err => err,
+        })?;
+        if !abbrev_ref {
+            for found in found {
+                let prefix = if found.excluded { "^" } else { "" };
+                output += &format!("{prefix}{}\n", found.id);
+            }
+            continue;
+        }
+        let name = match git_revision::resolve_ref_name(repository, revision)? {
+            Some(name) if name == "HEAD" => git_refs::resolve_name(repository, "HEAD")?,
+            name => name.unwrap_or_default(),
+        };
+        if name.starts_with("refs/") {
+            output += &format!("{}\n", git_refs::shorten(&name));
+        } else if !name.is_empty() {
+            // detached
+            output += "HEAD\n";
         }
+    }
+    return Ok(output);
+}
 
-        let ref_parts: Vec<&str> = str::from_utf8(data.as_ref().unwrap())
-            .map_err(|err| format!("error converting pack data to string: {err}"))?
-            .split(" ")
-            .collect();
-        if ref_parts.get(0) != Some(&head_hash) {
+/// Shows the history reachable from `arguments`' revisions (HEAD by
+/// default), limited to the paths among them and in `paths`, in the chosen
+/// `format`, optionally beside a graph of the history and with what
+/// `diff_options` show of each commit's changes.
+pub fn log(
+    repository: &Repository,
+    arguments: &Vec<String>,
+    paths: &Vec<String>,
+    options: &WalkOptions,
+    diff_options: &DiffOptions,
+    format: &Option<String>,
+    oneline: bool,
+    abbrev_commit: bool,
+    date: &Option<String>,
+    graph: bool,
+) -> Result<String, Error> {
+    let (revisions, paths) = revisions_and_paths(repository, arguments, paths)?;
+    let mut revisions: Vec<Revision> = revisions
+        .into_iter()
+        .map(|(_, revision)| revision)
+        .collect();
+    if revisions.is_empty() {
+        let head = repository.head()?.ok_or_else(|| {
+            let branch = git_refs::resolve_name(repository, "HEAD").unwrap_or_default();
+            let branch = git_refs::shorten(&branch).to_string();
+            Error::from(format!(
+                "fatal: your current branch '{branch}' does not have any commits yet"
+            ))
+        })?;
+        revisions.push(Revision {
+            id: head,
+            excluded: false,
+            left: false,
+        });
+    }
+    let mut options = options.clone();
+    options.paths = paths;
+    if options.follow.is_some() && options.paths.len() != 1 {
+        return Err("fatal: --follow requires exactly one pathspec".into());
+    }
+    // the graph draws lines to every parent that would be shown, even past
+    // the last one printed
+    let max_count = options.max_count.unwrap_or(usize::MAX);
+    if graph {
+        options.topo_order = true;
+        options.max_count = None;
+    }
+    let (format, abbreviate) = match (oneline, format) {
+        (true, _) => (Format::Oneline, true),
+        (false, Some(format)) => (Format::parse(format)?, abbrev_commit),
+        (false, None) => (Format::Medium, abbrev_commit),
+    };
+    let date_mode = date.as_deref().unwrap_or("default");
+    // reject an unknown mode before walking
+    git_date::format(0, 0, date_mode)?;
+
+    let commits = git_revwalk::walk(repository, &revisions, &options)?;
+    let mut graph = match graph {
+        true => Some(Graph::new(commits.iter().map(|walked| walked.id).collect())),
+        false => None,
+    };
+    let terminated = format.is_terminated();
+    let rename_options = diff_options.rename_options(repository)?;
+    let mut skipped = Skipped::default();
+    let mut output = String::new();
+    let mut missing_newline = false;
+    for (position, walked) in commits.iter().take(max_count).enumerate() {
+        if let Some(graph) = graph.as_mut() {
+            graph.update(&walked.id, &walked.parents);
+        }
+        if position > 0 && !terminated {
+            if let (Some(graph), false) = (graph.as_mut(), missing_newline) {
+                output += &graph.padding_line();
+            }
+            output.push('\n');
+        }
+        if let Some(graph) = graph.as_mut() {
+            output += &graph.show_commit();
+        }
+        let header = git_pretty::format_header(repository, walked, &format, abbreviate)?;
+        output += &header;
+        if let (Some(graph), true) = (graph.as_mut(), header.ends_with('\n')) {
+            output += &graph.next_line().0;
+        }
+
+        let body = git_pretty::format_body(repository, walked, &format, date_mode)?;
+        missing_newline = !body.ends_with('\n');
+        output += &match graph.as_mut() {
+            Some(graph) => graph.show_message(&body),
+            None => body,
+        };
+        if terminated && !format.is_empty() {
+            if let (Some(graph), false) = (graph.as_mut(), missing_newline) {
+                output += &graph.padding_line();
+            }
+            output.push('\n');
+        }
+
+        let mut diff = match diff_options.has_output() {
+            true => log_diff(
+                repository,
+                walked,
+                &options,
+                diff_options,
+                &rename_options,
+                &mut skipped,
+            )?,
+            false => String::new(),
+        };
+        if diff.is_empty() {
             continue;
         }
-        head_ref = ref_parts.get(1).copied().map(|r| {
-            let mut s = r.to_string();
-            if s.ends_with("\n") {
-                s.pop();
+        // a message gets a blank line after it, or a `---` one before a
+        // patch with its diffstat
+        if format != Format::Oneline && !format.is_empty() {
+            let dashes = diff_options.stat && diff_options.patch;
+            diff.insert_str(0, if dashes { "---\n" } else { "\n" });
+        }
+        for line in diff.split_inclusive('\n') {
+            if let Some(graph) = graph.as_mut() {
+                output += &graph.padding_line();
+            }
+            output += line;
+        }
+    }
+    if let Some(warning) = skipped.warning("diff.renameLimit") {
+        eprint!("{warning}");
+    }
+    return Ok(output);
+}
+
+/// What `log` shows of the changes `walked` makes to the paths it is limited
+/// to: those since its parent, or since nothing for a root commit. Merges
+/// show nothing unless only first parents are followed. A file `--follow`
+/// finds the origin of shows as renamed or copied from it.
+fn log_diff(
+    repository: &Repository,
+    walked: &WalkedCommit,
+    options: &WalkOptions,
+    diff_options: &DiffOptions,
+    rename_options: &RenameOptions,
+    skipped: &mut Skipped,
+) -> Result<String, Error> {
+    let parent = match walked.commit.parents.as_slice() {
+        [] => None,
+        [parent, ..] if walked.commit.parents.len() == 1 || options.first_parent => {
+            Some(git_object::read_commit(repository, parent)?.tree)
+        }
+        _ => return Ok(String::new()),
+    };
+    let tree = &walked.commit.tree;
+    if let (Some(follow), Some(parent)) = (&options.follow, &parent) {
+        let path = &walked.paths[0];
+        if let Some(pair) = git_rename::follow_origin(repository, parent, tree, path, follow)? {
+            return git_diff::format(repository, &[pair], diff_options);
+        }
+    }
+    let pairs = git_diff::diff_trees(
+        repository,
+        parent.as_ref(),
+        Some(tree),
+        &walked.paths,
+        rename_options.find_copies_harder,
+    )?;
+    let pairs = git_rename::detect_renames(repository, pairs, rename_options, skipped)?;
+    return git_diff::format(repository, &pairs, diff_options);
+}
+
+pub fn rev_list(
+    repository: &Repository,
+    arguments: &Vec<String>,
+    paths: &Vec<String>,
+    options: &WalkOptions,
+    count: bool,
+    left_right: bool,
+    objects: bool,
+    filter: &Option<String>,
+    reverse: bool,
+) -> Result<String, Error> {
+    let filter = match filter {
+        Some(spec) => Some(ObjectFilter::parse(spec)?),
+        None => None,
+    };
+    if filter.is_some() && !objects {
+        return Err("fatal: object filtering requires --objects".into());
+    }
+    let (revisions, paths) = revisions_and_paths(repository, arguments, paths)?;
+    if revisions.is_empty() {
+        return Err(Error::Exit {
+            message: "usage: git rev-list [<options>] <commit>... [--] [<path>...]".to_string(),
+            code: 129,
+        });
+    }
+
+    // commits are walked; tags, trees and blobs only matter to `--objects`
+    let mut commit_revisions = Vec::new();
+    let mut given = Vec::new();
+    let mut excluded = Vec::new();
+    for (argument, revision) in revisions {
+        let mut id = revision.id;
+        let mut name = match argument.split_once(':') {
+            Some((_, path)) => path.to_string(),
+            None => String::new(),
+        };
+        while git_object::get_type(repository, &id)? == ObjectType::Tag {
+            let tag = git_object::read_tag(repository, &id)?;
+            if !revision.excluded {
+                given.push((id, tag.tag));
+            }
+            id = tag.object;
+            name = String::new();
+        }
+        if git_object::get_type(repository, &id)? == ObjectType::Commit {
+            commit_revisions.push(Revision { id, ..revision });
+            if revision.excluded {
+                excluded.push(id);
+            }
+        } else if revision.excluded {
+            excluded.push(id);
+        } else {
+            given.push((id, name));
+        }
+    }
+    let mut options = options.clone();
+    options.paths = paths;
+    let mut commits = git_revwalk::walk(repository, &commit_revisions, &options)?;
+    if reverse {
+        commits.reverse();
+    }
+
+    if count {
+        return Ok(match left_right {
+            true => {
+                let left = commits.iter().filter(|walked| walked.left).count();
+                format!("{left}\t{}\n", commits.len() - left)
             }
-            return s;
+            false => format!("{}\n", commits.len()),
         });
     }
-    if head_ref.is_none() {
-        return Err("a ref that matches HEAD could not be found".to_string());
+    let show_commits = !matches!(filter, Some(ObjectFilter::ObjectType(object_type)) if object_type != ObjectType::Commit);
+    let mut output = String::new();
+    for walked in commits.iter().filter(|_| show_commits) {
+        let mark = match (walked.boundary, left_right, walked.left) {
+            (true, _, _) => "-",
+            (false, true, true) => "<",
+            (false, true, false) => ">",
+            (false, false, _) => "",
+        };
+        output += &format!("{mark}{}\n", walked.id);
     }
-    let ref_name = head_ref
-        .as_ref()
-        .unwrap()
-        .rsplit_once('/')
-        .map(|(_, right)| right.to_string())
-        .unwrap_or(head_ref.unwrap());
+    if objects {
+        for (id, name) in
+            git_revwalk::list_objects(repository, &commits, &given, &excluded, filter)?
+        {
+            output += &format!("{id} {name}\n");
+        }
+    }
+    return Ok(output);
+}
+
+pub fn merge_base(
+    repository: &Repository,
+    commits: &Vec<String>,
+    all: bool,
+    octopus: bool,
+    independent: bool,
+    is_ancestor: bool,
+    fork_point: bool,
+) -> Result<String, Error> {
+    let usage = || {
+        Error::Exit {
+        message: "usage: git merge-base [-a | --all] <commit> <commit>...\n   or: git merge-base [-a | --all] --octopus <commit>...\n   or: git merge-base --is-ancestor <commit> <commit>\n   or: git merge-base --independent <commit>...\n   or: git merge-base --fork-point <ref> [<commit>]".to_string(),
+        code: 129,
+    }
+    };
+    let resolve = |name: &str| {
+        let id = git_revision::resolve(repository, name)
+            .map_err(|_| Error::from(format!("fatal: Not a valid object name {name}")))?;
+        return git_revision::peel_to(repository, &id, ObjectType::Commit)
+            .map_err(|_| Error::from(format!("fatal: Not a valid commit name {name}")));
+    };
+    // "no" is an answer, given by the exit status alone
+    let no = || Error::CheckFailed {
+        output: String::new(),
+    };
+
+    if is_ancestor {
+        if commits.len() < 2 {
+            return Err(usage());
+        }
+        if all {
+            return Err(
+                "fatal: options '--is-ancestor' and '--all' cannot be used together".into(),
+            );
+        }
+        let [ancestor, descendant] = commits.as_slice() else {
+            return Err("fatal: --is-ancestor takes exactly two commits".into());
+        };
+        return match git_merge_base::is_ancestor(
+            repository,
+            &resolve(ancestor)?,
+            &resolve(descendant)?,
+        )? {
+            true => Ok(String::new()),
+            false => Err(no()),
+        };
+    }
+    if independent && all {
+        return Err("fatal: options '--independent' and '--all' cannot be used together".into());
+    }
+    if fork_point {
+        let (reference, commit) = match commits.as_slice() {
+            [reference] => (reference, "HEAD"),
+            [reference, commit] => (reference, commit.as_str()),
+            _ => return Err(usage()),
+        };
+        let commit = git_revision::resolve(repository, commit)
+            .map_err(|_| Error::from(format!("fatal: Not a valid object name: '{commit}'")))?;
+        let commit = git_revision::peel_to(repository, &commit, ObjectType::Commit)?;
+        let full_name = git_revision::resolve_ref_name(repository, reference)?
+            .filter(|name| git_refs::resolve(repository, name).is_ok_and(|id| id.is_some()))
+            .ok_or_else(|| Error::from(format!("fatal: No such ref: '{reference}'")))?;
+        // like git, `HEAD` means the reflog of the branch it is on
+        let full_name = git_refs::resolve_name(repository, &full_name)?;
+        return match git_merge_base::fork_point(repository, &full_name, &commit)? {
+            Some(fork_point) => Ok(format!("{fork_point}\n")),
+            None => Err(no()),
+        };
+    }
+    if !octopus && !independent && commits.len() < 2 {
+        return Err(usage());
+    }
+
+    let mut ids = Vec::new();
+    for commit in commits {
+        ids.push(resolve(commit)?);
+    }
+    let bases = if octopus {
+        git_merge_base::octopus_merge_bases(repository, &ids)?
+    } else if independent {
+        git_merge_base::independent(repository, &ids)?
+    } else {
+        git_merge_base::merge_bases(repository, &ids[0], &ids[1..])?
+    };
+    if bases.is_empty() {
+        return Err(no());
+    }
+    let shown = match all || independent {
+        true => bases.len(),
+        false => 1,
+    };
+    return Ok(bases
+        .iter()
+        .take(shown)
+        .map(|base| format!("{base}\n"))
+        .collect());
+}
 
-    let pack_body: Vec<u8> = format!("0032want {}\n00000009done\n", head_hash)
-        .bytes()
+/// Compares two of a commit's tree, the index and the working tree: the
+/// index against the working tree with no revisions, a tree against the
+/// working tree (or the index with `--cached`) with one, two trees with two
+/// or a range, and `A...B` from their merge base to B.
+pub fn diff(
+    repository: &Repository,
+    arguments: &Vec<String>,
+    paths: &Vec<String>,
+    cached: bool,
+    exit_code: bool,
+    quiet: bool,
+    options: &DiffOptions,
+) -> Result<String, Error> {
+    let (revisions, paths) = revisions_and_paths(repository, arguments, paths)?;
+    let rename_options = options.rename_options(repository)?;
+    let unchanged = rename_options.find_copies_harder;
+    let tree = |revision: &Revision| -> Result<ObjectId, Error> {
+        return git_revision::peel_to(repository, &revision.id, ObjectType::Tree);
+    };
+    let included: Vec<&Revision> = revisions
+        .iter()
+        .map(|(_, revision)| revision)
+        .filter(|revision| !revision.excluded)
         .collect();
-    let mut pack_response = client
-        .request(
-            Method::POST,
-            format!("{remote_url}/git-upload-pack?service=git-upload-pack"),
-        )
-        .header(CONTENT_TYPE, "application/x-git-upload-pack-request")
-        .body(pack_body)
-        .send()
-        .map_err(|err| format!("error sending pack data request: {err}"))?;
-    if pack_response.status() != StatusCode::OK {
-        return Err(format!("pack status: {}", pack_response.status()));
+    let excluded: Vec<&Revision> = revisions
+        .iter()
+        .map(|(_, revision)| revision)
+        .filter(|revision| revision.excluded)
+        .collect();
+
+    let pairs = if included.iter().any(|revision| revision.left) {
+        let (argument, _) = &revisions[0];
+        let base = excluded
+            .first()
+            .ok_or_else(|| Error::from(format!("fatal: {argument}: no merge base")))?;
+        let new = included.iter().find(|revision| !revision.left).unwrap();
+        git_diff::diff_trees(
+            repository,
+            Some(&tree(base)?),
+            Some(&tree(new)?),
+            &paths,
+            unchanged,
+        )?
+    } else {
+        match (included.as_slice(), excluded.as_slice()) {
+            ([], []) if cached => {
+                let head = match repository.head()? {
+                    Some(head) => Some(git_revision::peel_to(repository, &head, ObjectType::Tree)?),
+                    None => None,
+                };
+                git_diff::diff_tree_to_index(repository, head.as_ref(), &paths, unchanged)?
+            }
+            ([], []) => git_diff::diff_index_to_worktree(repository, &paths, unchanged)?,
+            ([old], []) if cached => {
+                git_diff::diff_tree_to_index(repository, Some(&tree(old)?), &paths, unchanged)?
+            }
+            ([old], []) => {
+                git_diff::diff_tree_to_worktree(repository, Some(&tree(old)?), &paths, unchanged)?
+            }
+            ([new], [old]) | ([old, new], []) => git_diff::diff_trees(
+                repository,
+                Some(&tree(old)?),
+                Some(&tree(new)?),
+                &paths,
+                unchanged,
+            )?,
+            _ => {
+                return Err(Error::Exit {
+                    message: "usage: git diff [<options>] [<commit>] [--] [<path>...]".to_string(),
+                    code: 129,
+                })
+            }
+        }
+    };
+    let mut skipped = Skipped::default();
+    let pairs = git_rename::detect_renames(repository, pairs, &rename_options, &mut skipped)?;
+    let output = match quiet {
+        true => String::new(),
+        false => git_diff::format(repository, &pairs, options)?,
+    };
+    if let Some(warning) = skipped.warning("diff.renameLimit") {
+        eprint!("{warning}");
+    }
+    if exit_code && git_diff::has_changes(repository, &pairs, options)? {
+        return Err(Error::CheckFailed { output });
     }
+    return Ok(output);
+}
 
-    fs::create_dir_all(directory).map_err(|err| format!("error creating directory: {err}"))?;
-    set_current_dir(directory).map_err(|err| format!("error changing directory: {err}"))?;
-    init()?;
+/// A revision and the argument it came from.
+type NamedRevision = (String, Revision);
 
-    reader_utils::read_git_pack_line(&mut pack_response)?; // NAK
-    git_pack::unpack(&mut pack_response)?;
-    git::make_branch(&ref_name, &head_hash.to_string())?;
-    git::checkout(&ref_name)?;
+/// Splits `log`'s arguments into the revisions they name, each with the
+/// argument naming it, and the paths, made relative to the top of the work
+/// tree, that follow them or `--`.
+fn revisions_and_paths(
+    repository: &Repository,
+    arguments: &Vec<String>,
+    paths: &Vec<String>,
+) -> Result<(Vec<NamedRevision>, Vec<String>), Error> {
+    let mut revisions = Vec::new();
+    let mut found_paths = Vec::new();
+    for argument in arguments {
+        if found_paths.is_empty() {
+            match git_revision::resolve_range(repository, argument) {
+                Ok(found) => {
+                    let on_disk = repository
+                        .path_from_cwd(argument)
+                        .is_ok_and(|path| repository.work_path(path).exists());
+                    if paths.is_empty() && on_disk {
+                        return Err(format!(
+                            "fatal: ambiguous argument '{argument}': both revision and filename\nUse '--' to separate paths from revisions, like this:\n'git <command> [<revision>...] -- [<file>...]'"
+                        )
+                        .into());
+                    }
+                    revisions.extend(
+                        found
+                            .into_iter()
+                            .map(|revision| (argument.clone(), revision)),
+                    );
+                    continue;
+                }
+                Err(Error::ObjectNotFound(_)) | Err(Error::AmbiguousPrefix(_))
+                    if argument.starts_with('^') =>
+                {
+                    return Err(format!("fatal: bad revision '{argument}'").into());
+                }
+                Err(Error::ObjectNotFound(_)) | Err(Error::AmbiguousPrefix(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let path = repository.path_from_cwd(argument)?;
+        if !repository.work_path(&path).exists() {
+            return Err(ambiguous_argument(argument));
+        }
+        found_paths.push(path);
+    }
+    for path in paths {
+        found_paths.push(repository.path_from_cwd(path)?);
+    }
+    return Ok((revisions, found_paths));
+}
 
-    return Ok(format!("cloned remote {remote_url} to {directory}"));
+/// The error for an argument that names neither a revision nor a path.
+fn ambiguous_argument(argument: &str) -> Error {
+    return format!(
+        "fatal: ambiguous argument '{argument}': unknown revision or path not in the working tree.\nUse '--' to separate paths from revisions, like this:\n'git <command> [<revision>...] -- [<file>...]'"
+    )
+    .into();
 }
 
-fn stringify_tree(reader: &mut impl Read, size: usize, name_only: bool) -> Result<String, String> {
-    let tree_nodes = git_object::read_tree(reader, size)?;
+fn stringify_tree(tree: &Tree, name_only: bool) -> String {
     let mut result = String::new();
-    for tree_node in tree_nodes {
+    for tree_node in tree.entries.iter() {
         if name_only {
             result += format!("{}\n", tree_node.name).as_str();
         } else {
-            let object_type = if tree_node.mode == 40000 {
-                "tree"
-            } else {
-                "blob"
-            };
             result += format!(
                 "{:0>6} {} {}    {}\n",
-                tree_node.mode, object_type, tree_node.hash, tree_node.name
+                tree_node.mode,
+                tree_node.object_type(),
+                tree_node.hash,
+                tree_node.name
             )
             .as_str();
         }
     }
-    return Ok(result);
+    return result;
 }
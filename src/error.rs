@@ -0,0 +1,107 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+use crate::object_id::ObjectId;
+
+/// Everything that can go wrong. The messages are what git prints for the
+/// same failure, so the CLI shows them as is.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("fatal: not a git repository (or any of the parent directories): .git")]
+    NotARepository,
+
+    #[error("fatal: Not a valid object name {0}")]
+    ObjectNotFound(String),
+
+    #[error("error: short object ID {0} is ambiguous\nfatal: Not a valid object name {0}")]
+    AmbiguousPrefix(String),
+
+    #[error("fatal: corrupt object {id}: {reason}")]
+    CorruptObject { id: ObjectId, reason: String },
+
+    /// The remote sent something we don't understand.
+    #[error("fatal: protocol error: {0}")]
+    Protocol(String),
+
+    /// Another process holds the `.lock` file of a ref or the index.
+    #[error("fatal: Unable to create '{}': File exists.\n\nAnother git process seems to be running in this repository, or a git process\ncrashed in this repository earlier: remove the file manually to continue.", path.display())]
+    LockConflict { path: PathBuf },
+
+    /// A ref didn't have the value a transaction expected, e.g. because
+    /// someone else moved it since it was read.
+    #[error("fatal: cannot lock ref '{name}': {reason}")]
+    RefRejected { name: String, reason: String },
+
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("{context}: {source}")]
+    Http {
+        context: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// A command that worked but whose answer is "no", like `check-ignore`
+    /// matching nothing or `diff --exit-code` finding changes. `output` is
+    /// still the command's regular output.
+    #[error("{output}")]
+    CheckFailed { output: String },
+
+    /// A failure git reports as an error instead of dying, which exits with
+    /// status 1.
+    #[error("error: {0}")]
+    Failed(String),
+
+    /// A failure with a command specific exit status, such as the ones
+    /// `git config` documents.
+    #[error("{message}")]
+    Exit { message: String, code: i32 },
+
+    /// Anything without a variant of its own.
+    #[error("{0}")]
+    Message(String),
+}
+
+impl Error {
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        return Error::Io {
+            context: context.into(),
+            source,
+        };
+    }
+
+    pub fn http(context: impl Into<String>, source: reqwest::Error) -> Self {
+        return Error::Http {
+            context: context.into(),
+            source,
+        };
+    }
+
+    /// The process exit status git uses for this kind of failure: 128 when
+    /// it dies, 1 when a command reports an error or a negative answer.
+    pub fn exit_code(&self) -> i32 {
+        return match self {
+            Error::CheckFailed { .. } | Error::Failed(_) => 1,
+            Error::Exit { code, .. } => *code,
+            _ => 128,
+        };
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        return Error::Message(message);
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        return Error::Message(message.to_string());
+    }
+}
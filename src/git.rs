@@ -1,74 +1,630 @@
-use core::str;
-use std::{fs, io::Read};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    env,
+    ffi::OsStr,
+    fs,
+    io::ErrorKind,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{symlink, PermissionsExt},
+    },
+};
 
 use crate::{
-    git_object::{self, ObjectType},
-    reader_utils,
+    error::Error,
+    git_attributes, git_config, git_date,
+    git_ignore::IgnoreRules,
+    git_index::{self, IndexEntry},
+    git_object::{self, ObjectType, Signature, TreeNode},
+    git_reflog, git_refs, git_revision,
+    object_id::ObjectId,
+    repository::Repository,
 };
 
-pub fn make_branch(reference: &String, hash: &String) -> Result<(), String> {
-    let object_type = git_object::get_type(hash)?;
+pub fn make_branch(
+    repository: &Repository,
+    reference: &str,
+    hash: &ObjectId,
+    reflog_message: &str,
+) -> Result<(), Error> {
+    let object_type = git_object::get_type(repository, hash)?;
     if object_type != ObjectType::Commit {
-        return Err(format!(
-            "{hash} isn't a commit and so can't be made a branch"
-        ));
+        return Err(format!("{hash} isn't a commit and so can't be made a branch").into());
     }
 
-    fs::create_dir_all(".git/refs/heads")
-        .map_err(|err| format!("error creating heads directory: {err}"))?;
-
-    fs::write(format!(".git/refs/heads/{reference}"), format!("{hash}\n"))
-        .map_err(|err| format!("error writing to heads/{reference}: {err}"))?;
+    return git_refs::write(
+        repository,
+        &format!("refs/heads/{reference}"),
+        hash,
+        reflog_message,
+    );
+}
 
+/// Fills the index and working tree of a fresh clone, where HEAD already
+/// names `reference`.
+pub fn checkout(repository: &Repository, reference: &str) -> Result<(), Error> {
+    let hash = git_refs::resolve(repository, &format!("refs/heads/{reference}"))?
+        .ok_or_else(|| format!("fatal: invalid reference: {reference}"))?;
+    switch_worktree(repository, &hash, true)?;
     return Ok(());
 }
 
-pub fn checkout(reference: &String) -> Result<(), String> {
-    let mut hash = fs::read_to_string(format!(".git/refs/heads/{reference}"))
-        .map_err(|err| format!("error reading refs/heads/{reference}: {err}"))?;
-    hash.pop(); // remove trailing "\n"
+/// Checks out `hash` with a detached HEAD, as done for submodules.
+pub fn checkout_detached(repository: &Repository, hash: &ObjectId) -> Result<(), Error> {
+    if git_object::get_type(repository, hash)? != ObjectType::Commit {
+        return Err(format!("{hash} isn't a commit and so can't be checked out").into());
+    }
+    let reflog_message = format!(
+        "checkout: moving from {} to {hash}",
+        head_description(repository)?
+    );
+    switch_worktree(repository, hash, false)?;
+    return set_head_detached(repository, hash, &reflog_message);
+}
+
+pub fn set_head_to_branch(
+    repository: &Repository,
+    branch: &str,
+    reflog_message: &str,
+) -> Result<(), Error> {
+    return git_refs::write_symbolic(
+        repository,
+        "HEAD",
+        &format!("refs/heads/{branch}"),
+        reflog_message,
+    );
+}
+
+pub fn set_head_detached(
+    repository: &Repository,
+    hash: &ObjectId,
+    reflog_message: &str,
+) -> Result<(), Error> {
+    return git_refs::write(repository, "HEAD", hash, reflog_message);
+}
+
+/// What HEAD is on, as checkout reflog messages name it: the current branch,
+/// or the commit when detached.
+pub fn head_description(repository: &Repository) -> Result<String, Error> {
+    if let Some(branch) = current_branch(repository)? {
+        return Ok(branch);
+    }
+    return Ok(head_commit(repository)?
+        .map(|hash| hash.to_hex())
+        .unwrap_or("HEAD".to_string()));
+}
+
+/// Moves HEAD to `hash` by updating the branch it points at, or HEAD itself
+/// when detached, and records the move in the reflogs of both.
+pub fn update_head(
+    repository: &Repository,
+    hash: &ObjectId,
+    reflog_message: &str,
+) -> Result<(), Error> {
+    let update = git_refs::RefUpdate {
+        name: "HEAD".to_string(),
+        new: Some(*hash),
+        old: None,
+        deref: true,
+        message: reflog_message.to_string(),
+    };
+    return git_refs::apply(repository, &[update]);
+}
+
+pub fn author_signature(repository: &Repository) -> Result<Signature, Error> {
+    return identity(repository, "AUTHOR", "Author", true);
+}
+
+/// The identity recorded as committer of new commits.
+pub fn committer_signature(repository: &Repository) -> Result<Signature, Error> {
+    return identity(repository, "COMMITTER", "Committer", true);
+}
+
+/// The identity recorded in reflog entries. Unlike making a commit, moving
+/// a ref doesn't require a configured identity: like git, whatever is
+/// missing defaults to the login name and `login@host`.
+pub fn reflog_signature(repository: &Repository) -> Result<Signature, Error> {
+    return identity(repository, "COMMITTER", "Committer", false);
+}
+
+/// Resolves an identity from `GIT_<ROLE>_NAME`, `GIT_<ROLE>_EMAIL` and
+/// `GIT_<ROLE>_DATE`, falling back to `user.name`, `user.email`, `EMAIL` and
+/// the current time. Unless `strict`, a name and email that are still
+/// missing are made up from the login and host names.
+fn identity(
+    repository: &Repository,
+    role: &str,
+    description: &str,
+    strict: bool,
+) -> Result<Signature, Error> {
+    let from_env = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+    let login = || from_env("USER").unwrap_or("unknown".to_string());
+    let name = match from_env(&format!("GIT_{role}_NAME")) {
+        Some(name) => Some(name),
+        None => git_config::get(repository, "user.name")?,
+    }
+    .or_else(|| (!strict).then(login));
+    let email = match from_env(&format!("GIT_{role}_EMAIL")) {
+        Some(email) => Some(email),
+        None => git_config::get(repository, "user.email")?.or_else(|| from_env("EMAIL")),
+    }
+    .or_else(|| (!strict).then(|| format!("{}@{}", login(), host_name())));
+    let unknown = |reason: &str| {
+        format!(
+            "{description} identity unknown\n\n*** Please tell me who you are.\n\nRun\n\n  git config --global user.email \"you@example.com\"\n  git config --global user.name \"Your Name\"\n\nto set your account's default identity.\nOmit --global to set the identity only in this repository.\n\nfatal: {reason}"
+        )
+    };
+    let name = name.ok_or_else(|| unknown("no name was given and auto-detection is disabled"))?;
+    let email =
+        email.ok_or_else(|| unknown("no email was given and auto-detection is disabled"))?;
 
-    fs::write(
-        format!(".git/HEAD"),
-        format!("ref: refs/heads/{reference}\n"),
-    )
-    .map_err(|err| format!("error writing to refs/HEAD: {err}"))?;
+    let (timestamp, timezone_offset) = match from_env(&format!("GIT_{role}_DATE")) {
+        Some(date) => git_date::parse(&date)?,
+        None => git_date::now(),
+    };
+    return Ok(Signature {
+        name,
+        email,
+        timestamp,
+        timezone_offset,
+    });
+}
 
-    let mut commit_reader = git_object::reader(&hash)?;
-    reader_utils::read_to_next_null_byte(&mut commit_reader)?;
-    reader_utils::read_n_bytes(5, &mut commit_reader)?; // 'tree '
-    let hash_bytes = reader_utils::read_n_bytes(20, &mut commit_reader)?;
-    let tree_hash = str::from_utf8(&hash_bytes)
-        .map_err(|err| format!("error parsing tree hash to string: {err}"))?;
-    return construct_tree(&"./".to_string(), &tree_hash.to_string());
+/// The machine's host name, or `(none)` as git puts it when there is none.
+fn host_name() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: gethostname writes at most `buffer.len()` bytes into it
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    let length = buffer.iter().position(|byte| *byte == 0).unwrap_or(0);
+    return match result == 0 && length > 0 {
+        true => String::from_utf8_lossy(&buffer[..length]).to_string(),
+        false => "(none)".to_string(),
+    };
 }
 
-fn construct_tree(path: &String, hash: &String) -> Result<(), String> {
-    let mut tree_reader = git_object::reader(hash)?;
+/// Moves the index and working tree from HEAD's tree to the tree of
+/// `commit_hash`. Only paths that differ between the two trees are touched,
+/// so local changes to other paths are carried over and returned. Unless
+/// `force` is set, nothing is changed if a local modification or an untracked
+/// file would be overwritten.
+pub fn switch_worktree(
+    repository: &Repository,
+    commit_hash: &ObjectId,
+    force: bool,
+) -> Result<Vec<String>, Error> {
+    let old_files = match head_commit(repository)? {
+        Some(head) => flatten_tree(
+            repository,
+            &git_object::read_commit(repository, &head)?.tree,
+        )?,
+        None => BTreeMap::new(),
+    };
+    let new_files = flatten_tree(
+        repository,
+        &git_object::read_commit(repository, commit_hash)?.tree,
+    )?;
+    let mut index: BTreeMap<String, IndexEntry> = git_index::read(repository)?
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+    let index_mtime = git_index::index_mtime(repository);
+    let mut ignore_rules = IgnoreRules::load(repository)?;
+
+    let paths: BTreeSet<String> = old_files
+        .keys()
+        .chain(new_files.keys())
+        .chain(index.keys())
+        .cloned()
+        .collect();
+
+    let mut to_remove: Vec<String> = Vec::new();
+    let mut to_write: Vec<&TreeNode> = Vec::new();
+    let mut carried_over: Vec<String> = Vec::new();
+    let mut local_changes: Vec<String> = Vec::new();
+    let mut untracked: Vec<String> = Vec::new();
+    for path in paths.iter() {
+        let old_node = old_files.get(path);
+        let new_node = new_files.get(path);
+        let entry = index.get(path);
+
+        if force {
+            match new_node {
+                Some(node) => to_write.push(node),
+                None => to_remove.push(path.clone()),
+            }
+            continue;
+        }
 
-    let (_, length) =
-        git_object::identify_header(&reader_utils::read_to_next_null_byte(&mut tree_reader)?)?;
-    let tree_nodes = git_object::read_tree(&mut tree_reader, length)?;
+        let index_matches = |node: Option<&TreeNode>| match (entry, node) {
+            (None, None) => true,
+            (Some(entry), Some(node)) => entry.hash == node.hash && entry.tree_mode() == node.mode,
+            _ => false,
+        };
+        let worktree_clean = match entry {
+            Some(entry) => entry.matches_worktree(repository, index_mtime)?,
+            None => repository.work_path(path).symlink_metadata().is_err(),
+        };
 
-    for tree_node in tree_nodes {
-        if tree_node.mode == 40000 {
-            fs::create_dir_all(format!("{}{}", path, tree_node.name))
-                .map_err(|err| format!("error creating directory for {}: {err}", tree_node.name))?;
-            construct_tree(&format!("{}{}/", path, tree_node.name), &tree_node.hash)?;
+        if same_node(old_node, new_node) || index_matches(new_node) {
+            // nothing to do between the commits, keep whatever the user has
+            if !index_matches(new_node) || !worktree_clean {
+                carried_over.push(path.clone());
+            }
+            continue;
+        }
+        if !index_matches(old_node) {
+            local_changes.push(path.clone());
+            continue;
+        }
+        if !worktree_clean {
+            if entry.is_some() {
+                local_changes.push(path.clone());
+                continue;
+            }
+            // an untracked file is in the way of a new one, or a directory
+            // holding files that neither the old tree nor the index track
+            let is_dir = repository.work_path(path).is_dir();
+            let in_the_way = match is_dir {
+                true => list_worktree_files(repository, path, &mut ignore_rules)?
+                    .iter()
+                    .any(|file| !old_files.contains_key(file) && !index.contains_key(file)),
+                false => !new_node.is_some_and(|node| {
+                    git_object::hash_blob_from_file(repository, path)
+                        .is_ok_and(|hash| hash == node.hash)
+                }),
+            };
+            if in_the_way && !ignore_rules.is_ignored(path, is_dir)? {
+                untracked.push(path.clone());
+                continue;
+            }
+        }
+        match new_node {
+            Some(node) => to_write.push(node),
+            None => to_remove.push(path.clone()),
+        }
+    }
+
+    if !local_changes.is_empty() {
+        return Err(Error::Failed(format!(
+            "Your local changes to the following files would be overwritten by checkout:\n\t{}\nPlease commit your changes or stash them before you switch branches.\nAborting",
+            local_changes.join("\n\t")
+        )));
+    }
+    if !untracked.is_empty() {
+        return Err(Error::Failed(format!(
+            "The following untracked working tree files would be overwritten by checkout:\n\t{}\nPlease move or remove them before you switch branches.\nAborting",
+            untracked.join("\n\t")
+        )));
+    }
+
+    for path in to_remove.iter() {
+        let is_gitlink = index.get(path).is_some_and(|entry| entry.is_gitlink());
+        index.remove(path);
+        if is_gitlink {
+            // leave populated submodules alone, only drop empty directories
+            let _ = fs::remove_dir(repository.work_path(path));
         } else {
-            construct_blob(path, &tree_node.name, &tree_node.hash)?;
+            remove_worktree_file(repository, path)?;
+        }
+        remove_empty_parents(repository, path);
+    }
+    for node in to_write {
+        index.insert(node.name.clone(), checkout_file(repository, node)?);
+    }
+
+    git_index::write(repository, &mut index.into_values().collect::<Vec<_>>())?;
+    return Ok(carried_over);
+}
+
+fn same_node(a: Option<&TreeNode>, b: Option<&TreeNode>) -> bool {
+    return match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => a.hash == b.hash && a.mode == b.mode,
+        _ => false,
+    };
+}
+
+fn remove_worktree_file(repository: &Repository, path: &str) -> Result<(), Error> {
+    forget_attributes_file(repository, path);
+    return match fs::remove_file(repository.work_path(path)) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(Error::io(format!("error removing {path}"), err)),
+    };
+}
+
+/// Makes the attributes be read again once `path` is a `.gitattributes`
+/// file that changed, so the files checked out after it follow it.
+fn forget_attributes_file(repository: &Repository, path: &str) {
+    if path.rsplit('/').next() == Some(".gitattributes") {
+        repository.forget_attributes();
+    }
+}
+
+/// Removes the directories above `path` that became empty, stopping at the
+/// first one that still has content.
+fn remove_empty_parents(repository: &Repository, path: &str) {
+    let mut current = path;
+    while let Some((parent, _)) = current.rsplit_once('/') {
+        if fs::remove_dir(repository.work_path(parent)).is_err() {
+            return;
+        }
+        current = parent;
+    }
+}
+
+/// Writes the tree entry `node` (whose name is its full path) to the working
+/// tree and returns the matching index entry.
+fn checkout_file(repository: &Repository, node: &TreeNode) -> Result<IndexEntry, Error> {
+    let path = &node.name;
+    let file_path = repository.work_path(path);
+    let mode = git_index::mode_from_tree_mode(node.mode);
+
+    // a file may be in the way of one of the new directories
+    let mut end = 0;
+    while let Some(offset) = path[end..].find('/') {
+        let parent = &path[..end + offset];
+        let parent_path = repository.work_path(parent);
+        if parent_path.is_file() || parent_path.is_symlink() {
+            remove_worktree_file(repository, parent)?;
         }
+        end += offset + 1;
     }
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| Error::io(format!("error creating directory for {path}"), err))?;
+    }
+
+    if node.is_gitlink() {
+        // submodules are populated separately by `submodule update`
+        fs::create_dir_all(&file_path)
+            .map_err(|err| Error::io(format!("error creating directory for {path}"), err))?;
+        return Ok(IndexEntry::without_stat(path, &node.hash, mode));
+    }
+
+    if file_path.is_dir() && !file_path.is_symlink() {
+        fs::remove_dir_all(&file_path)
+            .map_err(|err| Error::io(format!("error removing {path}"), err))?;
+    } else {
+        remove_worktree_file(repository, path)?;
+    }
+
+    let blob_data = git_object::read_blob(repository, &node.hash)?.data;
+
+    if node.mode == git_object::SYMLINK_MODE {
+        let target = OsStr::from_bytes(&blob_data);
+        symlink(target, &file_path)
+            .map_err(|err| Error::io(format!("error creating symlink {path}"), err))?;
+    } else {
+        let blob_data = git_attributes::convert_to_worktree(repository, path, blob_data)?;
+        fs::write(&file_path, blob_data).map_err(|err| Error::io("error writing file", err))?;
+        forget_attributes_file(repository, path);
+        if node.mode == 100755 {
+            fs::set_permissions(&file_path, fs::Permissions::from_mode(0o755))
+                .map_err(|err| Error::io(format!("error making {path} executable"), err))?;
+        }
+    }
+
+    let metadata = fs::symlink_metadata(&file_path)
+        .map_err(|err| Error::io(format!("error reading metadata of {path}"), err))?;
+    return Ok(IndexEntry::from_metadata(path, &node.hash, mode, &metadata));
+}
+
+/// Resolves a revision (see [`git_revision::resolve`]) to the commit it
+/// names, peeling tags.
+pub fn resolve_commit(repository: &Repository, name: &str) -> Result<ObjectId, Error> {
+    if name == "HEAD" && head_commit(repository)?.is_none() {
+        return Err("HEAD does not point to a commit".into());
+    }
+    let hash = git_revision::resolve(repository, name).map_err(|err| match err {
+        Error::ObjectNotFound(_) | Error::AmbiguousPrefix(_) => {
+            format!("fatal: invalid reference: {name}").into()
+        }
+        err => err,
+    })?;
+    let hash = git_refs::peel(repository, &hash)?;
+    if git_object::get_type(repository, &hash)? != ObjectType::Commit {
+        return Err(format!("fatal: reference is not a commit: {name}").into());
+    }
+    return Ok(hash);
+}
+
+/// The full name of the remote-tracking branch `branch` is configured to
+/// track through `branch.<name>.remote` and `branch.<name>.merge`, or the
+/// local branch when the remote is `.`.
+pub fn upstream_ref(repository: &Repository, branch: &str) -> Result<Option<String>, Error> {
+    let remote = git_config::get(repository, &format!("branch.{branch}.remote"))?;
+    let merge = git_config::get(repository, &format!("branch.{branch}.merge"))?;
+    if remote.is_none() || merge.is_none() {
+        return Ok(None);
+    }
+    let remote = remote.unwrap();
+    let merge = merge.unwrap();
+    if remote == "." {
+        return Ok(Some(merge));
+    }
+    let merge_branch = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+    return Ok(Some(format!("refs/remotes/{remote}/{merge_branch}")));
+}
+
+pub fn branch_exists(repository: &Repository, branch: &str) -> bool {
+    return git_refs::resolve(repository, &format!("refs/heads/{branch}"))
+        .is_ok_and(|hash| hash.is_some());
+}
+
+/// Resolves HEAD to a commit hash, returning `None` on an unborn branch.
+pub fn head_commit(repository: &Repository) -> Result<Option<ObjectId>, Error> {
+    return git_refs::resolve(repository, "HEAD");
+}
+
+/// Looks up the entry at the slash separated `path` below the tree `tree_hash`.
+pub fn find_tree_entry(
+    repository: &Repository,
+    tree_hash: &ObjectId,
+    path: &str,
+) -> Result<Option<TreeNode>, Error> {
+    let mut current_tree = *tree_hash;
+    let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+    while let Some(component) = components.next() {
+        let node = git_object::read_tree_object(repository, &current_tree)?
+            .entries
+            .into_iter()
+            .find(|node| node.name == component);
+        match node {
+            Some(node) if components.peek().is_none() => return Ok(Some(node)),
+            Some(node) if node.is_tree() => current_tree = node.hash,
+            _ => return Ok(None),
+        }
+    }
+    return Ok(None);
+}
+
+/// Recursively lists the non-tree entries below `tree_hash`, keyed by their
+/// full slash separated path.
+pub fn flatten_tree(
+    repository: &Repository,
+    tree_hash: &ObjectId,
+) -> Result<BTreeMap<String, TreeNode>, Error> {
+    let mut result = BTreeMap::new();
+    flatten_tree_into(repository, tree_hash, "", &mut result)?;
+    return Ok(result);
+}
+
+fn flatten_tree_into(
+    repository: &Repository,
+    tree_hash: &ObjectId,
+    prefix: &str,
+    result: &mut BTreeMap<String, TreeNode>,
+) -> Result<(), Error> {
+    for node in git_object::read_tree_object(repository, tree_hash)?.entries {
+        let path = format!("{prefix}{}", node.name);
+        if node.is_tree() {
+            flatten_tree_into(repository, &node.hash, &format!("{path}/"), result)?;
+        } else {
+            result.insert(
+                path.clone(),
+                TreeNode {
+                    mode: node.mode,
+                    name: path,
+                    hash: node.hash,
+                },
+            );
+        }
+    }
+    return Ok(());
+}
+
+/// Every commit reachable from `commit_hash`, including itself.
+pub fn reachable_commits(
+    repository: &Repository,
+    commit_hash: &ObjectId,
+) -> Result<HashSet<ObjectId>, Error> {
+    let mut seen = HashSet::new();
+    let mut pending = vec![*commit_hash];
+    while let Some(hash) = pending.pop() {
+        if !seen.insert(hash) {
+            continue;
+        }
+        pending.append(&mut git_object::read_commit(repository, &hash)?.parents);
+    }
+    return Ok(seen);
+}
+
+/// Makes `branch` track `upstream`, the full name of a remote-tracking or
+/// local branch.
+pub fn set_upstream(repository: &Repository, branch: &str, upstream: &str) -> Result<(), Error> {
+    let (remote, merge) = match upstream
+        .strip_prefix("refs/remotes/")
+        .and_then(|name| name.split_once('/'))
+    {
+        Some((remote, merge_branch)) => (remote, format!("refs/heads/{merge_branch}")),
+        None => (".", upstream.to_string()),
+    };
+    let config_path = repository.git_path("config");
+    git_config::set(&config_path, &format!("branch.{branch}.remote"), remote)?;
+    git_config::set(&config_path, &format!("branch.{branch}.merge"), &merge)?;
+    repository.forget_config();
     return Ok(());
 }
 
-fn construct_blob(path: &String, name: &String, hash: &String) -> Result<(), String> {
-    let mut blob_reader = git_object::reader(hash)?;
-    reader_utils::read_to_next_null_byte(&mut blob_reader)?;
-    let mut blob_data = Vec::new();
-    blob_reader
-        .read_to_end(&mut blob_data)
-        .map_err(|err| format!("error reading blob object: {err}"))?;
-    return fs::write(format!("{path}{name}"), blob_data)
-        .map_err(|err| format!("error writing file: {err}"));
+/// How `git branch` and `git status` describe a detached HEAD: at (or moved
+/// on from) what the last checkout named, per the HEAD log.
+pub fn detached_head_description(repository: &Repository) -> Result<String, Error> {
+    let head = head_commit(repository)?;
+    let checkout = git_reflog::read(repository, "HEAD")?
+        .into_iter()
+        .rev()
+        .find_map(|entry| {
+            let target = entry
+                .message
+                .strip_prefix("checkout: moving from ")?
+                .split_once(" to ")?
+                .1
+                .to_string();
+            Some((target, entry.new))
+        });
+    let (target, id) = match checkout {
+        Some(checkout) => checkout,
+        None => return Ok("(no branch)".to_string()),
+    };
+    let name = match git_refs::expand(repository, &target)? {
+        Some(name) => git_refs::shorten(&name).to_string(),
+        None => git_object::abbreviate(repository, &id, 7)?,
+    };
+    let position = if head == Some(id) { "at" } else { "from" };
+    return Ok(format!("(HEAD detached {position} {name})"));
+}
+
+/// The branch HEAD points at, or `None` when HEAD is detached.
+pub fn current_branch(repository: &Repository) -> Result<Option<String>, Error> {
+    return Ok(git_refs::resolve_name(repository, "HEAD")?
+        .strip_prefix("refs/heads/")
+        .map(|branch| branch.to_string()));
+}
+
+/// Lists the files below `directory` (relative to the working tree root)
+/// that aren't ignored, treating nested repositories as single entries.
+pub fn list_worktree_files(
+    repository: &Repository,
+    directory: &str,
+    ignore_rules: &mut IgnoreRules,
+) -> Result<Vec<String>, Error> {
+    let mut result = Vec::new();
+    list_worktree_files_into(repository, directory, ignore_rules, &mut result)?;
+    result.sort();
+    return Ok(result);
+}
+
+fn list_worktree_files_into(
+    repository: &Repository,
+    directory: &str,
+    ignore_rules: &mut IgnoreRules,
+    result: &mut Vec<String>,
+) -> Result<(), Error> {
+    let entries = fs::read_dir(repository.work_path(directory))
+        .map_err(|err| Error::io("error reading directory", err))?;
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::io("error reading directory entry", err))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == ".git" {
+            continue;
+        }
+        let path = if directory.is_empty() {
+            name
+        } else {
+            format!("{directory}/{name}")
+        };
+        let file_type = entry
+            .file_type()
+            .map_err(|err| Error::io(format!("error reading file type of {path}"), err))?;
+        if ignore_rules.is_ignored(&path, file_type.is_dir())? {
+            continue;
+        }
+        if file_type.is_dir() && !entry.path().join(".git").exists() {
+            list_worktree_files_into(repository, &path, ignore_rules, result)?;
+        } else {
+            result.push(path);
+        }
+    }
+    return Ok(());
 }
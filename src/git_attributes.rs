@@ -0,0 +1,434 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    rc::Rc,
+    thread,
+};
+
+use crate::{
+    error::Error,
+    git_config::{self, Config},
+    git_ignore,
+    repository::Repository,
+};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AttributeState {
+    Set,
+    Unset,
+    Unspecified,
+    Value(String),
+}
+
+impl AttributeState {
+    pub fn describe(&self) -> String {
+        return match self {
+            AttributeState::Set => "set".to_string(),
+            AttributeState::Unset => "unset".to_string(),
+            AttributeState::Unspecified => "unspecified".to_string(),
+            AttributeState::Value(value) => value.clone(),
+        };
+    }
+}
+
+struct AttributeLine {
+    pattern: String,
+    anchored: bool,
+    /// Directory of the attributes file relative to the working tree root,
+    /// with a trailing slash unless it is the root.
+    base: String,
+    assignments: Vec<(String, AttributeState)>,
+}
+
+impl AttributeLine {
+    fn matches(&self, path: &str) -> bool {
+        let relative = match path.strip_prefix(&self.base) {
+            Some(relative) => relative,
+            None => return false,
+        };
+        if self.anchored {
+            return git_ignore::wildmatch(self.pattern.as_bytes(), relative.as_bytes());
+        }
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        return git_ignore::wildmatch(self.pattern.as_bytes(), name.as_bytes());
+    }
+}
+
+/// The attribute lines of a repository, in increasing order of precedence:
+/// `core.attributesFile`, `.gitattributes` files from the root down to a
+/// path's directory, and `.git/info/attributes`. The files below the root
+/// are read the first time a path in their directory is looked up.
+pub struct Attributes {
+    work_tree: Option<PathBuf>,
+    /// `core.attributesFile` and the root `.gitattributes`.
+    top: Vec<AttributeLine>,
+    /// The lines of each directory's `.gitattributes`, by directory.
+    directories: RefCell<HashMap<String, Rc<Vec<AttributeLine>>>>,
+    info: Vec<AttributeLine>,
+    macros: HashMap<String, Vec<(String, AttributeState)>>,
+}
+
+impl Attributes {
+    pub fn load(repository: &Repository) -> Result<Self, Error> {
+        let mut macros = HashMap::from([(
+            "binary".to_string(),
+            vec![
+                ("diff".to_string(), AttributeState::Unset),
+                ("merge".to_string(), AttributeState::Unset),
+                ("text".to_string(), AttributeState::Unset),
+            ],
+        )]);
+        let mut top = Vec::new();
+        if let Some(attributes_file) = git_config::get(repository, "core.attributesfile")? {
+            top.extend(read_file(
+                Path::new(&attributes_file),
+                "",
+                Some(&mut macros),
+            )?);
+        }
+        if let Some(root) = &repository.work_tree {
+            top.extend(read_file(
+                &root.join(".gitattributes"),
+                "",
+                Some(&mut macros),
+            )?);
+        }
+        let info = read_file(
+            &repository.git_path("info/attributes"),
+            "",
+            Some(&mut macros),
+        )?;
+        return Ok(Attributes {
+            work_tree: repository.work_tree.clone(),
+            top,
+            directories: RefCell::new(HashMap::new()),
+            info,
+            macros,
+        });
+    }
+
+    /// The lines of the `.gitattributes` in `directory`, below the root.
+    fn directory_lines(&self, directory: &str) -> Result<Rc<Vec<AttributeLine>>, Error> {
+        if let Some(lines) = self.directories.borrow().get(directory) {
+            return Ok(lines.clone());
+        }
+        let lines = Rc::new(match &self.work_tree {
+            Some(root) => read_file(
+                &root.join(directory).join(".gitattributes"),
+                &format!("{directory}/"),
+                None,
+            )?,
+            None => Vec::new(),
+        });
+        self.directories
+            .borrow_mut()
+            .insert(directory.to_string(), lines.clone());
+        return Ok(lines);
+    }
+
+    /// The state of every attribute specified for `path`.
+    pub fn all(&self, path: &str) -> Result<Vec<(String, AttributeState)>, Error> {
+        let mut directories = Vec::new();
+        let mut end = 0;
+        while let Some(offset) = path[end..].find('/') {
+            directories.push(self.directory_lines(&path[..end + offset])?);
+            end += offset + 1;
+        }
+        let lines = self
+            .top
+            .iter()
+            .chain(directories.iter().flat_map(|lines| lines.iter()))
+            .chain(self.info.iter());
+
+        let mut states: Vec<(String, AttributeState)> = Vec::new();
+        for line in lines.filter(|line| line.matches(path)) {
+            for (name, state) in line.assignments.iter() {
+                self.assign(&mut states, name, state);
+            }
+        }
+        return Ok(states
+            .into_iter()
+            .filter(|(_, state)| *state != AttributeState::Unspecified)
+            .collect());
+    }
+
+    pub fn get(&self, path: &str, name: &str) -> Result<AttributeState, Error> {
+        return Ok(self
+            .all(path)?
+            .into_iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, state)| state)
+            .unwrap_or(AttributeState::Unspecified));
+    }
+
+    fn assign(
+        &self,
+        states: &mut Vec<(String, AttributeState)>,
+        name: &str,
+        state: &AttributeState,
+    ) {
+        states.retain(|(attribute, _)| attribute != name);
+        states.push((name.to_string(), state.clone()));
+        if *state == AttributeState::Set {
+            if let Some(expansion) = self.macros.get(name) {
+                for (macro_name, macro_state) in expansion {
+                    self.assign(states, macro_name, macro_state);
+                }
+            }
+        }
+    }
+}
+
+/// The lines of the attributes file at `path`, which lives in the directory
+/// `base`. Macro definitions go into `macros`, and are ignored where that
+/// isn't given because only top level files may define them.
+fn read_file(
+    path: &Path,
+    base: &str,
+    mut macros: Option<&mut HashMap<String, Vec<(String, AttributeState)>>>,
+) -> Result<Vec<AttributeLine>, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound || err.kind() == ErrorKind::NotADirectory => {
+            return Ok(Vec::new())
+        }
+        Err(err) => return Err(format!("error reading {}: {err}", path.display()).into()),
+    };
+    let mut lines = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let pattern = fields.next().unwrap();
+        let assignments: Vec<(String, AttributeState)> = fields.map(parse_assignment).collect();
+
+        if let Some(name) = pattern.strip_prefix("[attr]") {
+            if let Some(macros) = macros.as_mut() {
+                macros.insert(name.to_string(), assignments);
+            }
+            continue;
+        }
+        // negative patterns are forbidden and directory patterns never
+        // match files in attributes files
+        if pattern.starts_with('!') || pattern.ends_with('/') {
+            continue;
+        }
+        lines.push(AttributeLine {
+            anchored: pattern.contains('/'),
+            pattern: pattern.trim_start_matches('/').to_string(),
+            base: base.to_string(),
+            assignments,
+        });
+    }
+    return Ok(lines);
+}
+
+fn parse_assignment(field: &str) -> (String, AttributeState) {
+    if let Some(name) = field.strip_prefix('-') {
+        return (name.to_string(), AttributeState::Unset);
+    }
+    if let Some(name) = field.strip_prefix('!') {
+        return (name.to_string(), AttributeState::Unspecified);
+    }
+    return match field.split_once('=') {
+        Some((name, value)) => (name.to_string(), AttributeState::Value(value.to_string())),
+        None => (field.to_string(), AttributeState::Set),
+    };
+}
+
+#[derive(PartialEq)]
+enum TextMode {
+    Binary,
+    Text,
+    Auto,
+}
+
+/// `core.autocrlf`: a bool, or `input` to only convert when adding.
+#[derive(PartialEq)]
+enum AutoCrlf {
+    True,
+    False,
+    Input,
+}
+
+fn autocrlf(config: &Config) -> Result<AutoCrlf, Error> {
+    let name = "core.autocrlf";
+    return Ok(match config.matching(name)?.last() {
+        None => AutoCrlf::False,
+        Some(entry) => match entry.value.as_deref() {
+            Some(value) if value.eq_ignore_ascii_case("input") => AutoCrlf::Input,
+            value => match git_config::parse_bool(value, name)? {
+                true => AutoCrlf::True,
+                false => AutoCrlf::False,
+            },
+        },
+    });
+}
+
+fn text_mode(config: &Config, attributes: &Attributes, path: &str) -> Result<TextMode, Error> {
+    return Ok(match attributes.get(path, "text")? {
+        AttributeState::Set => TextMode::Text,
+        AttributeState::Unset => TextMode::Binary,
+        AttributeState::Value(value) if value == "auto" => TextMode::Auto,
+        _ => {
+            if attributes.get(path, "eol")? != AttributeState::Unspecified {
+                TextMode::Text
+            } else if autocrlf(config)? != AutoCrlf::False {
+                TextMode::Auto
+            } else {
+                TextMode::Binary
+            }
+        }
+    });
+}
+
+/// Whether text files should be written to the working tree with CRLF.
+fn checkout_uses_crlf(config: &Config, attributes: &Attributes, path: &str) -> Result<bool, Error> {
+    if let AttributeState::Value(eol) = attributes.get(path, "eol")? {
+        return Ok(eol == "crlf");
+    }
+    return Ok(match autocrlf(config)? {
+        AutoCrlf::True => true,
+        AutoCrlf::Input => false,
+        AutoCrlf::False => config.get("core.eol")?.as_deref() == Some("crlf"),
+    });
+}
+
+/// Applies the same heuristic as git: content with a null byte in the first
+/// 8000 bytes is binary.
+pub fn is_binary(data: &[u8]) -> bool {
+    return data.iter().take(8000).any(|byte| *byte == 0);
+}
+
+/// Converts working tree content of `path` into what gets stored in the
+/// repository: the clean filter runs first, then line endings are normalised.
+pub fn convert_to_git(
+    repository: &Repository,
+    path: &str,
+    data: Vec<u8>,
+) -> Result<Vec<u8>, Error> {
+    let (config, attributes) = (repository.config()?, repository.attributes()?);
+    let mut data = run_filter(repository, &config, &attributes, path, "clean", data)?;
+    let mode = text_mode(&config, &attributes, path)?;
+    if mode == TextMode::Text || (mode == TextMode::Auto && !is_binary(&data)) {
+        data = crlf_to_lf(&data);
+    }
+    return Ok(data);
+}
+
+/// Converts repository content of `path` into what gets written to the working
+/// tree: line endings are converted first, then the smudge filter runs.
+pub fn convert_to_worktree(
+    repository: &Repository,
+    path: &str,
+    mut data: Vec<u8>,
+) -> Result<Vec<u8>, Error> {
+    let (config, attributes) = (repository.config()?, repository.attributes()?);
+    let mode = text_mode(&config, &attributes, path)?;
+    let is_text = mode == TextMode::Text || (mode == TextMode::Auto && !is_binary(&data));
+    if is_text && checkout_uses_crlf(&config, &attributes, path)? {
+        data = lf_to_crlf(&data);
+    }
+    return run_filter(repository, &config, &attributes, path, "smudge", data);
+}
+
+fn crlf_to_lf(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    for (index, byte) in data.iter().enumerate() {
+        if *byte == b'\r' && data.get(index + 1) == Some(&b'\n') {
+            continue;
+        }
+        result.push(*byte);
+    }
+    return result;
+}
+
+fn lf_to_crlf(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    for (index, byte) in data.iter().enumerate() {
+        if *byte == b'\n' && (index == 0 || data[index - 1] != b'\r') {
+            result.push(b'\r');
+        }
+        result.push(*byte);
+    }
+    return result;
+}
+
+/// Pipes `data` through the `filter.<name>.<direction>` command configured for
+/// the path's `filter` attribute. Failing filters are skipped unless the
+/// driver is marked as required.
+fn run_filter(
+    repository: &Repository,
+    config: &Config,
+    attributes: &Attributes,
+    path: &str,
+    direction: &str,
+    data: Vec<u8>,
+) -> Result<Vec<u8>, Error> {
+    let driver = match attributes.get(path, "filter")? {
+        AttributeState::Value(driver) => driver,
+        _ => return Ok(data),
+    };
+    let command = config.get(&format!("filter.{driver}.{direction}"))?;
+    let required = config
+        .get_bool(&format!("filter.{driver}.required"))?
+        .unwrap_or(false);
+    let command = match command {
+        Some(command) => command,
+        None if required => {
+            return Err(format!(
+                "fatal: {path}: {direction} filter '{driver}' failed: no {direction} command configured"
+            ).into())
+        }
+        None => return Ok(data),
+    };
+
+    let quoted_path = format!("'{}'", path.replace('\'', "'\\''"));
+    // filters run from the top of the working tree, like git runs them
+    let directory = repository.work_path("");
+    return match spawn_filter(&command.replace("%f", &quoted_path), &directory, &data) {
+        Ok(filtered) => Ok(filtered),
+        Err(err) if required => {
+            Err(format!("fatal: {path}: {direction} filter '{driver}' failed: {err}").into())
+        }
+        Err(err) => {
+            eprintln!("error: cannot run {direction} filter '{driver}' on {path}: {err}");
+            Ok(data)
+        }
+    };
+}
+
+fn spawn_filter(command: &str, directory: &Path, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| Error::io("error spawning filter", err))?;
+
+    // write from another thread so a filter that streams its output can't
+    // deadlock against us filling its stdin
+    let mut stdin = child.stdin.take().unwrap();
+    let input = data.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|err| Error::io("error waiting for filter", err))?;
+    writer
+        .join()
+        .map_err(|_| "filter input thread panicked".to_string())?
+        .map_err(|err| Error::io("error writing to filter", err))?;
+    if !output.status.success() {
+        return Err(format!("filter exited with {}", output.status).into());
+    }
+    return Ok(output.stdout);
+}
@@ -0,0 +1,632 @@
+use std::{
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::Error, git_ignore, repository::Repository};
+
+/// Includes nested deeper than this are assumed to be a cycle.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+pub struct ConfigEntry {
+    pub section: String,
+    pub subsection: Option<String>,
+    pub key: String,
+    pub value: Option<String>,
+    /// The file the entry was read from, as shown by `--show-origin`.
+    pub origin: String,
+}
+
+impl ConfigEntry {
+    fn matches(&self, section: &str, subsection: &Option<String>, key: &str) -> bool {
+        return self.section == section && &self.subsection == subsection && self.key == key;
+    }
+
+    /// The canonical dotted name, e.g. `remote.origin.url`.
+    pub fn name(&self) -> String {
+        return match &self.subsection {
+            Some(subsection) => format!("{}.{subsection}.{}", self.section, self.key),
+            None => format!("{}.{}", self.section, self.key),
+        };
+    }
+}
+
+/// The config files git layers on top of each other, in increasing order of
+/// precedence.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scope {
+    System,
+    Global,
+    Local,
+    Worktree,
+}
+
+impl Scope {
+    /// The files read for this scope. The last one is where writes go. The
+    /// repository scopes have none outside of a repository.
+    pub fn paths(&self, repository: Option<&Repository>) -> Vec<PathBuf> {
+        let from_env = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        return match self {
+            Scope::System => {
+                if from_env("GIT_CONFIG_NOSYSTEM").is_some() {
+                    return Vec::new();
+                }
+                vec![PathBuf::from(
+                    from_env("GIT_CONFIG_SYSTEM").unwrap_or("/etc/gitconfig".to_string()),
+                )]
+            }
+            Scope::Global => {
+                if let Some(global) = from_env("GIT_CONFIG_GLOBAL") {
+                    return vec![PathBuf::from(global)];
+                }
+                let mut paths = Vec::new();
+                let home = from_env("HOME");
+                match (from_env("XDG_CONFIG_HOME"), &home) {
+                    (Some(config_home), _) => {
+                        paths.push(Path::new(&config_home).join("git/config"))
+                    }
+                    (None, Some(home)) => paths.push(Path::new(home).join(".config/git/config")),
+                    (None, None) => {}
+                }
+                if let Some(home) = home {
+                    paths.push(Path::new(&home).join(".gitconfig"));
+                }
+                paths
+            }
+            Scope::Local => repository
+                .map(|repository| vec![repository.git_path("config")])
+                .unwrap_or_default(),
+            Scope::Worktree => repository
+                .map(|repository| vec![repository.git_path("config.worktree")])
+                .unwrap_or_default(),
+        };
+    }
+}
+
+/// The merged view of every config file that applies to the repository,
+/// with `include.path` and `includeIf` sections expanded in place.
+pub struct Config {
+    pub entries: Vec<ConfigEntry>,
+}
+
+impl Config {
+    /// Reads every scope, leaving out the repository's own files when there
+    /// is no repository.
+    pub fn load(repository: Option<&Repository>) -> Result<Self, Error> {
+        let mut config = Config {
+            entries: Vec::new(),
+        };
+        for scope in [Scope::System, Scope::Global, Scope::Local] {
+            for path in scope.paths(repository) {
+                config.include_file(&path, 0, repository)?;
+            }
+        }
+        // per-worktree config only applies once the extension is enabled
+        if config.get_bool("extensions.worktreeconfig")? == Some(true) {
+            for path in Scope::Worktree.paths(repository) {
+                config.include_file(&path, 0, repository)?;
+            }
+        }
+        return Ok(config);
+    }
+
+    /// Reads the files of a single scope.
+    pub fn load_scope(scope: Scope, repository: Option<&Repository>) -> Result<Self, Error> {
+        let mut config = Config {
+            entries: Vec::new(),
+        };
+        for path in scope.paths(repository) {
+            config.include_file(&path, 0, repository)?;
+        }
+        return Ok(config);
+    }
+
+    /// Reads one specific file, following its includes.
+    pub fn load_file<P: AsRef<Path>>(
+        path: P,
+        repository: Option<&Repository>,
+    ) -> Result<Self, Error> {
+        let mut config = Config {
+            entries: Vec::new(),
+        };
+        config.include_file(path.as_ref(), 0, repository)?;
+        return Ok(config);
+    }
+
+    /// Appends the entries of `path`. The git directory of `repository` is
+    /// what `includeIf "gitdir:"` conditions are tested against.
+    fn include_file(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        repository: Option<&Repository>,
+    ) -> Result<(), Error> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(format!(
+                "fatal: exceeded maximum include depth ({MAX_INCLUDE_DEPTH}) while including {}",
+                path.display()
+            )
+            .into());
+        }
+        let base = path.parent().unwrap_or(Path::new("."));
+        let git_dir = repository.map(|repository| repository.git_dir.as_path());
+        for mut entry in read_file(path)? {
+            if let Some(repository) = repository {
+                entry.origin = relative_to_work_tree(repository, path);
+            }
+            let include = match (&entry.value, entry.section.as_str(), entry.key.as_str()) {
+                (Some(target), "include", "path") if entry.subsection.is_none() => Some(target),
+                (Some(target), "includeif", "path") => entry
+                    .subsection
+                    .as_ref()
+                    .is_some_and(|condition| include_condition_holds(condition, base, git_dir))
+                    .then_some(target),
+                _ => None,
+            };
+            let target = include.map(|target| resolve_path(target, base));
+            self.entries.push(entry);
+            if let Some(target) = target {
+                self.include_file(&target, depth + 1, repository)?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Every entry setting `name`, in the order they were read. Multi-valued
+    /// keys such as `remote.origin.fetch` have more than one.
+    pub fn matching(&self, name: &str) -> Result<Vec<&ConfigEntry>, Error> {
+        let (section, subsection, key) = split_name(name)?;
+        return Ok(self
+            .entries
+            .iter()
+            .filter(|entry| entry.matches(&section, &subsection, &key))
+            .collect());
+    }
+
+    /// The last value set for `name`. Keys without a value (`[core] bare`)
+    /// are reported as `"true"`.
+    pub fn get(&self, name: &str) -> Result<Option<String>, Error> {
+        return Ok(self
+            .matching(name)?
+            .last()
+            .map(|entry| entry.value.clone().unwrap_or("true".to_string())));
+    }
+
+    pub fn get_bool(&self, name: &str) -> Result<Option<bool>, Error> {
+        return match self.matching(name)?.last() {
+            Some(entry) => Ok(Some(parse_bool(entry.value.as_deref(), name)?)),
+            None => Ok(None),
+        };
+    }
+}
+
+/// Whether an `[includeIf "<condition>"]` section applies. Only `gitdir:`
+/// and its case-insensitive `gitdir/i:` variant are understood.
+fn include_condition_holds(condition: &str, base: &Path, git_dir: Option<&Path>) -> bool {
+    let (pattern, case_insensitive) = if let Some(pattern) = condition.strip_prefix("gitdir:") {
+        (pattern, false)
+    } else if let Some(pattern) = condition.strip_prefix("gitdir/i:") {
+        (pattern, true)
+    } else {
+        return false;
+    };
+    let git_dir = match git_dir {
+        Some(git_dir) => git_dir.to_string_lossy().to_string(),
+        None => return false,
+    };
+
+    let mut pattern = if pattern.starts_with("~/") || pattern.starts_with("./") {
+        resolve_path(pattern, base).to_string_lossy().to_string()
+    } else {
+        pattern.to_string()
+    };
+    if !pattern.starts_with('/') {
+        pattern = format!("**/{pattern}");
+    }
+    if pattern.ends_with('/') {
+        pattern += "**";
+    }
+    if case_insensitive {
+        return git_ignore::wildmatch(
+            pattern.to_lowercase().as_bytes(),
+            git_dir.to_lowercase().as_bytes(),
+        );
+    }
+    return git_ignore::wildmatch(pattern.as_bytes(), git_dir.as_bytes());
+}
+
+/// Shortens paths inside the working tree, so the repository's own files show
+/// up as `.git/config` like they do with git, which runs from the top of the
+/// working tree.
+fn relative_to_work_tree(repository: &Repository, path: &Path) -> String {
+    if let Some(root) = &repository.work_tree {
+        if let Ok(relative) = path.strip_prefix(root) {
+            return relative.display().to_string();
+        }
+    }
+    return path.display().to_string();
+}
+
+/// Expands `~/` to the home directory and makes relative paths relative to
+/// `base`, as done for include paths.
+fn resolve_path(path: &str, base: &Path) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Ok(home) = env::var("HOME") {
+            return Path::new(&home).join(rest);
+        }
+    }
+    return base.join(path);
+}
+
+/// Interprets a value the way git does for boolean options. A key without
+/// any value counts as true.
+pub fn parse_bool(value: Option<&str>, name: &str) -> Result<bool, Error> {
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(true),
+    };
+    return match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" | "" => Ok(false),
+        _ => match parse_int(value, name) {
+            Ok(number) => Ok(number != 0),
+            Err(_) => Err(format!("fatal: bad boolean config value '{value}' for '{name}'").into()),
+        },
+    };
+}
+
+/// Parses an integer with an optional `k`, `m` or `g` suffix.
+pub fn parse_int(value: &str, name: &str) -> Result<i64, Error> {
+    let invalid = |reason: &str| {
+        Error::from(format!(
+            "fatal: bad numeric config value '{value}' for '{name}': {reason}"
+        ))
+    };
+    let trimmed = value.trim();
+    let (digits, multiplier) = match trimmed.chars().last().map(|c| c.to_ascii_lowercase()) {
+        Some('k') => (&trimmed[..trimmed.len() - 1], 1 << 10),
+        Some('m') => (&trimmed[..trimmed.len() - 1], 1 << 20),
+        Some('g') => (&trimmed[..trimmed.len() - 1], 1 << 30),
+        Some(c) if c.is_ascii_digit() => (trimmed, 1),
+        _ => return Err(invalid("invalid unit")),
+    };
+    let number = digits.parse::<i64>().map_err(|_| invalid("invalid unit"))?;
+    return number
+        .checked_mul(multiplier)
+        .ok_or_else(|| invalid("out of range"));
+}
+
+/// Looks `name` up in the layered configuration of `repository`.
+pub fn get(repository: &Repository, name: &str) -> Result<Option<String>, Error> {
+    return repository.config()?.get(name);
+}
+
+pub fn get_bool(repository: &Repository, name: &str) -> Result<Option<bool>, Error> {
+    return repository.config()?.get_bool(name);
+}
+
+/// Splits a dotted config name such as `remote.origin.url` into its section,
+/// optional subsection and key. Section and key are case insensitive and get
+/// lowercased, the subsection is kept as is.
+pub fn split_name(name: &str) -> Result<(String, Option<String>, String), Error> {
+    let (section, rest) = name
+        .split_once('.')
+        .ok_or_else(|| format!("key does not contain a section: {name}"))?;
+    let (subsection, key) = match rest.rsplit_once('.') {
+        Some((subsection, key)) => (Some(subsection.to_string()), key),
+        None => (None, rest),
+    };
+    if section.is_empty() || key.is_empty() {
+        return Err(format!("invalid key: {name}").into());
+    }
+    return Ok((section.to_lowercase(), subsection, key.to_lowercase()));
+}
+
+pub fn parse(contents: &str, origin: &str) -> Result<Vec<ConfigEntry>, Error> {
+    let mut entries: Vec<ConfigEntry> = Vec::new();
+    let mut section: Option<(String, Option<String>)> = None;
+
+    let mut lines = contents.lines().enumerate();
+    while let Some((index, raw_line)) = lines.next() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let end = line
+                .find(']')
+                .ok_or_else(|| format!("bad config line {}: {raw_line}", index + 1))?;
+            section = Some(parse_section_header(&line[1..end], index + 1)?);
+            continue;
+        }
+
+        let (section_name, subsection) = section
+            .clone()
+            .ok_or_else(|| format!("bad config line {}: key outside of a section", index + 1))?;
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => {
+                let mut value = value.to_string();
+                // a trailing backslash continues the value on the next line
+                while value.trim_end().ends_with('\\') && !value.trim_end().ends_with("\\\\") {
+                    let trimmed = value.trim_end();
+                    value = trimmed[..trimmed.len() - 1].to_string();
+                    match lines.next() {
+                        Some((_, next)) => value += next,
+                        None => break,
+                    }
+                }
+                (key.trim(), Some(parse_value(&value, index + 1)?))
+            }
+            None => (line, None),
+        };
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(format!("bad config line {}: invalid key {key}", index + 1).into());
+        }
+
+        entries.push(ConfigEntry {
+            section: section_name,
+            subsection,
+            key: key.to_lowercase(),
+            value,
+            origin: origin.to_string(),
+        });
+    }
+    return Ok(entries);
+}
+
+fn parse_section_header(header: &str, line: usize) -> Result<(String, Option<String>), Error> {
+    let header = header.trim();
+    if let Some((name, rest)) = header.split_once(|c: char| c.is_whitespace()) {
+        let rest = rest.trim();
+        if !rest.starts_with('"') || !rest.ends_with('"') || rest.len() < 2 {
+            return Err(format!("bad config line {line}: malformed subsection").into());
+        }
+        let mut subsection = String::new();
+        let mut chars = rest[1..rest.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                if let Some(escaped) = chars.next() {
+                    subsection.push(escaped);
+                }
+            } else {
+                subsection.push(c);
+            }
+        }
+        return Ok((name.to_lowercase(), Some(subsection)));
+    }
+    // deprecated [section.subsection] syntax
+    return Ok(match header.split_once('.') {
+        Some((name, subsection)) => (name.to_lowercase(), Some(subsection.to_lowercase())),
+        None => (header.to_lowercase(), None),
+    });
+}
+
+fn parse_value(raw: &str, line: usize) -> Result<String, Error> {
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut pending_whitespace = String::new();
+    let mut chars = raw.trim_start().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => break,
+            '\\' => {
+                value += &pending_whitespace;
+                pending_whitespace.clear();
+                match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => {
+                        value.pop();
+                    }
+                    Some('\\') => value.push('\\'),
+                    Some('"') => value.push('"'),
+                    Some(other) => {
+                        return Err(
+                            format!("bad config line {line}: invalid escape \\{other}").into()
+                        )
+                    }
+                    None => {}
+                }
+                continue;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                pending_whitespace.push(c);
+                continue;
+            }
+            _ => {}
+        }
+        if c != '"' {
+            value += &pending_whitespace;
+            pending_whitespace.clear();
+            value.push(c);
+        }
+    }
+    if in_quotes {
+        return Err(format!("bad config line {line}: unterminated quote").into());
+    }
+    return Ok(value);
+}
+
+/// Reads a single config file without following includes. A missing file
+/// has no entries.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<ConfigEntry>, Error> {
+    let path = path.as_ref();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(format!("error reading config file {}: {err}", path.display()).into())
+        }
+    };
+    return parse(&contents, &path.to_string_lossy());
+}
+
+/// Sets `name` to `value` in the config file at `path`, replacing the last
+/// existing value or appending it to (or creating) the matching section.
+pub fn set<P: AsRef<Path>>(path: P, name: &str, value: &str) -> Result<(), Error> {
+    let (section, subsection, key) = split_name(name)?;
+    let mut lines = read_lines(path.as_ref())?;
+    let new_line = format!("\t{key} = {}", quote_value(value));
+    let (key_lines, last_section_line) = find_key(&lines, &section, &subsection, &key)?;
+
+    if let Some(&index) = key_lines.last() {
+        lines[index] = new_line;
+    } else if let Some(index) = last_section_line {
+        lines.insert(index + 1, new_line);
+    } else {
+        lines.push(section_header(&section, &subsection));
+        lines.push(new_line);
+    }
+
+    return write_lines(path.as_ref(), &lines);
+}
+
+/// Removes every line setting `name` from the config file at `path` and
+/// returns how many there were.
+pub fn unset<P: AsRef<Path>>(path: P, name: &str) -> Result<usize, Error> {
+    let (section, subsection, key) = split_name(name)?;
+    let mut lines = read_lines(path.as_ref())?;
+    let (key_lines, _) = find_key(&lines, &section, &subsection, &key)?;
+    for index in key_lines.iter().rev() {
+        lines.remove(*index);
+    }
+    // like git, drop the section header once nothing is left in it
+    if let Some(header) = key_lines.first().and_then(|first| {
+        lines[..*first]
+            .iter()
+            .rposition(|line| line.trim_start().starts_with('['))
+    }) {
+        let is_empty = lines[header + 1..]
+            .iter()
+            .take_while(|line| !line.trim_start().starts_with('['))
+            .all(|line| line.trim().is_empty());
+        if is_empty {
+            lines.remove(header);
+        }
+    }
+    if !key_lines.is_empty() {
+        write_lines(path.as_ref(), &lines)?;
+    }
+    return Ok(key_lines.len());
+}
+
+/// Renames the section `name` (e.g. `branch.main`) in the config file at
+/// `path` to `new_name`, or removes it along with its keys when `new_name`
+/// is `None`. Returns whether the section was there.
+pub fn rename_section<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    new_name: Option<&str>,
+) -> Result<bool, Error> {
+    let split = |name: &str| match name.split_once('.') {
+        Some((section, subsection)) => (section.to_lowercase(), Some(subsection.to_string())),
+        None => (name.to_lowercase(), None),
+    };
+    let section = split(name);
+    let mut lines = read_lines(path.as_ref())?;
+    let mut found = false;
+    let mut in_section = false;
+    let mut index = 0;
+    while index < lines.len() {
+        let trimmed = lines[index].trim();
+        if trimmed.starts_with('[') {
+            if let Some(end) = trimmed.find(']') {
+                in_section = parse_section_header(&trimmed[1..end], index + 1)? == section;
+                if in_section {
+                    found = true;
+                    if let Some(new_name) = new_name {
+                        let (new_section, new_subsection) = split(new_name);
+                        lines[index] = section_header(&new_section, &new_subsection);
+                    }
+                }
+            }
+        }
+        if in_section && new_name.is_none() {
+            lines.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+    if found {
+        write_lines(path.as_ref(), &lines)?;
+    }
+    return Ok(found);
+}
+
+fn section_header(section: &str, subsection: &Option<String>) -> String {
+    return match subsection {
+        Some(subsection) => format!(
+            "[{section} \"{}\"]",
+            subsection.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        None => format!("[{section}]"),
+    };
+}
+
+fn read_lines(path: &Path) -> Result<Vec<String>, Error> {
+    return match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents.lines().map(|line| line.to_string()).collect()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(Error::io("error reading config file", err)),
+    };
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> Result<(), Error> {
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    return fs::write(path, contents).map_err(|err| Error::io("error writing config file", err));
+}
+
+/// Finds the lines that set `key` in the given section, along with the last
+/// line belonging to that section so new keys can be added after it.
+fn find_key(
+    lines: &[String],
+    section: &str,
+    subsection: &Option<String>,
+    key: &str,
+) -> Result<(Vec<usize>, Option<usize>), Error> {
+    let mut current_section: Option<(String, Option<String>)> = None;
+    let mut key_lines: Vec<usize> = Vec::new();
+    let mut last_section_line: Option<usize> = None;
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if let Some(end) = trimmed.find(']') {
+                current_section = Some(parse_section_header(&trimmed[1..end], index + 1)?);
+            }
+        }
+        if current_section.as_ref() != Some(&(section.to_string(), subsection.clone())) {
+            continue;
+        }
+        last_section_line = Some(index);
+        if trimmed.starts_with('[') {
+            continue;
+        }
+        let line_key = trimmed.split('=').next().unwrap_or("").trim();
+        if line_key.eq_ignore_ascii_case(key) {
+            key_lines.push(index);
+        }
+    }
+    return Ok((key_lines, last_section_line));
+}
+
+fn quote_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    let needs_quotes = escaped.starts_with(' ')
+        || escaped.ends_with(' ')
+        || escaped.contains('#')
+        || escaped.contains(';');
+    return if needs_quotes {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    };
+}
@@ -0,0 +1,216 @@
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone,
+};
+
+use crate::error::Error;
+
+/// The current time as a unix timestamp and the local offset from UTC in
+/// minutes.
+pub fn now() -> (i64, i32) {
+    let now = Local::now();
+    return (now.timestamp(), now.offset().fix().local_minus_utc() / 60);
+}
+
+/// Formats a timestamp the way RFC 2822 and `--date=rfc` do, e.g.
+/// `Thu, 7 Apr 2005 15:13:13 -0700`.
+pub fn format_rfc2822(timestamp: i64, timezone_offset: i32) -> String {
+    let offset =
+        FixedOffset::east_opt(timezone_offset * 60).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    return match DateTime::from_timestamp(timestamp, 0) {
+        Some(time) => time
+            .with_timezone(&offset)
+            .format("%a, %-d %b %Y %H:%M:%S %z")
+            .to_string(),
+        None => timestamp.to_string(),
+    };
+}
+
+/// Formats a timestamp in one of the styles `--date=<mode>` selects:
+/// `default` (`Thu Apr 7 15:13:13 2005 -0700`), `iso`, `iso-strict`, `rfc`,
+/// `short`, `raw`, `unix` or `relative`.
+pub fn format(timestamp: i64, timezone_offset: i32, mode: &str) -> Result<String, Error> {
+    let offset =
+        FixedOffset::east_opt(timezone_offset * 60).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    let time = match DateTime::from_timestamp(timestamp, 0) {
+        Some(time) => time.with_timezone(&offset),
+        None => return Ok(timestamp.to_string()),
+    };
+    return Ok(match mode {
+        "default" => time.format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+        "iso" | "iso8601" => time.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        "iso-strict" | "iso8601-strict" => time.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        "rfc" | "rfc2822" => format_rfc2822(timestamp, timezone_offset),
+        "short" => time.format("%Y-%m-%d").to_string(),
+        "raw" => time.format("%s %z").to_string(),
+        "unix" => timestamp.to_string(),
+        "relative" => format_relative(timestamp, now().0),
+        _ => return Err(format!("fatal: unknown date format {mode}").into()),
+    });
+}
+
+/// How long before `now` a timestamp was, rounded the way git does:
+/// `5 minutes ago`, `3 weeks ago`, `1 year, 2 months ago`.
+fn format_relative(timestamp: i64, now: i64) -> String {
+    let plural = |count: i64, unit: &str| match count {
+        1 => format!("1 {unit}"),
+        count => format!("{count} {unit}s"),
+    };
+    if timestamp > now {
+        return "in the future".to_string();
+    }
+    let seconds = now - timestamp;
+    if seconds < 90 {
+        return format!("{} ago", plural(seconds, "second"));
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return format!("{} ago", plural(minutes, "minute"));
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return format!("{} ago", plural(hours, "hour"));
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return format!("{} ago", plural(days, "day"));
+    }
+    if days < 70 {
+        return format!("{} ago", plural((days + 3) / 7, "week"));
+    }
+    if days < 365 {
+        return format!("{} ago", plural((days + 15) / 30, "month"));
+    }
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months == 0 {
+            return format!("{} ago", plural(years, "year"));
+        }
+        return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+    }
+    return format!("{} ago", plural((days + 183) / 365, "year"));
+}
+
+/// Parses a date in one of the formats git accepts for `--date` and the
+/// `GIT_*_DATE` variables: git's internal `<unix> <zone>` form (optionally
+/// prefixed with `@`), RFC 2822, ISO 8601, or a relative date such as
+/// `yesterday` or `2 weeks ago`.
+pub fn parse(date: &str) -> Result<(i64, i32), Error> {
+    let date = date.trim();
+    let invalid = || Error::from(format!("fatal: invalid date format: {date}"));
+
+    if let Some(parsed) = parse_raw(date.strip_prefix('@').unwrap_or(date)) {
+        return Ok(parsed);
+    }
+    if let Ok(parsed) = DateTime::parse_from_rfc2822(date) {
+        return Ok((parsed.timestamp(), parsed.offset().local_minus_utc() / 60));
+    }
+    if let Some(parsed) = parse_iso8601(date) {
+        return Ok(parsed);
+    }
+    return parse_relative(date).ok_or_else(invalid);
+}
+
+/// `1112911993 +0200`, or just the timestamp which is then taken to be UTC.
+fn parse_raw(date: &str) -> Option<(i64, i32)> {
+    let mut parts = date.split_whitespace();
+    let timestamp = parts.next()?;
+    if !timestamp.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let timestamp = timestamp.parse::<i64>().ok()?;
+    let offset = match parts.next() {
+        Some(zone) => parse_zone(zone)?,
+        None => 0,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    return Some((timestamp, offset));
+}
+
+/// Parses `+0200`, `-05:30` or `Z` into minutes east of UTC.
+fn parse_zone(zone: &str) -> Option<i32> {
+    if zone == "Z" || zone == "z" {
+        return Some(0);
+    }
+    let sign = match zone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = zone[1..].replace(':', "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    return Some(sign * (hours * 60 + minutes));
+}
+
+/// `2005-04-07T22:13:13`, with an optional fractional part and zone, a space
+/// instead of the `T`, or no time at all (midnight). Dates without a zone are
+/// in local time.
+fn parse_iso8601(date: &str) -> Option<(i64, i32)> {
+    let day = NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()?;
+    let rest = date[10..].trim_start_matches(['T', ' ']);
+
+    let zone_start = rest.find(['+', '-', 'Z', 'z']).unwrap_or(rest.len());
+    let time_text = rest[..zone_start].trim();
+    let time = if time_text.is_empty() {
+        NaiveTime::MIN
+    } else {
+        NaiveTime::parse_from_str(time_text, "%H:%M:%S%.f")
+            .or_else(|_| NaiveTime::parse_from_str(time_text, "%H:%M"))
+            .ok()?
+    };
+    let local = NaiveDateTime::new(day, time);
+
+    let zone_text = rest[zone_start..].trim();
+    if zone_text.is_empty() {
+        let parsed = Local.from_local_datetime(&local).earliest()?;
+        return Some((
+            parsed.timestamp(),
+            parsed.offset().fix().local_minus_utc() / 60,
+        ));
+    }
+    let offset = parse_zone(zone_text)?;
+    let timestamp = local.and_utc().timestamp() - offset as i64 * 60;
+    return Some((timestamp, offset));
+}
+
+/// `now`, `yesterday` and `<n> <unit> ... ago`, where dots may separate the
+/// words as in `2.weeks.ago`.
+fn parse_relative(date: &str) -> Option<(i64, i32)> {
+    let normalized = date.to_lowercase().replace('.', " ");
+    let words: Vec<&str> = normalized.split_whitespace().collect();
+    let now = Local::now();
+    let offset = now.offset().fix().local_minus_utc() / 60;
+    match words.as_slice() {
+        ["now"] => return Some((now.timestamp(), offset)),
+        ["yesterday"] => return Some(((now - Duration::days(1)).timestamp(), offset)),
+        _ => {}
+    }
+
+    let (last, amounts) = words.split_last()?;
+    if *last != "ago" || amounts.is_empty() || amounts.len() % 2 != 0 {
+        return None;
+    }
+    let mut time = now;
+    for pair in amounts.chunks(2) {
+        let count: i64 = pair[0].parse().ok()?;
+        let unit = pair[1].trim_end_matches('s');
+        time = match unit {
+            "second" | "sec" => time - Duration::seconds(count),
+            "minute" | "min" => time - Duration::minutes(count),
+            "hour" => time - Duration::hours(count),
+            "day" => time - Duration::days(count),
+            "week" => time - Duration::weeks(count),
+            "month" => time.checked_sub_months(Months::new(count.try_into().ok()?))?,
+            "year" => time.checked_sub_months(Months::new((count * 12).try_into().ok()?))?,
+            _ => return None,
+        };
+    }
+    return Some((time.timestamp(), offset));
+}
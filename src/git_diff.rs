@@ -0,0 +1,1231 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::ErrorKind,
+};
+
+use crate::{
+    error::Error,
+    git,
+    git_attributes::{self, AttributeState},
+    git_config,
+    git_index::{self, IndexEntry},
+    git_object::{self, TreeNode},
+    git_rename::{self, Detection, RenameOptions},
+    git_status,
+    git_xdiff::{self, Algorithm, Hunk, LineOptions},
+    object_id::ObjectId,
+    repository::Repository,
+};
+
+/// How `diff` compares files and what it prints about them.
+#[derive(Clone, Debug)]
+pub struct DiffOptions {
+    /// Lines of context around each change in a patch.
+    pub context: usize,
+    pub patch: bool,
+    pub stat: bool,
+    pub numstat: bool,
+    /// Only the totals line of `stat`.
+    pub shortstat: bool,
+    /// Lines for created, deleted and renamed files and mode changes.
+    pub summary: bool,
+    pub name_only: bool,
+    pub name_status: bool,
+    pub raw: bool,
+    /// The algorithm asked for on the command line, over `diff.algorithm`.
+    pub algorithm: Option<Algorithm>,
+    /// Whether to slide changes by indentation, over `diff.indentHeuristic`.
+    pub indent_heuristic: Option<bool>,
+    pub ignore_space_change: bool,
+    pub ignore_all_space: bool,
+    pub ignore_blank_lines: bool,
+    /// What `-M`, `-C` and `--no-renames` asked for, over `diff.renames`.
+    pub detection: Option<Detection>,
+    /// The similarity `-M` or `-C` asked for, out of `git_rename::MAX_SCORE`.
+    pub rename_score: Option<u32>,
+    pub find_copies_harder: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        return DiffOptions {
+            context: 3,
+            patch: false,
+            stat: false,
+            numstat: false,
+            shortstat: false,
+            summary: false,
+            name_only: false,
+            name_status: false,
+            raw: false,
+            algorithm: None,
+            indent_heuristic: None,
+            ignore_space_change: false,
+            ignore_all_space: false,
+            ignore_blank_lines: false,
+            detection: None,
+            rename_score: None,
+            find_copies_harder: false,
+        };
+    }
+}
+
+impl DiffOptions {
+    /// Whether any format is asked for.
+    pub fn has_output(&self) -> bool {
+        return self.patch
+            || self.stat
+            || self.numstat
+            || self.shortstat
+            || self.summary
+            || self.name_only
+            || self.name_status
+            || self.raw;
+    }
+
+    /// How renames are detected, with what the command line leaves open
+    /// taken from the `diff` configuration.
+    pub fn rename_options(&self, repository: &Repository) -> Result<RenameOptions, Error> {
+        let mut options = RenameOptions::configured(repository, "diff")?;
+        if let Some(detection) = self.detection {
+            options.detection = detection;
+        }
+        if let Some(score) = self.rename_score {
+            options.min_score = score;
+        }
+        if self.find_copies_harder {
+            options.detection = Detection::Copies;
+            options.find_copies_harder = true;
+        }
+        return Ok(options);
+    }
+
+    /// How lines are compared, with what the command line leaves open
+    /// taken from the configuration. The indent heuristic is on by default.
+    fn line_options(&self, repository: &Repository) -> Result<LineOptions, Error> {
+        let algorithm = match self.algorithm {
+            Some(algorithm) => algorithm,
+            None => match git_config::get(repository, "diff.algorithm")? {
+                Some(name) => Algorithm::parse(&name).ok_or_else(|| {
+                    Error::from(format!(
+                        "fatal: unknown value for config 'diff.algorithm': {name}"
+                    ))
+                })?,
+                None => Algorithm::Myers,
+            },
+        };
+        let indent_heuristic = match self.indent_heuristic {
+            Some(indent_heuristic) => indent_heuristic,
+            None => git_config::get_bool(repository, "diff.indentHeuristic")?.unwrap_or(true),
+        };
+        return Ok(LineOptions {
+            algorithm,
+            ignore_space_change: self.ignore_space_change,
+            ignore_all_space: self.ignore_all_space,
+            ignore_blank_lines: self.ignore_blank_lines,
+            indent_heuristic,
+        });
+    }
+}
+
+/// One side of a changed path: its mode and blob. A null `id` stands for
+/// the working tree file, which is only read when its content is needed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DiffSide {
+    pub mode: u64,
+    pub id: ObjectId,
+}
+
+/// A path that differs between the two sides of a diff, with what it is on
+/// each: `None` where it doesn't exist.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FilePair {
+    pub path: String,
+    pub old: Option<DiffSide>,
+    pub new: Option<DiffSide>,
+    /// Where the file was renamed or copied from, if it was.
+    pub origin: Option<Origin>,
+    /// Whether the index has a conflict at the path, which is shown as a
+    /// `U` change of its own.
+    pub unmerged: bool,
+}
+
+/// The path a file was renamed or copied from, and how similar the two
+/// are, out of `git_rename::MAX_SCORE`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Origin {
+    pub path: String,
+    pub copy: bool,
+    pub score: u32,
+}
+
+impl Origin {
+    /// The similarity as a percentage, rounded down.
+    pub fn similarity(&self) -> u32 {
+        return self.score * 100 / git_rename::MAX_SCORE;
+    }
+}
+
+impl FilePair {
+    /// The path the old side is at, which is the origin's for a rename or
+    /// copy.
+    pub fn old_path(&self) -> &str {
+        return match &self.origin {
+            Some(origin) => &origin.path,
+            None => &self.path,
+        };
+    }
+
+    /// The letter `--name-status` and `--raw` show for the change.
+    pub fn status(&self) -> char {
+        if self.unmerged {
+            return 'U';
+        }
+        if let Some(origin) = &self.origin {
+            return if origin.copy { 'C' } else { 'R' };
+        }
+        return match (&self.old, &self.new) {
+            (None, _) => 'A',
+            (_, None) => 'D',
+            (Some(old), Some(new))
+                if git_status::file_kind(old.mode) != git_status::file_kind(new.mode) =>
+            {
+                'T'
+            }
+            _ => 'M',
+        };
+    }
+
+    /// The status with the similarity of a rename or copy, as `R086`.
+    fn status_with_score(&self) -> String {
+        return match &self.origin {
+            Some(origin) => format!("{}{:03}", self.status(), origin.similarity()),
+            None => self.status().to_string(),
+        };
+    }
+
+    /// The paths `--name-status` and `--raw` show: both for a rename or
+    /// copy, separated by a tab.
+    fn paths(&self) -> String {
+        return match &self.origin {
+            Some(origin) => format!("{}\t{}", origin.path, self.path),
+            None => self.path.clone(),
+        };
+    }
+
+    /// The name `--stat` and `--numstat` show, which for a rename or copy
+    /// is both paths around their common leading and trailing directories,
+    /// as `dir/{old => new}/file`, the way git's `pprint_rename` puts it.
+    fn display_name(&self) -> String {
+        let old = match &self.origin {
+            Some(origin) => origin.path.as_bytes(),
+            None => return self.path.clone(),
+        };
+        let new = self.path.as_bytes();
+        let mut prefix = 0;
+        for (at, (a, b)) in old.iter().zip(new).enumerate() {
+            if a != b {
+                break;
+            }
+            if *a == b'/' {
+                prefix = at + 1;
+            }
+        }
+        // the suffix may start at the slash that ends the prefix
+        let floor = prefix.saturating_sub(1);
+        let mut suffix = 0;
+        let (mut a, mut b) = (old.len(), new.len());
+        while a > floor && b > floor && old[a - 1] == new[b - 1] {
+            a -= 1;
+            b -= 1;
+            if old[a] == b'/' {
+                suffix = old.len() - a;
+            }
+        }
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+        let middle =
+            |path: &[u8]| text(&path[prefix.min(path.len() - suffix)..path.len() - suffix]);
+        if prefix + suffix == 0 {
+            return format!("{} => {}", text(old), text(new));
+        }
+        return format!(
+            "{}{{{} => {}}}{}",
+            text(&old[..prefix]),
+            middle(old),
+            middle(new),
+            text(&old[old.len() - suffix..])
+        );
+    }
+}
+
+/// Whether `path` is one of `paths` or below one of them. No paths means
+/// everything.
+fn in_paths(paths: &[String], path: &str) -> bool {
+    return paths.is_empty()
+        || paths.iter().any(|prefix| {
+            prefix.is_empty() || path == prefix || path.starts_with(&format!("{prefix}/"))
+        });
+}
+
+/// Whether anything below the directory `directory` can be in `paths`.
+fn directory_in_paths(paths: &[String], directory: &str) -> bool {
+    return in_paths(paths, directory)
+        || paths
+            .iter()
+            .any(|prefix| prefix.starts_with(&format!("{directory}/")));
+}
+
+/// Compares two trees, `None` being the empty tree. Subtrees with the same
+/// id on both sides are skipped without being read, unless `unchanged`
+/// asks for the files that stay the same as well.
+pub fn diff_trees(
+    repository: &Repository,
+    old: Option<&ObjectId>,
+    new: Option<&ObjectId>,
+    paths: &[String],
+    unchanged: bool,
+) -> Result<Vec<FilePair>, Error> {
+    let mut pairs = Vec::new();
+    diff_trees_into(repository, old, new, "", paths, unchanged, &mut pairs)?;
+    return Ok(pairs);
+}
+
+fn diff_trees_into(
+    repository: &Repository,
+    old: Option<&ObjectId>,
+    new: Option<&ObjectId>,
+    prefix: &str,
+    paths: &[String],
+    unchanged: bool,
+    pairs: &mut Vec<FilePair>,
+) -> Result<(), Error> {
+    let read = |tree: Option<&ObjectId>| -> Result<Vec<TreeNode>, Error> {
+        return Ok(match tree {
+            Some(tree) => git_object::read_tree_object(repository, tree)?.entries,
+            None => Vec::new(),
+        });
+    };
+    // trees sort as if their names ended in a slash
+    let key = |node: &TreeNode| match node.is_tree() {
+        true => format!("{}/", node.name),
+        false => node.name.clone(),
+    };
+    let mut old_entries = read(old)?.into_iter().peekable();
+    let mut new_entries = read(new)?.into_iter().peekable();
+    loop {
+        let order = match (old_entries.peek(), new_entries.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(old), Some(new)) => key(old).as_bytes().cmp(key(new).as_bytes()),
+        };
+        let (old, new) = match order {
+            Ordering::Less => (old_entries.next(), None),
+            Ordering::Greater => (None, new_entries.next()),
+            Ordering::Equal => (old_entries.next(), new_entries.next()),
+        };
+        if let (Some(old), Some(new)) = (&old, &new) {
+            if old.mode == new.mode && old.hash == new.hash && !unchanged {
+                continue;
+            }
+        }
+        let node = old.as_ref().or(new.as_ref()).unwrap();
+        let path = format!("{prefix}{}", node.name);
+        if node.is_tree() {
+            if directory_in_paths(paths, &path) {
+                diff_trees_into(
+                    repository,
+                    old.as_ref().map(|old| &old.hash),
+                    new.as_ref().map(|new| &new.hash),
+                    &format!("{path}/"),
+                    paths,
+                    unchanged,
+                    pairs,
+                )?;
+            }
+        } else if in_paths(paths, &path) {
+            let side = |node: &TreeNode| DiffSide {
+                mode: node.mode,
+                id: node.hash,
+            };
+            pairs.push(FilePair {
+                path,
+                old: old.as_ref().map(side),
+                new: new.as_ref().map(side),
+                origin: None,
+                unmerged: false,
+            });
+        }
+    }
+    return Ok(());
+}
+
+/// The files of a tree, `None` being the empty tree, that are in `paths`.
+fn tree_files(
+    repository: &Repository,
+    tree: Option<&ObjectId>,
+    paths: &[String],
+) -> Result<BTreeMap<String, DiffSide>, Error> {
+    let files = match tree {
+        Some(tree) => git::flatten_tree(repository, tree)?,
+        None => BTreeMap::new(),
+    };
+    return Ok(files
+        .into_iter()
+        .filter(|(path, _)| in_paths(paths, path))
+        .map(|(path, node)| {
+            (
+                path,
+                DiffSide {
+                    mode: node.mode,
+                    id: node.hash,
+                },
+            )
+        })
+        .collect());
+}
+
+/// What the index has at a path.
+enum IndexFile {
+    Merged(IndexEntry),
+    /// A conflict, with our side (stage 2) if there is one.
+    Unmerged(Option<IndexEntry>),
+}
+
+/// The index entries that are in `paths`, one per path.
+fn index_files(
+    repository: &Repository,
+    paths: &[String],
+) -> Result<BTreeMap<String, IndexFile>, Error> {
+    let mut files = BTreeMap::new();
+    for entry in git_index::read(repository)? {
+        if !in_paths(paths, &entry.path) {
+            continue;
+        }
+        if entry.stage == 0 {
+            files.insert(entry.path.clone(), IndexFile::Merged(entry));
+            continue;
+        }
+        let ours = files
+            .entry(entry.path.clone())
+            .or_insert(IndexFile::Unmerged(None));
+        if entry.stage == 2 {
+            *ours = IndexFile::Unmerged(Some(entry));
+        }
+    }
+    return Ok(files);
+}
+
+/// The side of an untracked file in the working tree, `None` if there is
+/// none, with a null id for the content to be read from the file.
+fn worktree_file(repository: &Repository, path: &str) -> Result<Option<DiffSide>, Error> {
+    return match fs::symlink_metadata(repository.work_path(path)) {
+        Ok(metadata) if metadata.is_dir() => Ok(None),
+        Ok(metadata) => Ok(Some(DiffSide {
+            mode: git_status::tree_mode_for(&metadata),
+            id: ObjectId::NULL,
+        })),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("error reading {path}: {err}").into()),
+    };
+}
+
+/// Compares a tree (`None` being the empty tree) with the index, as
+/// `diff --cached` does. With `unchanged`, files that are the same on both
+/// sides are listed too. A path with a conflict has no index side.
+pub fn diff_tree_to_index(
+    repository: &Repository,
+    tree: Option<&ObjectId>,
+    paths: &[String],
+    unchanged: bool,
+) -> Result<Vec<FilePair>, Error> {
+    let mut old_files = tree_files(repository, tree, paths)?;
+    let mut new_files = index_files(repository, paths)?;
+    let all_paths: BTreeSet<String> = old_files.keys().chain(new_files.keys()).cloned().collect();
+    let mut pairs = Vec::new();
+    for path in all_paths {
+        let old = old_files.remove(&path);
+        let (new, unmerged) = match new_files.remove(&path) {
+            Some(IndexFile::Merged(entry)) => {
+                let side = DiffSide {
+                    mode: entry.tree_mode(),
+                    id: entry.hash,
+                };
+                (Some(side), false)
+            }
+            Some(IndexFile::Unmerged(_)) => (None, true),
+            None => (None, false),
+        };
+        if old != new || unchanged || unmerged {
+            pairs.push(FilePair {
+                path,
+                old,
+                new,
+                origin: None,
+                unmerged,
+            });
+        }
+    }
+    return Ok(pairs);
+}
+
+/// What the working tree has at the path of an index entry: `None` if it's
+/// gone, the entry's own side if it still matches, and otherwise its mode
+/// with a null id, for the content to be read from the file.
+fn worktree_side(
+    repository: &Repository,
+    entry: &IndexEntry,
+    index_mtime: Option<(u32, u32)>,
+) -> Result<Option<DiffSide>, Error> {
+    let indexed = DiffSide {
+        mode: entry.tree_mode(),
+        id: entry.hash,
+    };
+    let metadata = match fs::symlink_metadata(repository.work_path(&entry.path)) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("error reading {}: {err}", entry.path).into()),
+    };
+    if entry.is_gitlink() {
+        let submodule = repository.work_path(&entry.path);
+        return Ok(Some(match git_object::submodule_head(&submodule) {
+            Ok(head) => DiffSide {
+                mode: git_object::GITLINK_MODE,
+                id: head,
+            },
+            // not checked out, which isn't a change
+            Err(_) => indexed,
+        }));
+    }
+    if metadata.is_dir() {
+        return Ok(None);
+    }
+    if entry.matches_worktree(repository, index_mtime)? {
+        return Ok(Some(indexed));
+    }
+    return Ok(Some(DiffSide {
+        mode: git_status::tree_mode_for(&metadata),
+        id: ObjectId::NULL,
+    }));
+}
+
+/// Compares the index with the working tree, as a plain `diff` does.
+/// Untracked files aren't part of it. With `unchanged`, files that are the
+/// same on both sides are listed too. A path with a conflict is listed as
+/// unmerged and then compared with our side of it, like `diff -2`.
+pub fn diff_index_to_worktree(
+    repository: &Repository,
+    paths: &[String],
+    unchanged: bool,
+) -> Result<Vec<FilePair>, Error> {
+    repository.work_tree()?;
+    let index_mtime = git_index::index_mtime(repository);
+    let mut pairs = Vec::new();
+    for (path, file) in index_files(repository, paths)? {
+        let entry = match file {
+            IndexFile::Merged(entry) => entry,
+            IndexFile::Unmerged(ours) => {
+                pairs.push(FilePair {
+                    path: path.clone(),
+                    old: None,
+                    new: worktree_file(repository, &path)?,
+                    origin: None,
+                    unmerged: true,
+                });
+                match ours {
+                    Some(ours) => ours,
+                    None => continue,
+                }
+            }
+        };
+        let old = DiffSide {
+            mode: entry.tree_mode(),
+            id: entry.hash,
+        };
+        let new = worktree_side(repository, &entry, index_mtime)?;
+        if new != Some(old) || unchanged {
+            pairs.push(FilePair {
+                path,
+                old: Some(old),
+                new,
+                origin: None,
+                unmerged: false,
+            });
+        }
+    }
+    return Ok(pairs);
+}
+
+/// Compares a tree (`None` being the empty tree) with the working tree, as
+/// `diff <commit>` does. The index decides which files are tracked. With
+/// `unchanged`, files that are the same on both sides are listed too. A
+/// path with a conflict is compared with whatever file it has.
+pub fn diff_tree_to_worktree(
+    repository: &Repository,
+    tree: Option<&ObjectId>,
+    paths: &[String],
+    unchanged: bool,
+) -> Result<Vec<FilePair>, Error> {
+    repository.work_tree()?;
+    let index_mtime = git_index::index_mtime(repository);
+    let mut old_files = tree_files(repository, tree, paths)?;
+    let mut index = index_files(repository, paths)?;
+    let all_paths: BTreeSet<String> = old_files.keys().chain(index.keys()).cloned().collect();
+    let mut pairs = Vec::new();
+    for path in all_paths {
+        let old = old_files.remove(&path);
+        let mut new = match index.remove(&path) {
+            Some(IndexFile::Merged(entry)) => worktree_side(repository, &entry, index_mtime)?,
+            Some(IndexFile::Unmerged(_)) => worktree_file(repository, &path)?,
+            None => None,
+        };
+        // a file changed since it was staged may be back to what the tree has
+        if let (Some(old), Some(changed)) = (old, new) {
+            if changed.id.is_null()
+                && changed.mode == old.mode
+                && git_object::hash_blob_from_file(repository, &path)? == old.id
+            {
+                new = Some(old);
+            }
+        }
+        if old != new || unchanged {
+            pairs.push(FilePair {
+                path,
+                old,
+                new,
+                origin: None,
+                unmerged: false,
+            });
+        }
+    }
+    return Ok(pairs);
+}
+
+/// The content of one side of a pair. Gitlinks read as the line a patch
+/// shows for them.
+pub fn read_side(repository: &Repository, path: &str, side: &DiffSide) -> Result<Vec<u8>, Error> {
+    if side.mode == git_object::GITLINK_MODE {
+        return Ok(format!("Subproject commit {}\n", side.id).into_bytes());
+    }
+    if side.id.is_null() {
+        return git_object::read_worktree_file(repository, path);
+    }
+    return Ok(git_object::read_blob(repository, &side.id)?.data);
+}
+
+/// Both sides of a pair read into memory, with the ids of their content.
+struct Contents {
+    old: Vec<u8>,
+    new: Vec<u8>,
+    old_id: ObjectId,
+    new_id: ObjectId,
+    binary: bool,
+}
+
+impl Contents {
+    fn read(repository: &Repository, pair: &FilePair) -> Result<Contents, Error> {
+        let read = |path: &str, side: &Option<DiffSide>| -> Result<(Vec<u8>, ObjectId), Error> {
+            return Ok(match side {
+                Some(side) => {
+                    let data = read_side(repository, path, side)?;
+                    let id = match side.id.is_null() {
+                        true => blob_id(&data),
+                        false => side.id,
+                    };
+                    (data, id)
+                }
+                None => (Vec::new(), ObjectId::NULL),
+            });
+        };
+        let (old, old_id) = read(pair.old_path(), &pair.old)?;
+        let (new, new_id) = read(&pair.path, &pair.new)?;
+        let binary = match binary_attribute(repository, &pair.path)? {
+            Some(binary) => binary,
+            None => git_attributes::is_binary(&old) || git_attributes::is_binary(&new),
+        };
+        return Ok(Contents {
+            old,
+            new,
+            old_id,
+            new_id,
+            binary,
+        });
+    }
+
+    /// The hunks of a patch with `context` lines of context, unless the
+    /// content is binary or the same.
+    fn hunks(&self, options: &LineOptions, context: usize) -> Vec<Hunk> {
+        if self.binary || self.old_id == self.new_id {
+            return Vec::new();
+        }
+        let old_lines = git_xdiff::split_lines(&self.old);
+        let new_lines = git_xdiff::split_lines(&self.new);
+        let changes = git_xdiff::diff(&old_lines, &new_lines, options);
+        return git_xdiff::hunks(&changes, old_lines.len(), new_lines.len(), context);
+    }
+}
+
+/// Whether the `diff` attribute makes `path` binary or text, if it is set.
+pub fn binary_attribute(repository: &Repository, path: &str) -> Result<Option<bool>, Error> {
+    return Ok(match repository.attributes()?.get(path, "diff")? {
+        AttributeState::Unset => Some(true),
+        AttributeState::Set => Some(false),
+        _ => None,
+    });
+}
+
+/// The id `data` has as a blob.
+fn blob_id(data: &[u8]) -> ObjectId {
+    let mut blob = format!("blob {}\0", data.len()).into_bytes();
+    blob.extend_from_slice(data);
+    return ObjectId::hash(&blob);
+}
+
+/// Prints `pairs` in the formats `options` ask for, in git's order: the
+/// per-file lines of `--raw`, `--name-status` or `--name-only`, then
+/// `--numstat` and `--stat`, and the patch last.
+pub fn format(
+    repository: &Repository,
+    pairs: &[FilePair],
+    options: &DiffOptions,
+) -> Result<String, Error> {
+    let line_options = options.line_options(repository)?;
+    let mut output = String::new();
+    let mut separate = false;
+    if options.raw || options.name_status || options.name_only {
+        for pair in pairs {
+            output += &if options.raw {
+                format_raw(repository, pair)?
+            } else if options.name_status {
+                format!("{}\t{}\n", pair.status_with_score(), pair.paths())
+            } else {
+                format!("{}\n", pair.path)
+            };
+        }
+        separate = true;
+    }
+    if options.numstat || options.stat || options.shortstat {
+        let mut counts = Vec::new();
+        for pair in pairs {
+            counts.push(count_changes(
+                repository,
+                pair,
+                &line_options,
+                options.context,
+            )?);
+        }
+        if options.numstat {
+            output += &format_numstat(pairs, &counts);
+        }
+        if options.stat {
+            output += &format_stat(pairs, &counts);
+        }
+        if options.shortstat {
+            output += &format_shortstat(pairs, &counts);
+        }
+        separate = true;
+    }
+    if options.summary {
+        let summary: String = pairs.iter().map(format_summary).collect();
+        separate |= !summary.is_empty();
+        output += &summary;
+    }
+    if options.patch && !pairs.is_empty() {
+        if separate {
+            output.push('\n');
+        }
+        for pair in pairs {
+            output += &format_patch(repository, pair, &line_options, options.context)?;
+        }
+    }
+    return Ok(output);
+}
+
+/// Whether any of `pairs` still differs once the whitespace and blank
+/// lines `options` ignore are left out, as `--exit-code` reports it.
+pub fn has_changes(
+    repository: &Repository,
+    pairs: &[FilePair],
+    options: &DiffOptions,
+) -> Result<bool, Error> {
+    let line_options = options.line_options(repository)?;
+    let ignores_lines = line_options.ignore_space_change
+        || line_options.ignore_all_space
+        || line_options.ignore_blank_lines;
+    for pair in pairs {
+        let content_only = match (&pair.old, &pair.new) {
+            (Some(old), Some(new)) => old.mode == new.mode,
+            _ => false,
+        };
+        if !ignores_lines || !content_only || pair.unmerged || pair.origin.is_some() {
+            return Ok(true);
+        }
+        let counts = count_changes(repository, pair, &line_options, options.context)?;
+        if counts.added + counts.deleted > 0 {
+            return Ok(true);
+        }
+    }
+    return Ok(false);
+}
+
+/// `:<old mode> <new mode> <old id> <new id> <status>\t<path>`, with ids
+/// abbreviated and zeros for a missing side or an unhashed working tree
+/// file.
+fn format_raw(repository: &Repository, pair: &FilePair) -> Result<String, Error> {
+    let mode = |side: &Option<DiffSide>| match side {
+        Some(side) => format!("{:06}", side.mode),
+        None => "000000".to_string(),
+    };
+    let id = |side: &Option<DiffSide>| match side {
+        Some(side) if !side.id.is_null() => git_object::abbreviate(repository, &side.id, 7),
+        _ => Ok("0".repeat(7)),
+    };
+    return Ok(format!(
+        ":{} {} {} {} {}\t{}\n",
+        mode(&pair.old),
+        mode(&pair.new),
+        id(&pair.old)?,
+        id(&pair.new)?,
+        pair.status_with_score(),
+        pair.paths()
+    ));
+}
+
+/// Lines added and deleted in a file, or its sizes before and after if it
+/// is binary. A file whose changes were all ignored isn't interesting.
+struct Counts {
+    added: usize,
+    deleted: usize,
+    binary: bool,
+    interesting: bool,
+}
+
+/// Counts the lines a patch would add and delete, which leaves out
+/// ignorable changes it has no hunk for.
+fn count_changes(
+    repository: &Repository,
+    pair: &FilePair,
+    options: &LineOptions,
+    context: usize,
+) -> Result<Counts, Error> {
+    if pair.unmerged {
+        return Ok(Counts {
+            added: 0,
+            deleted: 0,
+            binary: false,
+            interesting: true,
+        });
+    }
+    let contents = Contents::read(repository, pair)?;
+    if contents.binary {
+        let changed = contents.old_id != contents.new_id;
+        return Ok(Counts {
+            added: if changed { contents.new.len() } else { 0 },
+            deleted: if changed { contents.old.len() } else { 0 },
+            binary: true,
+            interesting: true,
+        });
+    }
+    let changes: Vec<_> = contents
+        .hunks(options, context)
+        .into_iter()
+        .flat_map(|hunk| hunk.changes)
+        .collect();
+    let added = changes.iter().map(|change| change.new_count).sum();
+    let deleted = changes.iter().map(|change| change.old_count).sum();
+    let ignored = added + deleted == 0
+        && contents.old_id != contents.new_id
+        && pair
+            .old
+            .is_some_and(|old| old.mode != git_object::GITLINK_MODE)
+        && pair.new.is_some();
+    return Ok(Counts {
+        added,
+        deleted,
+        binary: false,
+        interesting: !ignored,
+    });
+}
+
+fn format_numstat(pairs: &[FilePair], counts: &[Counts]) -> String {
+    let mut output = String::new();
+    for (pair, counts) in pairs.iter().zip(counts) {
+        if !counts.interesting {
+            continue;
+        }
+        output += &match counts.binary {
+            true => format!("-\t-\t{}\n", pair.display_name()),
+            false => format!(
+                "{}\t{}\t{}\n",
+                counts.added,
+                counts.deleted,
+                pair.display_name()
+            ),
+        };
+    }
+    return output;
+}
+
+/// The number of decimal digits in `number`.
+fn decimal_width(number: usize) -> usize {
+    return number.to_string().len();
+}
+
+/// `--stat`: a line per file with the number of changed lines and a graph
+/// of `+` and `-` scaled to fit 80 columns, then a summary, laid out the
+/// way git's `show_stats` does.
+fn format_stat(pairs: &[FilePair], counts: &[Counts]) -> String {
+    if pairs.is_empty() {
+        return String::new();
+    }
+    let width = 80;
+    let mut max_change = 0;
+    let mut max_len = 0;
+    let mut number_width = 0;
+    let mut bin_width = 0;
+    for (pair, counts) in pairs.iter().zip(counts) {
+        if !counts.interesting {
+            continue;
+        }
+        max_len = max_len.max(pair.display_name().chars().count());
+        if pair.unmerged {
+            continue;
+        }
+        if counts.binary {
+            bin_width =
+                bin_width.max(14 + decimal_width(counts.added) + decimal_width(counts.deleted));
+            number_width = 3;
+            continue;
+        }
+        max_change = max_change.max(counts.added + counts.deleted);
+    }
+    number_width = number_width.max(decimal_width(max_change));
+    let width = width.max(16 + 6 + number_width);
+
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    };
+    let mut name_width = max_len;
+    if name_width + number_width + 6 + graph_width > width {
+        if graph_width > (width * 3 / 8).saturating_sub(number_width + 6) {
+            graph_width = (width * 3 / 8).saturating_sub(number_width + 6).max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+    let scale = |count: usize| match count {
+        0 => 0,
+        count => 1 + count * (graph_width - 1) / max_change,
+    };
+
+    let mut output = String::new();
+    let (mut files, mut insertions, mut deletions) = (0, 0, 0);
+    for (pair, counts) in pairs.iter().zip(counts) {
+        if !counts.interesting {
+            continue;
+        }
+        if !pair.unmerged {
+            files += 1;
+        }
+        let display_name = pair.display_name();
+        let mut name: &str = &display_name;
+        let mut prefix = "";
+        let mut length = name_width;
+        if name_width < name.chars().count() {
+            prefix = "...";
+            length = length.saturating_sub(3);
+            let skip = name.chars().count() - length;
+            name = &name[name
+                .char_indices()
+                .nth(skip)
+                .map_or(name.len(), |(at, _)| at)..];
+            if let Some(slash) = name.find('/') {
+                name = &name[slash..];
+            }
+        }
+        let padding = length.saturating_sub(name.chars().count());
+        let name = format!("{prefix}{name}{}", " ".repeat(padding));
+
+        if pair.unmerged {
+            output += &format!(" {name} | Unmerged\n");
+            continue;
+        }
+        if counts.binary {
+            output += &format!(" {name} | {:>number_width$}", "Bin");
+            if counts.added == 0 && counts.deleted == 0 {
+                output.push('\n');
+            } else {
+                output += &format!(" {} -> {} bytes\n", counts.deleted, counts.added);
+            }
+            continue;
+        }
+        insertions += counts.added;
+        deletions += counts.deleted;
+        let (mut added, mut deleted) = (counts.added, counts.deleted);
+        if graph_width <= max_change {
+            let mut total = scale(added + deleted);
+            if total < 2 && added > 0 && deleted > 0 {
+                total = 2;
+            }
+            if added < deleted {
+                added = scale(added);
+                deleted = total - added;
+            } else {
+                deleted = scale(deleted);
+                added = total - deleted;
+            }
+        }
+        let changed = counts.added + counts.deleted;
+        output += &format!(
+            " {name} | {changed:>number_width$}{}{}{}\n",
+            if changed > 0 { " " } else { "" },
+            "+".repeat(added),
+            "-".repeat(deleted)
+        );
+    }
+
+    output += &format_totals(files, insertions, deletions);
+    return output;
+}
+
+/// `--shortstat`: only the totals line of `--stat`.
+fn format_shortstat(pairs: &[FilePair], counts: &[Counts]) -> String {
+    if pairs.is_empty() {
+        return String::new();
+    }
+    let (mut files, mut insertions, mut deletions) = (0, 0, 0);
+    for (pair, counts) in pairs.iter().zip(counts) {
+        if !counts.interesting || pair.unmerged {
+            continue;
+        }
+        files += 1;
+        if !counts.binary {
+            insertions += counts.added;
+            deletions += counts.deleted;
+        }
+    }
+    return format_totals(files, insertions, deletions);
+}
+
+/// The line ending `--stat`, as ` 2 files changed, 3 insertions(+)`.
+fn format_totals(files: usize, insertions: usize, deletions: usize) -> String {
+    let plural = |count: usize, one: &str, many: &str| match count {
+        1 => format!("{count} {one}"),
+        count => format!("{count} {many}"),
+    };
+    if files == 0 {
+        return " 0 files changed\n".to_string();
+    }
+    let mut output = format!(" {}", plural(files, "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        output += &format!(", {}", plural(insertions, "insertion(+)", "insertions(+)"));
+    }
+    if deletions > 0 || insertions == 0 {
+        output += &format!(", {}", plural(deletions, "deletion(-)", "deletions(-)"));
+    }
+    output.push('\n');
+    return output;
+}
+
+/// `--summary`: what happened to the file itself, such as
+/// ` create mode 100644 name`, ` rename a => b (90%)` or a mode change.
+fn format_summary(pair: &FilePair) -> String {
+    let mode_change = |show_name: bool| match (&pair.old, &pair.new) {
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            let name = match show_name {
+                true => format!(" {}", pair.path),
+                false => String::new(),
+            };
+            format!(" mode change {:06} => {:06}{name}\n", old.mode, new.mode)
+        }
+        _ => String::new(),
+    };
+    return match (pair.status(), &pair.old, &pair.new) {
+        ('A', _, Some(new)) => format!(" create mode {:06} {}\n", new.mode, pair.path),
+        ('D', Some(old), _) => format!(" delete mode {:06} {}\n", old.mode, pair.path),
+        ('R' | 'C', _, _) => format!(
+            " {} {} ({}%)\n{}",
+            if pair.status() == 'R' {
+                "rename"
+            } else {
+                "copy"
+            },
+            pair.display_name(),
+            pair.origin.as_ref().map_or(0, Origin::similarity),
+            mode_change(false)
+        ),
+        _ => mode_change(true),
+    };
+}
+
+/// The patch for one pair. A change between a file, a symlink and a
+/// submodule shows as the old one being deleted and the new one added, and
+/// a conflict only as a note that the path is unmerged.
+fn format_patch(
+    repository: &Repository,
+    pair: &FilePair,
+    options: &LineOptions,
+    context: usize,
+) -> Result<String, Error> {
+    if pair.unmerged {
+        return Ok(format!("* Unmerged path {}\n", pair.path));
+    }
+    if pair.status() == 'T' {
+        let deleted = FilePair {
+            new: None,
+            ..pair.clone()
+        };
+        let added = FilePair {
+            old: None,
+            ..pair.clone()
+        };
+        return Ok(format_patch(repository, &deleted, options, context)?
+            + &format_patch(repository, &added, options, context)?);
+    }
+
+    let contents = Contents::read(repository, pair)?;
+    let (old_path, path) = (pair.old_path(), &pair.path);
+    let mut output = format!("diff --git a/{old_path} b/{path}\n");
+    // with only the index line to say, the header is left out when all the
+    // changes turn out to be ignored
+    let mut must_show_header = true;
+    match (&pair.old, &pair.new) {
+        (None, Some(new)) => output += &format!("new file mode {:06}\n", new.mode),
+        (Some(old), None) => output += &format!("deleted file mode {:06}\n", old.mode),
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            output += &format!("old mode {:06}\nnew mode {:06}\n", old.mode, new.mode);
+        }
+        _ => must_show_header = false,
+    }
+    if let Some(origin) = &pair.origin {
+        let kind = if origin.copy { "copy" } else { "rename" };
+        output += &format!(
+            "similarity index {}%\n{kind} from {old_path}\n{kind} to {path}\n",
+            origin.similarity()
+        );
+        must_show_header = true;
+    }
+    if contents.old_id == contents.new_id {
+        return Ok(output);
+    }
+    let abbreviate = |id: &ObjectId| match id.is_null() {
+        true => Ok("0".repeat(7)),
+        false => git_object::abbreviate(repository, id, 7),
+    };
+    output += &format!(
+        "index {}..{}",
+        abbreviate(&contents.old_id)?,
+        abbreviate(&contents.new_id)?
+    );
+    match (&pair.old, &pair.new) {
+        (Some(old), Some(new)) if old.mode == new.mode => output += &format!(" {:06}\n", old.mode),
+        _ => output.push('\n'),
+    }
+
+    let old_name = match pair.old {
+        Some(_) => format!("a/{old_path}"),
+        None => "/dev/null".to_string(),
+    };
+    let new_name = match pair.new {
+        Some(_) => format!("b/{path}"),
+        None => "/dev/null".to_string(),
+    };
+    if contents.binary {
+        output += &format!("Binary files {old_name} and {new_name} differ\n");
+        return Ok(output);
+    }
+
+    let old_lines = git_xdiff::split_lines(&contents.old);
+    let new_lines = git_xdiff::split_lines(&contents.new);
+    let hunks = contents.hunks(options, context);
+    if hunks.is_empty() {
+        return Ok(match must_show_header {
+            true => output,
+            false => String::new(),
+        });
+    }
+    output += &format!("--- {old_name}\n+++ {new_name}\n");
+    let mut function = String::new();
+    let mut searched_to = None;
+    for hunk in hunks {
+        // the nearest line above the hunk that looks like the start of a
+        // function, if there is one since the previous hunk's
+        for line in (0..hunk.old_start).rev() {
+            if Some(line) == searched_to {
+                break;
+            }
+            if let Some(name) = function_name(old_lines[line]) {
+                function = name;
+                break;
+            }
+        }
+        searched_to = hunk.old_start.checked_sub(1);
+
+        let range = |start: usize, count: usize| match count {
+            0 => format!("{start},0"),
+            1 => format!("{}", start + 1),
+            count => format!("{},{count}", start + 1),
+        };
+        output += &format!(
+            "@@ -{} +{} @@",
+            range(hunk.old_start, hunk.old_count),
+            range(hunk.new_start, hunk.new_count)
+        );
+        if !function.is_empty() {
+            output += &format!(" {function}");
+        }
+        output.push('\n');
+
+        let line = |output: &mut String, marker: char, text: &[u8]| {
+            output.push(marker);
+            output.push_str(&String::from_utf8_lossy(text));
+            if !text.ends_with(b"\n") {
+                output.push_str("\n\\ No newline at end of file\n");
+            }
+        };
+        // context lines come from the new side, which matters when
+        // whitespace is ignored
+        let mut new = hunk.new_start;
+        for change in hunk.changes.iter() {
+            for context in &new_lines[new..change.new_start] {
+                line(&mut output, ' ', context);
+            }
+            for removed in &old_lines[change.old_start..change.old_start + change.old_count] {
+                line(&mut output, '-', removed);
+            }
+            for added in &new_lines[change.new_start..change.new_start + change.new_count] {
+                line(&mut output, '+', added);
+            }
+            new = change.new_start + change.new_count;
+        }
+        for context in &new_lines[new..hunk.new_start + hunk.new_count] {
+            line(&mut output, ' ', context);
+        }
+    }
+    return Ok(output);
+}
+
+/// The text a hunk header shows for `line` if it starts a function by
+/// git's default rule, which is that it starts with a letter, `_` or `$`:
+/// its first 80 bytes without trailing whitespace.
+fn function_name(line: &[u8]) -> Option<String> {
+    match line.first() {
+        Some(byte) if byte.is_ascii_alphabetic() || *byte == b'_' || *byte == b'$' => {}
+        _ => return None,
+    }
+    let mut line = &line[..line.len().min(80)];
+    while let Some((last, rest)) = line.split_last() {
+        if !last.is_ascii_whitespace() {
+            break;
+        }
+        line = rest;
+    }
+    return Some(String::from_utf8_lossy(line).to_string());
+}
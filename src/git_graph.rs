@@ -0,0 +1,537 @@
+use std::collections::HashSet;
+
+use crate::object_id::ObjectId;
+
+/// Where the graph is in drawing the rows around the current commit.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    /// Just the branch lines, between commits.
+    Padding,
+    /// A `...` row for commits that were never drawn.
+    Skip,
+    /// Rows making room before an octopus merge.
+    PreCommit,
+    Commit,
+    /// The row fanning a merge out to its parents.
+    PostMerge,
+    /// Rows moving branch lines left to close up gaps.
+    Collapsing,
+}
+
+/// Draws the ASCII history graph of `log --graph` a row at a time, the way
+/// git's `graph.c` does: each column is a line of history waiting for the
+/// commit it leads to, and `mapping` says which column each character cell
+/// of the next row leads into.
+pub struct Graph {
+    /// Commits that will be shown; lines to any other parent are dropped.
+    interesting: HashSet<ObjectId>,
+    commit: Option<ObjectId>,
+    parents: Vec<ObjectId>,
+    state: State,
+    prev_state: State,
+    /// Characters the rows for the current commit take up.
+    width: usize,
+    expansion_row: usize,
+    commit_index: usize,
+    prev_commit_index: usize,
+    /// How a merge's first parent line leaves it: 0 for `/`, 1 for `|`,
+    /// 2 for `\`.
+    merge_layout: isize,
+    edges_added: isize,
+    prev_edges_added: isize,
+    columns: Vec<ObjectId>,
+    new_columns: Vec<ObjectId>,
+    mapping: Vec<isize>,
+    old_mapping: Vec<isize>,
+    mapping_size: usize,
+}
+
+const MERGE_CHARS: [char; 3] = ['/', '|', '\\'];
+
+impl Graph {
+    pub fn new(interesting: HashSet<ObjectId>) -> Self {
+        return Graph {
+            interesting,
+            commit: None,
+            parents: Vec::new(),
+            state: State::Padding,
+            prev_state: State::Padding,
+            width: 0,
+            expansion_row: 0,
+            commit_index: 0,
+            prev_commit_index: 0,
+            merge_layout: 0,
+            edges_added: 0,
+            prev_edges_added: 0,
+            columns: Vec::new(),
+            new_columns: Vec::new(),
+            mapping: Vec::new(),
+            old_mapping: Vec::new(),
+            mapping_size: 0,
+        };
+    }
+
+    /// Moves on to drawing `commit`.
+    pub fn update(&mut self, commit: &ObjectId, parents: &[ObjectId]) {
+        self.commit = Some(*commit);
+        self.parents = parents
+            .iter()
+            .filter(|parent| self.interesting.contains(parent))
+            .copied()
+            .collect();
+        self.prev_commit_index = self.commit_index;
+        self.update_columns();
+        self.expansion_row = 0;
+
+        // the previous commit's rows weren't all drawn
+        self.state = if self.state != State::Padding {
+            State::Skip
+        } else if self.needs_pre_commit_line() {
+            State::PreCommit
+        } else {
+            State::Commit
+        };
+    }
+
+    /// Whether all rows for the current commit have been drawn.
+    pub fn is_commit_finished(&self) -> bool {
+        return self.state == State::Padding;
+    }
+
+    /// The rows up to and including the current commit's, which is left
+    /// unterminated for the commit's first line to follow.
+    pub fn show_commit(&mut self) -> String {
+        if self.is_commit_finished() {
+            return self.padding_line();
+        }
+        let mut output = String::new();
+        loop {
+            let (line, shown_commit) = self.next_line();
+            output += &line;
+            if shown_commit || self.is_commit_finished() {
+                return output;
+            }
+            output.push('\n');
+        }
+    }
+
+    /// `text` with the graph drawn before each of its lines but the first,
+    /// and then the rest of the current commit's rows.
+    pub fn show_message(&mut self, text: &str) -> String {
+        let mut output = String::new();
+        let mut lines = text.split_inclusive('\n').peekable();
+        while let Some(line) = lines.next() {
+            output += line;
+            if line.ends_with('\n') && lines.peek().is_some() {
+                output += &self.next_line().0;
+            }
+        }
+        if !self.is_commit_finished() {
+            let newline_terminated = text.ends_with('\n');
+            if !newline_terminated {
+                output.push('\n');
+            }
+            output += &self.remainder();
+            if newline_terminated {
+                output.push('\n');
+            }
+        }
+        return output;
+    }
+
+    /// The remaining rows for the current commit, newline separated.
+    pub fn remainder(&mut self) -> String {
+        let mut output = String::new();
+        while !self.is_commit_finished() {
+            output += &self.next_line().0;
+            if !self.is_commit_finished() {
+                output.push('\n');
+            }
+        }
+        return output;
+    }
+
+    /// The next row, and whether it was the commit's own.
+    pub fn next_line(&mut self) -> (String, bool) {
+        if self.commit.is_none() {
+            return (String::new(), false);
+        }
+        let mut line = String::new();
+        let shown_commit = self.state == State::Commit;
+        match self.state {
+            State::Padding => self.output_padding_line(&mut line),
+            State::Skip => self.output_skip_line(&mut line),
+            State::PreCommit => self.output_pre_commit_line(&mut line),
+            State::Commit => self.output_commit_line(&mut line),
+            State::PostMerge => self.output_post_merge_line(&mut line),
+            State::Collapsing => self.output_collapsing_line(&mut line),
+        }
+        self.pad(&mut line);
+        return (line, shown_commit);
+    }
+
+    /// A row that only continues the branch lines, for the space between
+    /// commits and within a commit's message.
+    pub fn padding_line(&mut self) -> String {
+        if self.state != State::Commit {
+            return self.next_line().0;
+        }
+        let mut line = String::new();
+        for column in self.columns.iter() {
+            line.push('|');
+            if Some(*column) == self.commit && self.parents.len() > 2 {
+                line += &" ".repeat((self.parents.len() - 2) * 2);
+            } else {
+                line.push(' ');
+            }
+        }
+        self.pad(&mut line);
+        self.prev_state = State::Padding;
+        return line;
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.prev_state = self.state;
+        self.state = state;
+    }
+
+    fn pad(&self, line: &mut String) {
+        let length = line.chars().count();
+        if length < self.width {
+            *line += &" ".repeat(self.width - length);
+        }
+    }
+
+    fn find_new_column(&self, commit: &ObjectId) -> Option<usize> {
+        return self.new_columns.iter().position(|column| column == commit);
+    }
+
+    fn needs_pre_commit_line(&self) -> bool {
+        return self.parents.len() >= 3
+            && self.commit_index + 1 < self.columns.len()
+            && (self.expansion_row as isize) < self.dashed_parents() * 2;
+    }
+
+    /// The parents of an octopus merge drawn with `-` after its `*`: all
+    /// but the two below it, or the one below and one to its left when the
+    /// merge is skewed left.
+    fn dashed_parents(&self) -> isize {
+        return self.parents.len() as isize + self.merge_layout - 3;
+    }
+
+    fn is_mapping_correct(&self) -> bool {
+        return (0..self.mapping_size).all(|i| {
+            let target = self.mapping[i];
+            target < 0 || target as usize == i / 2
+        });
+    }
+
+    fn update_columns(&mut self) {
+        self.columns = std::mem::take(&mut self.new_columns);
+        let max_new_columns = self.columns.len() + self.parents.len();
+        self.mapping_size = 2 * max_new_columns;
+        if self.mapping.len() < self.mapping_size {
+            self.mapping.resize(self.mapping_size, -1);
+            self.old_mapping.resize(self.mapping_size, -1);
+        }
+        for cell in self.mapping[..self.mapping_size].iter_mut() {
+            *cell = -1;
+        }
+        self.width = 0;
+        self.prev_edges_added = self.edges_added;
+        self.edges_added = 0;
+
+        let commit = self.commit.unwrap();
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column_commit = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                commit
+            } else {
+                self.columns[i]
+            };
+            if column_commit == commit {
+                seen_this = true;
+                self.commit_index = i;
+                self.merge_layout = -1;
+                for parent in self.parents.clone() {
+                    self.insert_into_new_columns(&parent, Some(i));
+                }
+                // the commit itself always takes up a column
+                if self.parents.is_empty() {
+                    self.width += 2;
+                }
+            } else {
+                self.insert_into_new_columns(&column_commit, None);
+            }
+        }
+
+        while self.mapping_size > 1 && self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+    }
+
+    /// Adds a line leading to `commit` to the next row, coming from the
+    /// current commit's column `commit_column` if it is one of its parents.
+    fn insert_into_new_columns(&mut self, commit: &ObjectId, commit_column: Option<usize>) {
+        let i = match self.find_new_column(commit) {
+            Some(i) => i,
+            None => {
+                self.new_columns.push(*commit);
+                self.new_columns.len() - 1
+            }
+        };
+
+        let mapping_index;
+        if self.parents.len() > 1 && commit_column.is_some() && self.merge_layout == -1 {
+            // lay out the merge by where its first parent's line goes
+            let distance = commit_column.unwrap() as isize - i as isize;
+            let shift = if distance > 1 { 2 * distance - 3 } else { 1 };
+            self.merge_layout = if distance > 0 { 0 } else { 1 };
+            self.edges_added = self.parents.len() as isize + self.merge_layout - 2;
+            mapping_index = self.width as isize + (self.merge_layout - 1) * shift;
+            self.width += 2 * self.merge_layout as usize;
+        } else if self.edges_added > 0
+            && self.width >= 2
+            && self.mapping[self.width - 2] == i as isize
+        {
+            // the line joins the one to its left straight away
+            mapping_index = self.width as isize - 2;
+            self.edges_added = -1;
+        } else {
+            mapping_index = self.width as isize;
+            self.width += 2;
+        }
+        self.mapping[mapping_index as usize] = i as isize;
+    }
+
+    fn output_padding_line(&mut self, line: &mut String) {
+        for _ in self.new_columns.iter() {
+            line.push_str("| ");
+        }
+    }
+
+    fn output_skip_line(&mut self, line: &mut String) {
+        line.push_str("...");
+        if self.needs_pre_commit_line() {
+            self.set_state(State::PreCommit);
+        } else {
+            self.set_state(State::Commit);
+        }
+    }
+
+    fn output_pre_commit_line(&mut self, line: &mut String) {
+        let mut seen_this = false;
+        for (i, column) in self.columns.iter().enumerate() {
+            if Some(*column) == self.commit {
+                seen_this = true;
+                line.push('|');
+                *line += &" ".repeat(self.expansion_row);
+            } else if seen_this && self.expansion_row == 0 {
+                if self.prev_state == State::PostMerge && self.prev_commit_index < i {
+                    line.push('\\');
+                } else {
+                    line.push('|');
+                }
+            } else if seen_this {
+                line.push('\\');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+        self.expansion_row += 1;
+        if !self.needs_pre_commit_line() {
+            self.set_state(State::Commit);
+        }
+    }
+
+    fn output_commit_line(&mut self, line: &mut String) {
+        let commit = self.commit.unwrap();
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column_commit = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                commit
+            } else {
+                self.columns[i]
+            };
+            if column_commit == commit {
+                seen_this = true;
+                line.push('*');
+                if self.parents.len() > 2 {
+                    self.draw_octopus_merge(line);
+                }
+            } else if seen_this && self.edges_added > 1 {
+                line.push('\\');
+            } else if seen_this && self.edges_added == 1 {
+                if self.prev_state == State::PostMerge
+                    && self.prev_edges_added > 0
+                    && self.prev_commit_index < i
+                {
+                    line.push('\\');
+                } else {
+                    line.push('|');
+                }
+            } else if self.prev_state == State::Collapsing
+                && self.old_mapping[2 * i + 1] == i as isize
+                && self.mapping[2 * i] < i as isize
+            {
+                line.push('/');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+
+        if self.parents.len() > 1 {
+            self.set_state(State::PostMerge);
+        } else if self.is_mapping_correct() {
+            self.set_state(State::Padding);
+        } else {
+            self.set_state(State::Collapsing);
+        }
+    }
+
+    /// The `-.` run after an octopus merge's `*`.
+    fn draw_octopus_merge(&self, line: &mut String) {
+        let dashed_parents = self.dashed_parents();
+        for i in 0..dashed_parents {
+            line.push('-');
+            line.push(if i == dashed_parents - 1 { '.' } else { '-' });
+        }
+    }
+
+    fn output_post_merge_line(&mut self, line: &mut String) {
+        let commit = self.commit.unwrap();
+        let first_parent = self.parents[0];
+        let mut parent_column_seen = false;
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column_commit = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                commit
+            } else {
+                self.columns[i]
+            };
+            if column_commit == commit {
+                seen_this = true;
+                let mut layout = self.merge_layout as usize;
+                for j in 0..self.parents.len() {
+                    line.push(MERGE_CHARS[layout]);
+                    if layout == 2 {
+                        if self.edges_added > 0 || j + 1 < self.parents.len() {
+                            line.push(' ');
+                        }
+                    } else {
+                        layout += 1;
+                    }
+                }
+                if self.edges_added == 0 {
+                    line.push(' ');
+                }
+            } else if seen_this {
+                line.push(if self.edges_added > 0 { '\\' } else { '|' });
+                line.push(' ');
+            } else {
+                line.push('|');
+                if self.merge_layout != 0 || i + 1 != self.commit_index {
+                    line.push(if parent_column_seen { '_' } else { ' ' });
+                }
+            }
+            if column_commit == first_parent {
+                parent_column_seen = true;
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.set_state(State::Padding);
+        } else {
+            self.set_state(State::Collapsing);
+        }
+    }
+
+    fn output_collapsing_line(&mut self, line: &mut String) {
+        let mut used_horizontal = false;
+        let mut horizontal_edge: isize = -1;
+        let mut horizontal_edge_target: isize = -1;
+
+        std::mem::swap(&mut self.mapping, &mut self.old_mapping);
+        for cell in self.mapping[..self.mapping_size].iter_mut() {
+            *cell = -1;
+        }
+
+        for i in 0..self.mapping_size {
+            let target = self.old_mapping[i];
+            if target < 0 {
+                continue;
+            }
+            // lines only ever move left
+            if target * 2 == i as isize {
+                self.mapping[i] = target;
+            } else if self.mapping[i - 1] < 0 {
+                // nothing to the left: move one place over
+                self.mapping[i - 1] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge = i as isize;
+                    horizontal_edge_target = target;
+                    let mut j = target * 2 + 3;
+                    while j < i as isize - 2 {
+                        self.mapping[j as usize] = target;
+                        j += 2;
+                    }
+                }
+            } else if self.mapping[i - 1] == target {
+                // joins the line to its left, which leads to the same commit
+            } else {
+                // crosses over the line to its left
+                self.mapping[i - 2] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge_target = target;
+                    horizontal_edge = i as isize - 1;
+                    let mut j = target * 2 + 3;
+                    while j < i as isize - 2 {
+                        self.mapping[j as usize] = target;
+                        j += 2;
+                    }
+                }
+            }
+        }
+
+        self.old_mapping[..self.mapping_size].copy_from_slice(&self.mapping[..self.mapping_size]);
+        if self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+
+        for i in 0..self.mapping_size {
+            let target = self.mapping[i];
+            if target < 0 {
+                line.push(' ');
+            } else if target * 2 == i as isize {
+                line.push('|');
+            } else if target == horizontal_edge_target && i as isize != horizontal_edge - 1 {
+                // only the first segment continues into the next row
+                if i as isize != target * 2 + 3 {
+                    self.mapping[i] = -1;
+                }
+                used_horizontal = true;
+                line.push('_');
+            } else {
+                if used_horizontal && (i as isize) < horizontal_edge {
+                    self.mapping[i] = -1;
+                }
+                line.push('/');
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.set_state(State::Padding);
+        }
+    }
+}
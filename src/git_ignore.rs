@@ -0,0 +1,390 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::Error, git_config, repository::Repository};
+
+pub struct Pattern {
+    /// The line as written in the ignore file, used by `check-ignore -v`.
+    pub original: String,
+    pub source: String,
+    pub line_number: usize,
+    pattern: String,
+    negated: bool,
+    directory_only: bool,
+    anchored: bool,
+    /// Directory the ignore file lives in, relative to the working tree root
+    /// and with a trailing slash unless it is the root itself.
+    base: String,
+}
+
+impl Pattern {
+    pub fn is_negated(&self) -> bool {
+        return self.negated;
+    }
+
+    fn parse(line: &str, source: &str, line_number: usize, base: &str) -> Option<Self> {
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let original = line.to_string();
+        let mut pattern = trim_unescaped_trailing_spaces(line);
+
+        let negated = pattern.starts_with('!');
+        // a leading `!` or an escaped `\!`/`\#` is dropped from the glob
+        if negated || pattern.starts_with("\\!") || pattern.starts_with("\\#") {
+            pattern = pattern[1..].to_string();
+        }
+
+        let directory_only = pattern.ends_with('/');
+        if directory_only {
+            pattern.pop();
+        }
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let anchored = pattern.contains('/');
+        if let Some(stripped) = pattern.strip_prefix('/') {
+            pattern = stripped.to_string();
+        }
+
+        return Some(Pattern {
+            original,
+            source: source.to_string(),
+            line_number,
+            pattern,
+            negated,
+            directory_only,
+            anchored,
+            base: base.to_string(),
+        });
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let relative = match path.strip_prefix(&self.base) {
+            Some(relative) => relative,
+            None => return false,
+        };
+        if self.anchored {
+            return wildmatch(self.pattern.as_bytes(), relative.as_bytes());
+        }
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        return wildmatch(self.pattern.as_bytes(), name.as_bytes());
+    }
+}
+
+fn trim_unescaped_trailing_spaces(line: &str) -> String {
+    let mut result = line.to_string();
+    while result.ends_with(' ') && !result.ends_with("\\ ") {
+        result.pop();
+    }
+    if result.ends_with("\\ ") {
+        result.remove(result.len() - 2);
+    }
+    return result;
+}
+
+/// The ignore patterns that apply to the working tree, in increasing order of
+/// precedence: `core.excludesFile`, `.git/info/exclude` and then every
+/// `.gitignore` from the root downwards. Per-directory files are loaded on
+/// demand as paths below them are checked.
+pub struct IgnoreRules {
+    patterns: Vec<Pattern>,
+    loaded_directories: HashSet<String>,
+    /// The working tree `.gitignore` files are read from, if any.
+    root: Option<PathBuf>,
+}
+
+impl IgnoreRules {
+    /// Rules that ignore nothing, for when ignore files should be bypassed.
+    pub fn empty() -> Self {
+        return IgnoreRules {
+            patterns: Vec::new(),
+            loaded_directories: HashSet::new(),
+            root: None,
+        };
+    }
+
+    pub fn load(repository: &Repository) -> Result<Self, Error> {
+        let mut rules = IgnoreRules {
+            patterns: Vec::new(),
+            loaded_directories: HashSet::new(),
+            root: Some(repository.work_tree()?.to_path_buf()),
+        };
+        if let Some(excludes_file) = excludes_file(repository)? {
+            rules.load_file(Path::new(&excludes_file), &excludes_file, "")?;
+        }
+        rules.load_file(
+            &repository.git_path("info/exclude"),
+            ".git/info/exclude",
+            "",
+        )?;
+        rules.load_directory("")?;
+        return Ok(rules);
+    }
+
+    fn load_directory(&mut self, directory: &str) -> Result<(), Error> {
+        let root = match &self.root {
+            Some(root) => root.clone(),
+            None => return Ok(()),
+        };
+        if !self.loaded_directories.insert(directory.to_string()) {
+            return Ok(());
+        }
+        let (source, base) = if directory.is_empty() {
+            (".gitignore".to_string(), String::new())
+        } else {
+            (format!("{directory}/.gitignore"), format!("{directory}/"))
+        };
+        return self.load_file(&root.join(&source), &source, &base);
+    }
+
+    /// Adds the patterns in the file at `path`, reported as coming from
+    /// `source`.
+    fn load_file(&mut self, path: &Path, source: &str, base: &str) -> Result<(), Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(Error::io(format!("error reading {source}"), err)),
+        };
+        for (index, line) in contents.lines().enumerate() {
+            if let Some(pattern) = Pattern::parse(line, source, index + 1, base) {
+                self.patterns.push(pattern);
+            }
+        }
+        return Ok(());
+    }
+
+    /// Finds the pattern deciding whether `path` is ignored, which may be a
+    /// negated one. Paths inside an ignored directory are reported with the
+    /// pattern that excluded the directory, since git never looks inside it.
+    pub fn matching_pattern(
+        &mut self,
+        path: &str,
+        is_dir: bool,
+    ) -> Result<Option<&Pattern>, Error> {
+        let mut end = 0;
+        while let Some(offset) = path[end..].find('/') {
+            let directory = &path[..end + offset];
+            self.load_directory(&path[..end.saturating_sub(1)])?;
+            if let Some(index) = self.last_match(directory, true) {
+                if !self.patterns[index].negated {
+                    return Ok(Some(&self.patterns[index]));
+                }
+            }
+            end += offset + 1;
+        }
+        self.load_directory(&path[..end.saturating_sub(1)])?;
+        return Ok(self
+            .last_match(path, is_dir)
+            .map(|index| &self.patterns[index]));
+    }
+
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> Result<bool, Error> {
+        return Ok(self
+            .matching_pattern(path, is_dir)?
+            .is_some_and(|pattern| !pattern.negated));
+    }
+
+    fn last_match(&self, path: &str, is_dir: bool) -> Option<usize> {
+        return self
+            .patterns
+            .iter()
+            .rposition(|pattern| pattern.matches(path, is_dir));
+    }
+}
+
+fn excludes_file(repository: &Repository) -> Result<Option<String>, Error> {
+    let configured = git_config::get(repository, "core.excludesfile")?;
+    if let Some(path) = configured {
+        return Ok(Some(expand_home(&path)));
+    }
+    let default = match env::var("XDG_CONFIG_HOME") {
+        Ok(config_home) if !config_home.is_empty() => format!("{config_home}/git/ignore"),
+        _ => match env::var("HOME") {
+            Ok(home) => format!("{home}/.config/git/ignore"),
+            Err(_) => return Ok(None),
+        },
+    };
+    return Ok(Path::new(&default).exists().then_some(default));
+}
+
+fn expand_home(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Ok(home) = env::var("HOME") {
+            return format!("{home}/{rest}");
+        }
+    }
+    return path.to_string();
+}
+
+/// Matches `text` against a glob `pattern` with git's wildmatch semantics:
+/// `*` and `?` don't cross `/`, while `**` between slashes (or at either end)
+/// matches any number of directories.
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    return wildmatch_from(pattern, 0, text);
+}
+
+/// Like [`wildmatch`], but with `/` an ordinary character that `*` and `?`
+/// match too, the way ref names are matched in `git tag -l`.
+pub fn wildmatch_across_slashes(pattern: &[u8], text: &[u8]) -> bool {
+    // NUL can't appear in either, so it stands in for `/` without the
+    // directory semantics
+    let hide_slashes = |bytes: &[u8]| -> Vec<u8> {
+        bytes
+            .iter()
+            .map(|byte| if *byte == b'/' { 0 } else { *byte })
+            .collect()
+    };
+    return wildmatch(&hide_slashes(pattern), &hide_slashes(text));
+}
+
+fn wildmatch_from(pattern: &[u8], start: usize, text: &[u8]) -> bool {
+    let mut p = start;
+    let mut t = 0;
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' if pattern.get(p + 1) == Some(&b'*')
+                && (p == 0 || pattern[p - 1] == b'/')
+                && (p + 2 == pattern.len() || pattern[p + 2] == b'/') =>
+            {
+                if p + 2 == pattern.len() {
+                    return true;
+                }
+                // `**/` matches zero or more leading directories
+                let rest = p + 3;
+                if wildmatch_from(pattern, rest, &text[t..]) {
+                    return true;
+                }
+                for (offset, byte) in text[t..].iter().enumerate() {
+                    if *byte == b'/' && wildmatch_from(pattern, rest, &text[t + offset + 1..]) {
+                        return true;
+                    }
+                }
+                return false;
+            }
+            b'*' => {
+                let mut rest = p + 1;
+                while pattern.get(rest) == Some(&b'*') {
+                    rest += 1;
+                }
+                for end in t..=text.len() {
+                    if wildmatch_from(pattern, rest, &text[end..]) {
+                        return true;
+                    }
+                    if end < text.len() && text[end] == b'/' {
+                        break;
+                    }
+                }
+                return false;
+            }
+            b'?' => {
+                if t >= text.len() || text[t] == b'/' {
+                    return false;
+                }
+                p += 1;
+                t += 1;
+            }
+            b'[' => {
+                if t >= text.len() || text[t] == b'/' {
+                    return false;
+                }
+                match match_class(pattern, p, text[t]) {
+                    Some((matched, next)) => {
+                        if !matched {
+                            return false;
+                        }
+                        p = next;
+                        t += 1;
+                    }
+                    // an unterminated class is matched literally
+                    None => {
+                        if text[t] != b'[' {
+                            return false;
+                        }
+                        p += 1;
+                        t += 1;
+                    }
+                }
+            }
+            b'\\' if p + 1 < pattern.len() => {
+                if t >= text.len() || text[t] != pattern[p + 1] {
+                    return false;
+                }
+                p += 2;
+                t += 1;
+            }
+            literal => {
+                if t >= text.len() || text[t] != literal {
+                    return false;
+                }
+                p += 1;
+                t += 1;
+            }
+        }
+    }
+    return t == text.len();
+}
+
+/// Matches `byte` against the bracket expression starting at `start`,
+/// returning whether it matched and the index just past the closing `]`.
+fn match_class(pattern: &[u8], start: usize, byte: u8) -> Option<(bool, usize)> {
+    let mut p = start + 1;
+    let negated = matches!(pattern.get(p), Some(b'!') | Some(b'^'));
+    if negated {
+        p += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let current = *pattern.get(p)?;
+        if current == b']' && !first {
+            break;
+        }
+        first = false;
+
+        if current == b'[' && pattern.get(p + 1) == Some(&b':') {
+            let end = pattern[p + 2..].windows(2).position(|w| w == b":]")? + p + 2;
+            let class = &pattern[p + 2..end];
+            matched |= match class {
+                b"alnum" => byte.is_ascii_alphanumeric(),
+                b"alpha" => byte.is_ascii_alphabetic(),
+                b"digit" => byte.is_ascii_digit(),
+                b"lower" => byte.is_ascii_lowercase(),
+                b"upper" => byte.is_ascii_uppercase(),
+                b"space" => byte.is_ascii_whitespace(),
+                b"punct" => byte.is_ascii_punctuation(),
+                b"xdigit" => byte.is_ascii_hexdigit(),
+                _ => false,
+            };
+            p = end + 2;
+            continue;
+        }
+
+        let mut low = current;
+        if low == b'\\' {
+            p += 1;
+            low = *pattern.get(p)?;
+        }
+        if pattern.get(p + 1) == Some(&b'-') && pattern.get(p + 2).is_some_and(|c| *c != b']') {
+            let mut high = pattern[p + 2];
+            p += 2;
+            if high == b'\\' {
+                p += 1;
+                high = *pattern.get(p)?;
+            }
+            matched |= low <= byte && byte <= high;
+        } else {
+            matched |= low == byte;
+        }
+        p += 1;
+    }
+    return Some((matched != negated, p + 1));
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, Metadata},
+    io::ErrorKind,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+};
+
+use crate::{
+    error::Error,
+    git_object::{self, Tree, TreeNode},
+    lock_file::LockFile,
+    object_id::ObjectId,
+    reader_utils,
+    repository::Repository,
+};
+
+const SIGNATURE: &[u8] = b"DIRC";
+/// Set in an entry's flags when a second, version 3 flags field follows.
+const EXTENDED_FLAG: u16 = 0x4000;
+
+pub struct IndexEntry {
+    pub ctime_seconds: u32,
+    pub ctime_nanoseconds: u32,
+    pub mtime_seconds: u32,
+    pub mtime_nanoseconds: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub hash: ObjectId,
+    pub stage: u8,
+    /// The version 3 flags, such as skip-worktree and intent-to-add, kept
+    /// as read so that writing the index doesn't lose them.
+    pub extended_flags: u16,
+    pub path: String,
+}
+
+impl IndexEntry {
+    /// Creates an entry for `path` whose stat data is taken from `metadata`.
+    pub fn from_metadata(path: &str, hash: &ObjectId, mode: u32, metadata: &Metadata) -> Self {
+        return IndexEntry {
+            ctime_seconds: metadata.ctime() as u32,
+            ctime_nanoseconds: metadata.ctime_nsec() as u32,
+            mtime_seconds: metadata.mtime() as u32,
+            mtime_nanoseconds: metadata.mtime_nsec() as u32,
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            mode,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+            hash: *hash,
+            stage: 0,
+            extended_flags: 0,
+            path: path.to_string(),
+        };
+    }
+
+    /// Creates an entry without any stat data, which will always be
+    /// re-hashed when compared against the working tree.
+    pub fn without_stat(path: &str, hash: &ObjectId, mode: u32) -> Self {
+        return IndexEntry {
+            ctime_seconds: 0,
+            ctime_nanoseconds: 0,
+            mtime_seconds: 0,
+            mtime_nanoseconds: 0,
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            hash: *hash,
+            stage: 0,
+            extended_flags: 0,
+            path: path.to_string(),
+        };
+    }
+
+    /// The mode as written in tree objects, e.g. `100644`.
+    pub fn tree_mode(&self) -> u64 {
+        return format!("{:o}", self.mode).parse().unwrap_or(0);
+    }
+
+    pub fn is_gitlink(&self) -> bool {
+        return self.mode == 0o160000;
+    }
+
+    /// Whether the working tree file still has the content recorded in the
+    /// index. Missing files and submodules count as unchanged, since checking
+    /// out over them loses nothing.
+    pub fn matches_worktree(
+        &self,
+        repository: &Repository,
+        index_mtime: Option<(u32, u32)>,
+    ) -> Result<bool, Error> {
+        if self.is_gitlink() {
+            return Ok(true);
+        }
+        let metadata = match fs::symlink_metadata(repository.work_path(&self.path)) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(true),
+            Err(err) => return Err(format!("error reading {}: {err}", self.path).into()),
+        };
+        if mode_for_metadata(&metadata) != self.mode {
+            return Ok(false);
+        }
+        if self.stat_matches(&metadata, index_mtime) {
+            return Ok(true);
+        }
+        return Ok(git_object::hash_blob_from_file(repository, &self.path)? == self.hash);
+    }
+
+    /// Whether the stat data recorded for this entry still matches the file,
+    /// meaning its content can be assumed unchanged without hashing it.
+    /// Entries modified in the same second the index was written are never
+    /// trusted, since a later change in that second wouldn't show up in mtime.
+    pub fn stat_matches(&self, metadata: &Metadata, index_mtime: Option<(u32, u32)>) -> bool {
+        if self.mtime_seconds == 0 && self.size == 0 && self.ino == 0 {
+            return false;
+        }
+        let racy = index_mtime.is_some_and(|(seconds, nanoseconds)| {
+            (self.mtime_seconds, self.mtime_nanoseconds) >= (seconds, nanoseconds)
+        });
+        return !racy
+            && self.mtime_seconds == metadata.mtime() as u32
+            && self.mtime_nanoseconds == metadata.mtime_nsec() as u32
+            && self.ctime_seconds == metadata.ctime() as u32
+            && self.ctime_nanoseconds == metadata.ctime_nsec() as u32
+            && self.ino == metadata.ino() as u32
+            && self.size == metadata.size() as u32
+            && self.mode == mode_for_metadata(metadata);
+    }
+}
+
+/// The index mode git would record for a file with this metadata.
+pub fn mode_for_metadata(metadata: &Metadata) -> u32 {
+    if metadata.file_type().is_symlink() {
+        return 0o120000;
+    }
+    if metadata.is_dir() {
+        return 0o160000;
+    }
+    if metadata.permissions().mode() & 0o111 != 0 {
+        return 0o100755;
+    }
+    return 0o100644;
+}
+
+/// Converts a tree mode such as `100644` into its index representation.
+pub fn mode_from_tree_mode(tree_mode: u64) -> u32 {
+    return u32::from_str_radix(&tree_mode.to_string(), 8).unwrap_or(0o100644);
+}
+
+/// Reads `.git/index`, returning an empty index if it doesn't exist yet.
+pub fn read(repository: &Repository) -> Result<Vec<IndexEntry>, Error> {
+    let mut data = match fs::read(repository.git_path("index")) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::io("error reading index", err)),
+    };
+    if data.len() < 32 || &data[..4] != SIGNATURE {
+        return Err("index file has an invalid signature".into());
+    }
+    let checksum = data.split_off(data.len() - 20);
+    if checksum != git_object::hash_data(&data) {
+        return Err("index file is corrupt: checksum mismatch".into());
+    }
+
+    let mut buffer = &data[4..];
+    let version = read_u32(&mut buffer)?;
+    if version != 2 && version != 3 {
+        return Err(format!("unsupported index version {version}").into());
+    }
+    let entry_count = read_u32(&mut buffer)?;
+
+    let mut entries = Vec::new();
+    for _ in 0..entry_count {
+        let start_length = buffer.len();
+        let mut stat = [0u32; 10];
+        for value in stat.iter_mut() {
+            *value = read_u32(&mut buffer)?;
+        }
+        let hash = ObjectId::from_bytes(&reader_utils::read_n_bytes(20, &mut buffer)?)?;
+        let flags = u16::from_be_bytes(
+            reader_utils::read_n_bytes(2, &mut buffer)?
+                .try_into()
+                .unwrap(),
+        );
+        let extended_flags = match version == 3 && flags & EXTENDED_FLAG != 0 {
+            true => u16::from_be_bytes(
+                reader_utils::read_n_bytes(2, &mut buffer)?
+                    .try_into()
+                    .unwrap(),
+            ),
+            false => 0,
+        };
+        let path = reader_utils::read_to_next_null_byte(&mut buffer)?;
+        // entries are padded with null bytes to a multiple of eight
+        let entry_length = start_length - buffer.len();
+        let padding = (8 - entry_length % 8) % 8;
+        reader_utils::read_n_bytes(padding, &mut buffer)?;
+
+        entries.push(IndexEntry {
+            ctime_seconds: stat[0],
+            ctime_nanoseconds: stat[1],
+            mtime_seconds: stat[2],
+            mtime_nanoseconds: stat[3],
+            dev: stat[4],
+            ino: stat[5],
+            mode: stat[6],
+            uid: stat[7],
+            gid: stat[8],
+            size: stat[9],
+            hash,
+            stage: ((flags >> 12) & 0b11) as u8,
+            extended_flags,
+            path,
+        });
+    }
+    // extensions whose signature starts with a capital letter are caches
+    // that can be dropped; any other one changes what the entries mean
+    while buffer.len() >= 8 {
+        let signature = reader_utils::read_n_bytes(4, &mut buffer)?;
+        let size = read_u32(&mut buffer)? as usize;
+        if !signature[0].is_ascii_uppercase() {
+            return Err(format!(
+                "error: index uses {} extension, which we do not understand\nfatal: index file corrupt",
+                String::from_utf8_lossy(&signature)
+            )
+            .into());
+        }
+        reader_utils::read_n_bytes(size, &mut buffer)?;
+    }
+    return Ok(entries);
+}
+
+/// Writes `entries` through `index.lock`, sorting them the way git expects.
+/// The index is version 2 unless an entry has extended flags, which need
+/// version 3. Optional extensions like the cached trees aren't written.
+pub fn write(repository: &Repository, entries: &mut [IndexEntry]) -> Result<(), Error> {
+    entries.sort_by(|a, b| {
+        a.path
+            .as_bytes()
+            .cmp(b.path.as_bytes())
+            .then(a.stage.cmp(&b.stage))
+    });
+
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(SIGNATURE);
+    let extended = entries.iter().any(|entry| entry.extended_flags != 0);
+    let version: u32 = if extended { 3 } else { 2 };
+    data.extend_from_slice(&version.to_be_bytes());
+    data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for entry in entries.iter() {
+        let entry_start = data.len();
+        for value in [
+            entry.ctime_seconds,
+            entry.ctime_nanoseconds,
+            entry.mtime_seconds,
+            entry.mtime_nanoseconds,
+            entry.dev,
+            entry.ino,
+            entry.mode,
+            entry.uid,
+            entry.gid,
+            entry.size,
+        ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data.extend_from_slice(entry.hash.as_bytes());
+        let mut flags = ((entry.stage as u16) << 12) | entry.path.len().min(0xfff) as u16;
+        if entry.extended_flags != 0 {
+            flags |= EXTENDED_FLAG;
+        }
+        data.extend_from_slice(&flags.to_be_bytes());
+        if entry.extended_flags != 0 {
+            data.extend_from_slice(&entry.extended_flags.to_be_bytes());
+        }
+        data.extend_from_slice(entry.path.as_bytes());
+        let entry_length = data.len() - entry_start;
+        let padding = 8 - entry_length % 8;
+        data.append(&mut vec![0u8; padding]);
+    }
+    let mut checksum = git_object::hash_data(&data);
+    data.append(&mut checksum);
+
+    let mut lock = LockFile::acquire(&repository.git_path("index"))?;
+    lock.write(&data)?;
+    return lock.commit();
+}
+
+/// Writes the stage 0 entries as a hierarchy of tree objects and returns the
+/// hash of the root tree.
+pub fn write_tree(repository: &Repository, entries: &[IndexEntry]) -> Result<ObjectId, Error> {
+    let entries: Vec<&IndexEntry> = entries.iter().filter(|entry| entry.stage == 0).collect();
+    return write_tree_level(repository, &entries, "");
+}
+
+fn write_tree_level(
+    repository: &Repository,
+    entries: &[&IndexEntry],
+    prefix: &str,
+) -> Result<ObjectId, Error> {
+    let mut nodes: Vec<TreeNode> = Vec::new();
+    let mut directories: BTreeMap<&str, Vec<&IndexEntry>> = BTreeMap::new();
+    for entry in entries {
+        let name = &entry.path[prefix.len()..];
+        match name.split_once('/') {
+            Some((directory, _)) => directories.entry(directory).or_default().push(entry),
+            None => nodes.push(TreeNode {
+                mode: entry.tree_mode(),
+                name: name.to_string(),
+                hash: entry.hash,
+            }),
+        }
+    }
+    for (directory, children) in directories {
+        nodes.push(TreeNode {
+            mode: git_object::TREE_MODE,
+            name: directory.to_string(),
+            hash: write_tree_level(repository, &children, &format!("{prefix}{directory}/"))?,
+        });
+    }
+    // git orders tree entries as if directory names ended with a slash
+    nodes.sort_by_cached_key(|node| {
+        let mut key = node.name.clone().into_bytes();
+        if node.is_tree() {
+            key.push(b'/');
+        }
+        return key;
+    });
+
+    let tree = Tree { entries: nodes };
+    return git_object::write_tree(repository, &mut tree.serialize());
+}
+
+/// The modification time of the index file, used to detect racily clean
+/// entries.
+pub fn index_mtime(repository: &Repository) -> Option<(u32, u32)> {
+    return fs::metadata(repository.git_path("index"))
+        .ok()
+        .map(|metadata| (metadata.mtime() as u32, metadata.mtime_nsec() as u32));
+}
+
+fn read_u32(reader: &mut &[u8]) -> Result<u32, Error> {
+    return Ok(u32::from_be_bytes(
+        reader_utils::read_n_bytes(4, reader)?.try_into().unwrap(),
+    ));
+}
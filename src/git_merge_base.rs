@@ -0,0 +1,284 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::{
+    error::Error, git_object, git_reflog, git_refs, object_id::ObjectId, repository::Repository,
+};
+
+/// Reachable from the commit the painting starts at.
+const PARENT1: u8 = 1;
+/// Reachable from one of the commits it is compared against.
+const PARENT2: u8 = 2;
+/// Below a common ancestor that was already found.
+const STALE: u8 = 4;
+/// Already found to be a common ancestor.
+const RESULT: u8 = 8;
+
+/// The state of a paint-down walk: which sides each commit seen so far is
+/// reachable from, and the commits whose parents are still to be painted,
+/// newest first.
+struct Painting<'a> {
+    repository: &'a Repository,
+    flags: HashMap<ObjectId, u8>,
+    dates: HashMap<ObjectId, i64>,
+    queue: BinaryHeap<(i64, Reverse<usize>, ObjectId)>,
+    sequence: usize,
+    /// How many times each commit is in the queue.
+    queued: HashMap<ObjectId, usize>,
+    /// How many queue entries are for commits that aren't stale.
+    nonstale: usize,
+}
+
+impl Painting<'_> {
+    fn new(repository: &Repository) -> Painting<'_> {
+        return Painting {
+            repository,
+            flags: HashMap::new(),
+            dates: HashMap::new(),
+            queue: BinaryHeap::new(),
+            sequence: 0,
+            queued: HashMap::new(),
+            nonstale: 0,
+        };
+    }
+
+    fn flags(&self, id: &ObjectId) -> u8 {
+        return self.flags.get(id).copied().unwrap_or(0);
+    }
+
+    fn date(&mut self, id: &ObjectId) -> Result<i64, Error> {
+        if let Some(date) = self.dates.get(id) {
+            return Ok(*date);
+        }
+        let date = git_object::read_commit(self.repository, id)?
+            .committer
+            .timestamp;
+        self.dates.insert(*id, date);
+        return Ok(date);
+    }
+
+    /// Adds `flags` to `id` and queues it to pass them on to its parents.
+    fn paint(&mut self, id: &ObjectId, flags: u8) -> Result<(), Error> {
+        let old = self.flags(id);
+        let queued = self.queued.entry(*id).or_default();
+        if old & STALE == 0 && flags & STALE != 0 {
+            self.nonstale -= *queued;
+        }
+        *queued += 1;
+        if (old | flags) & STALE == 0 {
+            self.nonstale += 1;
+        }
+        *self.flags.entry(*id).or_default() |= flags;
+        let date = self.date(id)?;
+        self.queue.push((date, Reverse(self.sequence), *id));
+        self.sequence += 1;
+        return Ok(());
+    }
+
+    /// Takes the newest commit off the queue, along with its date.
+    fn pop(&mut self) -> Option<(i64, ObjectId)> {
+        let (date, _, id) = self.queue.pop()?;
+        if let Some(queued) = self.queued.get_mut(&id) {
+            *queued -= 1;
+        }
+        if self.flags(&id) & STALE == 0 {
+            self.nonstale -= 1;
+        }
+        return Some((date, id));
+    }
+
+    /// Whether anything queued could still lead to a new common ancestor.
+    fn has_nonstale(&self) -> bool {
+        return self.nonstale > 0;
+    }
+}
+
+/// Paints everything reachable from `one` and from `others` down to where
+/// the two meet, the way git does. Returns the common ancestors found, some
+/// of which may turn out to be stale (below another one), along with the
+/// painting itself.
+fn paint_down_to_common<'a>(
+    repository: &'a Repository,
+    one: &ObjectId,
+    others: &[ObjectId],
+) -> Result<(Vec<ObjectId>, Painting<'a>), Error> {
+    let mut painting = Painting::new(repository);
+    painting.paint(one, PARENT1)?;
+    for other in others {
+        painting.paint(other, PARENT2)?;
+    }
+
+    let mut common = Vec::new();
+    while painting.has_nonstale() {
+        let (_, id) = match painting.pop() {
+            Some(next) => next,
+            None => break,
+        };
+        let mut flags = painting.flags(&id) & (PARENT1 | PARENT2 | STALE);
+        if flags == PARENT1 | PARENT2 {
+            if painting.flags(&id) & RESULT == 0 {
+                *painting.flags.entry(id).or_default() |= RESULT;
+                common.push(id);
+            }
+            // everything below a common ancestor is one too, but not a best one
+            flags |= STALE;
+        }
+        for parent in git_object::read_commit(repository, &id)?.parents {
+            if painting.flags(&parent) & flags != flags {
+                painting.paint(&parent, flags)?;
+            }
+        }
+    }
+    return Ok((common, painting));
+}
+
+/// The best common ancestors of `one` and any of `others` (as if they were
+/// merged together first), newest first: commits reachable from both sides
+/// that aren't reachable from another such commit.
+pub fn merge_bases(
+    repository: &Repository,
+    one: &ObjectId,
+    others: &[ObjectId],
+) -> Result<Vec<ObjectId>, Error> {
+    if others.contains(one) {
+        return Ok(vec![*one]);
+    }
+    let (common, mut painting) = paint_down_to_common(repository, one, others)?;
+    let mut bases = Vec::new();
+    for id in common {
+        if painting.flags(&id) & STALE == 0 {
+            bases.push((Reverse(painting.date(&id)?), id));
+        }
+    }
+    bases.sort_by_key(|(date, _)| *date);
+    let bases: Vec<ObjectId> = bases.into_iter().map(|(_, id)| id).collect();
+    if bases.len() <= 1 {
+        return Ok(bases);
+    }
+    return remove_redundant(repository, &bases);
+}
+
+/// The merge bases for merging all of `commits` at once, which can be fewer
+/// than those of any two of them.
+pub fn octopus_merge_bases(
+    repository: &Repository,
+    commits: &[ObjectId],
+) -> Result<Vec<ObjectId>, Error> {
+    let mut bases = match commits.first() {
+        Some(first) => vec![*first],
+        None => return Ok(Vec::new()),
+    };
+    for commit in &commits[1..] {
+        let mut next = Vec::new();
+        for base in bases.iter() {
+            next.extend(merge_bases(repository, commit, &[*base])?);
+        }
+        bases = next;
+    }
+    return independent(repository, &bases);
+}
+
+/// `commits` without duplicates and without any that another one of them
+/// can reach, in their original order.
+pub fn independent(repository: &Repository, commits: &[ObjectId]) -> Result<Vec<ObjectId>, Error> {
+    let mut unique = Vec::new();
+    for commit in commits {
+        if !unique.contains(commit) {
+            unique.push(*commit);
+        }
+    }
+    if unique.len() <= 1 {
+        return Ok(unique);
+    }
+    return remove_redundant(repository, &unique);
+}
+
+/// Drops the commits another one of `commits` can reach, keeping the order.
+fn remove_redundant(repository: &Repository, commits: &[ObjectId]) -> Result<Vec<ObjectId>, Error> {
+    let mut redundant = vec![false; commits.len()];
+    for (index, commit) in commits.iter().enumerate() {
+        if redundant[index] {
+            continue;
+        }
+        let others: Vec<usize> = (0..commits.len())
+            .filter(|other| *other != index && !redundant[*other])
+            .collect();
+        let other_ids: Vec<ObjectId> = others.iter().map(|other| commits[*other]).collect();
+        let (_, painting) = paint_down_to_common(repository, commit, &other_ids)?;
+        if painting.flags(commit) & PARENT2 != 0 {
+            redundant[index] = true;
+        }
+        for other in others {
+            if painting.flags(&commits[other]) & PARENT1 != 0 {
+                redundant[other] = true;
+            }
+        }
+    }
+    return Ok(commits
+        .iter()
+        .zip(redundant)
+        .filter(|(_, redundant)| !redundant)
+        .map(|(commit, _)| *commit)
+        .collect());
+}
+
+/// Whether `ancestor` can be reached from `descendant`, or is it. Walks down
+/// from `descendant` newest first. Without generation numbers there is no
+/// safe point to stop early, since committer dates can be skewed.
+pub fn is_ancestor(
+    repository: &Repository,
+    ancestor: &ObjectId,
+    descendant: &ObjectId,
+) -> Result<bool, Error> {
+    if ancestor == descendant {
+        return Ok(true);
+    }
+    let mut painting = Painting::new(repository);
+    painting.paint(descendant, PARENT2)?;
+    while let Some((_, id)) = painting.pop() {
+        if id == *ancestor {
+            return Ok(true);
+        }
+        for parent in git_object::read_commit(repository, &id)?.parents {
+            if painting.flags(&parent) == 0 {
+                painting.paint(&parent, PARENT2)?;
+            }
+        }
+    }
+    return Ok(false);
+}
+
+/// Where `commit` forked off the history of `reference` (a full ref name),
+/// going by every commit the reflog says `reference` has pointed at: the
+/// one merge base of `commit` and all of them, if that is such a commit.
+pub fn fork_point(
+    repository: &Repository,
+    reference: &str,
+    commit: &ObjectId,
+) -> Result<Option<ObjectId>, Error> {
+    let mut tips = Vec::new();
+    let entries = git_reflog::read(repository, reference)?;
+    let first = entries.first().map(|entry| entry.old);
+    for id in first
+        .into_iter()
+        .chain(entries.iter().map(|entry| entry.new))
+    {
+        if !id.is_null() && !tips.contains(&id) && git_object::read_commit(repository, &id).is_ok()
+        {
+            tips.push(id);
+        }
+    }
+    if tips.is_empty() {
+        if let Some(tip) = git_refs::resolve(repository, reference)? {
+            tips.push(tip);
+        }
+    }
+
+    let bases = merge_bases(repository, commit, &tips)?;
+    return Ok(match bases.as_slice() {
+        [base] if tips.contains(base) => Some(*base),
+        _ => None,
+    });
+}
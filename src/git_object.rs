@@ -3,17 +3,22 @@ use sha1::{Digest, Sha1};
 use std::{
     fmt::Display,
     fs::{self, File},
-    io::{BufReader, Read, Write},
+    io::{BufReader, ErrorKind, Read, Write},
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
 };
 
-use crate::reader_utils;
+use crate::{
+    error::Error, git_attributes, git_ignore::IgnoreRules, git_refs, object_id::ObjectId,
+    reader_utils, repository::Repository,
+};
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum ObjectType {
     Blob,
     Tree,
     Commit,
+    Tag,
 }
 
 impl Display for ObjectType {
@@ -25,108 +30,178 @@ impl Display for ObjectType {
                 ObjectType::Blob => "blob",
                 ObjectType::Tree => "tree",
                 ObjectType::Commit => "commit",
+                ObjectType::Tag => "tag",
             }
         )
     }
 }
 
-pub fn write_object(data: &Vec<u8>) -> Result<Vec<u8>, String> {
-    let hash = hash_data(data);
-    let hash_string = hex::encode(&hash);
-    let directory = &hash_string[..2];
+pub fn write_object(repository: &Repository, data: &[u8]) -> Result<ObjectId, Error> {
+    let hash = ObjectId::hash(data);
+    let hash_string = hash.to_hex();
+    let directory = repository.object_dir.join(&hash_string[..2]);
     let file_name = &hash_string[2..];
 
-    fs::create_dir_all(format!(".git/objects/{directory}"))
-        .map_err(|err| format!("error creating directory for git object: {err}"))?;
-    let file = File::create(format!(".git/objects/{directory}/{file_name}"))
-        .map_err(|err| format!("error creating file for git object: {err}"))?;
+    fs::create_dir_all(&directory)
+        .map_err(|err| Error::io("error creating directory for git object", err))?;
+    let file = File::create(directory.join(file_name))
+        .map_err(|err| Error::io("error creating file for git object", err))?;
 
     let mut encoder = ZlibEncoder::new(file, Compression::default());
     encoder
         .write_all(data)
-        .map_err(|err| format!("error compressing git object: {err}"))?;
+        .map_err(|err| Error::io("error compressing git object", err))?;
 
     return Ok(hash);
 }
 
-pub fn write_blob_from_file<P: AsRef<Path>>(file_path: P) -> Result<Vec<u8>, String> {
-    let mut file_bytes = fs::read(file_path).map_err(|err| format!("error reading file {err}"))?;
-    return write_blob(&mut file_bytes);
+/// Writes the working tree file at `path`, relative to the working tree
+/// root, as a blob.
+pub fn write_blob_from_file(repository: &Repository, path: &str) -> Result<ObjectId, Error> {
+    let mut file_bytes = read_worktree_file(repository, path)?;
+    return write_blob(repository, &mut file_bytes);
+}
+
+/// Hashes a file from the working tree as a blob without writing it.
+pub fn hash_blob_from_file(repository: &Repository, path: &str) -> Result<ObjectId, Error> {
+    let mut file_bytes = read_worktree_file(repository, path)?;
+    let mut blob_bytes: Vec<u8> = format!("blob {}\0", file_bytes.len()).bytes().collect();
+    blob_bytes.append(&mut file_bytes);
+    return Ok(ObjectId::hash(&blob_bytes));
+}
+
+/// Reads the blob content for a working tree file, which for symlinks is the
+/// link target rather than the file it points to. Regular files go through
+/// the clean conversions from `.gitattributes`.
+pub fn read_worktree_file(repository: &Repository, path: &str) -> Result<Vec<u8>, Error> {
+    let file_path = repository.work_path(path);
+    if file_path.is_symlink() {
+        let target =
+            fs::read_link(&file_path).map_err(|err| format!("error reading symlink {err}"))?;
+        return Ok(target.as_os_str().as_bytes().to_vec());
+    }
+    let data = fs::read(&file_path).map_err(|err| format!("error reading file {err}"))?;
+    return git_attributes::convert_to_git(repository, path, data);
 }
 
-pub fn write_blob(data: &mut Vec<u8>) -> Result<Vec<u8>, String> {
+pub fn write_blob(repository: &Repository, data: &mut Vec<u8>) -> Result<ObjectId, Error> {
     let mut blob_bytes: Vec<u8> = format!("blob {}\0", data.len()).bytes().collect();
     blob_bytes.append(data);
-    return write_object(&blob_bytes);
+    return write_object(repository, &blob_bytes);
 }
 
-pub fn write_tree_from_directory<P: AsRef<Path>>(directory_path: P) -> Result<Vec<u8>, String> {
-    let mut paths: Vec<PathBuf> = fs::read_dir(directory_path)
-        .map_err(|err| format!("error reading directory: {err}"))?
-        .into_iter()
-        .filter(|r| r.is_ok())
-        .map(|r| r.unwrap().path())
-        .collect();
-    paths.sort();
-
-    let mut tree_byte_buffer: Vec<u8> = Vec::new();
-    for path in paths {
-        let name = path
-            .file_name()
-            .map(|os_name| os_name.to_str())
-            .unwrap_or(None);
-        if name.is_none() {
-            return Err("error getting name for dir entry".to_string());
+pub const EMPTY_TREE_HASH: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// Writes the working tree directory at `directory` (relative to the root,
+/// empty for the root itself) as a tree, leaving out anything matched by
+/// `ignore_rules` and directories that end up empty.
+pub fn write_tree_from_directory(
+    repository: &Repository,
+    directory: &str,
+    ignore_rules: &mut IgnoreRules,
+) -> Result<ObjectId, Error> {
+    let mut names: Vec<String> = Vec::new();
+    for entry in fs::read_dir(repository.work_path(directory))
+        .map_err(|err| Error::io("error reading directory", err))?
+        .flatten()
+    {
+        let name = entry.file_name();
+        match name.to_str() {
+            Some(name) => names.push(name.to_string()),
+            None => return Err("error getting name for dir entry".into()),
         }
+    }
+    names.sort();
 
-        if name.unwrap() == ".git" {
+    let mut tree = Tree {
+        entries: Vec::new(),
+    };
+    for name in names {
+        if name == ".git" {
+            continue;
+        }
+        let relative_path = if directory.is_empty() {
+            name.clone()
+        } else {
+            format!("{directory}/{name}")
+        };
+        let path = repository.work_path(&relative_path);
+        if ignore_rules.is_ignored(&relative_path, path.is_dir() && !path.is_symlink())? {
             continue;
         }
 
-        let (mut entry_hash, mode) = if path.is_dir() {
-            (write_tree_from_directory(&path)?, 40000)
+        let (hash, mode) = if path.join(".git").exists() {
+            (submodule_head(&path)?, GITLINK_MODE)
+        } else if path.is_symlink() {
+            (
+                write_blob_from_file(repository, &relative_path)?,
+                SYMLINK_MODE,
+            )
+        } else if path.is_dir() {
+            let hash = write_tree_from_directory(repository, &relative_path, ignore_rules)?;
+            if hash.to_hex() == EMPTY_TREE_HASH {
+                continue;
+            }
+            (hash, TREE_MODE)
         } else {
-            (write_blob_from_file(&path)?, 100644)
+            (write_blob_from_file(repository, &relative_path)?, 100644)
         };
 
-        tree_byte_buffer.append(&mut format!("{} {}\0", mode, name.unwrap()).bytes().collect());
-        tree_byte_buffer.append(&mut entry_hash);
+        tree.entries.push(TreeNode { mode, name, hash });
     }
 
-    return write_tree(&mut tree_byte_buffer);
+    return write_tree(repository, &mut tree.serialize());
+}
+
+/// Resolves the commit checked out in a nested repository so it can be
+/// recorded as a gitlink.
+pub fn submodule_head(path: &Path) -> Result<ObjectId, Error> {
+    let submodule = Repository::open_work_tree(path)?;
+    return git_refs::resolve(&submodule, "HEAD")?
+        .ok_or_else(|| format!("submodule {} has no commit checked out", path.display()).into());
 }
 
-pub fn write_tree(data: &mut Vec<u8>) -> Result<Vec<u8>, String> {
+pub fn write_tree(repository: &Repository, data: &mut Vec<u8>) -> Result<ObjectId, Error> {
     let mut tree_bytes: Vec<u8> = format!("tree {}\0", data.len()).bytes().collect();
     tree_bytes.append(data);
-    return write_object(&tree_bytes);
+    return write_object(repository, &tree_bytes);
 }
 
-pub fn write_commit(data: &mut Vec<u8>) -> Result<Vec<u8>, String> {
+pub fn write_commit(repository: &Repository, data: &mut Vec<u8>) -> Result<ObjectId, Error> {
     let mut commit_bytes: Vec<u8> = format!("commit {}\0", data.len()).bytes().collect();
     commit_bytes.append(data);
-    return write_object(&commit_bytes);
+    return write_object(repository, &commit_bytes);
 }
 
-pub fn reader(object_name: &String) -> Result<impl Read, String> {
-    let path = path_for_object(object_name)?;
-    let f = File::open(path).map_err(|err| format!("error opening file: {err}"))?;
+pub fn write_tag(repository: &Repository, data: &mut Vec<u8>) -> Result<ObjectId, Error> {
+    let mut tag_bytes: Vec<u8> = format!("tag {}\0", data.len()).bytes().collect();
+    tag_bytes.append(data);
+    return write_object(repository, &tag_bytes);
+}
+
+pub fn reader(repository: &Repository, id: &ObjectId) -> Result<impl Read, Error> {
+    let path = path_for_object(repository, id);
+    let f = File::open(path).map_err(|err| match err.kind() {
+        ErrorKind::NotFound => Error::ObjectNotFound(id.to_hex()),
+        _ => Error::io("error opening file", err),
+    })?;
     let reader = BufReader::new(f);
     let decoder = ZlibDecoder::new(reader);
     return Ok(decoder);
 }
 
-pub fn identify_header(header: &String) -> Result<(ObjectType, usize), String> {
+pub fn identify_header(header: &str) -> Result<(ObjectType, usize), Error> {
     let parts: Vec<&str> = header.split(' ').collect();
     if parts.len() != 2 {
-        return Err("git object header didn't have the correct amount of parts".to_string());
+        return Err("git object header didn't have the correct amount of parts".into());
     }
 
     let object_type = match parts[0] {
         "blob" => ObjectType::Blob,
         "tree" => ObjectType::Tree,
         "commit" => ObjectType::Commit,
-        o_type => return Err(format!("unknown object type: {o_type}")),
+        "tag" => ObjectType::Tag,
+        o_type => return Err(format!("unknown object type: {o_type}").into()),
     };
 
     let size = str::parse::<usize>(parts[1])
@@ -135,34 +210,58 @@ pub fn identify_header(header: &String) -> Result<(ObjectType, usize), String> {
     return Ok((object_type, size));
 }
 
-fn path_for_object(object_name: &String) -> Result<PathBuf, String> {
-    if object_name.len() < 2 {
-        return Err("provided hash isn't long enough".to_string());
-    }
+fn path_for_object(repository: &Repository, id: &ObjectId) -> PathBuf {
+    let hex = id.to_hex();
+    return repository.object_dir.join(&hex[..2]).join(&hex[2..]);
+}
 
-    let directory = object_name[..2].to_string();
-    let filename = &object_name[2..];
-    let mut paths: Vec<PathBuf> = fs::read_dir(format!(".git/objects/{directory}/"))
-        .map_err(|err| format!("error reading objects directory: {err}"))?
-        .into_iter()
-        .filter(|r| r.is_ok())
-        .map(|r| r.unwrap().path())
-        .filter(|r| {
-            r.file_name()
-                .is_some_and(|name| name.to_str().is_some_and(|str| str.starts_with(filename)))
-        })
+/// Expands an abbreviated object name (at least two hex digits) to the one
+/// object it names.
+pub fn full_hash(repository: &Repository, partial_hash: &str) -> Result<ObjectId, Error> {
+    let invalid = || Error::ObjectNotFound(partial_hash.to_string());
+    if partial_hash.len() < 2
+        || partial_hash.len() > ObjectId::HEX_LENGTH
+        || !partial_hash.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(invalid());
+    }
+    let partial_hash = partial_hash.to_lowercase();
+
+    let directory = &partial_hash[..2];
+    let file_prefix = &partial_hash[2..];
+    let entries = match fs::read_dir(repository.object_dir.join(directory)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Err(invalid()),
+        Err(err) => return Err(Error::io("error reading objects directory", err)),
+    };
+    let mut matches: Vec<ObjectId> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+        .filter(|name| name.starts_with(file_prefix))
+        .filter_map(|name| ObjectId::from_hex(&format!("{directory}{name}")).ok())
         .collect();
 
-    if paths.len() < 1 {
-        return Err(format!("fatal: Not a valid object name {object_name}"));
+    if matches.is_empty() {
+        return Err(invalid());
     }
-    if paths.len() > 1 {
-        return Err(format!(
-            "fatal: Provided hash isn't unique enough {object_name}"
-        ));
+    if matches.len() > 1 {
+        return Err(Error::AmbiguousPrefix(partial_hash));
     }
+    return Ok(matches.pop().unwrap());
+}
 
-    return Ok(paths.pop().unwrap());
+/// The shortest prefix of `id`, at least `length` digits long, that names no
+/// other object.
+pub fn abbreviate(repository: &Repository, id: &ObjectId, length: usize) -> Result<String, Error> {
+    let hex = id.to_hex();
+    for length in length.clamp(4, ObjectId::HEX_LENGTH)..ObjectId::HEX_LENGTH {
+        match full_hash(repository, &hex[..length]) {
+            Err(Error::AmbiguousPrefix(_)) => continue,
+            Ok(_) | Err(Error::ObjectNotFound(_)) => return Ok(hex[..length].to_string()),
+            Err(err) => return Err(err),
+        }
+    }
+    return Ok(hex);
 }
 
 pub fn hash_data(data: &Vec<u8>) -> Vec<u8> {
@@ -171,19 +270,43 @@ pub fn hash_data(data: &Vec<u8>) -> Vec<u8> {
     return hasher.finalize().into_iter().collect();
 }
 
+pub const TREE_MODE: u64 = 40000;
+pub const SYMLINK_MODE: u64 = 120000;
+pub const GITLINK_MODE: u64 = 160000;
+
 pub struct TreeNode {
     pub mode: u64,
     pub name: String,
-    pub hash: String,
+    pub hash: ObjectId,
 }
 
-pub fn read_tree(reader: &mut impl Read, mut size: usize) -> Result<Vec<TreeNode>, String> {
+impl TreeNode {
+    pub fn is_tree(&self) -> bool {
+        return self.mode == TREE_MODE;
+    }
+
+    /// Gitlinks point at a commit in a submodule's repository, which usually
+    /// isn't present in our object store.
+    pub fn is_gitlink(&self) -> bool {
+        return self.mode == GITLINK_MODE;
+    }
+
+    pub fn object_type(&self) -> ObjectType {
+        return match self.mode {
+            TREE_MODE => ObjectType::Tree,
+            GITLINK_MODE => ObjectType::Commit,
+            _ => ObjectType::Blob,
+        };
+    }
+}
+
+pub fn read_tree(reader: &mut impl Read, mut size: usize) -> Result<Vec<TreeNode>, Error> {
     let mut result: Vec<TreeNode> = Vec::new();
     while size > 0 {
         let info = reader_utils::read_to_next_null_byte(reader)?;
-        let parts: Vec<&str> = info.split(' ').into_iter().collect();
+        let parts: Vec<&str> = info.split(' ').collect();
         if parts.len() != 2 {
-            return Err("tree info had the incorrect amount of parts".to_string());
+            return Err("tree info had the incorrect amount of parts".into());
         }
 
         let mode = str::parse::<u64>(parts[0])
@@ -192,7 +315,7 @@ pub fn read_tree(reader: &mut impl Read, mut size: usize) -> Result<Vec<TreeNode
         result.push(TreeNode {
             mode,
             name: parts[1].to_string(),
-            hash: hex::encode(reader_utils::read_n_bytes(20, reader)?),
+            hash: ObjectId::from_bytes(&reader_utils::read_n_bytes(20, reader)?)?,
         });
 
         size -= info.len() + 21;
@@ -200,18 +323,331 @@ pub fn read_tree(reader: &mut impl Read, mut size: usize) -> Result<Vec<TreeNode
     return Ok(result);
 }
 
-pub fn get_type(hash: &String) -> Result<ObjectType, String> {
-    let mut reader = reader(hash)?;
+pub fn get_type(repository: &Repository, hash: &ObjectId) -> Result<ObjectType, Error> {
+    let mut reader = reader(repository, hash)?;
     let (object_type, _) = identify_header(&reader_utils::read_to_next_null_byte(&mut reader)?)?;
     return Ok(object_type);
 }
 
-pub fn full_hash(partial_hash: &String) -> Result<String, String> {
-    let path = path_for_object(partial_hash)?;
-    let path_str = path.to_str();
-    if path_str.is_none() {
-        return Err("error converting path to string".to_string());
+/// The size of an object's content, as its header gives it.
+pub fn get_size(repository: &Repository, hash: &ObjectId) -> Result<usize, Error> {
+    let mut reader = reader(repository, hash)?;
+    let (_, size) = identify_header(&reader_utils::read_to_next_null_byte(&mut reader)?)?;
+    return Ok(size);
+}
+
+/// Reads an object's type and its content without the header.
+pub fn read_raw(repository: &Repository, id: &ObjectId) -> Result<(ObjectType, Vec<u8>), Error> {
+    let mut reader = reader(repository, id)?;
+    let header =
+        reader_utils::read_to_next_null_byte(&mut reader).map_err(|err| corrupt(id, err))?;
+    let (object_type, size) = identify_header(&header).map_err(|err| corrupt(id, err))?;
+    let data = reader_utils::read_n_bytes(size, &mut reader).map_err(|err| corrupt(id, err))?;
+    return Ok((object_type, data));
+}
+
+/// Reports a stored object that couldn't be decoded.
+fn corrupt(id: &ObjectId, err: Error) -> Error {
+    return Error::CorruptObject {
+        id: *id,
+        reason: err.to_string(),
+    };
+}
+
+/// An author, committer or tagger line: `Name <email> 1700000000 +0100`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    pub timestamp: i64,
+    /// Offset from UTC in minutes.
+    pub timezone_offset: i32,
+}
+
+impl Signature {
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let (name, rest) = line
+            .split_once(" <")
+            .ok_or_else(|| format!("malformed signature: {line}"))?;
+        let (email, rest) = rest
+            .split_once("> ")
+            .ok_or_else(|| format!("malformed signature: {line}"))?;
+        let (timestamp, timezone) = rest
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("malformed signature date: {line}"))?;
+        let timestamp = timestamp
+            .parse::<i64>()
+            .map_err(|err| format!("error parsing signature timestamp: {err}"))?;
+        return Ok(Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            timestamp,
+            timezone_offset: parse_timezone(timezone)?,
+        });
+    }
+
+    /// The timezone as written in objects, e.g. `+0100` or `-0530`.
+    pub fn timezone(&self) -> String {
+        let sign = if self.timezone_offset < 0 { '-' } else { '+' };
+        let offset = self.timezone_offset.abs();
+        return format!("{sign}{:02}{:02}", offset / 60, offset % 60);
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name,
+            self.email,
+            self.timestamp,
+            self.timezone()
+        )
+    }
+}
+
+pub fn parse_timezone(timezone: &str) -> Result<i32, Error> {
+    let (sign, digits) = match timezone.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, timezone.trim_start_matches('+')),
+    };
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("malformed timezone: {timezone}").into());
+    }
+    let hours: i32 = digits[..2].parse().unwrap();
+    let minutes: i32 = digits[2..].parse().unwrap();
+    return Ok(sign * (hours * 60 + minutes));
+}
+
+/// Splits the header section of a commit or tag into key/value pairs, joining
+/// continuation lines (which start with a space) into multi-line values.
+fn parse_headers(data: &[u8]) -> (Vec<(String, String)>, String) {
+    let text = String::from_utf8_lossy(data);
+    let (header_text, message) = match text.split_once("\n\n") {
+        Some((headers, message)) => (headers.to_string(), message.to_string()),
+        None => (text.trim_end_matches('\n').to_string(), String::new()),
+    };
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in header_text.lines() {
+        if let Some(continuation) = line.strip_prefix(' ') {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push('\n');
+                value.push_str(continuation);
+            }
+            continue;
+        }
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        headers.push((key.to_string(), value.to_string()));
+    }
+    return (headers, message);
+}
+
+fn serialize_header(buffer: &mut Vec<u8>, key: &str, value: &str) {
+    buffer.extend_from_slice(format!("{key} {}\n", value.replace('\n', "\n ")).as_bytes());
+}
+
+#[derive(Clone, Debug)]
+pub struct Commit {
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub author: Signature,
+    pub committer: Signature,
+    pub encoding: Option<String>,
+    /// Headers we don't interpret, such as `mergetag`, in their original order.
+    pub extra_headers: Vec<(String, String)>,
+    pub gpgsig: Option<String>,
+    pub message: String,
+}
+
+impl Commit {
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let (headers, message) = parse_headers(data);
+        let mut tree: Option<ObjectId> = None;
+        let mut parents = Vec::new();
+        let mut author: Option<Signature> = None;
+        let mut committer: Option<Signature> = None;
+        let mut encoding = None;
+        let mut gpgsig = None;
+        let mut extra_headers = Vec::new();
+        for (key, value) in headers {
+            match key.as_str() {
+                "tree" => tree = Some(ObjectId::from_hex(&value)?),
+                "parent" => parents.push(ObjectId::from_hex(&value)?),
+                "author" => author = Some(Signature::parse(&value)?),
+                "committer" => committer = Some(Signature::parse(&value)?),
+                "encoding" => encoding = Some(value),
+                "gpgsig" => gpgsig = Some(value),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+        return Ok(Commit {
+            tree: tree.ok_or("commit has no tree".to_string())?,
+            parents,
+            author: author.ok_or("commit has no author".to_string())?,
+            committer: committer.ok_or("commit has no committer".to_string())?,
+            encoding,
+            extra_headers,
+            gpgsig,
+            message,
+        });
+    }
+
+    /// The object content, without the `commit <size>\0` header.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        serialize_header(&mut buffer, "tree", &self.tree.to_hex());
+        for parent in self.parents.iter() {
+            serialize_header(&mut buffer, "parent", &parent.to_hex());
+        }
+        serialize_header(&mut buffer, "author", &self.author.to_string());
+        serialize_header(&mut buffer, "committer", &self.committer.to_string());
+        if let Some(encoding) = &self.encoding {
+            serialize_header(&mut buffer, "encoding", encoding);
+        }
+        for (key, value) in self.extra_headers.iter() {
+            serialize_header(&mut buffer, key, value);
+        }
+        if let Some(gpgsig) = &self.gpgsig {
+            serialize_header(&mut buffer, "gpgsig", gpgsig);
+        }
+        buffer.push(b'\n');
+        buffer.extend_from_slice(self.message.as_bytes());
+        return buffer;
+    }
+
+    /// The first line of the message.
+    pub fn subject(&self) -> &str {
+        return self.message.lines().next().unwrap_or("");
+    }
+}
+
+pub struct Tree {
+    pub entries: Vec<TreeNode>,
+}
+
+impl Tree {
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = data;
+        return Ok(Tree {
+            entries: read_tree(&mut reader, data.len())?,
+        });
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for entry in self.entries.iter() {
+            buffer.extend_from_slice(format!("{} {}\0", entry.mode, entry.name).as_bytes());
+            buffer.extend_from_slice(entry.hash.as_bytes());
+        }
+        return buffer;
     }
-    let parts: Vec<&str> = path_str.unwrap().split("/").collect();
-    return Ok(parts[parts.len() - 2..].join(""));
+}
+
+pub struct Blob {
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Tag {
+    pub object: ObjectId,
+    pub object_type: ObjectType,
+    pub tag: String,
+    pub tagger: Option<Signature>,
+    pub extra_headers: Vec<(String, String)>,
+    /// The message, including any trailing signature block.
+    pub message: String,
+}
+
+impl Tag {
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let (headers, message) = parse_headers(data);
+        let mut object = None;
+        let mut object_type = None;
+        let mut tag = None;
+        let mut tagger = None;
+        let mut extra_headers = Vec::new();
+        for (key, value) in headers {
+            match key.as_str() {
+                "object" => object = Some(ObjectId::from_hex(&value)?),
+                "type" => {
+                    object_type = Some(identify_header(&format!("{value} 0"))?.0);
+                }
+                "tag" => tag = Some(value),
+                "tagger" => tagger = Some(Signature::parse(&value)?),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+        return Ok(Tag {
+            object: object.ok_or("tag has no object".to_string())?,
+            object_type: object_type.ok_or("tag has no type".to_string())?,
+            tag: tag.ok_or("tag has no name".to_string())?,
+            tagger,
+            extra_headers,
+            message,
+        });
+    }
+
+    /// The object content, without the `tag <size>\0` header.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        serialize_header(&mut buffer, "object", &self.object.to_hex());
+        serialize_header(&mut buffer, "type", &self.object_type.to_string());
+        serialize_header(&mut buffer, "tag", &self.tag);
+        if let Some(tagger) = &self.tagger {
+            serialize_header(&mut buffer, "tagger", &tagger.to_string());
+        }
+        for (key, value) in self.extra_headers.iter() {
+            serialize_header(&mut buffer, key, value);
+        }
+        buffer.push(b'\n');
+        buffer.extend_from_slice(self.message.as_bytes());
+        return buffer;
+    }
+}
+
+pub enum Object {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+pub fn read_object(repository: &Repository, id: &ObjectId) -> Result<Object, Error> {
+    let (object_type, data) = read_raw(repository, id)?;
+    return Ok(match object_type {
+        ObjectType::Blob => Object::Blob(Blob { data }),
+        ObjectType::Tree => Object::Tree(Tree::parse(&data).map_err(|err| corrupt(id, err))?),
+        ObjectType::Commit => Object::Commit(Commit::parse(&data).map_err(|err| corrupt(id, err))?),
+        ObjectType::Tag => Object::Tag(Tag::parse(&data).map_err(|err| corrupt(id, err))?),
+    });
+}
+
+pub fn read_commit(repository: &Repository, id: &ObjectId) -> Result<Commit, Error> {
+    return match read_raw(repository, id)? {
+        (ObjectType::Commit, data) => Commit::parse(&data).map_err(|err| corrupt(id, err)),
+        (object_type, _) => Err(format!("{id} is a {object_type}, not a commit").into()),
+    };
+}
+
+pub fn read_tree_object(repository: &Repository, id: &ObjectId) -> Result<Tree, Error> {
+    return match read_raw(repository, id)? {
+        (ObjectType::Tree, data) => Tree::parse(&data).map_err(|err| corrupt(id, err)),
+        (object_type, _) => Err(format!("{id} is a {object_type}, not a tree").into()),
+    };
+}
+
+pub fn read_blob(repository: &Repository, id: &ObjectId) -> Result<Blob, Error> {
+    return match read_raw(repository, id)? {
+        (ObjectType::Blob, data) => Ok(Blob { data }),
+        (object_type, _) => Err(format!("{id} is a {object_type}, not a blob").into()),
+    };
+}
+
+pub fn read_tag(repository: &Repository, id: &ObjectId) -> Result<Tag, Error> {
+    return match read_raw(repository, id)? {
+        (ObjectType::Tag, data) => Tag::parse(&data).map_err(|err| corrupt(id, err)),
+        (object_type, _) => Err(format!("{id} is a {object_type}, not a tag").into()),
+    };
 }
@@ -3,20 +3,22 @@ use std::io::Read;
 
 use flate2::read::ZlibDecoder;
 
-use crate::{git_object, reader_utils};
+use crate::{error::Error, git_object, object_id::ObjectId, reader_utils, repository::Repository};
 
-pub fn unpack(reader: &mut impl Read) -> Result<(), String> {
+pub fn unpack(repository: &Repository, reader: &mut impl Read) -> Result<(), Error> {
     let mut pack_data: Vec<u8> = Vec::new();
     reader
         .read_to_end(&mut pack_data)
-        .map_err(|err| format!("error reading pack data: {err}"))?;
+        .map_err(|err| Error::io("error reading pack data", err))?;
     if str::from_utf8(&pack_data[..4]) != Ok("PACK") {
-        return Err("not a valid pack".to_string());
+        return Err(Error::Protocol("not a valid pack".to_string()));
     }
 
     let checksum = pack_data.split_off(pack_data.len() - 20);
     if checksum != git_object::hash_data(&pack_data) {
-        return Err("pack data did not pass checksum".to_string());
+        return Err(Error::Protocol(
+            "pack data did not pass checksum".to_string(),
+        ));
     }
 
     let mut pack_buffer = &pack_data[..];
@@ -32,26 +34,26 @@ pub fn unpack(reader: &mut impl Read) -> Result<(), String> {
         let (o_type, size) = read_type_and_size(&mut pack_buffer)?;
         match o_type {
             ObjectType::Commit => {
-                git_object::write_commit(&mut zlib_read(size, &mut pack_buffer)?)?;
+                git_object::write_commit(repository, &mut zlib_read(size, &mut pack_buffer)?)?;
             }
             ObjectType::Tree => {
-                git_object::write_tree(&mut zlib_read(size, &mut pack_buffer)?)?;
+                git_object::write_tree(repository, &mut zlib_read(size, &mut pack_buffer)?)?;
             }
             ObjectType::Blob => {
-                git_object::write_blob(&mut zlib_read(size, &mut pack_buffer)?)?;
+                git_object::write_blob(repository, &mut zlib_read(size, &mut pack_buffer)?)?;
             }
             ObjectType::Tag => {
-                // unsupported
-                zlib_read(size, &mut pack_buffer)?;
+                git_object::write_tag(repository, &mut zlib_read(size, &mut pack_buffer)?)?;
             }
             ObjectType::OfsDelta => {
-                return Err("offset deltas not currently supported".to_string());
+                return Err("offset deltas not currently supported".into());
             }
             ObjectType::RefDelta => {
                 let reference_hash = reader_utils::read_n_bytes(20, &mut pack_buffer)?;
                 let data = zlib_read(size, &mut pack_buffer)?;
-                let target_data = apply_delta(&hex::encode(reference_hash), &data)?;
-                git_object::write_object(&target_data)?;
+                let target_data =
+                    apply_delta(repository, &ObjectId::from_bytes(&reference_hash)?, &data)?;
+                git_object::write_object(repository, &target_data)?;
             }
         }
     }
@@ -59,24 +61,24 @@ pub fn unpack(reader: &mut impl Read) -> Result<(), String> {
     return Ok(());
 }
 
-fn apply_delta(reference_hash: &String, delta: &Vec<u8>) -> Result<Vec<u8>, String> {
-    let mut delta_buffer = &delta[..];
+fn apply_delta(
+    repository: &Repository,
+    reference_hash: &ObjectId,
+    delta: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let mut delta_buffer = delta;
     let source_length = read_size(&mut delta_buffer)?;
     let target_length = read_size(&mut delta_buffer)?;
 
-    let object_type = git_object::get_type(reference_hash)?;
-    let mut source_reader = git_object::reader(reference_hash)?;
-    reader_utils::read_to_next_null_byte(&mut source_reader)?;
-    let mut source_data = Vec::new();
-    source_reader
-        .read_to_end(&mut source_data)
-        .map_err(|err| format!("error reading reference object: {err}"))?;
+    let (object_type, source_data) = git_object::read_raw(repository, reference_hash)?;
     if source_data.len() != source_length {
-        return Err("source object wasn't the correct length for de deltifying".to_string());
+        return Err(Error::Protocol(
+            "source object wasn't the correct length for de deltifying".to_string(),
+        ));
     }
 
     let mut target_data: Vec<u8> = Vec::new();
-    while delta_buffer.len() > 0 {
+    while !delta_buffer.is_empty() {
         let command = reader_utils::read_byte(&mut delta_buffer)?;
         if command & 0b10000000 == 0 {
             // insert
@@ -106,7 +108,9 @@ fn apply_delta(reference_hash: &String, delta: &Vec<u8>) -> Result<Vec<u8>, Stri
     }
 
     if target_data.len() != target_length {
-        return Err("target object wasn't the correct length for de deltifying".to_string());
+        return Err(Error::Protocol(
+            "target object wasn't the correct length for de deltifying".to_string(),
+        ));
     }
 
     let mut object_bytes: Vec<u8> = format!("{} {}\0", object_type, target_data.len())
@@ -126,7 +130,7 @@ enum ObjectType {
     RefDelta,
 }
 
-fn read_type_and_size(reader: &mut impl Read) -> Result<(ObjectType, usize), String> {
+fn read_type_and_size(reader: &mut impl Read) -> Result<(ObjectType, usize), Error> {
     let first_byte = reader_utils::read_byte(reader)?;
     let o_type = match (first_byte & 0b01110000) >> 4 {
         0b001 => ObjectType::Commit,
@@ -136,10 +140,10 @@ fn read_type_and_size(reader: &mut impl Read) -> Result<(ObjectType, usize), Str
         0b110 => ObjectType::OfsDelta,
         0b111 => ObjectType::RefDelta,
         pack_object_type => {
-            return Err(format!(
+            return Err(Error::Protocol(format!(
                 "unknown pack object type: {:0<3b}",
                 pack_object_type
-            ))
+            )))
         }
     };
     let mut size: usize = (first_byte & 0b1111).into();
@@ -160,7 +164,7 @@ fn read_type_and_size(reader: &mut impl Read) -> Result<(ObjectType, usize), Str
     return Ok((o_type, size));
 }
 
-fn read_size(reader: &mut impl Read) -> Result<usize, String> {
+fn read_size(reader: &mut impl Read) -> Result<usize, Error> {
     let mut size = 0;
     let mut bytes_read = 0;
     loop {
@@ -174,16 +178,16 @@ fn read_size(reader: &mut impl Read) -> Result<usize, String> {
     return Ok(size);
 }
 
-fn zlib_read(size: usize, reader: &mut impl Read) -> Result<Vec<u8>, String> {
+fn zlib_read(size: usize, reader: &mut impl Read) -> Result<Vec<u8>, Error> {
     let mut zlib_reader = ZlibDecoder::new_with_buf(reader, vec![0; 1]); // giving this a buffer size of 1 so it doesn't overconsume from reader
     let mut data = Vec::new();
     let size_read = zlib_reader
         .read_to_end(&mut data)
-        .map_err(|err| format!("error uncompressing pack object: {err}"))?;
+        .map_err(|err| Error::io("error uncompressing pack object", err))?;
     if size_read != size {
-        return Err(format!(
+        return Err(Error::Protocol(format!(
             "expected object length of {size}, got length {size_read}"
-        ));
+        )));
     }
     return Ok(data);
 }
@@ -0,0 +1,262 @@
+use crate::{
+    error::Error,
+    git_date,
+    git_object::{self, Signature},
+    git_revwalk::WalkedCommit,
+    object_id::ObjectId,
+    repository::Repository,
+};
+
+/// How `log` prints each commit, as chosen by `--pretty`/`--format`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Format {
+    Oneline,
+    Short,
+    Medium,
+    Full,
+    Fuller,
+    /// A `format:` template; `terminated` (`tformat:`) ends every commit
+    /// with a newline rather than putting one between commits.
+    User {
+        template: String,
+        terminated: bool,
+    },
+}
+
+impl Format {
+    /// Parses the value of `--pretty` or `--format`: a format name, or a
+    /// template that is taken to be `tformat:` when it has no prefix.
+    pub fn parse(spec: &str) -> Result<Format, Error> {
+        if let Some(template) = spec.strip_prefix("format:") {
+            return Ok(Format::User {
+                template: template.to_string(),
+                terminated: false,
+            });
+        }
+        if let Some(template) = spec.strip_prefix("tformat:") {
+            return Ok(Format::User {
+                template: template.to_string(),
+                terminated: true,
+            });
+        }
+        return match spec {
+            "oneline" => Ok(Format::Oneline),
+            "short" => Ok(Format::Short),
+            "medium" => Ok(Format::Medium),
+            "full" => Ok(Format::Full),
+            "fuller" => Ok(Format::Fuller),
+            template if template.is_empty() || template.contains('%') => Ok(Format::User {
+                template: template.to_string(),
+                terminated: true,
+            }),
+            _ => Err(format!("fatal: invalid --pretty format: {spec}").into()),
+        };
+    }
+
+    /// Whether each commit's output ends with a newline, as opposed to
+    /// commits being separated by one.
+    pub fn is_terminated(&self) -> bool {
+        return match self {
+            Format::Oneline => true,
+            Format::User { terminated, .. } => *terminated,
+            _ => false,
+        };
+    }
+
+    /// Whether commits print as nothing at all, not even a newline.
+    pub fn is_empty(&self) -> bool {
+        return matches!(self, Format::User { template, .. } if template.is_empty());
+    }
+}
+
+/// The lines introducing a commit in the built-in formats: `commit <id>`,
+/// or just the id before the subject for `oneline`.
+pub fn format_header(
+    repository: &Repository,
+    walked: &WalkedCommit,
+    format: &Format,
+    abbreviate: bool,
+) -> Result<String, Error> {
+    let id = match abbreviate {
+        true => git_object::abbreviate(repository, &walked.id, 7)?,
+        false => walked.id.to_string(),
+    };
+    return Ok(match format {
+        Format::User { .. } => String::new(),
+        Format::Oneline => format!("{id} "),
+        _ => format!("commit {id}\n"),
+    });
+}
+
+/// What follows the header: the author and message for the built-in
+/// formats, or the expanded template. Only `oneline` and templates may
+/// leave the last line unterminated.
+pub fn format_body(
+    repository: &Repository,
+    walked: &WalkedCommit,
+    format: &Format,
+    date_mode: &str,
+) -> Result<String, Error> {
+    let commit = &walked.commit;
+    let identity = |signature: &Signature| format!("{} <{}>", signature.name, signature.email);
+    let date = |signature: &Signature| {
+        git_date::format(signature.timestamp, signature.timezone_offset, date_mode)
+    };
+
+    let mut output = String::new();
+    match format {
+        Format::User { template, .. } => return expand(repository, walked, template, date_mode),
+        Format::Oneline => return Ok(subject(&commit.message)),
+        Format::Short | Format::Medium | Format::Full | Format::Fuller => {}
+    }
+    if walked.parents.len() > 1 {
+        let mut parents = Vec::new();
+        for parent in walked.parents.iter() {
+            parents.push(git_object::abbreviate(repository, parent, 7)?);
+        }
+        output += &format!("Merge: {}\n", parents.join(" "));
+    }
+    match format {
+        Format::Short => output += &format!("Author: {}\n", identity(&commit.author)),
+        Format::Medium => {
+            output += &format!("Author: {}\n", identity(&commit.author));
+            output += &format!("Date:   {}\n", date(&commit.author)?);
+        }
+        Format::Full => {
+            output += &format!("Author: {}\n", identity(&commit.author));
+            output += &format!("Commit: {}\n", identity(&commit.committer));
+        }
+        _ => {
+            output += &format!("Author:     {}\n", identity(&commit.author));
+            output += &format!("AuthorDate: {}\n", date(&commit.author)?);
+            output += &format!("Commit:     {}\n", identity(&commit.committer));
+            output += &format!("CommitDate: {}\n", date(&commit.committer)?);
+        }
+    }
+    output.push('\n');
+
+    for line in commit
+        .message
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+    {
+        if *format == Format::Short && line.trim().is_empty() {
+            break;
+        }
+        output += &format!("    {line}\n");
+    }
+    let trimmed = output.trim_end().len();
+    output.truncate(trimmed);
+    output.push('\n');
+    return Ok(output);
+}
+
+/// The first paragraph of a message, joined into one line.
+pub fn subject(message: &str) -> String {
+    return message
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| !line.trim().is_empty())
+        .map(|line| line.trim())
+        .collect::<Vec<&str>>()
+        .join(" ");
+}
+
+/// Everything in a message after its subject paragraph.
+fn body(message: &str) -> String {
+    return message
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .skip_while(|line| !line.trim().is_empty())
+        .skip_while(|line| line.trim().is_empty())
+        .map(|line| format!("{line}\n"))
+        .collect();
+}
+
+/// Expands the `%` placeholders of a `--format` template for one commit.
+/// Unknown placeholders are left as they are.
+fn expand(
+    repository: &Repository,
+    walked: &WalkedCommit,
+    template: &str,
+    date_mode: &str,
+) -> Result<String, Error> {
+    let commit = &walked.commit;
+    let abbreviate = |id: &ObjectId| git_object::abbreviate(repository, id, 7);
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(percent) = rest.find('%') {
+        output += &rest[..percent];
+        rest = &rest[percent + 1..];
+        let mut chars = rest.chars();
+        let (expansion, length) = match (chars.next(), chars.next()) {
+            (Some('%'), _) => ("%".to_string(), 1),
+            (Some('n'), _) => ("\n".to_string(), 1),
+            (Some('H'), _) => (walked.id.to_string(), 1),
+            (Some('h'), _) => (abbreviate(&walked.id)?, 1),
+            (Some('T'), _) => (commit.tree.to_string(), 1),
+            (Some('t'), _) => (abbreviate(&commit.tree)?, 1),
+            (Some('P'), _) => {
+                let parents: Vec<String> = walked
+                    .parents
+                    .iter()
+                    .map(|parent| parent.to_string())
+                    .collect();
+                (parents.join(" "), 1)
+            }
+            (Some('p'), _) => {
+                let mut parents = Vec::new();
+                for parent in walked.parents.iter() {
+                    parents.push(abbreviate(parent)?);
+                }
+                (parents.join(" "), 1)
+            }
+            (Some('s'), _) => (subject(&commit.message), 1),
+            (Some('b'), _) => (body(&commit.message), 1),
+            (Some('B'), _) => (commit.message.clone(), 1),
+            (Some('a'), Some(field)) => match expand_signature(&commit.author, field, date_mode)? {
+                Some(expansion) => (expansion, 2),
+                None => ("%".to_string(), 0),
+            },
+            (Some('c'), Some(field)) => {
+                match expand_signature(&commit.committer, field, date_mode)? {
+                    Some(expansion) => (expansion, 2),
+                    None => ("%".to_string(), 0),
+                }
+            }
+            (Some('x'), _) => match rest
+                .get(1..3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => ((byte as char).to_string(), 3),
+                None => ("%".to_string(), 0),
+            },
+            _ => ("%".to_string(), 0),
+        };
+        output += &expansion;
+        rest = &rest[length..];
+    }
+    output += rest;
+    return Ok(output);
+}
+
+/// `%a<field>` and `%c<field>`: the name, email or date of a signature.
+fn expand_signature(
+    signature: &Signature,
+    field: char,
+    date_mode: &str,
+) -> Result<Option<String>, Error> {
+    let date = |mode: &str| git_date::format(signature.timestamp, signature.timezone_offset, mode);
+    return Ok(Some(match field {
+        'n' => signature.name.clone(),
+        'e' => signature.email.clone(),
+        'd' => date(date_mode)?,
+        'D' => date("rfc")?,
+        'r' => date("relative")?,
+        't' => date("unix")?,
+        'i' => date("iso")?,
+        'I' => date("iso-strict")?,
+        's' => date("short")?,
+        _ => return Ok(None),
+    }));
+}
@@ -0,0 +1,258 @@
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    error::Error, git, git_config, git_date, git_object::Signature, git_refs, lock_file::LockFile,
+    object_id::ObjectId, repository::Repository,
+};
+
+/// One line of `.git/logs/<ref>`: a move of the ref from `old` to `new`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReflogEntry {
+    pub old: ObjectId,
+    pub new: ObjectId,
+    pub committer: Signature,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let malformed = || format!("fatal: malformed reflog line: {line}");
+        let (old, rest) = line.split_once(' ').ok_or_else(malformed)?;
+        let (new, rest) = rest.split_once(' ').ok_or_else(malformed)?;
+        let (committer, message) = rest.split_once('\t').unwrap_or((rest, ""));
+        return Ok(ReflogEntry {
+            old: ObjectId::from_hex(old)?,
+            new: ObjectId::from_hex(new)?,
+            committer: Signature::parse(committer)?,
+            message: message.to_string(),
+        });
+    }
+
+    pub fn serialize(&self) -> String {
+        if self.message.is_empty() {
+            return format!("{} {} {}\n", self.old, self.new, self.committer);
+        }
+        return format!(
+            "{} {} {}\t{}\n",
+            self.old, self.new, self.committer, self.message
+        );
+    }
+}
+
+fn log_path(repository: &Repository, name: &str) -> PathBuf {
+    return repository.git_path(format!("logs/{name}"));
+}
+
+pub fn exists(repository: &Repository, name: &str) -> bool {
+    return log_path(repository, name).is_file();
+}
+
+/// Whether updates of `name` get logged: refs that already have a log
+/// always do, others depend on `core.logAllRefUpdates`, which by default
+/// covers HEAD, branches, remote-tracking branches and notes in non-bare
+/// repositories.
+fn should_log(repository: &Repository, name: &str) -> Result<bool, Error> {
+    if exists(repository, name) {
+        return Ok(true);
+    }
+    let enabled = match git_config::get(repository, "core.logallrefupdates")? {
+        Some(value) if value.eq_ignore_ascii_case("always") => return Ok(true),
+        Some(value) => git_config::parse_bool(Some(&value), "core.logallrefupdates")?,
+        None => repository.work_tree.is_some(),
+    };
+    return Ok(enabled
+        && (name == "HEAD"
+            || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))));
+}
+
+/// Records that `name` moved from `old` to `new`, if `name` is logged.
+pub fn append(
+    repository: &Repository,
+    name: &str,
+    old: &ObjectId,
+    new: &ObjectId,
+    message: &str,
+) -> Result<(), Error> {
+    if !should_log(repository, name)? {
+        return Ok(());
+    }
+    let path = log_path(repository, name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| Error::io(format!("error creating reflog directory for {name}"), err))?;
+    }
+    let entry = ReflogEntry {
+        old: *old,
+        new: *new,
+        committer: git::reflog_signature(repository)?,
+        message: message.lines().next().unwrap_or("").to_string(),
+    };
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|err| Error::io(format!("error opening reflog for {name}"), err))?;
+    return log
+        .write_all(entry.serialize().as_bytes())
+        .map_err(|err| Error::io(format!("error writing reflog for {name}"), err));
+}
+
+/// The entries of `name`'s log, oldest first. Refs without a log have none.
+pub fn read(repository: &Repository, name: &str) -> Result<Vec<ReflogEntry>, Error> {
+    let contents = match fs::read_to_string(log_path(repository, name)) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::io(format!("error reading reflog for {name}"), err)),
+    };
+    return contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(ReflogEntry::parse)
+        .collect();
+}
+
+/// Replaces the log of `name` with `entries`.
+pub fn write(repository: &Repository, name: &str, entries: &[ReflogEntry]) -> Result<(), Error> {
+    let mut lock = LockFile::acquire(&log_path(repository, name))?;
+    let contents: String = entries.iter().map(ReflogEntry::serialize).collect();
+    lock.write(contents.as_bytes())?;
+    return lock.commit();
+}
+
+/// Removes the log of `name`, as done when the ref itself is deleted.
+pub fn delete(repository: &Repository, name: &str) -> Result<(), Error> {
+    match fs::remove_file(log_path(repository, name)) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(Error::io(format!("error deleting reflog for {name}"), err)),
+    }
+    git_refs::remove_empty_parents(repository, "logs/", name);
+    return Ok(());
+}
+
+/// Where `name` was according to its log: `selector` is either `n`, the
+/// value `n` moves ago, or a date, the value it had at that time. This is
+/// the `@{...}` part of `<ref>@{...}`.
+pub fn lookup(repository: &Repository, name: &str, selector: &str) -> Result<ObjectId, Error> {
+    let entries = read(repository, name)?;
+    let oldest = match entries.first() {
+        Some(oldest) => oldest,
+        // the newest entry of an empty log is the ref as it is now
+        None if selector == "0" => {
+            return git_refs::resolve(repository, name)?
+                .ok_or_else(|| Error::ObjectNotFound(format!("{name}@{{0}}")))
+        }
+        None => return Err(format!("fatal: log for '{name}' is empty").into()),
+    };
+    let name = git_refs::shorten(name);
+
+    if let Ok(count) = selector.parse::<usize>() {
+        if count < entries.len() {
+            return Ok(entries[entries.len() - 1 - count].new);
+        }
+        // one past the end is where the oldest entry started from
+        if count == entries.len() && !oldest.old.is_null() {
+            return Ok(oldest.old);
+        }
+        return Err(format!("fatal: log for '{name}' only has {} entries", entries.len()).into());
+    }
+
+    let (timestamp, _) = git_date::parse(selector)?;
+    if let Some(entry) = entries
+        .iter()
+        .rev()
+        .find(|entry| entry.committer.timestamp <= timestamp)
+    {
+        return Ok(entry.new);
+    }
+    eprintln!(
+        "warning: log for '{name}' only goes back to {}",
+        git_date::format_rfc2822(oldest.committer.timestamp, oldest.committer.timezone_offset)
+    );
+    return Ok(if oldest.old.is_null() {
+        oldest.new
+    } else {
+        oldest.old
+    });
+}
+
+/// Every ref that has a log, HEAD first.
+pub fn list(repository: &Repository) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    if exists(repository, "HEAD") {
+        names.push("HEAD".to_string());
+    }
+    git_refs::list_loose(&log_path(repository, "refs"), "refs", &mut names)?;
+    return Ok(names);
+}
+
+/// Drops the entries of `name`'s log older than `expire`, and those older
+/// than `expire_unreachable` that are no longer reachable from the ref.
+/// Returns how many entries were removed.
+pub fn expire(
+    repository: &Repository,
+    name: &str,
+    expire: i64,
+    expire_unreachable: i64,
+) -> Result<usize, Error> {
+    let entries = read(repository, name)?;
+    let mut reachable = None;
+    let mut kept = Vec::new();
+    for entry in entries.iter() {
+        let timestamp = entry.committer.timestamp;
+        if timestamp < expire {
+            continue;
+        }
+        if timestamp < expire_unreachable {
+            if reachable.is_none() {
+                reachable = Some(match git_refs::resolve(repository, name)? {
+                    Some(tip) => {
+                        git::reachable_commits(repository, &git_refs::peel(repository, &tip)?)?
+                    }
+                    None => Default::default(),
+                });
+            }
+            // like git, an entry goes once either end is unreachable; a
+            // null end (a creation or deletion) doesn't count
+            let reachable = reachable.as_ref().unwrap();
+            let unreachable = |id: &ObjectId| *id != ObjectId::NULL && !reachable.contains(id);
+            if unreachable(&entry.old) || unreachable(&entry.new) {
+                continue;
+            }
+        }
+        kept.push(entry.clone());
+    }
+    if kept.len() != entries.len() {
+        write(repository, name, &kept)?;
+    }
+    return Ok(entries.len() - kept.len());
+}
+
+/// Removes the entry `index` moves ago from `name`'s log, if there is one.
+/// With `rewrite` the entry after it is changed to start where the removed
+/// one did, so the log stays a connected chain.
+pub fn delete_entry(
+    repository: &Repository,
+    name: &str,
+    index: usize,
+    rewrite: bool,
+) -> Result<(), Error> {
+    let mut entries = read(repository, name)?;
+    if index >= entries.len() {
+        return Ok(());
+    }
+    let position = entries.len() - 1 - index;
+    let removed = entries.remove(position);
+    if rewrite {
+        if let Some(next) = entries.get_mut(position) {
+            next.old = removed.old;
+        }
+    }
+    return write(repository, name, &entries);
+}
@@ -0,0 +1,1142 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+use crate::{
+    error::Error,
+    git_object::{self, ObjectType},
+    git_reflog,
+    lock_file::LockFile,
+    object_id::ObjectId,
+    repository::Repository,
+};
+
+/// How deep symbolic refs may nest before we give up, like git.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+const PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted ";
+
+/// What a single ref holds, without following symbolic refs.
+#[derive(Clone, PartialEq, Debug)]
+pub enum RefValue {
+    Direct(ObjectId),
+    /// `ref: <name>`, e.g. HEAD pointing at `refs/heads/main`.
+    Symbolic(String),
+}
+
+/// One change in a ref transaction, using git's convention that the null id
+/// stands for a ref that doesn't exist.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RefUpdate {
+    pub name: String,
+    /// The value to store, `ObjectId::NULL` to delete the ref or `None` to
+    /// only check `old`.
+    pub new: Option<ObjectId>,
+    /// The value the ref must have for the transaction to go ahead,
+    /// `ObjectId::NULL` if it must not exist or `None` for any value.
+    pub old: Option<ObjectId>,
+    /// Whether a symbolic `name` is followed to the ref it points at.
+    pub deref: bool,
+    /// What the reflog entry for the update says.
+    pub message: String,
+}
+
+/// A ref that resolves to an object, as listed by `show-ref`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ref {
+    pub name: String,
+    pub id: ObjectId,
+    /// For annotated tags, what the tag ultimately points at. Only known
+    /// up front for packed refs that have a `^` line.
+    pub peeled: Option<ObjectId>,
+}
+
+/// `packed-refs` as last read, along with the size and modification time
+/// the file had then, so it is only parsed again once it changes.
+pub struct PackedRefs {
+    stamp: Option<(u64, SystemTime)>,
+    /// Sorted by name.
+    refs: Rc<Vec<Ref>>,
+}
+
+/// Reads `name` from its loose file or, failing that, from `packed-refs`.
+pub fn read(repository: &Repository, name: &str) -> Result<Option<RefValue>, Error> {
+    if let Some(value) = read_loose(repository, name)? {
+        return Ok(Some(value));
+    }
+    return Ok(find_packed(&read_packed(repository)?, name)
+        .map(|reference| RefValue::Direct(reference.id)));
+}
+
+fn find_packed<'a>(packed: &'a [Ref], name: &str) -> Option<&'a Ref> {
+    return packed
+        .binary_search_by(|reference| reference.name.as_str().cmp(name))
+        .ok()
+        .map(|at| &packed[at]);
+}
+
+/// Whether `name` may be looked up in the git directory: a well-formed
+/// name below `refs/` or one like `HEAD`, but never something like
+/// `refs/../config` that leads elsewhere.
+fn is_safe(name: &str) -> bool {
+    return is_pseudo_ref(name) || (name.starts_with("refs/") && check_format(name, true));
+}
+
+fn read_loose(repository: &Repository, name: &str) -> Result<Option<RefValue>, Error> {
+    if !is_safe(name) {
+        return Ok(None);
+    }
+    let path = repository.git_path(name);
+    if path.is_dir() {
+        return Ok(None);
+    }
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        // a ref below what is a file is no more there than a missing one
+        Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+            return Ok(None)
+        }
+        Err(err) => return Err(Error::io(format!("error reading {name}"), err)),
+    };
+    let contents = contents.trim_end();
+    if let Some(target) = contents.strip_prefix("ref:") {
+        return Ok(Some(RefValue::Symbolic(target.trim().to_string())));
+    }
+    return ObjectId::from_hex(contents)
+        .map(|id| Some(RefValue::Direct(id)))
+        .map_err(|_| format!("fatal: bad ref '{name}': {contents}").into());
+}
+
+/// Follows symbolic refs from `name` to the ref that holds an object id and
+/// returns its name, which may not exist yet (an unborn branch).
+pub fn resolve_name(repository: &Repository, name: &str) -> Result<String, Error> {
+    let mut name = name.to_string();
+    for _ in 0..=MAX_SYMREF_DEPTH {
+        match read(repository, &name)? {
+            Some(RefValue::Symbolic(target)) => name = target,
+            _ => return Ok(name),
+        }
+    }
+    return Err(format!("fatal: symbolic ref loop at {name}").into());
+}
+
+/// The object `name` ultimately points at, `None` if it (or the ref a
+/// symbolic ref points at) doesn't exist.
+pub fn resolve(repository: &Repository, name: &str) -> Result<Option<ObjectId>, Error> {
+    let name = resolve_name(repository, name)?;
+    return Ok(match read(repository, &name)? {
+        Some(RefValue::Direct(id)) => Some(id),
+        _ => None,
+    });
+}
+
+/// Expands a short ref name the way git does, trying `<name>`,
+/// `refs/<name>`, `refs/tags/<name>`, `refs/heads/<name>`,
+/// `refs/remotes/<name>` and `refs/remotes/<name>/HEAD` in that order.
+pub fn expand(repository: &Repository, name: &str) -> Result<Option<String>, Error> {
+    if name.is_empty() {
+        return Ok(None);
+    }
+    for candidate in [
+        name.to_string(),
+        format!("refs/{name}"),
+        format!("refs/tags/{name}"),
+        format!("refs/heads/{name}"),
+        format!("refs/remotes/{name}"),
+        format!("refs/remotes/{name}/HEAD"),
+    ] {
+        // only HEAD-like names are looked up outside refs/
+        if !candidate.starts_with("refs/") && !is_pseudo_ref(&candidate) {
+            continue;
+        }
+        if resolve(repository, &candidate)?.is_some() {
+            return Ok(Some(candidate));
+        }
+    }
+    return Ok(None);
+}
+
+/// Names like `HEAD` or `ORIG_HEAD` that live directly in the git directory.
+fn is_pseudo_ref(name: &str) -> bool {
+    return !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+        && name.ends_with("HEAD");
+}
+
+/// Points `name` at `id`, replacing a packed value if there is one.
+pub fn write(
+    repository: &Repository,
+    name: &str,
+    id: &ObjectId,
+    message: &str,
+) -> Result<(), Error> {
+    let update = RefUpdate {
+        name: name.to_string(),
+        new: Some(*id),
+        old: None,
+        deref: false,
+        message: message.to_string(),
+    };
+    return apply(repository, &[update]);
+}
+
+/// Makes `name` a symbolic ref to `target`. Unless `target` is unborn, the
+/// switch is logged even if `name` pointed at the same object before.
+pub fn write_symbolic(
+    repository: &Repository,
+    name: &str,
+    target: &str,
+    message: &str,
+) -> Result<(), Error> {
+    let old = resolve(repository, name)?;
+    write_loose(repository, name, &format!("ref: {target}\n"))?;
+    if let Some(new) = resolve(repository, target)? {
+        git_reflog::append(
+            repository,
+            name,
+            &old.unwrap_or(ObjectId::NULL),
+            &new,
+            message,
+        )?;
+    }
+    return Ok(());
+}
+
+fn write_loose(repository: &Repository, name: &str, contents: &str) -> Result<(), Error> {
+    if !is_safe(name) {
+        return Err(format!("fatal: refusing to update ref with bad name '{name}'").into());
+    }
+    let mut lock = LockFile::acquire(&repository.git_path(name))?;
+    lock.write(contents.as_bytes())?;
+    return lock.commit();
+}
+
+/// Removes `name` from both loose and packed storage.
+pub fn delete(repository: &Repository, name: &str) -> Result<(), Error> {
+    let lock = LockFile::acquire(&repository.git_path(name))?;
+    remove_packed(repository, &[name])?;
+    remove_loose(repository, name, lock)?;
+    return git_reflog::delete(repository, name);
+}
+
+/// Moves the ref `name` to `new_name` along with its log, which gets an
+/// entry saying so. HEAD follows if it pointed at `name`. An existing
+/// `new_name` is replaced.
+///
+/// The new ref is written before the old one goes, so a failure in between
+/// leaves both rather than neither. Only when one is in the way of the
+/// other, as with `refs/heads/a` and `refs/heads/a/b`, does the old ref go
+/// first, and it is put back if the new one can't be written.
+pub fn rename(
+    repository: &Repository,
+    name: &str,
+    new_name: &str,
+    message: &str,
+) -> Result<(), Error> {
+    let id = resolve(repository, name)?.ok_or_else(|| format!("fatal: No such ref: {name}"))?;
+    let log = git_reflog::read(repository, name)?;
+    let head = resolve_name(repository, "HEAD")?;
+    let in_the_way =
+        new_name.starts_with(&format!("{name}/")) || name.starts_with(&format!("{new_name}/"));
+    if in_the_way {
+        delete(repository, name)?;
+    }
+    if let Err(err) = write_renamed(repository, new_name, &id, &log, message) {
+        if in_the_way {
+            write_loose(repository, name, &format!("{id}\n"))?;
+            git_reflog::write(repository, name, &log)?;
+        }
+        return Err(err);
+    }
+    if !in_the_way && new_name != name {
+        delete(repository, name)?;
+    }
+    if head == name {
+        // like git, HEAD's log shows the old branch going away and the new
+        // one taking its place
+        git_reflog::append(repository, "HEAD", &id, &ObjectId::NULL, message)?;
+        write_symbolic(repository, "HEAD", new_name, message)?;
+    }
+    return Ok(());
+}
+
+/// Writes the ref a rename creates, with the `log` of the old one.
+fn write_renamed(
+    repository: &Repository,
+    new_name: &str,
+    id: &ObjectId,
+    log: &[git_reflog::ReflogEntry],
+    message: &str,
+) -> Result<(), Error> {
+    if log.is_empty() {
+        git_reflog::delete(repository, new_name)?;
+    } else {
+        git_reflog::write(repository, new_name, log)?;
+    }
+    write_loose(repository, new_name, &format!("{id}\n"))?;
+    return git_reflog::append(repository, new_name, id, id, message);
+}
+
+/// Deletes the loose file of `name` while holding its `lock`.
+fn remove_loose(repository: &Repository, name: &str, lock: LockFile) -> Result<(), Error> {
+    match fs::remove_file(repository.git_path(name)) {
+        Ok(()) => {}
+        // a directory of other refs means this one is only packed
+        Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::IsADirectory) => {}
+        Err(err) => return Err(Error::io(format!("error deleting {name}"), err)),
+    }
+    // the lock file has to go before its directory can
+    drop(lock);
+    remove_empty_parents(repository, "", name);
+    return Ok(());
+}
+
+/// Applies all `updates` or, if any of them can't be made, none of them.
+/// Every ref involved is locked and its new value written to the lock file
+/// before the first one is touched, so other processes see either the old
+/// or the new values. Changed refs get a reflog entry, and so does HEAD when
+/// it points at one of them.
+pub fn apply(repository: &Repository, updates: &[RefUpdate]) -> Result<(), Error> {
+    return prepare(repository, updates)?.commit();
+}
+
+/// The first half of [`apply`]: locks every ref of `updates`, checks their
+/// old values and writes the new ones to the lock files, without changing
+/// anything yet.
+pub fn prepare<'a>(
+    repository: &'a Repository,
+    updates: &[RefUpdate],
+) -> Result<Transaction<'a>, Error> {
+    let mut names: Vec<String> = Vec::new();
+    for update in updates {
+        let name = if update.deref {
+            resolve_name(repository, &update.name)?
+        } else {
+            update.name.clone()
+        };
+        if !is_safe(&name) {
+            return Err(format!("fatal: refusing to update ref with bad name '{name}'").into());
+        }
+        if names.contains(&name) {
+            return Err(format!("fatal: multiple updates for ref '{name}' not allowed").into());
+        }
+        names.push(name);
+    }
+    let written: Vec<&str> = names
+        .iter()
+        .zip(updates)
+        .filter(|(_, update)| update.new.is_some_and(|new| !new.is_null()))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let deleted: Vec<String> = names
+        .iter()
+        .zip(updates)
+        .filter(|(_, update)| update.new == Some(ObjectId::NULL))
+        .map(|(name, _)| name.clone())
+        .collect();
+    check_conflicts(repository, &written)?;
+
+    let mut locked: Vec<Locked> = Vec::new();
+    for (name, update) in names.into_iter().zip(updates) {
+        let mut lock = LockFile::acquire(&repository.git_path(&name))?;
+        let current = resolve(repository, &name)?;
+        if let Some(old) = update.old {
+            verify(&name, &current, &old)?;
+        }
+        let new = match update.new {
+            Some(new) if new.is_null() => Some(new),
+            // rewriting a ref with its own value is a no-op, but replacing
+            // a symbolic ref with the id it resolved to isn't
+            Some(new) if read(repository, &name)? != Some(RefValue::Direct(new)) => {
+                lock.write(format!("{new}\n").as_bytes())?;
+                Some(new)
+            }
+            _ => None,
+        };
+        locked.push(Locked {
+            name,
+            current,
+            new,
+            update: update.clone(),
+            lock,
+        });
+    }
+    return Ok(Transaction {
+        repository,
+        locked,
+        deleted,
+    });
+}
+
+/// Ref updates that are locked and checked, waiting to be committed.
+/// Dropping the transaction releases the locks and changes nothing.
+pub struct Transaction<'a> {
+    repository: &'a Repository,
+    locked: Vec<Locked>,
+    deleted: Vec<String>,
+}
+
+impl Transaction<'_> {
+    /// Moves the prepared values into place and logs the changes.
+    pub fn commit(self) -> Result<(), Error> {
+        let repository = self.repository;
+        // nothing has changed up to here, and from here on only renames and
+        // deletions are left
+        let deleted: Vec<&str> = self.deleted.iter().map(|name| name.as_str()).collect();
+        remove_packed(repository, &deleted)?;
+        let head = resolve_name(repository, "HEAD")?;
+        let mut changed = Vec::new();
+        for locked in self.locked {
+            match locked.new {
+                Some(new) if new.is_null() => remove_loose(repository, &locked.name, locked.lock)?,
+                Some(new) => {
+                    locked.lock.commit()?;
+                    let old = locked.current.unwrap_or(ObjectId::NULL);
+                    changed.push((locked.name, old, new, locked.update));
+                }
+                None => {}
+            }
+        }
+        for name in deleted {
+            git_reflog::delete(repository, name)?;
+        }
+        for (name, old, new, update) in changed {
+            git_reflog::append(repository, &name, &old, &new, &update.message)?;
+            if update.name != name {
+                git_reflog::append(repository, &update.name, &old, &new, &update.message)?;
+            }
+            if name != "HEAD" && head == name && update.name != "HEAD" {
+                git_reflog::append(repository, "HEAD", &old, &new, &update.message)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// A ref held by a transaction, with the value it had and the one it gets
+/// if it changes.
+struct Locked {
+    name: String,
+    current: Option<ObjectId>,
+    new: Option<ObjectId>,
+    update: RefUpdate,
+    lock: LockFile,
+}
+
+/// Checks that none of the refs to be `written` needs a file where there is
+/// a directory of other refs or the other way around, e.g. `refs/heads/a`
+/// next to `refs/heads/a/b`, whether those are loose, packed or part of the
+/// same transaction. Like git, this holds even for refs the transaction
+/// deletes.
+fn check_conflicts(repository: &Repository, written: &[&str]) -> Result<(), Error> {
+    let packed = read_packed(repository)?;
+    let exists =
+        |name: &str| repository.git_path(name).is_file() || find_packed(&packed, name).is_some();
+    for name in written {
+        let rejected = |locked: &str, reason: String| Error::RefRejected {
+            name: locked.to_string(),
+            reason,
+        };
+        let mut current = *name;
+        while let Some((parent, _)) = current.rsplit_once('/') {
+            if written.contains(&parent) {
+                return Err(rejected(
+                    name,
+                    format!("cannot process '{name}' and '{parent}' at the same time"),
+                ));
+            }
+            if exists(parent) {
+                return Err(rejected(
+                    name,
+                    format!("'{parent}' exists; cannot create '{name}'"),
+                ));
+            }
+            current = parent;
+        }
+
+        let directory = format!("{name}/");
+        if let Some(child) = written.iter().find(|other| other.starts_with(&directory)) {
+            return Err(rejected(
+                name,
+                format!("cannot process '{name}' and '{child}' at the same time"),
+            ));
+        }
+        let mut below = Vec::new();
+        if repository.git_path(name).is_dir() {
+            list_loose(&repository.git_path(name), name, &mut below)?;
+        }
+        let first = packed.partition_point(|reference| reference.name < directory);
+        below.extend(
+            packed[first..]
+                .iter()
+                .take_while(|reference| reference.name.starts_with(&directory))
+                .map(|reference| reference.name.clone()),
+        );
+        if let Some(child) = below.first() {
+            return Err(rejected(
+                name,
+                format!("'{child}' exists; cannot create '{name}'"),
+            ));
+        }
+    }
+    return Ok(());
+}
+
+/// Checks that a ref at `current` holds `expected`.
+fn verify(name: &str, current: &Option<ObjectId>, expected: &ObjectId) -> Result<(), Error> {
+    let rejected = |reason: String| Error::RefRejected {
+        name: name.to_string(),
+        reason,
+    };
+    return match (current, expected.is_null()) {
+        (None, true) => Ok(()),
+        (None, false) => Err(rejected(format!("unable to resolve reference '{name}'"))),
+        (Some(_), true) => Err(rejected("reference already exists".to_string())),
+        (Some(current), false) if current == expected => Ok(()),
+        (Some(current), false) => Err(rejected(format!("is at {current} but expected {expected}"))),
+    };
+}
+
+/// Drops the directories of the git directory below `prefix` (`""` for
+/// refs, `"logs/"` for their logs) left empty by deleting `name`, keeping
+/// the top-level ones like `refs/heads` in place.
+pub(crate) fn remove_empty_parents(repository: &Repository, prefix: &str, name: &str) {
+    let mut current = name;
+    while let Some((parent, _)) = current.rsplit_once('/') {
+        if !parent.starts_with("refs/") || parent.matches('/').count() < 2 {
+            return;
+        }
+        if fs::remove_dir(repository.git_path(format!("{prefix}{parent}"))).is_err() {
+            return;
+        }
+        current = parent;
+    }
+}
+
+/// Every ref below `prefix` (e.g. `refs/` or `refs/tags/`) that resolves to
+/// an object, sorted by name. Loose refs take precedence over packed ones.
+pub fn list(repository: &Repository, prefix: &str) -> Result<Vec<Ref>, Error> {
+    let mut refs: BTreeMap<String, Ref> = BTreeMap::new();
+    for reference in read_packed(repository)?.iter() {
+        if reference.name.starts_with(prefix) {
+            refs.insert(reference.name.clone(), reference.clone());
+        }
+    }
+    let mut loose = Vec::new();
+    list_loose(&repository.git_path("refs"), "refs", &mut loose)?;
+    for name in loose.into_iter().filter(|name| name.starts_with(prefix)) {
+        let id = match read_loose(repository, &name)? {
+            Some(RefValue::Direct(id)) => Some(id),
+            Some(RefValue::Symbolic(target)) => resolve(repository, &target)?,
+            None => continue,
+        };
+        match id {
+            Some(id) => {
+                refs.insert(
+                    name.clone(),
+                    Ref {
+                        name,
+                        id,
+                        peeled: None,
+                    },
+                );
+            }
+            // a dangling symbolic ref
+            None => {
+                refs.remove(&name);
+            }
+        }
+    }
+    return Ok(refs.into_values().collect());
+}
+
+/// Collects the files below `directory` as `<name>/<path>`, skipping
+/// `.lock` files.
+pub fn list_loose(directory: &Path, name: &str, result: &mut Vec<String>) -> Result<(), Error> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(Error::io(format!("error reading {name}"), err)),
+    };
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::io(format!("error reading {name}"), err))?;
+        let entry_name = format!("{name}/{}", entry.file_name().to_string_lossy());
+        let path = entry.path();
+        if path.is_dir() {
+            list_loose(&path, &entry_name, result)?;
+        } else if !entry_name.ends_with(".lock") {
+            result.push(entry_name);
+        }
+    }
+    return Ok(());
+}
+
+/// Follows annotated tags from `id` to the object they are about.
+pub fn peel(repository: &Repository, id: &ObjectId) -> Result<ObjectId, Error> {
+    let mut id = *id;
+    while git_object::get_type(repository, &id)? == ObjectType::Tag {
+        id = git_object::read_tag(repository, &id)?.object;
+    }
+    return Ok(id);
+}
+
+fn packed_refs_path(repository: &Repository) -> PathBuf {
+    return repository.git_path("packed-refs");
+}
+
+/// Reads `packed-refs`, attaching each `^<id>` line to the ref before it.
+/// The result is kept in `repository` and reused until the file changes.
+pub fn read_packed(repository: &Repository) -> Result<Rc<Vec<Ref>>, Error> {
+    let stamp = packed_stamp(repository)?;
+    if let Some(cached) = repository.packed_refs.borrow().as_ref() {
+        if cached.stamp == stamp {
+            return Ok(cached.refs.clone());
+        }
+    }
+    let contents = match stamp {
+        Some(_) => fs::read_to_string(packed_refs_path(repository))
+            .map_err(|err| Error::io("error reading packed-refs", err))?,
+        None => String::new(),
+    };
+    let mut refs: Vec<Ref> = Vec::new();
+    for line in contents.lines() {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(peeled) = line.strip_prefix('^') {
+            let last = refs
+                .last_mut()
+                .ok_or_else(|| format!("fatal: unexpected line in packed-refs: {line}"))?;
+            last.peeled = Some(ObjectId::from_hex(peeled)?);
+            continue;
+        }
+        let (id, name) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("fatal: unexpected line in packed-refs: {line}"))?;
+        refs.push(Ref {
+            name: name.to_string(),
+            id: ObjectId::from_hex(id)?,
+            peeled: None,
+        });
+    }
+    // files written without the `sorted` trait may be in any order
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    let refs = Rc::new(refs);
+    *repository.packed_refs.borrow_mut() = Some(PackedRefs {
+        stamp,
+        refs: refs.clone(),
+    });
+    return Ok(refs);
+}
+
+/// The size and modification time of `packed-refs`, `None` if there is
+/// none.
+fn packed_stamp(repository: &Repository) -> Result<Option<(u64, SystemTime)>, Error> {
+    let metadata = match fs::metadata(packed_refs_path(repository)) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::io("error reading packed-refs", err)),
+    };
+    let modified = metadata
+        .modified()
+        .map_err(|err| Error::io("error reading packed-refs", err))?;
+    return Ok(Some((metadata.len(), modified)));
+}
+
+/// Replaces `packed-refs` with `refs`, writing peeled lines for annotated
+/// tags so readers don't have to open the tag objects.
+pub fn write_packed(repository: &Repository, refs: &[Ref]) -> Result<(), Error> {
+    let lock = LockFile::acquire(&packed_refs_path(repository))?;
+    return commit_packed(repository, refs, lock);
+}
+
+/// Drops `names` from `packed-refs`, leaving the file alone if none of them
+/// are in it.
+fn remove_packed(repository: &Repository, names: &[&str]) -> Result<(), Error> {
+    if names.is_empty() || !packed_refs_path(repository).exists() {
+        return Ok(());
+    }
+    let lock = LockFile::acquire(&packed_refs_path(repository))?;
+    let mut packed = read_packed(repository)?.to_vec();
+    let count = packed.len();
+    packed.retain(|reference| !names.contains(&reference.name.as_str()));
+    if packed.len() == count {
+        return Ok(());
+    }
+    return commit_packed(repository, &packed, lock);
+}
+
+fn commit_packed(repository: &Repository, refs: &[Ref], mut lock: LockFile) -> Result<(), Error> {
+    let mut refs = refs.to_vec();
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut contents = format!("{PACKED_REFS_HEADER}\n");
+    for reference in refs.iter_mut() {
+        contents += format!("{} {}\n", reference.id, reference.name).as_str();
+        reference.peeled = match reference.peeled {
+            Some(peeled) => Some(peeled),
+            None if git_object::get_type(repository, &reference.id).ok()
+                == Some(ObjectType::Tag) =>
+            {
+                Some(peel(repository, &reference.id)?)
+            }
+            None => None,
+        };
+        if let Some(peeled) = reference.peeled {
+            contents += format!("^{peeled}\n").as_str();
+        }
+    }
+    lock.write(contents.as_bytes())?;
+    lock.commit()?;
+    *repository.packed_refs.borrow_mut() = Some(PackedRefs {
+        stamp: packed_stamp(repository)?,
+        refs: Rc::new(refs),
+    });
+    return Ok(());
+}
+
+/// Checks `name` against git's rules for ref names (see
+/// `git check-ref-format`). Names need at least two components unless
+/// `allow_onelevel` is set.
+pub fn check_format(name: &str, allow_onelevel: bool) -> bool {
+    if name.is_empty()
+        || name == "@"
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.contains("..")
+        || name.contains("@{")
+        || name.contains("//")
+    {
+        return false;
+    }
+    if name
+        .chars()
+        .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return false;
+    }
+    if !allow_onelevel && !name.contains('/') {
+        return false;
+    }
+    return name
+        .split('/')
+        .all(|component| !component.starts_with('.') && !component.ends_with(".lock"));
+}
+
+/// The short form git shows for a ref, e.g. `main` for `refs/heads/main`.
+pub fn shorten(name: &str) -> &str {
+    for prefix in ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"] {
+        if let Some(short) = name.strip_prefix(prefix) {
+            return short;
+        }
+    }
+    return name;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    /// A repository in a fresh temporary directory, removed when dropped.
+    struct TestRepository {
+        directory: PathBuf,
+        repository: Repository,
+    }
+
+    impl TestRepository {
+        fn new(name: &str) -> TestRepository {
+            let directory = env::temp_dir().join(format!("git-refs-{}-{name}", process::id()));
+            let _ = fs::remove_dir_all(&directory);
+            let repository = Repository::init(&directory, false).unwrap();
+            return TestRepository {
+                directory,
+                repository,
+            };
+        }
+    }
+
+    impl Drop for TestRepository {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.directory);
+        }
+    }
+
+    fn id(name: &str) -> ObjectId {
+        return ObjectId::hash(name.as_bytes());
+    }
+
+    fn update(name: &str, new: Option<ObjectId>, old: Option<ObjectId>) -> RefUpdate {
+        return RefUpdate {
+            name: name.to_string(),
+            new,
+            old,
+            deref: false,
+            message: format!("update {name}"),
+        };
+    }
+
+    fn reason(result: Result<(), Error>) -> String {
+        return match result {
+            Err(Error::RefRejected { reason, .. }) => reason,
+            other => panic!("expected a rejected ref, got {other:?}"),
+        };
+    }
+
+    fn no_locks_left(repository: &Repository) -> bool {
+        let mut names = Vec::new();
+        list_loose(&repository.git_path("refs"), "refs", &mut names).unwrap();
+        return !names.iter().any(|name| name.ends_with(".lock"))
+            && !repository.git_path("packed-refs.lock").exists();
+    }
+
+    #[test]
+    fn apply_writes_and_logs_every_update() {
+        let test = TestRepository::new("apply");
+        let repository = &test.repository;
+        let branch = resolve_name(repository, "HEAD").unwrap();
+        apply(
+            repository,
+            &[
+                update(&branch, Some(id("one")), Some(ObjectId::NULL)),
+                update("refs/tags/v1", Some(id("two")), None),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(resolve(repository, &branch).unwrap(), Some(id("one")));
+        assert_eq!(resolve(repository, "HEAD").unwrap(), Some(id("one")));
+        assert_eq!(
+            resolve(repository, "refs/tags/v1").unwrap(),
+            Some(id("two"))
+        );
+        let log = git_reflog::read(repository, &branch).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!((log[0].old, log[0].new), (ObjectId::NULL, id("one")));
+        assert_eq!(log[0].message, format!("update {branch}"));
+        // HEAD points at the branch, so it logs the move too; tags aren't
+        // logged by default
+        assert_eq!(git_reflog::read(repository, "HEAD").unwrap().len(), 1);
+        assert!(!git_reflog::exists(repository, "refs/tags/v1"));
+
+        // writing the same value again changes nothing
+        apply(repository, &[update(&branch, Some(id("one")), None)]).unwrap();
+        assert_eq!(git_reflog::read(repository, &branch).unwrap().len(), 1);
+        assert!(no_locks_left(repository));
+    }
+
+    #[test]
+    fn apply_follows_symbolic_refs_when_asked() {
+        let test = TestRepository::new("deref");
+        let repository = &test.repository;
+        let branch = resolve_name(repository, "HEAD").unwrap();
+        let mut head = update("HEAD", Some(id("one")), None);
+        head.deref = true;
+        apply(repository, &[head]).unwrap();
+        assert_eq!(
+            read(repository, "HEAD").unwrap(),
+            Some(RefValue::Symbolic(branch.clone()))
+        );
+        assert_eq!(resolve(repository, &branch).unwrap(), Some(id("one")));
+        assert_eq!(git_reflog::read(repository, "HEAD").unwrap().len(), 1);
+        assert_eq!(git_reflog::read(repository, &branch).unwrap().len(), 1);
+
+        // without deref HEAD itself is detached
+        apply(repository, &[update("HEAD", Some(id("one")), None)]).unwrap();
+        assert_eq!(
+            read(repository, "HEAD").unwrap(),
+            Some(RefValue::Direct(id("one")))
+        );
+    }
+
+    #[test]
+    fn apply_is_all_or_nothing() {
+        let test = TestRepository::new("atomic");
+        let repository = &test.repository;
+        write(repository, "refs/heads/a", &id("one"), "create").unwrap();
+
+        let result = apply(
+            repository,
+            &[
+                update("refs/heads/b", Some(id("two")), None),
+                update("refs/heads/a", Some(id("two")), Some(id("other"))),
+            ],
+        );
+        assert!(reason(result).starts_with("is at "));
+        assert_eq!(
+            resolve(repository, "refs/heads/a").unwrap(),
+            Some(id("one"))
+        );
+        assert_eq!(resolve(repository, "refs/heads/b").unwrap(), None);
+
+        let result = apply(
+            repository,
+            &[update(
+                "refs/heads/a",
+                Some(id("two")),
+                Some(ObjectId::NULL),
+            )],
+        );
+        assert_eq!(reason(result), "reference already exists");
+        let result = apply(repository, &[update("refs/heads/c", None, Some(id("one")))]);
+        assert_eq!(reason(result), "unable to resolve reference 'refs/heads/c'");
+
+        // someone else holding a lock stops the whole transaction
+        fs::write(repository.git_path("refs/heads/a.lock"), "").unwrap();
+        let result = apply(
+            repository,
+            &[
+                update("refs/heads/b", Some(id("two")), None),
+                update("refs/heads/a", Some(id("two")), None),
+            ],
+        );
+        assert!(matches!(result, Err(Error::LockConflict { .. })));
+        assert_eq!(resolve(repository, "refs/heads/b").unwrap(), None);
+        fs::remove_file(repository.git_path("refs/heads/a.lock")).unwrap();
+        assert!(no_locks_left(repository));
+    }
+
+    #[test]
+    fn prepared_transactions_hold_their_locks_until_closed() {
+        let test = TestRepository::new("prepare");
+        let repository = &test.repository;
+        write(repository, "refs/heads/a", &id("one"), "create").unwrap();
+        let updates = [
+            update("refs/heads/a", Some(id("two")), Some(id("one"))),
+            update("refs/heads/b", Some(id("two")), None),
+        ];
+
+        let transaction = prepare(repository, &updates).unwrap();
+        assert!(repository.git_path("refs/heads/a.lock").is_file());
+        assert!(matches!(
+            apply(repository, &updates),
+            Err(Error::LockConflict { .. })
+        ));
+        drop(transaction);
+        assert!(no_locks_left(repository));
+        assert_eq!(
+            resolve(repository, "refs/heads/a").unwrap(),
+            Some(id("one"))
+        );
+        assert_eq!(resolve(repository, "refs/heads/b").unwrap(), None);
+
+        prepare(repository, &updates).unwrap().commit().unwrap();
+        assert_eq!(
+            resolve(repository, "refs/heads/a").unwrap(),
+            Some(id("two"))
+        );
+        assert_eq!(
+            resolve(repository, "refs/heads/b").unwrap(),
+            Some(id("two"))
+        );
+        assert!(no_locks_left(repository));
+    }
+
+    #[test]
+    fn apply_rejects_bad_and_repeated_names() {
+        let test = TestRepository::new("names");
+        let repository = &test.repository;
+        let twice = [
+            update("refs/heads/a", Some(id("one")), None),
+            update("refs/heads/a", Some(id("two")), None),
+        ];
+        let message = apply(repository, &twice).unwrap_err().to_string();
+        assert!(message.contains("multiple updates for ref 'refs/heads/a'"));
+        let message = apply(repository, &[update("../escape", Some(id("one")), None)])
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("bad name"));
+        assert_eq!(resolve(repository, "refs/heads/a").unwrap(), None);
+    }
+
+    #[test]
+    fn apply_rejects_directory_file_conflicts() {
+        let test = TestRepository::new("conflicts");
+        let repository = &test.repository;
+        write(repository, "refs/heads/a", &id("one"), "create").unwrap();
+        write(repository, "refs/heads/b/c", &id("one"), "create").unwrap();
+
+        let result = apply(
+            repository,
+            &[update("refs/heads/a/b", Some(id("two")), None)],
+        );
+        assert_eq!(
+            reason(result),
+            "'refs/heads/a' exists; cannot create 'refs/heads/a/b'"
+        );
+        let result = apply(repository, &[update("refs/heads/b", Some(id("two")), None)]);
+        assert_eq!(
+            reason(result),
+            "'refs/heads/b/c' exists; cannot create 'refs/heads/b'"
+        );
+        let result = apply(
+            repository,
+            &[
+                update("refs/heads/x", Some(id("two")), None),
+                update("refs/heads/x/y", Some(id("two")), None),
+            ],
+        );
+        assert_eq!(
+            reason(result),
+            "cannot process 'refs/heads/x' and 'refs/heads/x/y' at the same time"
+        );
+        let result = apply(
+            repository,
+            &[
+                update("refs/heads/x/y", Some(id("two")), None),
+                update("refs/heads/x", Some(id("two")), None),
+            ],
+        );
+        assert_eq!(
+            reason(result),
+            "cannot process 'refs/heads/x/y' and 'refs/heads/x' at the same time"
+        );
+
+        // packed refs are in the way just the same
+        let packed = Ref {
+            name: "refs/heads/p/q".to_string(),
+            id: id("one"),
+            peeled: None,
+        };
+        write_packed(repository, &[packed]).unwrap();
+        let result = apply(repository, &[update("refs/heads/p", Some(id("two")), None)]);
+        assert_eq!(
+            reason(result),
+            "'refs/heads/p/q' exists; cannot create 'refs/heads/p'"
+        );
+        assert!(no_locks_left(repository));
+    }
+
+    #[test]
+    fn deletions_reach_packed_refs() {
+        let test = TestRepository::new("delete");
+        let repository = &test.repository;
+        let packed = ["refs/heads/a", "refs/heads/b", "refs/tags/t"].map(|name| Ref {
+            name: name.to_string(),
+            id: id(name),
+            peeled: None,
+        });
+        write_packed(repository, &packed).unwrap();
+        // a loose ref shadows its packed value
+        write(repository, "refs/heads/a", &id("loose"), "create").unwrap();
+        assert_eq!(
+            resolve(repository, "refs/heads/a").unwrap(),
+            Some(id("loose"))
+        );
+        assert_eq!(list(repository, "refs/heads/").unwrap().len(), 2);
+
+        apply(
+            repository,
+            &[
+                update("refs/heads/a", Some(ObjectId::NULL), None),
+                update("refs/tags/t", Some(ObjectId::NULL), Some(id("refs/tags/t"))),
+            ],
+        )
+        .unwrap();
+        assert_eq!(resolve(repository, "refs/heads/a").unwrap(), None);
+        assert_eq!(resolve(repository, "refs/tags/t").unwrap(), None);
+        assert!(!git_reflog::exists(repository, "refs/heads/a"));
+        let names: Vec<String> = read_packed(repository)
+            .unwrap()
+            .iter()
+            .map(|reference| reference.name.clone())
+            .collect();
+        assert_eq!(names, vec!["refs/heads/b"]);
+
+        // a deleted ref's directory no longer blocks a ref of that name
+        write(repository, "refs/heads/d/e", &id("one"), "create").unwrap();
+        delete(repository, "refs/heads/d/e").unwrap();
+        write(repository, "refs/heads/d", &id("one"), "create").unwrap();
+        assert!(no_locks_left(repository));
+    }
+
+    #[test]
+    fn rename_moves_the_ref_its_log_and_head() {
+        let test = TestRepository::new("rename");
+        let repository = &test.repository;
+        let branch = resolve_name(repository, "HEAD").unwrap();
+        write(repository, &branch, &id("one"), "create").unwrap();
+
+        rename(repository, &branch, "refs/heads/renamed", "renamed").unwrap();
+        assert_eq!(resolve(repository, &branch).unwrap(), None);
+        assert_eq!(
+            resolve(repository, "refs/heads/renamed").unwrap(),
+            Some(id("one"))
+        );
+        assert_eq!(
+            resolve_name(repository, "HEAD").unwrap(),
+            "refs/heads/renamed"
+        );
+        let log = git_reflog::read(repository, "refs/heads/renamed").unwrap();
+        assert_eq!(
+            log.iter()
+                .map(|entry| entry.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["create", "renamed"]
+        );
+        assert!(!git_reflog::exists(repository, &branch));
+
+        // a ref can move into a directory of its own name and back
+        rename(
+            repository,
+            "refs/heads/renamed",
+            "refs/heads/renamed/inner",
+            "down",
+        )
+        .unwrap();
+        assert_eq!(
+            resolve(repository, "refs/heads/renamed/inner").unwrap(),
+            Some(id("one"))
+        );
+        rename(
+            repository,
+            "refs/heads/renamed/inner",
+            "refs/heads/renamed",
+            "up",
+        )
+        .unwrap();
+        assert_eq!(
+            resolve(repository, "refs/heads/renamed").unwrap(),
+            Some(id("one"))
+        );
+        assert_eq!(
+            git_reflog::read(repository, "refs/heads/renamed")
+                .unwrap()
+                .len(),
+            4
+        );
+
+        let message = rename(repository, "refs/heads/missing", "refs/heads/x", "x")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(message, "fatal: No such ref: refs/heads/missing");
+    }
+
+    #[test]
+    fn ref_name_format() {
+        assert!(check_format("refs/heads/main", false));
+        assert!(check_format("HEAD", true));
+        assert!(!check_format("HEAD", false));
+        for bad in [
+            "refs/heads/a..b",
+            "refs/heads/a.lock",
+            "refs/heads/.hidden",
+            "refs/heads/a b",
+            "refs/heads/a@{1}",
+            "refs//heads",
+            "refs/heads/",
+            "@",
+        ] {
+            assert!(!check_format(bad, true), "{bad}");
+        }
+        assert_eq!(shorten("refs/heads/main"), "main");
+        assert_eq!(shorten("refs/remotes/origin/main"), "origin/main");
+        assert_eq!(shorten("HEAD"), "HEAD");
+    }
+}
@@ -0,0 +1,711 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    error::Error,
+    git_attributes, git_config,
+    git_diff::{self, DiffSide, FilePair, Origin},
+    object_id::ObjectId,
+    repository::Repository,
+};
+
+/// The score of two files that are the same; similarity is measured
+/// against it.
+pub const MAX_SCORE: u32 = 60000;
+/// 50%, the similarity `-M` and `-C` ask for without a number.
+pub const DEFAULT_SCORE: u32 = 30000;
+/// How many files on each side inexact detection compares when
+/// `diff.renameLimit` isn't set.
+const DEFAULT_LIMIT: usize = 1000;
+/// The limit used when it is set to 0 or less.
+const UNLIMITED: usize = 32767;
+/// How many of the best sources are kept for each destination.
+const CANDIDATES_PER_DESTINATION: usize = 4;
+/// The modulus of the hashes content is split into spans by.
+const HASHBASE: u32 = 107927;
+
+/// What rename detection looks for.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Detection {
+    Off,
+    /// Deleted files that turn up as added ones.
+    Renames,
+    /// Also added files that come from files the diff keeps.
+    Copies,
+}
+
+impl Detection {
+    /// Parses `diff.renames` and `status.renames`: a boolean, or `copies`.
+    fn parse(value: Option<&str>, name: &str) -> Result<Detection, Error> {
+        return match value {
+            Some(value) if value.eq_ignore_ascii_case("copies") => Ok(Detection::Copies),
+            Some(value) if value.eq_ignore_ascii_case("copy") => Ok(Detection::Copies),
+            value => Ok(match git_config::parse_bool(value, name)? {
+                true => Detection::Renames,
+                false => Detection::Off,
+            }),
+        };
+    }
+}
+
+/// How renames and copies are found, as `-M`, `-C` and
+/// `--find-copies-harder` and the configuration set it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RenameOptions {
+    pub detection: Detection,
+    /// Files the diff leaves alone count as copy sources too.
+    pub find_copies_harder: bool,
+    /// How similar files must be to pair them, out of `MAX_SCORE`.
+    pub min_score: u32,
+    /// Inexact detection is skipped when it would compare more than this
+    /// many files on each side.
+    pub limit: usize,
+    /// Only the file added at this path is looked for, as `--follow` does.
+    pub follow: Option<String>,
+}
+
+impl RenameOptions {
+    /// The options `<section>.renames` and `<section>.renameLimit` set,
+    /// falling back to the `diff` ones. Renames are detected by default.
+    pub fn configured(repository: &Repository, section: &str) -> Result<RenameOptions, Error> {
+        let config = repository.config()?;
+        let mut detection = Detection::Renames;
+        let mut limit = DEFAULT_LIMIT as i64;
+        for section in ["diff", section] {
+            let name = format!("{section}.renames");
+            if let Some(entry) = config.matching(&name)?.last() {
+                detection = Detection::parse(entry.value.as_deref(), &name)?;
+            }
+            let name = format!("{section}.renameLimit");
+            if let Some(value) = config.get(&name)? {
+                limit = git_config::parse_int(&value, &name)?;
+            }
+        }
+        return Ok(RenameOptions {
+            detection,
+            find_copies_harder: false,
+            min_score: DEFAULT_SCORE,
+            limit: if limit <= 0 {
+                UNLIMITED
+            } else {
+                limit as usize
+            },
+            follow: None,
+        });
+    }
+}
+
+/// Parses the number `-M<n>` and `-C<n>` take into a score. Digits are
+/// read as a fraction, so `5` and `50%` are both half, and `.5` is too.
+/// `None` if anything else follows.
+pub fn parse_score(value: &str) -> Option<u32> {
+    let mut number: u64 = 0;
+    let mut scale: u64 = 1;
+    let mut dot = false;
+    let mut chars = value.chars();
+    for c in chars.by_ref() {
+        match c {
+            '.' if !dot => {
+                scale = 1;
+                dot = true;
+            }
+            '%' => {
+                scale = if dot { scale * 100 } else { 100 };
+                break;
+            }
+            '0'..='9' => {
+                if scale < 100000 {
+                    scale *= 10;
+                    number = number * 10 + c.to_digit(10).unwrap() as u64;
+                }
+            }
+            _ => return None,
+        }
+    }
+    if chars.next().is_some() {
+        return None;
+    }
+    return Some(match number >= scale {
+        true => MAX_SCORE,
+        false => (MAX_SCORE as u64 * number / scale) as u32,
+    });
+}
+
+/// What detection left out for the rename limit, to warn about once a
+/// command is done.
+#[derive(Clone, Default, Debug)]
+pub struct Skipped {
+    /// The limit that would have let detection look at every file.
+    pub needed_limit: usize,
+    /// Copies were only looked for among changed files.
+    pub degraded: bool,
+}
+
+impl Skipped {
+    /// The warnings git prints, naming the config `variable` to raise.
+    pub fn warning(&self, variable: &str) -> Option<String> {
+        let mut warning = match (self.degraded, self.needed_limit) {
+            (true, _) => "warning: only found copies from modified paths due to too many files.\n"
+                .to_string(),
+            (false, 0) => return None,
+            (false, _) => {
+                "warning: exhaustive rename detection was skipped due to too many files.\n"
+                    .to_string()
+            }
+        };
+        if self.needed_limit > 0 {
+            warning += &format!(
+                "warning: you may want to set your {variable} variable to at least {} and retry the command.\n",
+                self.needed_limit
+            );
+        }
+        return Some(warning);
+    }
+}
+
+/// A file renames and copies may come from: the old side of a pair, and
+/// how many of the pairs it is used by. A file that stays where it is
+/// starts out used once.
+struct Source {
+    pair: usize,
+    used: usize,
+}
+
+/// A file that may have been renamed or copied: the new side of an added
+/// pair, and the source found for it with its score.
+struct Destination {
+    pair: usize,
+    found: Option<(usize, u32)>,
+}
+
+/// A possible pairing in the matrix inexact detection fills in.
+#[derive(Clone, Copy)]
+struct Candidate {
+    score: u32,
+    same_name: bool,
+    source: usize,
+    destination: usize,
+}
+
+/// Better candidates order first, those with the same file name winning
+/// ties; the empty slots go last.
+fn compare(a: &Option<Candidate>, b: &Option<Candidate>) -> std::cmp::Ordering {
+    return match (a, b) {
+        (None, None) => std::cmp::Ordering::Equal,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (Some(a), Some(b)) if a.score == b.score => b.same_name.cmp(&a.same_name),
+        (Some(a), Some(b)) => b.score.cmp(&a.score),
+    };
+}
+
+/// Content loaded for similarity estimation: its size and, once needed,
+/// the spans it breaks into.
+struct Blob {
+    data: Vec<u8>,
+    binary: bool,
+    spans: Option<Vec<(u32, u64)>>,
+}
+
+/// The files on both sides of the detection, read as they are needed.
+struct Blobs<'a> {
+    repository: &'a Repository,
+    loaded: HashMap<(String, bool), Blob>,
+}
+
+impl<'a> Blobs<'a> {
+    fn load(&mut self, path: &str, side: &DiffSide, new: bool) -> Result<&mut Blob, Error> {
+        let key = (path.to_string(), new);
+        if !self.loaded.contains_key(&key) {
+            let data = git_diff::read_side(self.repository, path, side)?;
+            let binary = match git_diff::binary_attribute(self.repository, path)? {
+                Some(binary) => binary,
+                None => git_attributes::is_binary(&data),
+            };
+            self.loaded.insert(
+                key.clone(),
+                Blob {
+                    data,
+                    binary,
+                    spans: None,
+                },
+            );
+        }
+        return Ok(self.loaded.get_mut(&key).unwrap());
+    }
+}
+
+/// The file names of `paths`, with the index of the only path that has
+/// each, or `None` if more than one do.
+fn unique_names<'a>(
+    paths: impl Iterator<Item = (usize, &'a str)>,
+) -> HashMap<String, Option<usize>> {
+    let mut names: HashMap<String, Option<usize>> = HashMap::new();
+    for (index, path) in paths {
+        names
+            .entry(file_name(path).to_string())
+            .and_modify(|found| *found = None)
+            .or_insert(Some(index));
+    }
+    return names;
+}
+
+fn is_regular(mode: u64) -> bool {
+    return mode == 100644 || mode == 100755;
+}
+
+/// Whether two paths end in the same file name.
+fn same_name(a: &str, b: &str) -> bool {
+    return file_name(a) == file_name(b);
+}
+
+fn file_name(path: &str) -> &str {
+    return path.rsplit('/').next().unwrap_or(path);
+}
+
+/// Pairs deleted files in `pairs` with added ones they were renamed to, and
+/// with `Detection::Copies` files the diff keeps with added ones copied from
+/// them, the way git's diffcore-rename does: exact matches by blob first,
+/// then files with the same name and finally the most similar files. The
+/// pairs used up are merged into one with an `origin`, and pairs that don't
+/// change anything are left out.
+pub fn detect_renames(
+    repository: &Repository,
+    pairs: Vec<FilePair>,
+    options: &RenameOptions,
+    skipped: &mut Skipped,
+) -> Result<Vec<FilePair>, Error> {
+    let copies = options.detection == Detection::Copies || options.find_copies_harder;
+    let mut sources: Vec<Source> = Vec::new();
+    let mut destinations: Vec<Destination> = Vec::new();
+    if options.detection != Detection::Off || options.find_copies_harder {
+        for (index, pair) in pairs.iter().enumerate() {
+            let followed = options
+                .follow
+                .as_ref()
+                .is_none_or(|path| *path == pair.path);
+            match (&pair.old, &pair.new) {
+                _ if pair.unmerged => {}
+                (None, Some(_)) if followed => destinations.push(Destination {
+                    pair: index,
+                    found: None,
+                }),
+                (Some(_), None) => sources.push(Source {
+                    pair: index,
+                    used: 0,
+                }),
+                (Some(_), Some(_)) if copies => sources.push(Source {
+                    pair: index,
+                    used: 1,
+                }),
+                _ => {}
+            }
+        }
+    }
+    if !destinations.is_empty() && !sources.is_empty() {
+        let mut blobs = Blobs {
+            repository,
+            loaded: HashMap::new(),
+        };
+        let source_count = sources.len();
+        let mut search = Search {
+            pairs: &pairs,
+            sources: &mut sources,
+            destinations: &mut destinations,
+            blobs: &mut blobs,
+            copies,
+            remaining: (0..source_count).collect(),
+        };
+        search.run(options, skipped)?;
+    }
+
+    // a deletion goes once anything used it, and of the pairs using a
+    // source only the last is a rename, the others being copies
+    let used_sources: HashSet<usize> = sources
+        .iter()
+        .filter(|source| source.used > 0)
+        .map(|source| source.pair)
+        .collect();
+    let found_for: HashMap<usize, (usize, u32)> = destinations
+        .iter()
+        .filter_map(|destination| Some((destination.pair, destination.found?)))
+        .collect();
+    let mut result = Vec::new();
+    for (index, pair) in pairs.iter().enumerate() {
+        if let Some((source, score)) = found_for.get(&index) {
+            let source_pair = &pairs[sources[*source].pair];
+            sources[*source].used -= 1;
+            result.push(FilePair {
+                path: pair.path.clone(),
+                old: source_pair.old,
+                new: pair.new,
+                origin: Some(Origin {
+                    path: source_pair.path.clone(),
+                    copy: sources[*source].used > 0,
+                    score: *score,
+                }),
+                unmerged: false,
+            });
+        } else if pair.new.is_none() && used_sources.contains(&index) {
+            continue;
+        } else if pair.old != pair.new || pair.unmerged {
+            result.push(pair.clone());
+        }
+    }
+    return Ok(result);
+}
+
+/// The pair of the file at `path` and the file of `old_tree` it was renamed
+/// or copied from, if `new_tree` adds it, as `--follow` looks for it: among
+/// all of the old tree's files, unchanged ones too.
+pub fn follow_origin(
+    repository: &Repository,
+    old_tree: &ObjectId,
+    new_tree: &ObjectId,
+    path: &str,
+    options: &RenameOptions,
+) -> Result<Option<FilePair>, Error> {
+    let paths = [path.to_string()];
+    let added = git_diff::diff_trees(repository, Some(old_tree), Some(new_tree), &paths, false)?
+        .iter()
+        .any(|pair| pair.path == path && pair.old.is_none());
+    if !added {
+        return Ok(None);
+    }
+    let pairs = git_diff::diff_trees(repository, Some(old_tree), Some(new_tree), &[], true)?;
+    let options = RenameOptions {
+        detection: Detection::Copies,
+        find_copies_harder: true,
+        follow: Some(path.to_string()),
+        ..options.clone()
+    };
+    let pairs = detect_renames(repository, pairs, &options, &mut Skipped::default())?;
+    return Ok(pairs
+        .into_iter()
+        .find(|pair| pair.path == path && pair.origin.is_some()));
+}
+
+/// The state of one run of detection over `pairs`.
+struct Search<'a, 'b> {
+    pairs: &'a [FilePair],
+    sources: &'a mut Vec<Source>,
+    destinations: &'a mut Vec<Destination>,
+    blobs: &'a mut Blobs<'b>,
+    copies: bool,
+    /// The sources still worth comparing.
+    remaining: Vec<usize>,
+}
+
+impl Search<'_, '_> {
+    fn source_side(&self, source: usize) -> (&str, DiffSide) {
+        let pair = &self.pairs[self.sources[source].pair];
+        return (&pair.path, pair.old.unwrap());
+    }
+
+    fn destination_side(&self, destination: usize) -> (&str, DiffSide) {
+        let pair = &self.pairs[self.destinations[destination].pair];
+        return (&pair.path, pair.new.unwrap());
+    }
+
+    /// Drops the sources that renames have used up from the ones compared.
+    fn cull(&mut self) {
+        let sources = &self.sources;
+        self.remaining.retain(|source| sources[*source].used == 0);
+    }
+
+    fn record(&mut self, destination: usize, source: usize, score: u32) {
+        self.sources[source].used += 1;
+        self.destinations[destination].found = Some((source, score));
+    }
+
+    fn run(&mut self, options: &RenameOptions, skipped: &mut Skipped) -> Result<(), Error> {
+        self.find_exact();
+        if options.min_score == MAX_SCORE {
+            return Ok(());
+        }
+        if !self.copies {
+            // what is left to find is likely a move keeping the file name,
+            // so those are tried on their own first, at a higher bar
+            self.cull();
+            let min_score = options.min_score + (MAX_SCORE - options.min_score) / 2;
+            self.find_same_names(min_score)?;
+            self.cull();
+        }
+
+        let remaining: Vec<usize> = (0..self.destinations.len())
+            .filter(|destination| self.destinations[*destination].found.is_none())
+            .collect();
+        if remaining.is_empty() || self.remaining.is_empty() {
+            return Ok(());
+        }
+        let skip_unchanged = match self.too_many(remaining.len(), options, skipped) {
+            Some(skip_unchanged) => skip_unchanged,
+            None => return Ok(()),
+        };
+
+        let mut matrix: Vec<Option<Candidate>> = Vec::new();
+        for destination in remaining {
+            let mut best: [Option<Candidate>; CANDIDATES_PER_DESTINATION] =
+                [None; CANDIDATES_PER_DESTINATION];
+            for source in self.remaining.clone() {
+                let pair = &self.pairs[self.sources[source].pair];
+                if skip_unchanged && pair.old == pair.new {
+                    continue;
+                }
+                let candidate = Some(Candidate {
+                    score: self.similarity(source, destination, options.min_score)?,
+                    same_name: same_name(
+                        self.source_side(source).0,
+                        self.destination_side(destination).0,
+                    ),
+                    source,
+                    destination,
+                });
+                let mut worst = 0;
+                for slot in 1..CANDIDATES_PER_DESTINATION {
+                    if compare(&best[slot], &best[worst]).is_gt() {
+                        worst = slot;
+                    }
+                }
+                if compare(&best[worst], &candidate).is_gt() {
+                    best[worst] = candidate;
+                }
+            }
+            matrix.extend(best);
+        }
+        matrix.sort_by(compare);
+        self.take_best(&matrix, options.min_score, false);
+        if self.copies {
+            self.take_best(&matrix, options.min_score, true);
+        }
+        return Ok(());
+    }
+
+    /// Pairs destinations with a source of the same blob, preferring
+    /// unused sources and then ones with the same file name.
+    fn find_exact(&mut self) {
+        let mut by_id: HashMap<_, Vec<usize>> = HashMap::new();
+        for source in 0..self.sources.len() {
+            let side = self.source_side(source).1;
+            by_id.entry(side.id).or_default().push(source);
+        }
+        for destination in 0..self.destinations.len() {
+            let (path, side) = self.destination_side(destination);
+            let mut best = None;
+            let mut best_score = -1;
+            for source in by_id.get(&side.id).into_iter().flatten().take(100) {
+                let (source_path, source_side) = self.source_side(*source);
+                if (!is_regular(source_side.mode) || !is_regular(side.mode))
+                    && source_side.mode != side.mode
+                {
+                    continue;
+                }
+                let used = self.sources[*source].used > 0;
+                if used && !self.copies {
+                    continue;
+                }
+                let score = !used as i32 + same_name(source_path, path) as i32;
+                if score > best_score {
+                    best = Some(*source);
+                    best_score = score;
+                    if score == 2 {
+                        break;
+                    }
+                }
+            }
+            if let Some(source) = best {
+                self.record(destination, source, MAX_SCORE);
+            }
+        }
+    }
+
+    /// Pairs sources and destinations whose file name no other source or
+    /// destination has, if they are similar enough.
+    fn find_same_names(&mut self, min_score: u32) -> Result<(), Error> {
+        let source_names = unique_names(
+            self.remaining
+                .iter()
+                .map(|source| (*source, self.source_side(*source).0)),
+        );
+        let destination_names = unique_names(
+            (0..self.destinations.len())
+                .filter(|destination| self.destinations[*destination].found.is_none())
+                .map(|destination| (destination, self.destination_side(destination).0)),
+        );
+        let mut found = Vec::new();
+        for source in self.remaining.iter().copied() {
+            let name = file_name(self.source_side(source).0);
+            if let (Some(Some(_)), Some(Some(destination))) =
+                (source_names.get(name), destination_names.get(name))
+            {
+                found.push((source, *destination));
+            }
+        }
+        for (source, destination) in found {
+            if self.destinations[destination].found.is_some() {
+                continue;
+            }
+            let score = self.similarity(source, destination, min_score)?;
+            if score >= min_score {
+                self.record(destination, source, score);
+            }
+        }
+        return Ok(());
+    }
+
+    /// Whether comparing every source with `destinations` destinations is
+    /// over the limit: `None` if it is, and otherwise whether sources the
+    /// diff leaves unchanged have to be skipped to stay under it.
+    fn too_many(
+        &self,
+        destinations: usize,
+        options: &RenameOptions,
+        skipped: &mut Skipped,
+    ) -> Option<bool> {
+        let fits = |sources: usize| {
+            (destinations <= options.limit || sources <= options.limit)
+                && destinations * sources <= options.limit * options.limit
+        };
+        if fits(self.remaining.len()) {
+            return Some(false);
+        }
+        skipped.needed_limit = self.remaining.len().max(destinations);
+        if !options.find_copies_harder {
+            return None;
+        }
+        let changed = self
+            .remaining
+            .iter()
+            .filter(|source| {
+                let pair = &self.pairs[self.sources[**source].pair];
+                pair.old != pair.new
+            })
+            .count();
+        if fits(changed) {
+            skipped.degraded = true;
+            return Some(true);
+        }
+        return None;
+    }
+
+    /// Goes through `matrix` best first, pairing each destination with the
+    /// first source left for it. Renames take sources nobody used yet.
+    fn take_best(&mut self, matrix: &[Option<Candidate>], min_score: u32, copies: bool) {
+        for candidate in matrix {
+            let candidate = match candidate {
+                Some(candidate) if candidate.score >= min_score => candidate,
+                _ => break,
+            };
+            if self.destinations[candidate.destination].found.is_some() {
+                continue;
+            }
+            if !copies && self.sources[candidate.source].used > 0 {
+                continue;
+            }
+            self.record(candidate.destination, candidate.source, candidate.score);
+        }
+    }
+
+    /// How much of the destination's content comes from the source, out
+    /// of `MAX_SCORE`. Only regular files are compared, and files too
+    /// different in size to reach `min_score` score 0.
+    fn similarity(
+        &mut self,
+        source: usize,
+        destination: usize,
+        min_score: u32,
+    ) -> Result<u32, Error> {
+        let (source_path, source_side) = self.source_side(source);
+        let (destination_path, destination_side) = self.destination_side(destination);
+        if !is_regular(source_side.mode) || !is_regular(destination_side.mode) {
+            return Ok(0);
+        }
+        let (source_path, destination_path) =
+            (source_path.to_string(), destination_path.to_string());
+        let source_size = self
+            .blobs
+            .load(&source_path, &source_side, false)?
+            .data
+            .len() as u64;
+        let destination_size = self
+            .blobs
+            .load(&destination_path, &destination_side, true)?
+            .data
+            .len() as u64;
+        let max_size = source_size.max(destination_size);
+        let delta_size = max_size - source_size.min(destination_size);
+        if max_size * ((MAX_SCORE - min_score) as u64) < delta_size * MAX_SCORE as u64 {
+            return Ok(0);
+        }
+        if destination_size == 0 {
+            return Ok(0);
+        }
+        self.blobs
+            .load(&source_path, &source_side, false)?
+            .compute_spans();
+        self.blobs
+            .load(&destination_path, &destination_side, true)?
+            .compute_spans();
+        let spans = |path: &str, new: bool| {
+            return self.blobs.loaded[&(path.to_string(), new)]
+                .spans
+                .as_deref()
+                .unwrap();
+        };
+        let copied = copied_size(spans(&source_path, false), spans(&destination_path, true));
+        return Ok((copied * MAX_SCORE as u64 / max_size) as u32);
+    }
+}
+
+impl Blob {
+    /// Splits the content into spans, unless that was done already.
+    fn compute_spans(&mut self) {
+        if self.spans.is_none() {
+            self.spans = Some(spans(&self.data, !self.binary));
+        }
+    }
+}
+
+/// Splits `data` into lines, or 64 byte runs of long ones, and counts how
+/// many bytes there are of each, keyed by a hash of the span and sorted by
+/// it. The CR of a CRLF doesn't count in text.
+fn spans(data: &[u8], text: bool) -> Vec<(u32, u64)> {
+    let mut counts: BTreeMap<u32, u64> = BTreeMap::new();
+    let (mut accum1, mut accum2): (u32, u32) = (0, 0);
+    let mut length = 0;
+    for (index, byte) in data.iter().enumerate() {
+        if text && *byte == b'\r' && data.get(index + 1) == Some(&b'\n') {
+            continue;
+        }
+        let old_accum1 = accum1;
+        accum1 = (accum1 << 7) ^ (accum2 >> 25);
+        accum2 = (accum2 << 7) ^ (old_accum1 >> 25);
+        accum1 = accum1.wrapping_add(*byte as u32);
+        length += 1;
+        if length < 64 && *byte != b'\n' {
+            continue;
+        }
+        let hash = accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASHBASE;
+        *counts.entry(hash).or_default() += length;
+        length = 0;
+        accum1 = 0;
+        accum2 = 0;
+    }
+    if length > 0 {
+        let hash = accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASHBASE;
+        *counts.entry(hash).or_default() += length;
+    }
+    return counts.into_iter().collect();
+}
+
+/// How many bytes of the source's spans the destination has as well.
+fn copied_size(source: &[(u32, u64)], destination: &[(u32, u64)]) -> u64 {
+    let mut copied = 0;
+    let mut destination = destination.iter().peekable();
+    for (hash, count) in source {
+        while destination.next_if(|(other, _)| other < hash).is_some() {}
+        if let Some((_, other_count)) = destination.next_if(|(other, _)| other == hash) {
+            copied += count.min(other_count);
+        }
+    }
+    return copied;
+}
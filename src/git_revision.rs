@@ -0,0 +1,429 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use crate::{
+    error::Error,
+    git, git_config, git_index, git_merge_base,
+    git_object::{self, ObjectType},
+    git_reflog, git_refs,
+    object_id::ObjectId,
+    repository::Repository,
+};
+
+/// One object named by a revision argument. Ranges like `A..B` name several,
+/// the ones to leave out being `excluded` (`^A`), and `A...B` marks A as the
+/// `left` side.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Revision {
+    pub id: ObjectId,
+    pub excluded: bool,
+    pub left: bool,
+}
+
+/// Resolves a revision argument that names a single object (see
+/// `gitrevisions(7)`): a hash or a ref name, optionally followed by `@{...}`
+/// and any chain of `~<n>`, `^<n>` and `^{<type>}`, a `<rev>:<path>` into a
+/// tree, an index entry `:[<stage>:]<path>`, or a `:/<regex>` message search.
+pub fn resolve(repository: &Repository, spec: &str) -> Result<ObjectId, Error> {
+    if let Some(pattern) = spec.strip_prefix(":/") {
+        let starts = git_refs::list(repository, "refs/")?
+            .into_iter()
+            .map(|reference| reference.id)
+            .chain(git_refs::resolve(repository, "HEAD")?)
+            .collect();
+        return search_message(repository, starts, pattern)?
+            .ok_or_else(|| Error::ObjectNotFound(spec.to_string()));
+    }
+    if let Some(rest) = spec.strip_prefix(':') {
+        return resolve_index_path(repository, rest);
+    }
+    if let Some(colon) = find_outside_braces(spec, |c| c == ':') {
+        let (revision, path) = (&spec[..colon], &spec[colon + 1..]);
+        let tree = peel_to(
+            repository,
+            &resolve(repository, revision)?,
+            ObjectType::Tree,
+        )?;
+        if path.is_empty() {
+            return Ok(tree);
+        }
+        let full_path = relative_to_cwd(repository, path)?;
+        return match git::find_tree_entry(repository, &tree, &full_path)? {
+            Some(node) => Ok(node.hash),
+            None => Err(format!("fatal: path '{full_path}' does not exist in '{revision}'").into()),
+        };
+    }
+
+    let split = find_outside_braces(spec, |c| c == '^' || c == '~').unwrap_or(spec.len());
+    let (base, mut suffix) = spec.split_at(split);
+    let not_found = || Error::ObjectNotFound(spec.to_string());
+    let mut id = resolve_base(repository, base, spec)?;
+    while !suffix.is_empty() {
+        let operator = suffix.chars().next().unwrap();
+        suffix = &suffix[1..];
+        if operator == '^' && suffix.starts_with('{') {
+            let end = suffix.find('}').ok_or_else(not_found)?;
+            let argument = &suffix[1..end];
+            suffix = &suffix[end + 1..];
+            id = match argument {
+                "" => git_refs::peel(repository, &id)?,
+                "object" => id,
+                "commit" => peel_to(repository, &id, ObjectType::Commit)?,
+                "tree" => peel_to(repository, &id, ObjectType::Tree)?,
+                "blob" => peel_to(repository, &id, ObjectType::Blob)?,
+                "tag" => peel_to(repository, &id, ObjectType::Tag)?,
+                _ => match argument.strip_prefix('/') {
+                    Some(pattern) => {
+                        let commit = peel_to(repository, &id, ObjectType::Commit)?;
+                        search_message(repository, vec![commit], pattern)?.ok_or_else(not_found)?
+                    }
+                    None => return Err(not_found()),
+                },
+            };
+            continue;
+        }
+
+        let digits = suffix.len()
+            - suffix
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let count = match digits {
+            0 => 1,
+            _ => suffix[..digits].parse::<usize>().map_err(|_| not_found())?,
+        };
+        suffix = &suffix[digits..];
+        id = peel_to(repository, &id, ObjectType::Commit)?;
+        if operator == '~' {
+            for _ in 0..count {
+                id = *git_object::read_commit(repository, &id)?
+                    .parents
+                    .first()
+                    .ok_or_else(not_found)?;
+            }
+        } else if count > 0 {
+            id = *git_object::read_commit(repository, &id)?
+                .parents
+                .get(count - 1)
+                .ok_or_else(not_found)?;
+        }
+    }
+    return Ok(id);
+}
+
+/// Resolves an argument that may name several objects: `A..B` (reachable
+/// from B but not A), `A...B` (from either but not both), `^A`, `A^@` (A's
+/// parents) and `A^!` (A without its parents), or else a single revision.
+/// An empty side of a range means HEAD.
+pub fn resolve_range(repository: &Repository, spec: &str) -> Result<Vec<Revision>, Error> {
+    let included = |id: ObjectId| Revision {
+        id,
+        excluded: false,
+        left: false,
+    };
+    let excluded = |id: ObjectId| Revision {
+        id,
+        excluded: true,
+        left: false,
+    };
+    let side = |name: &str| match name {
+        "" => resolve(repository, "HEAD"),
+        name => resolve(repository, name),
+    };
+
+    if let Some((left, right)) = spec.split_once("..") {
+        let range = match right.strip_prefix('.') {
+            Some(right) => symmetric_difference(repository, side(left), side(right)),
+            None => side(right).and_then(|right| Ok(vec![included(right), excluded(side(left)?)])),
+        };
+        // like git, take the whole argument as one revision when it isn't a
+        // range of two revisions, as with `HEAD:a..b`
+        return match range {
+            Ok(revisions) => Ok(revisions),
+            Err(err) => match resolve(repository, spec) {
+                Ok(id) => Ok(vec![included(id)]),
+                Err(_) => Err(err),
+            },
+        };
+    }
+    if let Some(name) = spec.strip_prefix('^') {
+        return Ok(vec![excluded(resolve(repository, name)?)]);
+    }
+    if let Some(name) = spec.strip_suffix("^@") {
+        let commit = peel_to(repository, &resolve(repository, name)?, ObjectType::Commit)?;
+        let parents = git_object::read_commit(repository, &commit)?.parents;
+        return Ok(parents.into_iter().map(included).collect());
+    }
+    if let Some(name) = spec.strip_suffix("^!") {
+        let commit = peel_to(repository, &resolve(repository, name)?, ObjectType::Commit)?;
+        let parents = git_object::read_commit(repository, &commit)?.parents;
+        return Ok(std::iter::once(included(commit))
+            .chain(parents.into_iter().map(excluded))
+            .collect());
+    }
+    return Ok(vec![included(resolve(repository, spec)?)]);
+}
+
+/// `left...right`: both sides, with the left one marked as such, without
+/// their merge bases.
+fn symmetric_difference(
+    repository: &Repository,
+    left: Result<ObjectId, Error>,
+    right: Result<ObjectId, Error>,
+) -> Result<Vec<Revision>, Error> {
+    let (left, right) = (left?, right?);
+    let mut revisions = vec![
+        Revision {
+            id: right,
+            excluded: false,
+            left: false,
+        },
+        Revision {
+            id: left,
+            excluded: false,
+            left: true,
+        },
+    ];
+    let left = peel_to(repository, &left, ObjectType::Commit)?;
+    let right = peel_to(repository, &right, ObjectType::Commit)?;
+    for base in git_merge_base::merge_bases(repository, &left, &[right])? {
+        revisions.push(Revision {
+            id: base,
+            excluded: true,
+            left: false,
+        });
+    }
+    return Ok(revisions);
+}
+
+/// Follows `id` until reaching an object of `object_type`: tags are peeled
+/// and commits lead to their trees.
+pub fn peel_to(
+    repository: &Repository,
+    id: &ObjectId,
+    object_type: ObjectType,
+) -> Result<ObjectId, Error> {
+    let mut id = *id;
+    loop {
+        let current = git_object::get_type(repository, &id)?;
+        if current == object_type {
+            return Ok(id);
+        }
+        id = match current {
+            ObjectType::Tag => git_object::read_tag(repository, &id)?.object,
+            ObjectType::Commit if object_type == ObjectType::Tree => {
+                git_object::read_commit(repository, &id)?.tree
+            }
+            _ => return Err(Error::ObjectNotFound(format!("{id}^{{{object_type}}}"))),
+        };
+    }
+}
+
+/// The ref a revision's base names, if it is one: `HEAD` for `@` or
+/// `refs/heads/main` for `main`. Used by `rev-parse --abbrev-ref`.
+pub fn resolve_ref_name(repository: &Repository, base: &str) -> Result<Option<String>, Error> {
+    if base == "@" || base == "HEAD" {
+        return Ok(Some("HEAD".to_string()));
+    }
+    if let Some((reference, selector)) = split_at_selector(base) {
+        if is_upstream_selector(selector) {
+            return Ok(Some(upstream_of(repository, reference)?));
+        }
+        if let Some(branch) = selector.strip_prefix('-') {
+            return Ok(Some(previous_branch(repository, reference, branch, base)?));
+        }
+        return Ok(None);
+    }
+    return git_refs::expand(repository, base);
+}
+
+/// The object `base` names, without any `~`, `^` or `:` suffix.
+fn resolve_base(repository: &Repository, base: &str, spec: &str) -> Result<ObjectId, Error> {
+    let not_found = || Error::ObjectNotFound(spec.to_string());
+    if base == "@" {
+        return git_refs::resolve(repository, "HEAD")?.ok_or_else(not_found);
+    }
+    if let Some((reference, selector)) = split_at_selector(base) {
+        if is_upstream_selector(selector) {
+            let upstream = upstream_of(repository, reference)?;
+            return git_refs::resolve(repository, &upstream)?.ok_or_else(not_found);
+        }
+        if let Some(count) = selector.strip_prefix('-') {
+            let branch = previous_branch(repository, reference, count, spec)?;
+            return resolve_base(repository, git_refs::shorten(&branch), spec);
+        }
+        let name = match reference {
+            "" | "@" => git_refs::resolve_name(repository, "HEAD")?,
+            reference => git_refs::expand(repository, reference)?.ok_or_else(not_found)?,
+        };
+        return git_reflog::lookup(repository, &name, selector);
+    }
+
+    if let Ok(id) = ObjectId::from_hex(base) {
+        return Ok(id);
+    }
+    if let Some(name) = git_refs::expand(repository, base)? {
+        return git_refs::resolve(repository, &name)?.ok_or_else(not_found);
+    }
+    if base.len() >= 4 && base.chars().all(|c| c.is_ascii_hexdigit()) {
+        return git_object::full_hash(repository, base).map_err(|err| match err {
+            Error::ObjectNotFound(_) => not_found(),
+            err => err,
+        });
+    }
+    return Err(not_found());
+}
+
+/// Splits `<ref>@{<selector>}`.
+fn split_at_selector(base: &str) -> Option<(&str, &str)> {
+    return base.strip_suffix('}')?.split_once("@{");
+}
+
+fn is_upstream_selector(selector: &str) -> bool {
+    return selector.eq_ignore_ascii_case("u") || selector.eq_ignore_ascii_case("upstream");
+}
+
+/// The full name of the ref `<branch>@{upstream}` names.
+fn upstream_of(repository: &Repository, branch: &str) -> Result<String, Error> {
+    let branch = match branch {
+        "" | "@" | "HEAD" => git::current_branch(repository)?
+            .ok_or("fatal: HEAD does not point to a branch".to_string())?,
+        branch => branch
+            .strip_prefix("refs/heads/")
+            .unwrap_or(branch)
+            .to_string(),
+    };
+    let upstream = git::upstream_ref(repository, &branch)?
+        .ok_or_else(|| format!("fatal: no upstream configured for branch '{branch}'"))?;
+    // a remote without a fetch refspec has no remote-tracking branches
+    if let Some(remote) = git_config::get(repository, &format!("branch.{branch}.remote"))? {
+        let fetch = git_config::get(repository, &format!("remote.{remote}.fetch"))?;
+        if remote != "." && fetch.is_none() {
+            let merge = git_config::get(repository, &format!("branch.{branch}.merge"))?;
+            return Err(format!(
+                "fatal: upstream branch '{}' not stored as a remote-tracking branch",
+                merge.unwrap_or_default()
+            )
+            .into());
+        }
+    }
+    return Ok(upstream);
+}
+
+/// The branch (or commit, if HEAD was detached) checked out before the
+/// `count`th most recent checkout, read from the HEAD log.
+fn previous_branch(
+    repository: &Repository,
+    reference: &str,
+    count: &str,
+    spec: &str,
+) -> Result<String, Error> {
+    let count = count
+        .parse::<usize>()
+        .ok()
+        .filter(|count| *count > 0 && reference.is_empty())
+        .ok_or_else(|| Error::ObjectNotFound(spec.to_string()))?;
+    return git_reflog::read(repository, "HEAD")?
+        .iter()
+        .rev()
+        .filter_map(|entry| entry.message.strip_prefix("checkout: moving from "))
+        .filter_map(|moving| moving.split_once(" to ").map(|(from, _)| from))
+        .nth(count - 1)
+        .map(|from| match ObjectId::from_hex(from) {
+            Ok(_) => from.to_string(),
+            Err(_) => format!("refs/heads/{from}"),
+        })
+        .ok_or_else(|| Error::ObjectNotFound(spec.to_string()));
+}
+
+/// `[<stage>:]<path>` in the index.
+fn resolve_index_path(repository: &Repository, spec: &str) -> Result<ObjectId, Error> {
+    let mut characters = spec.chars();
+    let (stage, path) = match (characters.next(), characters.next()) {
+        (Some(stage @ '0'..='3'), Some(':')) => (stage as u8 - b'0', &spec[2..]),
+        _ => (0, spec),
+    };
+    let path = relative_to_cwd(repository, path)?;
+    if let Some(entry) = git_index::read(repository)?
+        .into_iter()
+        .find(|entry| entry.path == path && entry.stage == stage)
+    {
+        return Ok(entry.hash);
+    }
+    if repository.work_tree.is_some() && repository.work_path(&path).exists() {
+        return Err(format!("fatal: path '{path}' exists on disk, but not in the index").into());
+    }
+    return Err(
+        format!("fatal: path '{path}' does not exist (neither on disk nor in the index)").into(),
+    );
+}
+
+/// Paths starting with `./` or `../` are relative to the current directory,
+/// all others to the top of the working tree.
+fn relative_to_cwd(repository: &Repository, path: &str) -> Result<String, Error> {
+    if path.starts_with("./") || path.starts_with("../") {
+        return repository.path_from_cwd(path);
+    }
+    return Ok(path.to_string());
+}
+
+/// The youngest commit reachable from `starts` whose message matches
+/// `pattern`. A leading `!-` negates the match and `!!` stands for a literal
+/// `!`; other uses of `!` are reserved.
+fn search_message(
+    repository: &Repository,
+    starts: Vec<ObjectId>,
+    pattern: &str,
+) -> Result<Option<ObjectId>, Error> {
+    let (pattern, negated) = match pattern.strip_prefix('!') {
+        Some(rest) if rest.starts_with('!') => (rest, false),
+        Some(rest) if rest.starts_with('-') => (&rest[1..], true),
+        Some(_) => return Ok(None),
+        None => (pattern, false),
+    };
+    let regex =
+        Regex::new(pattern).map_err(|err| format!("fatal: invalid regex '{pattern}': {err}"))?;
+
+    let mut seen = HashSet::new();
+    let mut pending = Vec::new();
+    for start in starts {
+        if let Ok(commit) = peel_to(repository, &start, ObjectType::Commit) {
+            if seen.insert(commit) {
+                pending.push((commit, git_object::read_commit(repository, &commit)?));
+            }
+        }
+    }
+    // newest first, like git's commit date ordered walk
+    while !pending.is_empty() {
+        // the first of equally old commits, so ties go to the earlier start
+        let newest = (0..pending.len())
+            .rev()
+            .max_by_key(|index| pending[*index].1.committer.timestamp)
+            .unwrap();
+        let (id, commit) = pending.remove(newest);
+        if regex.is_match(&commit.message) != negated {
+            return Ok(Some(id));
+        }
+        for parent in commit.parents {
+            if seen.insert(parent) {
+                pending.push((parent, git_object::read_commit(repository, &parent)?));
+            }
+        }
+    }
+    return Ok(None);
+}
+
+/// The position of the first character matching `predicate` that isn't
+/// inside `{...}` and isn't the first character.
+fn find_outside_braces(spec: &str, predicate: impl Fn(char) -> bool) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in spec.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ if depth == 0 && index > 0 && predicate(c) => return Some(index),
+            _ => {}
+        }
+    }
+    return None;
+}
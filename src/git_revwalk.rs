@@ -0,0 +1,865 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use regex::Regex;
+
+use crate::{
+    error::Error,
+    git,
+    git_object::{self, Commit, ObjectType},
+    git_rename::{self, RenameOptions},
+    git_revision::{self, Revision},
+    object_id::ObjectId,
+    repository::Repository,
+};
+
+/// What to walk and which of the commits found to show, as set by the
+/// options `log` and `rev-list` share.
+#[derive(Clone, Default, Debug)]
+pub struct WalkOptions {
+    /// Show parents before all of their children instead of by date.
+    pub topo_order: bool,
+    /// Follow only the first parent of merges.
+    pub first_parent: bool,
+    pub max_count: Option<usize>,
+    /// Only commits with at least this many parents (2 for `--merges`).
+    pub min_parents: usize,
+    /// Only commits with at most this many parents (1 for `--no-merges`).
+    pub max_parents: Option<usize>,
+    /// Regexes for the author's `Name <email>`, any of which must match.
+    pub authors: Vec<String>,
+    /// Regexes for the message, any of which must match.
+    pub grep: Vec<String>,
+    /// Commit date bounds as unix timestamps.
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Only commits changing these paths, relative to the top of the work
+    /// tree; an empty path stands for the whole tree.
+    pub paths: Vec<String>,
+    /// Keep following the single path in `paths` across renames and
+    /// copies, found the way these options say.
+    pub follow: Option<RenameOptions>,
+    /// Only commits descending from an excluded revision, i.e. on the
+    /// ancestry chain between the two ends of a range.
+    pub ancestry_path: bool,
+    /// Also return the excluded and left out commits right behind the
+    /// shown ones.
+    pub boundary: bool,
+}
+
+/// A commit the walk shows, with the parents it is drawn against: those
+/// left after `--first-parent`, and with path limiting the nearest shown
+/// ancestors in place of the commits skipped for not touching the paths.
+#[derive(Clone, Debug)]
+pub struct WalkedCommit {
+    pub id: ObjectId,
+    pub commit: Commit,
+    pub parents: Vec<ObjectId>,
+    /// Reachable from the left side of a symmetric difference `A...B`.
+    pub left: bool,
+    /// Not part of the range, but the parent of a commit that is.
+    pub boundary: bool,
+    /// The paths the walk was limited to at this commit, which `follow`
+    /// changes at renames.
+    pub paths: Vec<String>,
+}
+
+/// A commit visited by the walk, shown or not.
+struct Visit {
+    id: ObjectId,
+    commit: Commit,
+    /// The parents the walk went on to, after history simplification.
+    parents: Vec<ObjectId>,
+    /// The parents that must come after it in topological order: all of
+    /// them, even those `--first-parent` doesn't walk, unless simplified.
+    order_parents: Vec<ObjectId>,
+    /// Whether the commit leaves the limited paths as its parent had them.
+    treesame: bool,
+    paths: Vec<String>,
+}
+
+/// How many more excluded commits a limited walk looks at once only
+/// excluded ones are left in its queue, in case clock skew put an included
+/// one behind them.
+const SLOP: usize = 5;
+
+/// Walks the history reachable from the included `revisions` but not from
+/// the excluded ones, youngest first (or in topological order), and returns
+/// the commits that pass `options`' filters.
+///
+/// With paths to limit to, history is simplified the way git does by
+/// default: a merge that leaves the paths as one of its parents had them is
+/// followed down that parent only, and commits that don't touch the paths
+/// aren't shown.
+pub fn walk(
+    repository: &Repository,
+    revisions: &[Revision],
+    options: &WalkOptions,
+) -> Result<Vec<WalkedCommit>, Error> {
+    // like git, a walk with excluded revisions or in topological order has
+    // to look at the history first, and then marks everything behind a
+    // commit too old for `--since` as not wanted
+    let limited = options.topo_order || revisions.iter().any(|revision| revision.excluded);
+    let mut walker = Walker {
+        repository,
+        options,
+        limited,
+        queue: BinaryHeap::new(),
+        queued: HashSet::new(),
+        pending: HashSet::new(),
+        interesting_pending: 0,
+        uninteresting: HashSet::new(),
+        left: HashSet::new(),
+        walked_parents: HashMap::new(),
+        paths: options.paths.clone(),
+        sequence: 0,
+        last_date: i64::MAX,
+        slop: SLOP,
+    };
+    let mut bottoms = HashSet::new();
+    for revision in revisions {
+        let id = git_revision::peel_to(repository, &revision.id, ObjectType::Commit)?;
+        if revision.excluded {
+            bottoms.insert(id);
+            walker.exclude(id);
+        }
+        if revision.left {
+            walker.left.insert(id);
+        }
+        walker.push(id)?;
+    }
+    let authors = compile(&options.authors)?;
+    let grep = compile(&options.grep)?;
+
+    let mut visits: Vec<Visit> = Vec::new();
+    if limited {
+        while let Some(visit) = walker.next()? {
+            visits.push(visit);
+        }
+        visits.retain(|visit| !walker.uninteresting.contains(&visit.id));
+        if options.ancestry_path {
+            let descendants = descendants_of(&visits, &bottoms);
+            visits.retain(|visit| descendants.contains(&visit.id));
+        }
+        if options.topo_order {
+            visits = sort_topologically(visits);
+        }
+    }
+
+    let simplified = !options.paths.is_empty();
+    let mut index: HashMap<ObjectId, usize> = visits
+        .iter()
+        .enumerate()
+        .map(|(position, visit)| (visit.id, position))
+        .collect();
+    let mut walked = Vec::new();
+    let mut position = 0;
+    while walked.len() < options.max_count.unwrap_or(usize::MAX) {
+        // otherwise commits are shown as the walk finds them
+        if position == visits.len() && !walker.pull(&mut visits, &mut index)? {
+            break;
+        }
+        let visit = &visits[position];
+        position += 1;
+        let parent_count = if simplified {
+            visit.parents.len()
+        } else {
+            visit.commit.parents.len()
+        };
+        let shown = !visit.treesame
+            && parent_count >= options.min_parents
+            && options.max_parents.is_none_or(|max| parent_count <= max)
+            && options
+                .until
+                .is_none_or(|until| visit.commit.committer.timestamp <= until)
+            && matches_any(&authors, &author_identity(&visit.commit))
+            && matches_any(&grep, &visit.commit.message);
+        if !shown {
+            continue;
+        }
+        let mut parents: Vec<ObjectId> = Vec::new();
+        for parent in visit.parents.clone() {
+            // skip over the commits path limiting leaves out, walking on
+            // to them first if they are still queued
+            let mut parent = Some(parent);
+            while let Some(id) = parent {
+                while walker.pending.contains(&id) && walker.pull(&mut visits, &mut index)? {}
+                match index.get(&id).map(|&at| &visits[at]) {
+                    Some(visit) if visit.treesame => parent = visit.parents.first().copied(),
+                    _ => break,
+                }
+            }
+            if let Some(parent) = parent.filter(|parent| !parents.contains(parent)) {
+                parents.push(parent);
+            }
+        }
+        let visit = &visits[position - 1];
+        walked.push(WalkedCommit {
+            id: visit.id,
+            commit: visit.commit.clone(),
+            parents,
+            left: walker.left.contains(&visit.id),
+            boundary: false,
+            paths: visit.paths.clone(),
+        });
+    }
+    if options.boundary {
+        let boundary = boundary_of(repository, &walked, simplified)?;
+        walked.extend(boundary.into_iter().map(|visit| WalkedCommit {
+            id: visit.id,
+            left: walker.left.contains(&visit.id),
+            boundary: true,
+            parents: visit.parents,
+            commit: visit.commit,
+            paths: visit.paths,
+        }));
+    }
+    return Ok(walked);
+}
+
+/// A commit waiting in the walk's queue. The youngest comes out first, and
+/// among those of the same date the one found first.
+struct Queued {
+    id: ObjectId,
+    commit: Commit,
+    sequence: usize,
+}
+
+impl Queued {
+    fn key(&self) -> (i64, Reverse<usize>) {
+        return (self.commit.committer.timestamp, Reverse(self.sequence));
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        return self.key() == other.key();
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self.key().cmp(&other.key());
+    }
+}
+
+/// The state of a walk: the commits queued to visit next, and which
+/// commits are excluded, found as the walk goes the way git's `limit_list`
+/// does.
+struct Walker<'a> {
+    repository: &'a Repository,
+    options: &'a WalkOptions,
+    limited: bool,
+    queue: BinaryHeap<Queued>,
+    /// Every commit ever queued.
+    queued: HashSet<ObjectId>,
+    /// The commits in the queue.
+    pending: HashSet<ObjectId>,
+    /// How many commits in the queue aren't excluded.
+    interesting_pending: usize,
+    uninteresting: HashSet<ObjectId>,
+    /// The commits reachable from the left side of a symmetric difference,
+    /// passed on from child to parent as the walk goes.
+    left: HashSet<ObjectId>,
+    /// The parents of the commits visited, to pass exclusion on to.
+    walked_parents: HashMap<ObjectId, Vec<ObjectId>>,
+    paths: Vec<String>,
+    sequence: usize,
+    /// The date of the last commit visited that isn't excluded.
+    last_date: i64,
+    slop: usize,
+}
+
+impl Walker<'_> {
+    fn push(&mut self, id: ObjectId) -> Result<(), Error> {
+        if !self.queued.insert(id) {
+            return Ok(());
+        }
+        let commit = git_object::read_commit(self.repository, &id)?;
+        if !self.uninteresting.contains(&id) {
+            self.interesting_pending += 1;
+        }
+        self.pending.insert(id);
+        self.queue.push(Queued {
+            id,
+            commit,
+            sequence: self.sequence,
+        });
+        self.sequence += 1;
+        return Ok(());
+    }
+
+    /// Excludes `id` and the ancestors of it visited so far.
+    fn exclude(&mut self, id: ObjectId) {
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if !self.uninteresting.insert(id) {
+                continue;
+            }
+            if self.pending.contains(&id) {
+                self.interesting_pending -= 1;
+            }
+            if let Some(parents) = self.walked_parents.get(&id) {
+                pending.extend(parents.iter().copied());
+            }
+        }
+    }
+
+    /// Whether an excluded commit just visited leaves anything worth
+    /// walking on for: a commit that isn't excluded, or one at least as
+    /// young as the last of those.
+    fn still_interesting(&mut self) -> bool {
+        let next_date = match self.queue.peek() {
+            Some(next) => next.commit.committer.timestamp,
+            None => return false,
+        };
+        if next_date >= self.last_date || self.interesting_pending > 0 {
+            self.slop = SLOP;
+            return true;
+        }
+        self.slop -= 1;
+        return self.slop > 0;
+    }
+
+    /// Visits the next commit that isn't excluded, or returns `None` once
+    /// there are no more.
+    fn next(&mut self) -> Result<Option<Visit>, Error> {
+        let options = self.options;
+        while let Some(Queued { id, commit, .. }) = self.queue.pop() {
+            self.pending.remove(&id);
+            let excluded = self.uninteresting.contains(&id);
+            if !excluded {
+                self.interesting_pending -= 1;
+            }
+            let too_old = options
+                .since
+                .is_some_and(|since| commit.committer.timestamp < since);
+            if excluded || (too_old && self.limited) {
+                self.exclude(id);
+                for parent in commit.parents.iter() {
+                    self.exclude(*parent);
+                    self.push(*parent)?;
+                }
+                if self.still_interesting() {
+                    continue;
+                }
+                self.queue.clear();
+                self.pending.clear();
+                return Ok(None);
+            }
+            if too_old {
+                continue;
+            }
+
+            let mut parents = commit.parents.clone();
+            let mut order_parents = commit.parents.clone();
+            if options.first_parent {
+                parents.truncate(1);
+            }
+            let commit_paths = self.paths.clone();
+            let treesame = if self.paths.is_empty() {
+                false
+            } else {
+                let same_parent = treesame_parent(
+                    self.repository,
+                    &commit,
+                    &parents,
+                    &mut self.paths,
+                    &options.follow,
+                )?;
+                if let Some(parent) = same_parent {
+                    parents = vec![parent];
+                    order_parents = vec![parent];
+                }
+                same_parent.is_some()
+                    || (parents.is_empty() && !touches(self.repository, &commit, &self.paths)?)
+            };
+            self.walked_parents.insert(id, commit.parents.clone());
+            let left = self.left.contains(&id);
+            for parent in parents.iter() {
+                if left {
+                    self.left.insert(*parent);
+                }
+                self.push(*parent)?;
+            }
+            // and leaves commits too new for `--until` out of the ordering
+            if self.limited
+                && options
+                    .until
+                    .is_some_and(|until| commit.committer.timestamp > until)
+            {
+                continue;
+            }
+            self.last_date = commit.committer.timestamp;
+            return Ok(Some(Visit {
+                id,
+                commit,
+                parents,
+                order_parents,
+                treesame,
+                paths: commit_paths,
+            }));
+        }
+        return Ok(None);
+    }
+
+    /// Visits the next commit and adds it to `visits`, returning whether
+    /// there was one.
+    fn pull(
+        &mut self,
+        visits: &mut Vec<Visit>,
+        index: &mut HashMap<ObjectId, usize>,
+    ) -> Result<bool, Error> {
+        return Ok(match self.next()? {
+            Some(visit) => {
+                index.insert(visit.id, visits.len());
+                visits.push(visit);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// The commits among `visits` that have one of `bottoms` as an ancestor.
+fn descendants_of(visits: &[Visit], bottoms: &HashSet<ObjectId>) -> HashSet<ObjectId> {
+    let parents: HashMap<ObjectId, &Vec<ObjectId>> = visits
+        .iter()
+        .map(|visit| (visit.id, &visit.order_parents))
+        .collect();
+    let mut descends: HashMap<ObjectId, bool> = HashMap::new();
+    for visit in visits {
+        // depth first, settling parents before their children
+        let mut pending = vec![visit.id];
+        while let Some(&id) = pending.last() {
+            if descends.contains_key(&id) {
+                pending.pop();
+                continue;
+            }
+            let unsettled: Vec<ObjectId> = parents[&id]
+                .iter()
+                .filter(|parent| parents.contains_key(parent) && !descends.contains_key(parent))
+                .copied()
+                .collect();
+            if !unsettled.is_empty() {
+                pending.extend(unsettled);
+                continue;
+            }
+            let found = parents[&id]
+                .iter()
+                .any(|parent| bottoms.contains(parent) || descends.get(parent) == Some(&true));
+            descends.insert(id, found);
+            pending.pop();
+        }
+    }
+    return descends
+        .into_iter()
+        .filter(|(_, found)| *found)
+        .map(|(id, _)| id)
+        .collect();
+}
+
+/// The parents of `walked` commits that weren't shown themselves, children
+/// first and otherwise latest found first, as git lists them.
+fn boundary_of(
+    repository: &Repository,
+    walked: &[WalkedCommit],
+    simplified: bool,
+) -> Result<Vec<Visit>, Error> {
+    let shown: HashSet<ObjectId> = walked.iter().map(|walked| walked.id).collect();
+    let mut found = Vec::new();
+    for walked in walked {
+        let parents = match simplified {
+            true => &walked.parents,
+            false => &walked.commit.parents,
+        };
+        for parent in parents {
+            if !shown.contains(parent) && !found.contains(parent) {
+                found.push(*parent);
+            }
+        }
+    }
+    let mut boundary = Vec::new();
+    for id in found.into_iter().rev() {
+        let commit = git_object::read_commit(repository, &id)?;
+        boundary.push(Visit {
+            id,
+            parents: commit.parents.clone(),
+            order_parents: commit.parents.clone(),
+            commit,
+            treesame: false,
+            // only shown by rev-list, which doesn't need them
+            paths: Vec::new(),
+        });
+    }
+    return Ok(sort_topologically(boundary));
+}
+
+/// Which trees and blobs `rev-list --objects --filter=<spec>` leaves out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ObjectFilter {
+    /// `blob:none`
+    NoBlobs,
+    /// `blob:limit=<n>[kmg]`: blobs of at least this many bytes.
+    BlobLimit(usize),
+    /// `tree:<depth>`: trees and blobs this deep below a root tree or more.
+    TreeDepth(usize),
+    /// `object:type=<type>`: everything not of this type.
+    ObjectType(ObjectType),
+}
+
+impl ObjectFilter {
+    pub fn parse(spec: &str) -> Result<ObjectFilter, Error> {
+        let invalid = || Error::from(format!("fatal: invalid filter-spec '{spec}'"));
+        if spec == "blob:none" {
+            return Ok(ObjectFilter::NoBlobs);
+        }
+        if let Some(limit) = spec.strip_prefix("blob:limit=") {
+            let (digits, unit) = match limit.find(|c: char| !c.is_ascii_digit()) {
+                Some(at) => limit.split_at(at),
+                None => (limit, ""),
+            };
+            let scale = match unit.to_lowercase().as_str() {
+                "" => 1,
+                "k" => 1 << 10,
+                "m" => 1 << 20,
+                "g" => 1 << 30,
+                _ => return Err(invalid()),
+            };
+            let limit: usize = digits.parse().map_err(|_| invalid())?;
+            return Ok(ObjectFilter::BlobLimit(limit * scale));
+        }
+        if let Some(depth) = spec.strip_prefix("tree:") {
+            return Ok(ObjectFilter::TreeDepth(
+                depth.parse().map_err(|_| invalid())?,
+            ));
+        }
+        return match spec.strip_prefix("object:type=") {
+            Some("blob") => Ok(ObjectFilter::ObjectType(ObjectType::Blob)),
+            Some("tree") => Ok(ObjectFilter::ObjectType(ObjectType::Tree)),
+            Some("commit") => Ok(ObjectFilter::ObjectType(ObjectType::Commit)),
+            Some("tag") => Ok(ObjectFilter::ObjectType(ObjectType::Tag)),
+            _ => Err(invalid()),
+        };
+    }
+
+    /// Whether an object of `object_type`, `depth` levels below a root
+    /// tree, is listed.
+    fn keeps(
+        &self,
+        repository: &Repository,
+        id: &ObjectId,
+        object_type: ObjectType,
+        depth: usize,
+    ) -> Result<bool, Error> {
+        return Ok(match self {
+            ObjectFilter::NoBlobs => object_type != ObjectType::Blob,
+            ObjectFilter::BlobLimit(limit) => {
+                object_type != ObjectType::Blob || git_object::get_size(repository, id)? < *limit
+            }
+            ObjectFilter::TreeDepth(max) => depth < *max,
+            ObjectFilter::ObjectType(wanted) => object_type == *wanted,
+        });
+    }
+}
+
+/// Everything `rev-list --objects` lists after the commits: first the
+/// objects named directly in `given` (tags, trees and blobs, with the name
+/// to show them under), then the trees and blobs of `commits`, each with its
+/// path. What the `excluded` commits right behind the range and the
+/// `excluded` trees already have is left out.
+pub fn list_objects(
+    repository: &Repository,
+    commits: &[WalkedCommit],
+    given: &[(ObjectId, String)],
+    excluded: &[ObjectId],
+    filter: Option<ObjectFilter>,
+) -> Result<Vec<(ObjectId, String)>, Error> {
+    let mut seen = HashSet::new();
+    let mut uninteresting = HashSet::new();
+    for id in excluded {
+        match git_object::get_type(repository, id)? {
+            ObjectType::Commit => uninteresting.extend(git::reachable_commits(repository, id)?),
+            ObjectType::Tree => mark_seen(repository, id, &mut seen)?,
+            _ => {
+                seen.insert(*id);
+            }
+        }
+    }
+    for walked in commits.iter().filter(|walked| !walked.boundary) {
+        for parent in walked.commit.parents.iter() {
+            if uninteresting.contains(parent) {
+                let tree = git_object::read_commit(repository, parent)?.tree;
+                mark_seen(repository, &tree, &mut seen)?;
+            }
+        }
+    }
+
+    let mut listing = ObjectListing {
+        repository,
+        filter,
+        seen,
+        listed: Vec::new(),
+    };
+    for (id, name) in given {
+        // named objects are listed even when the other side has them
+        listing.seen.remove(id);
+        match git_object::get_type(repository, id)? {
+            ObjectType::Tree => listing.tree(id, name, 0)?,
+            object_type => {
+                listing.object(id, name, object_type, 0)?;
+            }
+        }
+    }
+    for walked in commits.iter().filter(|walked| !walked.boundary) {
+        listing.tree(&walked.commit.tree, "", 0)?;
+    }
+    return Ok(listing.listed);
+}
+
+/// Marks a tree and everything below it as already present.
+fn mark_seen(
+    repository: &Repository,
+    tree: &ObjectId,
+    seen: &mut HashSet<ObjectId>,
+) -> Result<(), Error> {
+    if !seen.insert(*tree) {
+        return Ok(());
+    }
+    for node in git_object::read_tree_object(repository, tree)?.entries {
+        if node.is_tree() {
+            mark_seen(repository, &node.hash, seen)?;
+        } else {
+            seen.insert(node.hash);
+        }
+    }
+    return Ok(());
+}
+
+/// The state of a `--objects` listing: what has been listed or is known
+/// to be present on the other side already.
+struct ObjectListing<'a> {
+    repository: &'a Repository,
+    filter: Option<ObjectFilter>,
+    seen: HashSet<ObjectId>,
+    listed: Vec<(ObjectId, String)>,
+}
+
+impl ObjectListing<'_> {
+    fn object(
+        &mut self,
+        id: &ObjectId,
+        path: &str,
+        object_type: ObjectType,
+        depth: usize,
+    ) -> Result<bool, Error> {
+        if self.seen.contains(id) {
+            return Ok(false);
+        }
+        let keep = match self.filter {
+            Some(filter) => filter.keeps(self.repository, id, object_type, depth)?,
+            None => true,
+        };
+        if keep {
+            self.seen.insert(*id);
+            self.listed.push((*id, path.to_string()));
+        }
+        return Ok(keep);
+    }
+
+    /// Lists `tree` and then its entries in order, depth first.
+    fn tree(&mut self, tree: &ObjectId, path: &str, depth: usize) -> Result<(), Error> {
+        if self.seen.contains(tree) {
+            return Ok(());
+        }
+        let listed = self.object(tree, path, ObjectType::Tree, depth)?;
+        if !listed && matches!(self.filter, Some(ObjectFilter::TreeDepth(_))) {
+            return Ok(());
+        }
+        self.seen.insert(*tree);
+        for node in git_object::read_tree_object(self.repository, tree)?.entries {
+            let node_path = match path {
+                "" => node.name.clone(),
+                path => format!("{path}/{}", node.name),
+            };
+            if node.is_tree() {
+                self.tree(&node.hash, &node_path, depth + 1)?;
+            } else if !node.is_gitlink() {
+                self.object(&node.hash, &node_path, ObjectType::Blob, depth + 1)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// The first of `parents` that has the limited paths as `commit` has them.
+/// When following a file that `commit` adds, `paths` switches to the file
+/// of the parent it was renamed or copied from.
+fn treesame_parent(
+    repository: &Repository,
+    commit: &Commit,
+    parents: &[ObjectId],
+    paths: &mut [String],
+    follow: &Option<RenameOptions>,
+) -> Result<Option<ObjectId>, Error> {
+    for parent in parents {
+        let parent_tree = git_object::read_commit(repository, parent)?.tree;
+        if !paths_differ(repository, &parent_tree, &commit.tree, paths)? {
+            return Ok(Some(*parent));
+        }
+    }
+    if let (Some(follow), [parent]) = (follow, parents) {
+        let parent_tree = git_object::read_commit(repository, parent)?.tree;
+        let found =
+            git_rename::follow_origin(repository, &parent_tree, &commit.tree, &paths[0], follow)?;
+        if let Some(pair) = found {
+            paths[0] = pair.old_path().to_string();
+        }
+    }
+    return Ok(None);
+}
+
+/// Whether any of `paths` names something different in the two trees.
+fn paths_differ(
+    repository: &Repository,
+    old_tree: &ObjectId,
+    new_tree: &ObjectId,
+    paths: &[String],
+) -> Result<bool, Error> {
+    if old_tree == new_tree {
+        return Ok(false);
+    }
+    for path in paths {
+        if path.is_empty() {
+            return Ok(true);
+        }
+        let old = git::find_tree_entry(repository, old_tree, path)?;
+        let new = git::find_tree_entry(repository, new_tree, path)?;
+        if old.map(|node| (node.mode, node.hash)) != new.map(|node| (node.mode, node.hash)) {
+            return Ok(true);
+        }
+    }
+    return Ok(false);
+}
+
+/// Whether a root commit has anything at `paths`.
+fn touches(repository: &Repository, commit: &Commit, paths: &[String]) -> Result<bool, Error> {
+    for path in paths {
+        if path.is_empty() || git::find_tree_entry(repository, &commit.tree, path)?.is_some() {
+            return Ok(true);
+        }
+    }
+    return Ok(false);
+}
+
+/// Orders `visits` so that every commit comes before its parents, keeping
+/// the walk's order among the tips and going down one line of history as
+/// far as possible before coming back for the next, the way `--graph`
+/// draws it.
+fn sort_topologically(visits: Vec<Visit>) -> Vec<Visit> {
+    let mut indegree: HashMap<ObjectId, usize> = visits.iter().map(|visit| (visit.id, 1)).collect();
+    for visit in visits.iter() {
+        for parent in visit.order_parents.iter() {
+            if let Some(count) = indegree.get_mut(parent) {
+                *count += 1;
+            }
+        }
+    }
+    let mut stack: Vec<ObjectId> = visits
+        .iter()
+        .filter(|visit| indegree[&visit.id] == 1)
+        .map(|visit| visit.id)
+        .collect();
+    stack.reverse();
+
+    let mut by_id: HashMap<ObjectId, Visit> =
+        visits.into_iter().map(|visit| (visit.id, visit)).collect();
+    let mut sorted = Vec::new();
+    while let Some(id) = stack.pop() {
+        let visit = by_id.remove(&id).unwrap();
+        for parent in visit.order_parents.iter() {
+            if let Some(count) = indegree.get_mut(parent) {
+                if *count == 0 {
+                    continue;
+                }
+                *count -= 1;
+                if *count == 1 {
+                    stack.push(*parent);
+                }
+            }
+        }
+        indegree.insert(id, 0);
+        sorted.push(visit);
+    }
+    return sorted;
+}
+
+/// Compiles `--author` and `--grep` patterns, which like git's are POSIX
+/// basic regular expressions.
+fn compile(patterns: &[String]) -> Result<Vec<Regex>, Error> {
+    let mut compiled = Vec::new();
+    for pattern in patterns {
+        compiled.push(
+            Regex::new(&from_basic(pattern))
+                .map_err(|err| format!("fatal: invalid regex '{pattern}': {err}"))?,
+        );
+    }
+    return Ok(compiled);
+}
+
+/// Rewrites a basic regular expression in the extended syntax `Regex`
+/// takes: `(`, `)`, `{`, `}`, `+`, `?` and `|` are literal unless escaped.
+fn from_basic(pattern: &str) -> String {
+    let mut extended = String::new();
+    let mut chars = pattern.chars();
+    let mut in_brackets = false;
+    while let Some(c) = chars.next() {
+        match c {
+            _ if in_brackets => {
+                in_brackets = c != ']';
+                extended.push(c);
+            }
+            '[' => {
+                in_brackets = true;
+                extended.push(c);
+                // a `]` right at the start is part of the set
+                if let Some(next) = chars.next() {
+                    extended.push(next);
+                }
+            }
+            '\\' => match chars.next() {
+                Some(next) if "(){}+?|".contains(next) => extended.push(next),
+                Some(next) => {
+                    extended.push(c);
+                    extended.push(next);
+                }
+                None => extended.push(c),
+            },
+            '(' | ')' | '{' | '}' | '+' | '?' | '|' => {
+                extended.push('\\');
+                extended.push(c);
+            }
+            _ => extended.push(c),
+        }
+    }
+    return extended;
+}
+
+fn matches_any(patterns: &[Regex], text: &str) -> bool {
+    return patterns.is_empty() || patterns.iter().any(|pattern| pattern.is_match(text));
+}
+
+/// The part of the author line `--author` matches against.
+fn author_identity(commit: &Commit) -> String {
+    return format!("{} <{}>", commit.author.name, commit.author.email);
+}
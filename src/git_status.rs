@@ -0,0 +1,444 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::ErrorKind,
+};
+
+use crate::{
+    error::Error,
+    git,
+    git_diff::{DiffSide, FilePair, Origin},
+    git_ignore::IgnoreRules,
+    git_index::{self, IndexEntry},
+    git_merge_base, git_object, git_refs,
+    git_rename::{self, RenameOptions, Skipped},
+    git_revision::Revision,
+    git_revwalk::{self, WalkOptions},
+    object_id::ObjectId,
+    repository::Repository,
+};
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+    TypeChanged,
+    Renamed,
+    Copied,
+}
+
+impl Change {
+    pub fn short_code(&self) -> char {
+        return match self {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+            Change::TypeChanged => 'T',
+            Change::Renamed => 'R',
+            Change::Copied => 'C',
+        };
+    }
+
+    pub fn long_label(&self) -> &'static str {
+        return match self {
+            Change::Added => "new file:",
+            Change::Modified => "modified:",
+            Change::Deleted => "deleted:",
+            Change::TypeChanged => "typechange:",
+            Change::Renamed => "renamed:",
+            Change::Copied => "copied:",
+        };
+    }
+}
+
+/// The conflict stages (base, ours and theirs) of an unmerged path.
+#[derive(Clone, Copy)]
+pub struct Conflict {
+    pub stages: [Option<(u64, ObjectId)>; 3],
+}
+
+impl Conflict {
+    /// Which stages are present, one bit each from base to theirs.
+    fn mask(&self) -> usize {
+        return (0..3)
+            .filter(|stage| self.stages[*stage].is_some())
+            .map(|stage| 1 << stage)
+            .sum();
+    }
+
+    pub fn short_code(&self) -> &'static str {
+        return match self.mask() {
+            1 => "DD",
+            2 => "AU",
+            3 => "UD",
+            4 => "UA",
+            5 => "DU",
+            6 => "AA",
+            _ => "UU",
+        };
+    }
+
+    pub fn long_label(&self) -> &'static str {
+        return match self.mask() {
+            1 => "both deleted:",
+            2 => "added by us:",
+            3 => "deleted by them:",
+            4 => "added by them:",
+            5 => "deleted by us:",
+            6 => "both added:",
+            _ => "both modified:",
+        };
+    }
+}
+
+pub struct StatusEntry {
+    pub path: String,
+    pub staged: Option<Change>,
+    pub unstaged: Option<Change>,
+    /// Mode and hash of the path in HEAD, the index and the working tree.
+    pub head: Option<(u64, ObjectId)>,
+    pub index: Option<(u64, ObjectId)>,
+    pub worktree_mode: Option<u64>,
+    /// Where a staged rename or copy comes from in HEAD.
+    pub origin: Option<Origin>,
+    /// The stages of an unmerged path, which has neither staged nor
+    /// unstaged changes of its own.
+    pub conflict: Option<Conflict>,
+}
+
+pub struct Upstream {
+    pub name: String,
+    /// `None` when the upstream is configured but its ref doesn't exist.
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+pub struct Status {
+    pub branch: Option<String>,
+    pub head: Option<ObjectId>,
+    pub upstream: Option<Upstream>,
+    pub entries: Vec<StatusEntry>,
+    pub untracked: Vec<String>,
+    /// Whether a merge is in progress (`MERGE_HEAD` exists).
+    pub merging: bool,
+}
+
+/// The status of the work tree, with staged files paired up with the HEAD
+/// files they were renamed or copied from as `renames` asks.
+pub fn status(repository: &Repository, renames: &RenameOptions) -> Result<Status, Error> {
+    repository.work_tree()?;
+    let branch = git::current_branch(repository)?;
+    let head = git::head_commit(repository)?;
+    let head_files = match &head {
+        Some(head) => {
+            git::flatten_tree(repository, &git_object::read_commit(repository, head)?.tree)?
+        }
+        None => BTreeMap::new(),
+    };
+    let mut index_entries = git_index::read(repository)?;
+    let index_mtime = git_index::index_mtime(repository);
+
+    let mut entries: BTreeMap<String, StatusEntry> = BTreeMap::new();
+    let mut index_refreshed = false;
+    for index_entry in index_entries.iter_mut().filter(|e| e.stage == 0) {
+        let head_node = head_files.get(&index_entry.path);
+        let staged = match head_node {
+            None => Some(Change::Added),
+            Some(node) if node.hash == index_entry.hash && node.mode == index_entry.tree_mode() => {
+                None
+            }
+            Some(node) if file_kind(node.mode) != file_kind(index_entry.tree_mode()) => {
+                Some(Change::TypeChanged)
+            }
+            Some(_) => Some(Change::Modified),
+        };
+
+        let (unstaged, worktree_mode) =
+            worktree_change(repository, index_entry, index_mtime, &mut index_refreshed)?;
+
+        entries.insert(
+            index_entry.path.clone(),
+            StatusEntry {
+                path: index_entry.path.clone(),
+                staged,
+                unstaged,
+                head: head_node.map(|node| (node.mode, node.hash)),
+                index: Some((index_entry.tree_mode(), index_entry.hash)),
+                worktree_mode,
+                origin: None,
+                conflict: None,
+            },
+        );
+    }
+    for index_entry in index_entries.iter().filter(|e| e.stage != 0) {
+        let worktree_mode = fs::symlink_metadata(repository.work_path(&index_entry.path))
+            .ok()
+            .map(|metadata| tree_mode_for(&metadata));
+        let entry = entries
+            .entry(index_entry.path.clone())
+            .or_insert_with(|| StatusEntry {
+                path: index_entry.path.clone(),
+                staged: None,
+                unstaged: None,
+                head: head_files
+                    .get(&index_entry.path)
+                    .map(|node| (node.mode, node.hash)),
+                index: None,
+                worktree_mode,
+                origin: None,
+                conflict: Some(Conflict { stages: [None; 3] }),
+            });
+        if let Some(conflict) = &mut entry.conflict {
+            conflict.stages[index_entry.stage as usize - 1] =
+                Some((index_entry.tree_mode(), index_entry.hash));
+        }
+    }
+    for (path, node) in head_files.iter() {
+        if entries.contains_key(path) {
+            continue;
+        }
+        let worktree_mode = fs::symlink_metadata(repository.work_path(path))
+            .ok()
+            .map(|metadata| tree_mode_for(&metadata));
+        entries.insert(
+            path.clone(),
+            StatusEntry {
+                path: path.clone(),
+                staged: Some(Change::Deleted),
+                unstaged: None,
+                head: Some((node.mode, node.hash)),
+                index: None,
+                worktree_mode,
+                origin: None,
+                conflict: None,
+            },
+        );
+    }
+    find_staged_renames(repository, &mut entries, renames)?;
+    if index_refreshed {
+        // keep the refreshed stat data so the next status doesn't need to
+        // hash these files again
+        git_index::write(repository, &mut index_entries)?;
+    }
+
+    let tracked: HashSet<&str> = index_entries.iter().map(|e| e.path.as_str()).collect();
+    let untracked = untracked_paths(repository, &tracked)?;
+
+    return Ok(Status {
+        upstream: match &branch {
+            Some(branch) => upstream(repository, branch, &head)?,
+            None => None,
+        },
+        branch,
+        head,
+        entries: entries
+            .into_values()
+            .filter(|entry| {
+                entry.staged.is_some() || entry.unstaged.is_some() || entry.conflict.is_some()
+            })
+            .collect(),
+        untracked,
+        merging: repository.git_path("MERGE_HEAD").exists(),
+    });
+}
+
+/// Turns staged files that `git_rename` pairs with a HEAD file into
+/// renames or copies of it. The file a rename leaves is no longer listed.
+fn find_staged_renames(
+    repository: &Repository,
+    entries: &mut BTreeMap<String, StatusEntry>,
+    renames: &RenameOptions,
+) -> Result<(), Error> {
+    let side = |side: &Option<(u64, ObjectId)>| side.map(|(mode, id)| DiffSide { mode, id });
+    let pairs = entries
+        .values()
+        .filter(|entry| entry.staged.is_some())
+        .map(|entry| FilePair {
+            path: entry.path.clone(),
+            old: side(&entry.head),
+            new: side(&entry.index),
+            origin: None,
+            unmerged: false,
+        })
+        .collect();
+    let pairs = git_rename::detect_renames(repository, pairs, renames, &mut Skipped::default())?;
+    for pair in pairs {
+        let origin = match pair.origin {
+            Some(origin) => origin,
+            None => continue,
+        };
+        if !origin.copy {
+            entries.remove(&origin.path);
+        }
+        let entry = entries.get_mut(&pair.path).unwrap();
+        entry.staged = Some(match origin.copy {
+            true => Change::Copied,
+            false => Change::Renamed,
+        });
+        entry.head = pair.old.map(|old| (old.mode, old.id));
+        entry.origin = Some(origin);
+    }
+    return Ok(());
+}
+
+fn worktree_change(
+    repository: &Repository,
+    index_entry: &mut IndexEntry,
+    index_mtime: Option<(u32, u32)>,
+    index_refreshed: &mut bool,
+) -> Result<(Option<Change>, Option<u64>), Error> {
+    let metadata = match fs::symlink_metadata(repository.work_path(&index_entry.path)) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok((Some(Change::Deleted), None)),
+        Err(err) => return Err(format!("error reading {}: {err}", index_entry.path).into()),
+    };
+    let worktree_mode = tree_mode_for(&metadata);
+
+    if index_entry.is_gitlink() {
+        if !metadata.is_dir() {
+            return Ok((Some(Change::TypeChanged), Some(worktree_mode)));
+        }
+        let submodule = repository.work_path(&index_entry.path);
+        if !submodule.join(".git").exists() {
+            // not populated, which isn't a change
+            return Ok((None, Some(worktree_mode)));
+        }
+        let checked_out = git_object::submodule_head(&submodule).ok();
+        return Ok(if checked_out.as_ref() == Some(&index_entry.hash) {
+            (None, Some(worktree_mode))
+        } else {
+            (Some(Change::Modified), Some(worktree_mode))
+        });
+    }
+    if metadata.is_dir() {
+        return Ok((Some(Change::Deleted), None));
+    }
+
+    if file_kind(worktree_mode) != file_kind(index_entry.tree_mode()) {
+        return Ok((Some(Change::TypeChanged), Some(worktree_mode)));
+    }
+    if worktree_mode != index_entry.tree_mode() {
+        return Ok((Some(Change::Modified), Some(worktree_mode)));
+    }
+    if index_entry.stat_matches(&metadata, index_mtime) {
+        return Ok((None, Some(worktree_mode)));
+    }
+
+    let hash = git_object::hash_blob_from_file(repository, &index_entry.path)?;
+    if hash != index_entry.hash {
+        return Ok((Some(Change::Modified), Some(worktree_mode)));
+    }
+    *index_entry = IndexEntry::from_metadata(
+        &index_entry.path,
+        &index_entry.hash,
+        index_entry.mode,
+        &metadata,
+    );
+    *index_refreshed = true;
+    return Ok((None, Some(worktree_mode)));
+}
+
+pub fn tree_mode_for(metadata: &fs::Metadata) -> u64 {
+    return format!("{:o}", git_index::mode_for_metadata(metadata))
+        .parse()
+        .unwrap_or(0);
+}
+
+/// Groups modes into regular files, symlinks and gitlinks; switching between
+/// them is reported as a type change.
+pub fn file_kind(mode: u64) -> u64 {
+    return match mode {
+        git_object::SYMLINK_MODE | git_object::GITLINK_MODE => mode,
+        _ => 0,
+    };
+}
+
+/// Lists working tree files missing from the index. Directories that contain
+/// no tracked files at all are collapsed into a single `dir/` entry.
+fn untracked_paths(repository: &Repository, tracked: &HashSet<&str>) -> Result<Vec<String>, Error> {
+    let mut tracked_directories: HashSet<&str> = HashSet::new();
+    for path in tracked.iter() {
+        let mut rest = *path;
+        while let Some((parent, _)) = rest.rsplit_once('/') {
+            tracked_directories.insert(parent);
+            rest = parent;
+        }
+    }
+
+    let mut result: Vec<String> = Vec::new();
+    let mut ignore_rules = IgnoreRules::load(repository)?;
+    for path in git::list_worktree_files(repository, "", &mut ignore_rules)? {
+        if tracked.contains(path.as_str()) {
+            continue;
+        }
+        let mut reported = path.clone();
+        let mut end = 0;
+        while let Some(offset) = path[end..].find('/') {
+            end += offset;
+            if !tracked_directories.contains(&path[..end]) {
+                reported = format!("{}/", &path[..end]);
+                break;
+            }
+            end += 1;
+        }
+        if reported == path && repository.work_path(&path).is_dir() {
+            // nested repository that isn't a submodule
+            reported = format!("{path}/");
+        }
+        if result.last() != Some(&reported) {
+            result.push(reported);
+        }
+    }
+    return Ok(result);
+}
+
+/// Looks up the configured upstream of `branch` and counts how many commits
+/// each side has that the other doesn't, walking only down to their merge
+/// bases.
+pub fn upstream(
+    repository: &Repository,
+    branch: &str,
+    head: &Option<ObjectId>,
+) -> Result<Option<Upstream>, Error> {
+    let reference = match git::upstream_ref(repository, branch)? {
+        Some(reference) => reference,
+        None => return Ok(None),
+    };
+    let name = git_refs::shorten(&reference).to_string();
+
+    let upstream_hash = match git_refs::resolve(repository, &reference)? {
+        Some(hash) => hash,
+        None => {
+            return Ok(Some(Upstream {
+                name,
+                ahead_behind: None,
+            }))
+        }
+    };
+
+    let mut revisions = vec![Revision {
+        id: upstream_hash,
+        excluded: false,
+        left: false,
+    }];
+    if let Some(head) = head {
+        revisions.push(Revision {
+            id: *head,
+            excluded: false,
+            left: true,
+        });
+        for base in git_merge_base::merge_bases(repository, head, &[upstream_hash])? {
+            revisions.push(Revision {
+                id: base,
+                excluded: true,
+                left: false,
+            });
+        }
+    }
+    let commits = git_revwalk::walk(repository, &revisions, &WalkOptions::default())?;
+    let ahead = commits.iter().filter(|commit| commit.left).count();
+    return Ok(Some(Upstream {
+        name,
+        ahead_behind: Some((ahead, commits.len() - ahead)),
+    }));
+}
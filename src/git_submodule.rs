@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use crate::{error::Error, git_config, repository::Repository};
+
+pub struct Submodule {
+    pub name: String,
+    pub path: String,
+    pub url: String,
+}
+
+/// Reads the submodules declared in `.gitmodules` of the working tree, in the
+/// order they are declared.
+pub fn read_gitmodules(repository: &Repository) -> Result<Vec<Submodule>, Error> {
+    let mut names: Vec<String> = Vec::new();
+    let mut values: HashMap<(String, String), String> = HashMap::new();
+    for entry in git_config::read_file(repository.work_path(".gitmodules"))? {
+        if entry.section != "submodule" || entry.subsection.is_none() {
+            continue;
+        }
+        let name = entry.subsection.unwrap();
+        if !names.contains(&name) {
+            names.push(name.clone());
+        }
+        if let Some(value) = entry.value {
+            values.insert((name, entry.key), value);
+        }
+    }
+
+    let mut submodules = Vec::new();
+    for name in names {
+        let path = values
+            .get(&(name.clone(), "path".to_string()))
+            .ok_or_else(|| format!("no submodule mapping found in .gitmodules for {name}"))?;
+        let url = values
+            .get(&(name.clone(), "url".to_string()))
+            .ok_or_else(|| format!("no url found for submodule path '{path}' in .gitmodules"))?;
+        submodules.push(Submodule {
+            path: path.trim_end_matches('/').to_string(),
+            url: url.clone(),
+            name,
+        });
+    }
+    return Ok(submodules);
+}
+
+/// Keeps the submodules whose path is one of `paths`, or all of them when no
+/// paths were given.
+pub fn filter_by_paths(submodules: Vec<Submodule>, paths: &[String]) -> Vec<Submodule> {
+    if paths.is_empty() {
+        return submodules;
+    }
+    return submodules
+        .into_iter()
+        .filter(|submodule| {
+            paths
+                .iter()
+                .any(|path| path.trim_end_matches('/') == submodule.path)
+        })
+        .collect();
+}
+
+/// Resolves `./` and `../` submodule urls relative to the superproject's
+/// `remote.origin.url`.
+pub fn resolve_url(repository: &Repository, url: &str) -> Result<String, Error> {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return Ok(url.to_string());
+    }
+
+    let mut base = git_config::get(repository, "remote.origin.url")?
+        .ok_or_else(|| format!("cannot resolve relative url {url} without remote.origin.url"))?;
+    while base.ends_with('/') {
+        base.pop();
+    }
+
+    let mut relative = url;
+    loop {
+        if let Some(rest) = relative.strip_prefix("./") {
+            relative = rest;
+        } else if let Some(rest) = relative.strip_prefix("../") {
+            relative = rest;
+            match base.rsplit_once('/') {
+                Some((parent, _)) if !parent.ends_with('/') => base = parent.to_string(),
+                _ => return Err(format!("cannot strip one component off url {base}").into()),
+            }
+        } else {
+            break;
+        }
+    }
+    return Ok(format!("{base}/{relative}"));
+}
@@ -0,0 +1,200 @@
+use core::str;
+use std::path::Path;
+
+use reqwest::{blocking::Client, header::CONTENT_TYPE, Method, StatusCode};
+
+use crate::{
+    error::Error,
+    git, git_config, git_pack,
+    git_refs::{self, Ref},
+    object_id::ObjectId,
+    reader_utils,
+    repository::Repository,
+};
+
+/// The refs a remote advertises during discovery.
+pub struct Advertisement {
+    /// What HEAD points at, `None` for an empty repository.
+    pub head: Option<ObjectId>,
+    /// The branch HEAD points at, e.g. `refs/heads/main`.
+    pub head_ref: Option<String>,
+    /// Every other ref, with annotated tags carrying their peeled value.
+    pub refs: Vec<Ref>,
+}
+
+/// Asks the remote at `url` (smart HTTP) which refs it has.
+pub fn discover_refs(client: &Client, url: &str) -> Result<Advertisement, Error> {
+    let mut discovery_response = client
+        .request(
+            Method::GET,
+            format!("{url}/info/refs?service=git-upload-pack"),
+        )
+        .send()
+        .map_err(|err| Error::http("error sending discovery request", err))?;
+    if discovery_response.status() != StatusCode::OK {
+        return Err(format!("discovery status: {}", discovery_response.status()).into());
+    }
+
+    // junk lines before ref data
+    let data = reader_utils::read_git_pack_line(&mut discovery_response)?;
+    if data.is_some() {
+        reader_utils::read_git_pack_line(&mut discovery_response)?;
+    }
+
+    let mut advertisement = Advertisement {
+        head: None,
+        head_ref: None,
+        refs: Vec::new(),
+    };
+    let mut capabilities = String::new();
+    while let Some(data) = reader_utils::read_git_pack_line(&mut discovery_response)? {
+        let line = str::from_utf8(&data)
+            .map_err(|err| Error::Protocol(format!("pack data isn't text: {err}")))?
+            .trim_end_matches('\n');
+        // the first ref has the capabilities after a NUL
+        let line = match line.split_once('\0') {
+            Some((line, rest)) => {
+                capabilities = rest.to_string();
+                line
+            }
+            None => line,
+        };
+        let (id, name) = line
+            .split_once(' ')
+            .ok_or_else(|| Error::Protocol(format!("malformed ref line: {line}")))?;
+        if name == "capabilities^{}" {
+            // an empty repository only advertises its capabilities
+            continue;
+        }
+        let id = ObjectId::from_hex(id)?;
+        if name == "HEAD" {
+            advertisement.head = Some(id);
+        } else if let Some(tag) = name.strip_suffix("^{}") {
+            if let Some(last) = advertisement.refs.last_mut().filter(|r| r.name == tag) {
+                last.peeled = Some(id);
+            }
+        } else {
+            advertisement.refs.push(Ref {
+                name: name.to_string(),
+                id,
+                peeled: None,
+            });
+        }
+    }
+
+    // newer servers say where HEAD points, older ones leave us to guess from
+    // the branches at the same commit
+    advertisement.head_ref = capabilities
+        .split(' ')
+        .find_map(|capability| capability.strip_prefix("symref=HEAD:"))
+        .map(|target| target.to_string());
+    if advertisement.head_ref.is_none() {
+        if let Some(head) = advertisement.head {
+            advertisement.head_ref = advertisement
+                .refs
+                .iter()
+                .find(|r| r.id == head && r.name.starts_with("refs/heads/"))
+                .map(|r| r.name.clone());
+        }
+    }
+    return Ok(advertisement);
+}
+
+/// Requests a pack containing `wants` (and everything reachable from them)
+/// from the remote and unpacks it into `repository`.
+pub fn fetch(
+    repository: &Repository,
+    client: &Client,
+    url: &str,
+    wants: &[ObjectId],
+) -> Result<(), Error> {
+    let mut pack_body: Vec<u8> = Vec::new();
+    for want in wants {
+        let line = format!("want {want}\n");
+        pack_body.append(&mut format!("{:04x}{line}", line.len() + 4).bytes().collect());
+    }
+    pack_body.append(&mut "00000009done\n".bytes().collect());
+
+    let mut pack_response = client
+        .request(
+            Method::POST,
+            format!("{url}/git-upload-pack?service=git-upload-pack"),
+        )
+        .header(CONTENT_TYPE, "application/x-git-upload-pack-request")
+        .body(pack_body)
+        .send()
+        .map_err(|err| Error::http("error sending pack data request", err))?;
+    if pack_response.status() != StatusCode::OK {
+        return Err(format!("pack status: {}", pack_response.status()).into());
+    }
+
+    reader_utils::read_git_pack_line(&mut pack_response)?; // NAK
+    return git_pack::unpack(repository, &mut pack_response);
+}
+
+/// Creates a repository in `directory` with the remote's HEAD branch checked
+/// out and `origin` configured to track it. The remote's branches and tags
+/// are stored in `packed-refs`, which stays fast with many refs.
+pub fn clone(url: &str, directory: &Path) -> Result<Repository, Error> {
+    let remote_url = url.strip_suffix('/').unwrap_or(url);
+
+    let client = Client::new();
+    let advertisement = discover_refs(&client, remote_url)?;
+    let head = advertisement
+        .head
+        .ok_or_else(|| Error::Protocol("no HEAD ref advertized".to_string()))?;
+    let branch = advertisement
+        .head_ref
+        .as_deref()
+        .and_then(|head_ref| head_ref.strip_prefix("refs/heads/"))
+        .ok_or_else(|| Error::Protocol("a ref that matches HEAD could not be found".to_string()))?
+        .to_string();
+
+    let repository = Repository::init(directory, false)?;
+    let config_path = repository.git_path("config");
+    git_config::set(&config_path, "remote.origin.url", remote_url)?;
+    repository.forget_config();
+
+    let mut wants: Vec<ObjectId> = vec![head];
+    let mut packed: Vec<Ref> = Vec::new();
+    for reference in advertisement.refs {
+        let name = if let Some(name) = reference.name.strip_prefix("refs/heads/") {
+            format!("refs/remotes/origin/{name}")
+        } else if reference.name.starts_with("refs/tags/") {
+            reference.name.clone()
+        } else {
+            continue;
+        };
+        if !wants.contains(&reference.id) {
+            wants.push(reference.id);
+        }
+        packed.push(Ref { name, ..reference });
+    }
+    fetch(&repository, &client, remote_url, &wants)?;
+    let reflog_message = format!("clone: from {remote_url}");
+    git_refs::write_packed(&repository, &packed)?;
+    git_refs::write_symbolic(
+        &repository,
+        "refs/remotes/origin/HEAD",
+        &format!("refs/remotes/origin/{branch}"),
+        &reflog_message,
+    )?;
+
+    // HEAD goes first so creating the branch logs the clone for both
+    git::set_head_to_branch(&repository, &branch, "")?;
+    git::make_branch(&repository, &branch, &head, &reflog_message)?;
+    git_config::set(
+        &config_path,
+        "remote.origin.fetch",
+        "+refs/heads/*:refs/remotes/origin/*",
+    )?;
+    repository.forget_config();
+    git::set_upstream(
+        &repository,
+        &branch,
+        &format!("refs/remotes/origin/{branch}"),
+    )?;
+    git::checkout(&repository, &branch)?;
+
+    return Ok(repository);
+}
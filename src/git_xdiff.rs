@@ -0,0 +1,1822 @@
+use std::{borrow::Cow, collections::HashMap};
+
+/// Past this many lines a multiple-match scan gives up.
+const SIMSCAN_WINDOW: usize = 100;
+/// How much more common than unmatched lines multiple-match lines around a
+/// line must be for it to be kept.
+const KPDIS_RUN: usize = 4;
+/// The most matches a line may have before it is a multiple-match line.
+const MAX_EQLIMIT: usize = 1024;
+/// The lowest edit cost before the split gives up looking for the best path.
+const MAX_COST_MIN: isize = 256;
+/// The edit cost above which good snakes are taken as splitting points.
+const HEUR_MIN_COST: isize = 256;
+/// How long a run of matching lines has to be to count as a good snake.
+const SNAKE_CNT: isize = 20;
+const K_HEUR: isize = 4;
+
+/// A run of changed lines: `old_count` lines at `old_start` in the old file
+/// replaced by `new_count` lines at `new_start` in the new one, counting
+/// lines from 0. `ignore` marks changes made only of blank lines when those
+/// are being ignored.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Change {
+    pub old_start: usize,
+    pub old_count: usize,
+    pub new_start: usize,
+    pub new_count: usize,
+    pub ignore: bool,
+}
+
+/// The changes one hunk of a patch shows, with the lines of context around
+/// them: `old_count` lines from `old_start` and `new_count` lines from
+/// `new_start`, counting lines from 0.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_count: usize,
+    pub new_start: usize,
+    pub new_count: usize,
+    pub changes: Vec<Change>,
+}
+
+/// How changed lines are found, as chosen by `--diff-algorithm`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Algorithm {
+    #[default]
+    Myers,
+    /// Myers without the shortcuts it takes on expensive inputs.
+    Minimal,
+    /// Matches lines that occur once on each side first.
+    Patience,
+    /// Patience extended to lines that occur rarely rather than once.
+    Histogram,
+}
+
+impl Algorithm {
+    /// Parses an algorithm name as `--diff-algorithm` and `diff.algorithm`
+    /// take it.
+    pub fn parse(name: &str) -> Option<Algorithm> {
+        return match name.to_ascii_lowercase().as_str() {
+            "myers" | "default" => Some(Algorithm::Myers),
+            "minimal" => Some(Algorithm::Minimal),
+            "patience" => Some(Algorithm::Patience),
+            "histogram" => Some(Algorithm::Histogram),
+            _ => None,
+        };
+    }
+}
+
+/// How lines are compared and where changes are placed.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct LineOptions {
+    pub algorithm: Algorithm,
+    /// Runs of whitespace match each other, and whitespace at the end of a
+    /// line is ignored.
+    pub ignore_space_change: bool,
+    pub ignore_all_space: bool,
+    /// Changes made only of blank lines don't get a hunk of their own.
+    pub ignore_blank_lines: bool,
+    /// Slides changes that could go in several places to where their
+    /// indentation suggests they belong.
+    pub indent_heuristic: bool,
+}
+
+impl LineOptions {
+    fn ignores_whitespace(&self) -> bool {
+        return self.ignore_space_change || self.ignore_all_space;
+    }
+
+    /// What a line is compared by: itself, or with whitespace dropped or
+    /// collapsed as the options ask.
+    fn key<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.ignore_all_space {
+            return Cow::Owned(
+                line.iter()
+                    .copied()
+                    .filter(|byte| !is_space(*byte))
+                    .collect(),
+            );
+        }
+        if self.ignore_space_change {
+            let mut key = Vec::new();
+            let mut bytes = line.iter().copied().peekable();
+            while let Some(byte) = bytes.next() {
+                if !is_space(byte) {
+                    key.push(byte);
+                    continue;
+                }
+                while bytes.next_if(|next| is_space(*next)).is_some() {}
+                if bytes.peek().is_some() {
+                    key.push(b' ');
+                }
+            }
+            return Cow::Owned(key);
+        }
+        return Cow::Borrowed(line);
+    }
+
+    /// Whether a line counts as blank for `ignore_blank_lines`.
+    fn is_blank(&self, line: &[u8]) -> bool {
+        if self.ignores_whitespace() {
+            return line.iter().all(|byte| is_space(*byte));
+        }
+        return line.len() <= 1;
+    }
+}
+
+/// Whitespace as git's own `isspace` has it.
+fn is_space(byte: u8) -> bool {
+    return matches!(byte, b' ' | b'\t' | b'\n' | b'\r');
+}
+
+/// Splits file content into lines, each keeping its newline; only the last
+/// one may lack it.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    return data.split_inclusive(|byte| *byte == b'\n').collect();
+}
+
+/// One side of a diff: a class for every line, equal lines sharing one, and
+/// which lines are changed.
+struct File {
+    classes: Vec<usize>,
+    /// Offset by one so that the lines before the first and after the last
+    /// read as unchanged.
+    changed: Vec<bool>,
+    /// The indentation of every line for the indent heuristic, -1 for blank
+    /// lines, or nothing when it is off.
+    indents: Vec<isize>,
+    /// xdiff's hash of every line, which the histogram algorithm files lines
+    /// under, or nothing for the other algorithms.
+    hashes: Vec<u64>,
+    /// The lines the Myers algorithm looks at: those left after trimming
+    /// the common ends and discarding lines the other side doesn't have.
+    indices: Vec<usize>,
+    reduced_classes: Vec<usize>,
+}
+
+impl File {
+    fn new(classes: Vec<usize>) -> File {
+        let changed = vec![false; classes.len() + 2];
+        return File {
+            classes,
+            changed,
+            indents: Vec::new(),
+            hashes: Vec::new(),
+            indices: Vec::new(),
+            reduced_classes: Vec::new(),
+        };
+    }
+
+    fn len(&self) -> usize {
+        return self.classes.len();
+    }
+
+    fn is_changed(&self, line: isize) -> bool {
+        return self.changed[(line + 1) as usize];
+    }
+
+    fn set_changed(&mut self, line: usize, changed: bool) {
+        self.changed[line + 1] = changed;
+    }
+}
+
+/// Finds which lines changed between `old` and `new` the way git does,
+/// including where it places changes that could go in several spots, and
+/// returns the runs of changed lines in order.
+pub fn diff<'a>(old: &[&'a [u8]], new: &[&'a [u8]], options: &LineOptions) -> Vec<Change> {
+    let mut classifier: HashMap<Cow<'a, [u8]>, usize> = HashMap::new();
+    let mut classify = |lines: &[&'a [u8]]| -> Vec<usize> {
+        let mut classes = Vec::new();
+        for line in lines {
+            let next = classifier.len();
+            classes.push(*classifier.entry(options.key(line)).or_insert(next));
+        }
+        return classes;
+    };
+    let mut old_file = File::new(classify(old));
+    let mut new_file = File::new(classify(new));
+    if options.indent_heuristic {
+        old_file.indents = old.iter().map(|line| indent(line)).collect();
+        new_file.indents = new.iter().map(|line| indent(line)).collect();
+    }
+
+    let (old_length, new_length) = (old_file.len(), new_file.len());
+    match options.algorithm {
+        Algorithm::Myers | Algorithm::Minimal => {
+            let minimal = options.algorithm == Algorithm::Minimal;
+            let (trimmed_old, trimmed_new) = myers(&old_file.classes, &new_file.classes, minimal);
+            old_file.changed = trimmed_old.changed;
+            new_file.changed = trimmed_new.changed;
+        }
+        Algorithm::Patience => {
+            patience(&mut old_file, 0, old_length, &mut new_file, 0, new_length);
+        }
+        Algorithm::Histogram => {
+            old_file.hashes = old
+                .iter()
+                .map(|line| hash_line(&options.key(line)))
+                .collect();
+            new_file.hashes = new
+                .iter()
+                .map(|line| hash_line(&options.key(line)))
+                .collect();
+            histogram(&mut old_file, 0, old_length, &mut new_file, 0, new_length);
+        }
+    }
+
+    compact(&mut old_file, &mut new_file, options.indent_heuristic);
+    compact(&mut new_file, &mut old_file, options.indent_heuristic);
+    let mut changes = build_script(&old_file, &new_file);
+    if options.ignore_blank_lines {
+        for change in changes.iter_mut() {
+            change.ignore = old[change.old_start..change.old_start + change.old_count]
+                .iter()
+                .chain(&new[change.new_start..change.new_start + change.new_count])
+                .all(|line| options.is_blank(line));
+        }
+    }
+    return changes;
+}
+
+/// Git's Myers diff of two runs of line classes: trims the lines both
+/// start and end with, sets aside the lines only one side has, and runs the
+/// divide and conquer search on the rest. Returns both sides with their
+/// changed lines marked.
+fn myers(old_classes: &[usize], new_classes: &[usize], minimal: bool) -> (File, File) {
+    let mut counts: HashMap<usize, (usize, usize)> = HashMap::new();
+    for class in old_classes {
+        counts.entry(*class).or_default().0 += 1;
+    }
+    for class in new_classes {
+        counts.entry(*class).or_default().1 += 1;
+    }
+    let mut old = File::new(old_classes.to_vec());
+    let mut new = File::new(new_classes.to_vec());
+
+    let (start, old_end, new_end) = trim_ends(&old, &new);
+    discard_unmatched(&mut old, start, old_end, |class| counts[&class].1);
+    discard_unmatched(&mut new, start, new_end, |class| counts[&class].0);
+
+    let diagonals = old.indices.len() + new.indices.len() + 3;
+    let mut forward = vec![0; diagonals];
+    let mut backward = vec![0; diagonals];
+    let mut search = Search {
+        forward: &mut forward,
+        backward: &mut backward,
+        offset: new.indices.len() as isize + 1,
+        max_cost: bogosqrt(diagonals).max(MAX_COST_MIN as usize) as isize,
+    };
+    let (old_length, new_length) = (old.indices.len(), new.indices.len());
+    search.compare(&mut old, 0, old_length, &mut new, 0, new_length, minimal);
+    return (old, new);
+}
+
+/// Runs the Myers diff on lines `old_start..old_end` and
+/// `new_start..new_end` alone, for when the other algorithms find no
+/// better way to line them up.
+fn fall_back_to_myers(
+    old: &mut File,
+    old_start: usize,
+    old_end: usize,
+    new: &mut File,
+    new_start: usize,
+    new_end: usize,
+) {
+    let (old_part, new_part) = myers(
+        &old.classes[old_start..old_end],
+        &new.classes[new_start..new_end],
+        false,
+    );
+    for line in 0..old_part.len() {
+        old.set_changed(old_start + line, old_part.is_changed(line as isize));
+    }
+    for line in 0..new_part.len() {
+        new.set_changed(new_start + line, new_part.is_changed(line as isize));
+    }
+}
+
+/// Marks every line of both ranges as changed, when they have nothing in
+/// common.
+fn mark_all_changed(
+    old: &mut File,
+    old_start: usize,
+    old_end: usize,
+    new: &mut File,
+    new_start: usize,
+    new_end: usize,
+) {
+    for line in old_start..old_end {
+        old.set_changed(line, true);
+    }
+    for line in new_start..new_end {
+        new.set_changed(line, true);
+    }
+}
+
+/// Groups `changes` into hunks with `context` lines around them, merging
+/// changes that are close enough for their context to touch. Ignorable
+/// changes only join a hunk when they are close to one that isn't, as in
+/// xdiff's `xdl_get_hunk`.
+pub fn hunks(changes: &[Change], old_len: usize, new_len: usize, context: usize) -> Vec<Hunk> {
+    let end = |change: &Change| change.old_start + change.old_count;
+    let (max_common, max_ignorable) = (2 * context, context);
+    let mut hunks = Vec::new();
+    let mut index = 0;
+    while index < changes.len() {
+        // leave out ignorable changes too far before the next change
+        let mut first = index;
+        while index < changes.len() && changes[index].ignore {
+            if index + 1 == changes.len()
+                || changes[index + 1].old_start - end(&changes[index]) >= max_ignorable
+            {
+                first = index + 1;
+            }
+            index += 1;
+        }
+        if first == changes.len() {
+            break;
+        }
+
+        let mut last = first;
+        let mut ignored = 0;
+        for next in first + 1..changes.len() {
+            let previous = next - 1;
+            let change = &changes[next];
+            let distance = change.old_start - end(&changes[previous]);
+            if distance > max_common {
+                break;
+            }
+            if distance < max_ignorable && (!change.ignore || last == previous) {
+                last = next;
+                ignored = 0;
+            } else if distance < max_ignorable && change.ignore {
+                ignored += change.new_count;
+            } else if last != previous
+                && change.old_start + ignored > end(&changes[last]) + max_common
+            {
+                break;
+            } else if !change.ignore {
+                last = next;
+                ignored = 0;
+            } else {
+                ignored += change.new_count;
+            }
+        }
+
+        let (first_change, last_change) = (changes[first], changes[last]);
+        let old_start = first_change.old_start.saturating_sub(context);
+        let new_start = first_change.new_start.saturating_sub(context);
+        let after = context
+            .min(old_len - end(&last_change))
+            .min(new_len - (last_change.new_start + last_change.new_count));
+        hunks.push(Hunk {
+            old_start,
+            old_count: end(&last_change) + after - old_start,
+            new_start,
+            new_count: last_change.new_start + last_change.new_count + after - new_start,
+            changes: changes[first..=last].to_vec(),
+        });
+        index = last + 1;
+    }
+    return hunks;
+}
+
+/// The integer square root, roughly, as xdiff computes it.
+fn bogosqrt(mut n: usize) -> usize {
+    let mut root = 1;
+    while n > 0 {
+        root <<= 1;
+        n >>= 2;
+    }
+    return root;
+}
+
+/// The number of lines both files start with, and the last line of each
+/// before the lines they both end with.
+fn trim_ends(old: &File, new: &File) -> (usize, isize, isize) {
+    let limit = old.len().min(new.len());
+    let start = (0..limit)
+        .find(|line| old.classes[*line] != new.classes[*line])
+        .unwrap_or(limit);
+    let end = (0..limit - start)
+        .find(|back| old.classes[old.len() - 1 - back] != new.classes[new.len() - 1 - back])
+        .unwrap_or(limit - start);
+    return (
+        start,
+        old.len() as isize - end as isize - 1,
+        new.len() as isize - end as isize - 1,
+    );
+}
+
+/// Marks the lines between `start` and `end` that the other side doesn't
+/// have at all as changed, as well as lines it has a great many of that sit
+/// among such lines, and keeps the rest for the Myers algorithm.
+fn discard_unmatched(
+    file: &mut File,
+    start: usize,
+    end: isize,
+    matches_in_other: impl Fn(usize) -> usize,
+) {
+    if end < start as isize {
+        return;
+    }
+    let end = end as usize;
+    let limit = bogosqrt(file.len()).min(MAX_EQLIMIT);
+    let mut discard = vec![0; file.len()];
+    for (line, class) in file.classes.iter().enumerate().take(end + 1).skip(start) {
+        discard[line] = match matches_in_other(*class) {
+            0 => 0,
+            matches if matches >= limit => 2,
+            _ => 1,
+        };
+    }
+    for line in start..=end {
+        if discard[line] == 1
+            || (discard[line] == 2 && keeps_multimatch(&discard, line, start, end))
+        {
+            file.indices.push(line);
+            file.reduced_classes.push(file.classes[line]);
+        } else {
+            file.set_changed(line, true);
+        }
+    }
+}
+
+/// Whether the multiple-match line `line` is kept for the Myers search: it
+/// isn't when it sits in a run made mostly of lines without a match.
+fn keeps_multimatch(discard: &[u8], line: usize, start: usize, end: usize) -> bool {
+    let start = start.max(line.saturating_sub(SIMSCAN_WINDOW));
+    let end = end.min(line + SIMSCAN_WINDOW);
+
+    let (mut unmatched_before, mut multiple_before) = (0, 1);
+    for state in discard[start..line].iter().rev() {
+        match state {
+            0 => unmatched_before += 1,
+            2 => multiple_before += 1,
+            _ => break,
+        }
+    }
+    if unmatched_before == 0 {
+        return true;
+    }
+    let (mut unmatched, mut multiple) = (0, 1);
+    for state in &discard[line + 1..=end] {
+        match state {
+            0 => unmatched += 1,
+            2 => multiple += 1,
+            _ => break,
+        }
+    }
+    if unmatched == 0 {
+        return true;
+    }
+    unmatched += unmatched_before;
+    multiple += multiple_before;
+    return multiple * KPDIS_RUN >= multiple + unmatched;
+}
+
+/// The state of xdiff's divide and conquer Myers search: the furthest
+/// reaching paths on each diagonal, forward and backward.
+struct Search<'a> {
+    forward: &'a mut Vec<isize>,
+    backward: &'a mut Vec<isize>,
+    /// Where diagonal 0 is in `forward` and `backward`.
+    offset: isize,
+    max_cost: isize,
+}
+
+/// Where to split a box of the edit graph, and whether each half needs the
+/// minimal diff.
+struct Split {
+    old: isize,
+    new: isize,
+    minimal_low: bool,
+    minimal_high: bool,
+}
+
+impl Search<'_> {
+    fn f(&self, diagonal: isize) -> isize {
+        return self.forward[(diagonal + self.offset) as usize];
+    }
+
+    fn set_f(&mut self, diagonal: isize, value: isize) {
+        self.forward[(diagonal + self.offset) as usize] = value;
+    }
+
+    fn b(&self, diagonal: isize) -> isize {
+        return self.backward[(diagonal + self.offset) as usize];
+    }
+
+    fn set_b(&mut self, diagonal: isize, value: isize) {
+        self.backward[(diagonal + self.offset) as usize] = value;
+    }
+
+    /// Marks the changed lines between the reduced lines `old_start` to
+    /// `old_end` and `new_start` to `new_end`.
+    #[allow(clippy::too_many_arguments)]
+    fn compare(
+        &mut self,
+        old: &mut File,
+        mut old_start: usize,
+        mut old_end: usize,
+        new: &mut File,
+        mut new_start: usize,
+        mut new_end: usize,
+        minimal: bool,
+    ) {
+        let (a, b) = (&old.reduced_classes, &new.reduced_classes);
+        while old_start < old_end && new_start < new_end && a[old_start] == b[new_start] {
+            old_start += 1;
+            new_start += 1;
+        }
+        while old_start < old_end && new_start < new_end && a[old_end - 1] == b[new_end - 1] {
+            old_end -= 1;
+            new_end -= 1;
+        }
+
+        if old_start == old_end {
+            for index in new_start..new_end {
+                new.set_changed(new.indices[index], true);
+            }
+        } else if new_start == new_end {
+            for index in old_start..old_end {
+                old.set_changed(old.indices[index], true);
+            }
+        } else {
+            let split = self.split(
+                &old.reduced_classes,
+                old_start as isize,
+                old_end as isize,
+                &new.reduced_classes,
+                new_start as isize,
+                new_end as isize,
+                minimal,
+            );
+            let (split_old, split_new) = (split.old as usize, split.new as usize);
+            self.compare(
+                old,
+                old_start,
+                split_old,
+                new,
+                new_start,
+                split_new,
+                split.minimal_low,
+            );
+            self.compare(
+                old,
+                split_old,
+                old_end,
+                new,
+                split_new,
+                new_end,
+                split.minimal_high,
+            );
+        }
+    }
+
+    /// Finds the middle snake of the box, or a good enough place to split
+    /// it when that gets too expensive, as xdiff's `xdl_split` does.
+    #[allow(clippy::too_many_arguments)]
+    fn split(
+        &mut self,
+        a: &[usize],
+        off1: isize,
+        lim1: isize,
+        b: &[usize],
+        off2: isize,
+        lim2: isize,
+        minimal: bool,
+    ) -> Split {
+        let (dmin, dmax) = (off1 - lim2, lim1 - off2);
+        let (fmid, bmid) = (off1 - off2, lim1 - lim2);
+        let odd = (fmid - bmid) & 1 != 0;
+        let (mut fmin, mut fmax) = (fmid, fmid);
+        let (mut bmin, mut bmax) = (bmid, bmid);
+        let at = |lines: &[usize], index: isize| lines[index as usize];
+
+        self.set_f(fmid, off1);
+        self.set_b(bmid, lim1);
+
+        let mut cost = 1;
+        loop {
+            let mut got_snake = false;
+
+            if fmin > dmin {
+                fmin -= 1;
+                self.set_f(fmin - 1, -1);
+            } else {
+                fmin += 1;
+            }
+            if fmax < dmax {
+                fmax += 1;
+                self.set_f(fmax + 1, -1);
+            } else {
+                fmax -= 1;
+            }
+            let mut d = fmax;
+            while d >= fmin {
+                let mut i1 = if self.f(d - 1) >= self.f(d + 1) {
+                    self.f(d - 1) + 1
+                } else {
+                    self.f(d + 1)
+                };
+                let previous = i1;
+                let mut i2 = i1 - d;
+                while i1 < lim1 && i2 < lim2 && at(a, i1) == at(b, i2) {
+                    i1 += 1;
+                    i2 += 1;
+                }
+                if i1 - previous > SNAKE_CNT {
+                    got_snake = true;
+                }
+                self.set_f(d, i1);
+                if odd && bmin <= d && d <= bmax && self.b(d) <= i1 {
+                    return Split {
+                        old: i1,
+                        new: i2,
+                        minimal_low: true,
+                        minimal_high: true,
+                    };
+                }
+                d -= 2;
+            }
+
+            if bmin > dmin {
+                bmin -= 1;
+                self.set_b(bmin - 1, isize::MAX);
+            } else {
+                bmin += 1;
+            }
+            if bmax < dmax {
+                bmax += 1;
+                self.set_b(bmax + 1, isize::MAX);
+            } else {
+                bmax -= 1;
+            }
+            let mut d = bmax;
+            while d >= bmin {
+                let mut i1 = if self.b(d - 1) < self.b(d + 1) {
+                    self.b(d - 1)
+                } else {
+                    self.b(d + 1) - 1
+                };
+                let previous = i1;
+                let mut i2 = i1 - d;
+                while i1 > off1 && i2 > off2 && at(a, i1 - 1) == at(b, i2 - 1) {
+                    i1 -= 1;
+                    i2 -= 1;
+                }
+                if previous - i1 > SNAKE_CNT {
+                    got_snake = true;
+                }
+                self.set_b(d, i1);
+                if !odd && fmin <= d && d <= fmax && i1 <= self.f(d) {
+                    return Split {
+                        old: i1,
+                        new: i2,
+                        minimal_low: true,
+                        minimal_high: true,
+                    };
+                }
+                d -= 2;
+            }
+
+            if minimal {
+                cost += 1;
+                continue;
+            }
+
+            // past a certain cost, a diagonal that got far along a good
+            // snake is taken as the split even if it isn't the middle one
+            if got_snake && cost > HEUR_MIN_COST {
+                let mut best = 0;
+                let mut split = (0, 0);
+                let mut d = fmax;
+                while d >= fmin {
+                    let distance = (d - fmid).abs();
+                    let i1 = self.f(d);
+                    let i2 = i1 - d;
+                    let value = (i1 - off1) + (i2 - off2) - distance;
+                    if value > K_HEUR * cost
+                        && value > best
+                        && off1 + SNAKE_CNT <= i1
+                        && i1 < lim1
+                        && off2 + SNAKE_CNT <= i2
+                        && i2 < lim2
+                    {
+                        let mut k = 1;
+                        while at(a, i1 - k) == at(b, i2 - k) {
+                            if k == SNAKE_CNT {
+                                best = value;
+                                split = (i1, i2);
+                                break;
+                            }
+                            k += 1;
+                        }
+                    }
+                    d -= 2;
+                }
+                if best > 0 {
+                    return Split {
+                        old: split.0,
+                        new: split.1,
+                        minimal_low: true,
+                        minimal_high: false,
+                    };
+                }
+
+                let mut d = bmax;
+                while d >= bmin {
+                    let distance = (d - bmid).abs();
+                    let i1 = self.b(d);
+                    let i2 = i1 - d;
+                    let value = (lim1 - i1) + (lim2 - i2) - distance;
+                    if value > K_HEUR * cost
+                        && value > best
+                        && off1 < i1
+                        && i1 <= lim1 - SNAKE_CNT
+                        && off2 < i2
+                        && i2 <= lim2 - SNAKE_CNT
+                    {
+                        let mut k = 0;
+                        while at(a, i1 + k) == at(b, i2 + k) {
+                            if k == SNAKE_CNT - 1 {
+                                best = value;
+                                split = (i1, i2);
+                                break;
+                            }
+                            k += 1;
+                        }
+                    }
+                    d -= 2;
+                }
+                if best > 0 {
+                    return Split {
+                        old: split.0,
+                        new: split.1,
+                        minimal_low: false,
+                        minimal_high: true,
+                    };
+                }
+            }
+
+            // enough: take whichever path got furthest
+            if cost >= self.max_cost {
+                let (mut fbest, mut fbest1) = (-1, -1);
+                let mut d = fmax;
+                while d >= fmin {
+                    let mut i1 = self.f(d).min(lim1);
+                    let mut i2 = i1 - d;
+                    if lim2 < i2 {
+                        i1 = lim2 + d;
+                        i2 = lim2;
+                    }
+                    if fbest < i1 + i2 {
+                        fbest = i1 + i2;
+                        fbest1 = i1;
+                    }
+                    d -= 2;
+                }
+                let (mut bbest, mut bbest1) = (isize::MAX, isize::MAX);
+                let mut d = bmax;
+                while d >= bmin {
+                    let mut i1 = off1.max(self.b(d));
+                    let mut i2 = i1 - d;
+                    if i2 < off2 {
+                        i1 = off2 + d;
+                        i2 = off2;
+                    }
+                    if i1 + i2 < bbest {
+                        bbest = i1 + i2;
+                        bbest1 = i1;
+                    }
+                    d -= 2;
+                }
+                if (lim1 + lim2) - bbest < fbest - (off1 + off2) {
+                    return Split {
+                        old: fbest1,
+                        new: fbest - fbest1,
+                        minimal_low: true,
+                        minimal_high: false,
+                    };
+                }
+                return Split {
+                    old: bbest1,
+                    new: bbest - bbest1,
+                    minimal_low: false,
+                    minimal_high: true,
+                };
+            }
+            cost += 1;
+        }
+    }
+}
+
+/// A run of changed lines in one file, from `start` up to `end`.
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn first(file: &File) -> Group {
+        let mut group = Group { start: 0, end: 0 };
+        while file.is_changed(group.end as isize) {
+            group.end += 1;
+        }
+        return group;
+    }
+
+    /// Moves on to the next group, which may be empty.
+    fn next(&mut self, file: &File) -> bool {
+        if self.end == file.len() {
+            return false;
+        }
+        self.start = self.end + 1;
+        self.end = self.start;
+        while file.is_changed(self.end as isize) {
+            self.end += 1;
+        }
+        return true;
+    }
+
+    fn previous(&mut self, file: &File) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+        self.end = self.start - 1;
+        self.start = self.end;
+        while file.is_changed(self.start as isize - 1) {
+            self.start -= 1;
+        }
+        return true;
+    }
+
+    /// Shifts the group down a line if the line after it equals its first,
+    /// merging it with any group it then touches.
+    fn slide_down(&mut self, file: &mut File) -> bool {
+        if self.end < file.len() && file.classes[self.start] == file.classes[self.end] {
+            file.set_changed(self.start, false);
+            file.set_changed(self.end, true);
+            self.start += 1;
+            self.end += 1;
+            while file.is_changed(self.end as isize) {
+                self.end += 1;
+            }
+            return true;
+        }
+        return false;
+    }
+
+    fn slide_up(&mut self, file: &mut File) -> bool {
+        if self.start > 0 && file.classes[self.start - 1] == file.classes[self.end - 1] {
+            file.set_changed(self.start - 1, true);
+            file.set_changed(self.end - 1, false);
+            self.start -= 1;
+            self.end -= 1;
+            while file.is_changed(self.start as isize - 1) {
+                self.start -= 1;
+            }
+            return true;
+        }
+        return false;
+    }
+}
+
+/// Moves each group of changes in `file` as far down as it can go, merging
+/// groups on the way, then back up to line up with a group of changes in
+/// `other` if it passed one, or else to where the indent heuristic likes it
+/// best. Keeps `other`'s groups in step.
+fn compact(file: &mut File, other: &mut File, indent_heuristic: bool) {
+    let mut group = Group::first(file);
+    let mut other_group = Group::first(other);
+    loop {
+        if group.end != group.start {
+            let mut size;
+            let mut earliest_end;
+            let mut end_matching_other;
+            loop {
+                size = group.end - group.start;
+                end_matching_other = None;
+
+                while group.slide_up(file) {
+                    other_group.previous(other);
+                }
+                earliest_end = group.end;
+                if other_group.end > other_group.start {
+                    end_matching_other = Some(group.end);
+                }
+
+                while group.slide_down(file) {
+                    other_group.next(other);
+                    if other_group.end > other_group.start {
+                        end_matching_other = Some(group.end);
+                    }
+                }
+                if size == group.end - group.start {
+                    break;
+                }
+            }
+
+            if group.end == earliest_end {
+                // it couldn't move
+            } else if end_matching_other.is_some() {
+                while other_group.end == other_group.start {
+                    group.slide_up(file);
+                    other_group.previous(other);
+                }
+            } else if indent_heuristic {
+                // score the split before and after the group at every spot
+                // it can take, and move it to the best one
+                let lowest = (earliest_end as isize)
+                    .max(group.end as isize - size as isize - 1)
+                    .max(group.end as isize - INDENT_HEURISTIC_MAX_SLIDING);
+                let mut best: Option<(isize, SplitScore)> = None;
+                for shift in lowest..=group.end as isize {
+                    let mut score = SplitScore::default();
+                    score.add(&measure_split(file, shift));
+                    score.add(&measure_split(file, shift - size as isize));
+                    if best
+                        .as_ref()
+                        .is_none_or(|(_, best_score)| score.compare(best_score) <= 0)
+                    {
+                        best = Some((shift, score));
+                    }
+                }
+                let (best_shift, _) = best.unwrap();
+                while group.end as isize > best_shift {
+                    group.slide_up(file);
+                    other_group.previous(other);
+                }
+            }
+        }
+
+        if !group.next(file) {
+            break;
+        }
+        other_group.next(other);
+    }
+}
+
+/// Collects the changed lines of both files into runs.
+fn build_script(old: &File, new: &File) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let (mut i1, mut i2) = (old.len() as isize, new.len() as isize);
+    while i1 >= 0 || i2 >= 0 {
+        if old.is_changed(i1 - 1) || new.is_changed(i2 - 1) {
+            let (l1, l2) = (i1, i2);
+            while old.is_changed(i1 - 1) {
+                i1 -= 1;
+            }
+            while new.is_changed(i2 - 1) {
+                i2 -= 1;
+            }
+            changes.push(Change {
+                old_start: i1 as usize,
+                old_count: (l1 - i1) as usize,
+                new_start: i2 as usize,
+                new_count: (l2 - i2) as usize,
+                ignore: false,
+            });
+        }
+        i1 -= 1;
+        i2 -= 1;
+    }
+    changes.reverse();
+    return changes;
+}
+
+/// Indentation past which lines all count as equally indented.
+const MAX_INDENT: isize = 200;
+/// Blank lines past which the indent heuristic stops looking for text.
+const MAX_BLANKS: isize = 20;
+/// How far the indent heuristic slides a group of changes at most.
+const INDENT_HEURISTIC_MAX_SLIDING: isize = 100;
+
+// The weights of the indent heuristic, found empirically by git's authors.
+// Higher makes a split less favourable.
+const START_OF_FILE_PENALTY: isize = 1;
+const END_OF_FILE_PENALTY: isize = 21;
+const TOTAL_BLANK_WEIGHT: isize = -30;
+const POST_BLANK_WEIGHT: isize = 6;
+const RELATIVE_INDENT_PENALTY: isize = -4;
+const RELATIVE_INDENT_WITH_BLANK_PENALTY: isize = 10;
+const RELATIVE_OUTDENT_PENALTY: isize = 24;
+const RELATIVE_OUTDENT_WITH_BLANK_PENALTY: isize = 17;
+const RELATIVE_DEDENT_PENALTY: isize = 23;
+const RELATIVE_DEDENT_WITH_BLANK_PENALTY: isize = 17;
+const INDENT_WEIGHT: isize = 60;
+
+/// How far a line is indented, a tab taking it to the next multiple of 8,
+/// or -1 if it is blank.
+fn indent(line: &[u8]) -> isize {
+    let mut indent = 0;
+    for byte in line {
+        if !is_space(*byte) {
+            return indent;
+        }
+        match byte {
+            b' ' => indent += 1,
+            b'\t' => indent += 8 - indent % 8,
+            _ => {}
+        }
+        if indent >= MAX_INDENT {
+            return MAX_INDENT;
+        }
+    }
+    return -1;
+}
+
+/// The surroundings of a place a group of changes could start or end at,
+/// just above line `split`. Indents are -1 where there is no such line.
+struct SplitMeasurement {
+    end_of_file: bool,
+    /// The indent of the line right below the split.
+    indent: isize,
+    /// How many blank lines are right above the split.
+    pre_blank: isize,
+    /// The indent of the nearest non-blank line above the split.
+    pre_indent: isize,
+    /// How many blank lines follow the line right below the split.
+    post_blank: isize,
+    /// The indent of the nearest non-blank line after that one.
+    post_indent: isize,
+}
+
+fn measure_split(file: &File, split: isize) -> SplitMeasurement {
+    let length = file.len() as isize;
+    let indent_at = |line: isize| file.indents[line as usize];
+    let (end_of_file, indent) = match split >= length {
+        true => (true, -1),
+        false => (false, indent_at(split)),
+    };
+
+    let (mut pre_blank, mut pre_indent) = (0, -1);
+    for line in (0..split).rev() {
+        pre_indent = indent_at(line);
+        if pre_indent != -1 {
+            break;
+        }
+        pre_blank += 1;
+        if pre_blank == MAX_BLANKS {
+            pre_indent = 0;
+            break;
+        }
+    }
+
+    let (mut post_blank, mut post_indent) = (0, -1);
+    for line in split + 1..length {
+        post_indent = indent_at(line);
+        if post_indent != -1 {
+            break;
+        }
+        post_blank += 1;
+        if post_blank == MAX_BLANKS {
+            post_indent = 0;
+            break;
+        }
+    }
+
+    return SplitMeasurement {
+        end_of_file,
+        indent,
+        pre_blank,
+        pre_indent,
+        post_blank,
+        post_indent,
+    };
+}
+
+/// How bad a placement of a group of changes looks; lower is better.
+#[derive(Default)]
+struct SplitScore {
+    effective_indent: isize,
+    penalty: isize,
+}
+
+impl SplitScore {
+    /// Adds the score of one of the two splits around a group.
+    fn add(&mut self, split: &SplitMeasurement) {
+        if split.pre_indent == -1 && split.pre_blank == 0 {
+            self.penalty += START_OF_FILE_PENALTY;
+        }
+        if split.end_of_file {
+            self.penalty += END_OF_FILE_PENALTY;
+        }
+
+        // the blank lines after the split, including the one right below it
+        let post_blank = match split.indent {
+            -1 => 1 + split.post_blank,
+            _ => 0,
+        };
+        let total_blank = split.pre_blank + post_blank;
+        self.penalty += TOTAL_BLANK_WEIGHT * total_blank;
+        self.penalty += POST_BLANK_WEIGHT * post_blank;
+
+        let indent = match split.indent {
+            -1 => split.post_indent,
+            indent => indent,
+        };
+        let any_blanks = total_blank != 0;
+        self.effective_indent += indent;
+
+        if indent == -1 || split.pre_indent == -1 || indent == split.pre_indent {
+            // nothing more to go by
+        } else if indent > split.pre_indent {
+            self.penalty += match any_blanks {
+                true => RELATIVE_INDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_INDENT_PENALTY,
+            };
+        } else if split.post_indent != -1 && split.post_indent > indent {
+            // less indented than what comes before and after: likely the
+            // start of a block
+            self.penalty += match any_blanks {
+                true => RELATIVE_OUTDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_OUTDENT_PENALTY,
+            };
+        } else {
+            // likely the end of a block
+            self.penalty += match any_blanks {
+                true => RELATIVE_DEDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_DEDENT_PENALTY,
+            };
+        }
+    }
+
+    /// Negative if this score is better than `other`.
+    fn compare(&self, other: &SplitScore) -> isize {
+        let indents = (self.effective_indent - other.effective_indent).signum();
+        return INDENT_WEIGHT * indents + (self.penalty - other.penalty);
+    }
+}
+
+/// A line class in the patience algorithm's table: where it is if it
+/// occurs once in the old range, and where in the new range if it occurs
+/// once there too.
+struct PatienceEntry {
+    old_line: usize,
+    new_line: Option<usize>,
+    unique: bool,
+    previous: Option<usize>,
+}
+
+/// The patience diff of lines `old_start..old_end` and
+/// `new_start..new_end`: lines that occur exactly once on each side are
+/// lined up by their longest common sequence, and the gaps between them
+/// diffed the same way, falling back to Myers where there are none.
+fn patience(
+    old: &mut File,
+    old_start: usize,
+    old_end: usize,
+    new: &mut File,
+    new_start: usize,
+    new_end: usize,
+) {
+    if old_start == old_end || new_start == new_end {
+        mark_all_changed(old, old_start, old_end, new, new_start, new_end);
+        return;
+    }
+
+    let mut entries: Vec<PatienceEntry> = Vec::new();
+    let mut by_class: HashMap<usize, usize> = HashMap::new();
+    for line in old_start..old_end {
+        match by_class.get(&old.classes[line]) {
+            Some(entry) => entries[*entry].unique = false,
+            None => {
+                by_class.insert(old.classes[line], entries.len());
+                entries.push(PatienceEntry {
+                    old_line: line,
+                    new_line: None,
+                    unique: true,
+                    previous: None,
+                });
+            }
+        }
+    }
+    let mut has_matches = false;
+    for line in new_start..new_end {
+        if let Some(entry) = by_class.get(&new.classes[line]) {
+            has_matches = true;
+            let entry = &mut entries[*entry];
+            match entry.new_line {
+                Some(_) => entry.unique = false,
+                None => entry.new_line = Some(line),
+            }
+        }
+    }
+    if !has_matches {
+        mark_all_changed(old, old_start, old_end, new, new_start, new_end);
+        return;
+    }
+
+    // the longest sequence of unique common lines in order on both sides,
+    // keeping for each length the sequence ending lowest in the new range
+    let mut sequence: Vec<usize> = Vec::new();
+    for index in 0..entries.len() {
+        let new_line = match (&entries[index].new_line, entries[index].unique) {
+            (Some(new_line), true) => *new_line,
+            _ => continue,
+        };
+        let position =
+            sequence.partition_point(|entry| entries[*entry].new_line.unwrap() <= new_line);
+        entries[index].previous = position.checked_sub(1).map(|before| sequence[before]);
+        if position == sequence.len() {
+            sequence.push(index);
+        } else {
+            sequence[position] = index;
+        }
+    }
+    let mut common = Vec::new();
+    let mut entry = sequence.last().copied();
+    while let Some(index) = entry {
+        common.push((entries[index].old_line, entries[index].new_line.unwrap()));
+        entry = entries[index].previous;
+    }
+    common.reverse();
+    if common.is_empty() {
+        fall_back_to_myers(old, old_start, old_end, new, new_start, new_end);
+        return;
+    }
+
+    let (mut old_line, mut new_line) = (old_start, new_start);
+    let mut index = 0;
+    loop {
+        // grow the runs of common lines around the next unique pair
+        let (mut next_old, mut next_new) = match common.get(index) {
+            Some(pair) => *pair,
+            None => (old_end, new_end),
+        };
+        if index < common.len() {
+            while next_old > old_line
+                && next_new > new_line
+                && old.classes[next_old - 1] == new.classes[next_new - 1]
+            {
+                next_old -= 1;
+                next_new -= 1;
+            }
+        }
+        while old_line < next_old
+            && new_line < next_new
+            && old.classes[old_line] == new.classes[new_line]
+        {
+            old_line += 1;
+            new_line += 1;
+        }
+        if next_old > old_line || next_new > new_line {
+            patience(old, old_line, next_old, new, new_line, next_new);
+        }
+        if index == common.len() {
+            return;
+        }
+        while index + 1 < common.len()
+            && common[index + 1] == (common[index].0 + 1, common[index].1 + 1)
+        {
+            index += 1;
+        }
+        old_line = common[index].0 + 1;
+        new_line = common[index].1 + 1;
+        index += 1;
+    }
+}
+
+/// How many lines alike the histogram algorithm looks at before giving up
+/// on a range.
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// A line class in the histogram algorithm's table: its first line in the
+/// old range and how often it occurs there.
+struct HistogramRecord {
+    line: usize,
+    count: usize,
+}
+
+/// The common run the histogram algorithm lines the two ranges up by.
+#[derive(Default)]
+struct Region {
+    old_start: usize,
+    old_end: usize,
+    new_start: usize,
+    new_end: usize,
+}
+
+/// What the histogram algorithm's search for a common run found.
+enum Lcs {
+    Found(Region),
+    None,
+    /// Every common line is too frequent to go by.
+    FallBack,
+}
+
+/// xdiff's hash of a line as compared, up to its newline. Bytes are widened
+/// as the signed chars they are in C.
+fn hash_line(key: &[u8]) -> u64 {
+    let mut hash: u64 = 5381;
+    for byte in key.iter().take_while(|byte| **byte != b'\n') {
+        hash = hash.wrapping_add(hash << 5);
+        hash ^= *byte as i8 as u64;
+    }
+    return hash;
+}
+
+/// xdiff's multiplicative hash of a line hash into a table of `2^bits`
+/// buckets.
+fn hash_bucket(hash: u64, bits: u32) -> usize {
+    return (hash.wrapping_mul(0x9e370001) >> (64 - bits)) as usize;
+}
+
+/// The number of bits for a table of at least `size` buckets.
+fn hash_bits(size: usize) -> u32 {
+    let mut bits = 0;
+    while (1usize << bits) < size && bits < 32 {
+        bits += 1;
+    }
+    return bits.max(1);
+}
+
+/// The histogram diff of lines `old_start..old_end` and
+/// `new_start..new_end`: the longest run of common lines whose rarest line
+/// is rarest in the old range splits both, and the parts before and after
+/// it are diffed the same way.
+fn histogram(
+    old: &mut File,
+    mut old_start: usize,
+    old_end: usize,
+    new: &mut File,
+    mut new_start: usize,
+    new_end: usize,
+) {
+    loop {
+        if old_start == old_end || new_start == new_end {
+            mark_all_changed(old, old_start, old_end, new, new_start, new_end);
+            return;
+        }
+        match find_lcs(old, old_start, old_end, new, new_start, new_end) {
+            Lcs::FallBack => {
+                fall_back_to_myers(old, old_start, old_end, new, new_start, new_end);
+                return;
+            }
+            Lcs::None => {
+                mark_all_changed(old, old_start, old_end, new, new_start, new_end);
+                return;
+            }
+            Lcs::Found(region) => {
+                histogram(
+                    old,
+                    old_start,
+                    region.old_start,
+                    new,
+                    new_start,
+                    region.new_start,
+                );
+                old_start = region.old_end;
+                new_start = region.new_end;
+            }
+        }
+    }
+}
+
+/// The search of xdiff's `find_lcs`: every old line goes into a table by
+/// class, and every new line is tried against the old lines of its class,
+/// keeping the longest common run around them with the rarest lines.
+fn find_lcs(
+    old: &File,
+    old_start: usize,
+    old_end: usize,
+    new: &File,
+    new_start: usize,
+    new_end: usize,
+) -> Lcs {
+    let bits = hash_bits(old_end - old_start);
+    // each bucket lists its records newest first, as xdiff's chains do
+    let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); 1 << bits];
+    let mut records: Vec<HistogramRecord> = Vec::new();
+    let mut record_of = vec![0; old_end - old_start];
+    // the next line of the same class, or nothing
+    let mut next_line: Vec<Option<usize>> = vec![None; old_end - old_start];
+    for line in (old_start..old_end).rev() {
+        let class = old.classes[line];
+        let bucket = &mut buckets[hash_bucket(old.hashes[line], bits)];
+        match bucket
+            .iter()
+            .rev()
+            .find(|record| old.classes[records[**record].line] == class)
+        {
+            Some(record) => {
+                let record_index = *record;
+                let record = &mut records[record_index];
+                next_line[line - old_start] = Some(record.line);
+                record.line = line;
+                record.count += 1;
+                record_of[line - old_start] = record_index;
+            }
+            None => {
+                bucket.push(records.len());
+                record_of[line - old_start] = records.len();
+                records.push(HistogramRecord { line, count: 1 });
+            }
+        }
+    }
+    let count_at = |line: usize| records[record_of[line - old_start]].count;
+
+    let mut lcs = Region::default();
+    let mut found = false;
+    let mut lowest_count = MAX_CHAIN_LENGTH + 1;
+    let mut has_common = false;
+    let mut new_line = new_start;
+    while new_line < new_end {
+        let mut next_new = new_line + 1;
+        let class = new.classes[new_line];
+        for record in buckets[hash_bucket(new.hashes[new_line], bits)]
+            .iter()
+            .rev()
+        {
+            let record = &records[*record];
+            if record.count > lowest_count {
+                if !has_common {
+                    has_common = old.classes[record.line] == class;
+                }
+                continue;
+            }
+            let mut old_line = record.line;
+            if old.classes[old_line] != class {
+                continue;
+            }
+            has_common = true;
+            loop {
+                let next_old = next_line[old_line - old_start];
+                let (mut run_old, mut run_new) = (old_line, new_line);
+                let (mut run_old_end, mut run_new_end) = (old_line + 1, new_line + 1);
+                let mut count = record.count;
+                while old_start < run_old
+                    && new_start < run_new
+                    && old.classes[run_old - 1] == new.classes[run_new - 1]
+                {
+                    run_old -= 1;
+                    run_new -= 1;
+                    if count > 1 {
+                        count = count.min(count_at(run_old));
+                    }
+                }
+                while run_old_end < old_end
+                    && run_new_end < new_end
+                    && old.classes[run_old_end] == new.classes[run_new_end]
+                {
+                    if count > 1 {
+                        count = count.min(count_at(run_old_end));
+                    }
+                    run_old_end += 1;
+                    run_new_end += 1;
+                }
+
+                if next_new < run_new_end {
+                    next_new = run_new_end;
+                }
+                if !found
+                    || lcs.old_end - lcs.old_start < run_old_end - run_old
+                    || count < lowest_count
+                {
+                    lcs = Region {
+                        old_start: run_old,
+                        old_end: run_old_end,
+                        new_start: run_new,
+                        new_end: run_new_end,
+                    };
+                    found = true;
+                    lowest_count = count;
+                }
+
+                // on to the next occurrence past this run
+                let mut next_old = next_old;
+                while let Some(line) = next_old {
+                    if line >= run_old_end {
+                        break;
+                    }
+                    next_old = next_line[line - old_start];
+                }
+                match next_old {
+                    Some(line) => old_line = line,
+                    None => break,
+                }
+            }
+        }
+        new_line = next_new;
+    }
+
+    if has_common && MAX_CHAIN_LENGTH < lowest_count {
+        return Lcs::FallBack;
+    }
+    return match found {
+        true => Lcs::Found(lcs),
+        false => Lcs::None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [Algorithm; 4] = [
+        Algorithm::Myers,
+        Algorithm::Minimal,
+        Algorithm::Patience,
+        Algorithm::Histogram,
+    ];
+
+    fn lines(text: &str) -> Vec<&[u8]> {
+        return split_lines(text.as_bytes());
+    }
+
+    fn changes(old: &str, new: &str, options: &LineOptions) -> Vec<(usize, usize, usize, usize)> {
+        return diff(&lines(old), &lines(new), options)
+            .iter()
+            .map(|change| {
+                (
+                    change.old_start,
+                    change.old_count,
+                    change.new_start,
+                    change.new_count,
+                )
+            })
+            .collect();
+    }
+
+    fn with_algorithm(algorithm: Algorithm) -> LineOptions {
+        return LineOptions {
+            algorithm,
+            ..LineOptions::default()
+        };
+    }
+
+    /// Rebuilds `new` from `old` and the changes, checking that the lines
+    /// the changes leave alone match on both sides.
+    fn apply(old: &[&[u8]], new: &[&[u8]], changes: &[Change]) -> Vec<Vec<u8>> {
+        let mut result = Vec::new();
+        let (mut old_line, mut new_line) = (0, 0);
+        for change in changes {
+            assert!(change.old_start >= old_line && change.new_start >= new_line);
+            assert_eq!(change.old_start - old_line, change.new_start - new_line);
+            while old_line < change.old_start {
+                assert_eq!(old[old_line], new[new_line]);
+                result.push(old[old_line].to_vec());
+                old_line += 1;
+                new_line += 1;
+            }
+            for line in &new[change.new_start..change.new_start + change.new_count] {
+                result.push(line.to_vec());
+            }
+            old_line += change.old_count;
+            new_line += change.new_count;
+        }
+        assert_eq!(old.len() - old_line, new.len() - new_line);
+        result.extend(old[old_line..].iter().map(|line| line.to_vec()));
+        return result;
+    }
+
+    #[test]
+    fn split_lines_keeps_newlines() {
+        assert_eq!(lines("a\nb\nc"), vec![&b"a\n"[..], b"b\n", b"c"]);
+        assert_eq!(lines("a\n\n"), vec![&b"a\n"[..], b"\n"]);
+        assert!(lines("").is_empty());
+    }
+
+    #[test]
+    fn algorithm_names() {
+        assert_eq!(Algorithm::parse("default"), Some(Algorithm::Myers));
+        assert_eq!(Algorithm::parse("Histogram"), Some(Algorithm::Histogram));
+        assert_eq!(Algorithm::parse("patience"), Some(Algorithm::Patience));
+        assert_eq!(Algorithm::parse("minimal"), Some(Algorithm::Minimal));
+        assert_eq!(Algorithm::parse("fast"), None);
+    }
+
+    #[test]
+    fn identical_and_empty_inputs() {
+        for algorithm in ALGORITHMS {
+            let options = with_algorithm(algorithm);
+            assert!(changes("a\nb\n", "a\nb\n", &options).is_empty());
+            assert!(changes("", "", &options).is_empty());
+            assert_eq!(changes("", "a\nb\n", &options), vec![(0, 0, 0, 2)]);
+            assert_eq!(changes("a\nb\n", "", &options), vec![(0, 2, 0, 0)]);
+        }
+    }
+
+    #[test]
+    fn simple_edits_agree_across_algorithms() {
+        for algorithm in ALGORITHMS {
+            assert_eq!(
+                changes(
+                    "a\nb\nc\nd\ne\nf\n",
+                    "a\nc\nd\nx\ne\nf\ng\n",
+                    &with_algorithm(algorithm)
+                ),
+                vec![(1, 1, 1, 0), (4, 0, 3, 1), (6, 0, 6, 1)],
+                "{algorithm:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn algorithms_pick_different_matches() {
+        let (old, new) = ("x\na\nb\nc\nx\nd\n", "a\nx\nb\nx\nc\nd\n");
+        let myers = vec![(0, 1, 0, 0), (2, 0, 1, 1), (3, 1, 3, 0), (5, 0, 4, 1)];
+        assert_eq!(changes(old, new, &with_algorithm(Algorithm::Myers)), myers);
+        assert_eq!(
+            changes(old, new, &with_algorithm(Algorithm::Minimal)),
+            myers
+        );
+        assert_eq!(
+            changes(old, new, &with_algorithm(Algorithm::Patience)),
+            vec![(0, 1, 0, 0), (2, 0, 1, 1), (3, 0, 3, 1), (4, 1, 5, 0)]
+        );
+        assert_eq!(
+            changes(old, new, &with_algorithm(Algorithm::Histogram)),
+            vec![(0, 1, 0, 0), (2, 2, 1, 0), (5, 0, 2, 3)]
+        );
+    }
+
+    #[test]
+    fn changes_rebuild_the_new_side() {
+        // a fixed linear congruential generator, so failures reproduce
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| -> u64 {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            return (state >> 33) % bound;
+        };
+        let words = ["a\n", "b\n", "c\n", "{\n", "}\n", "\n", "    x\n"];
+        for _ in 0..200 {
+            let old: Vec<&[u8]> = (0..next(30))
+                .map(|_| words[next(words.len() as u64) as usize].as_bytes())
+                .collect();
+            let mut new = old.clone();
+            for _ in 0..next(6) {
+                let at = next(new.len() as u64 + 1) as usize;
+                if next(2) == 0 && at < new.len() {
+                    new.remove(at);
+                } else {
+                    new.insert(at, words[next(words.len() as u64) as usize].as_bytes());
+                }
+            }
+            for algorithm in ALGORITHMS {
+                for indent_heuristic in [false, true] {
+                    let options = LineOptions {
+                        algorithm,
+                        indent_heuristic,
+                        ..LineOptions::default()
+                    };
+                    let found = diff(&old, &new, &options);
+                    let rebuilt = apply(&old, &new, &found);
+                    assert_eq!(
+                        rebuilt,
+                        new.iter().map(|line| line.to_vec()).collect::<Vec<_>>()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn minimal_finds_a_shortest_edit_script() {
+        let old = "a\nb\nc\na\nb\nb\na\n";
+        let new = "c\nb\na\nb\na\nc\n";
+        let edits: usize = changes(old, new, &with_algorithm(Algorithm::Minimal))
+            .iter()
+            .map(|(_, old_count, _, new_count)| old_count + new_count)
+            .sum();
+        assert_eq!(edits, 5);
+    }
+
+    #[test]
+    fn whitespace_options() {
+        let (old, more_space, no_space) = ("a b\nc\n", "a  b \nc\n", "ab\nc\n");
+        let default = LineOptions::default();
+        assert_eq!(changes(old, more_space, &default), vec![(0, 1, 0, 1)]);
+        assert_eq!(changes(old, no_space, &default), vec![(0, 1, 0, 1)]);
+
+        let space_change = LineOptions {
+            ignore_space_change: true,
+            ..LineOptions::default()
+        };
+        assert!(changes(old, more_space, &space_change).is_empty());
+        assert!(changes("a b\n", "a b\t\n", &space_change).is_empty());
+        assert_eq!(changes(old, no_space, &space_change), vec![(0, 1, 0, 1)]);
+
+        let all_space = LineOptions {
+            ignore_all_space: true,
+            ..LineOptions::default()
+        };
+        assert!(changes(old, more_space, &all_space).is_empty());
+        assert!(changes(old, no_space, &all_space).is_empty());
+        assert!(changes("a\r\n", "a\n", &all_space).is_empty());
+    }
+
+    #[test]
+    fn blank_line_changes_are_marked_ignorable() {
+        let options = LineOptions {
+            ignore_blank_lines: true,
+            ..LineOptions::default()
+        };
+        let found = diff(
+            &lines("a\nb\nc\nd\ne\nf\ng\nh\n"),
+            &lines("a\n\nb\nc\nd\ne\nf\nG\nh\n"),
+            &options,
+        );
+        assert_eq!(found.len(), 2);
+        assert!(found[0].ignore);
+        assert!(!found[1].ignore);
+
+        // whitespace-only lines count as blank only when whitespace is ignored
+        let found = diff(&lines("a\n"), &lines("a\n  \n"), &options);
+        assert!(!found[0].ignore);
+        let options = LineOptions {
+            ignore_space_change: true,
+            ..options
+        };
+        let found = diff(&lines("a\n"), &lines("a\n  \n"), &options);
+        assert!(found[0].ignore);
+    }
+
+    #[test]
+    fn indent_heuristic_moves_sliding_changes() {
+        let old =
+            "fn c() {\n    if z {\n        z();\n    }\n    c();\n}\n\nfn a() {\n    a();\n}\n";
+        let new = "fn c() {\n    if z {\n        z();\n    }\n    if z {\n        z();\n    }\n    c();\n}\n\nfn a() {\n    a();\n}\n";
+        assert_eq!(
+            changes(old, new, &LineOptions::default()),
+            vec![(4, 0, 4, 3)]
+        );
+        let options = LineOptions {
+            indent_heuristic: true,
+            ..LineOptions::default()
+        };
+        assert_eq!(changes(old, new, &options), vec![(1, 0, 1, 3)]);
+
+        // blocks with a single placement stay where they are
+        let old = "int a()\n{\n    return 1;\n}\n\nint c()\n{\n    return 3;\n}\n";
+        let new = "int a()\n{\n    return 1;\n}\n\nint b()\n{\n    return 2;\n}\n\nint c()\n{\n    return 3;\n}\n";
+        for indent_heuristic in [false, true] {
+            let options = LineOptions {
+                indent_heuristic,
+                ..LineOptions::default()
+            };
+            assert_eq!(changes(old, new, &options), vec![(5, 0, 5, 5)]);
+        }
+    }
+
+    #[test]
+    fn hunks_join_changes_whose_context_touches() {
+        let old: Vec<String> = (1..=20).map(|n| format!("{n}\n")).collect();
+        let old: Vec<&[u8]> = old.iter().map(|line| line.as_bytes()).collect();
+        let edit = |replaced: &[usize]| -> Vec<Change> {
+            let mut new = old.clone();
+            for line in replaced {
+                new[*line] = b"changed\n";
+            }
+            return diff(&old, &new, &LineOptions::default());
+        };
+
+        // six lines apart still share their context
+        let found = hunks(&edit(&[2, 9]), 20, 20, 3);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            (
+                found[0].old_start,
+                found[0].old_count,
+                found[0].changes.len()
+            ),
+            (0, 13, 2)
+        );
+
+        let found = hunks(&edit(&[2, 10]), 20, 20, 3);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].old_start, found[0].old_count), (0, 6));
+        assert_eq!((found[1].new_start, found[1].new_count), (7, 7));
+
+        // context is cut short at the end of the file
+        let found = hunks(&edit(&[19]), 20, 20, 3);
+        assert_eq!((found[0].old_start, found[0].old_count), (16, 4));
+    }
+
+    #[test]
+    fn hunks_drop_lone_ignorable_changes() {
+        let options = LineOptions {
+            ignore_blank_lines: true,
+            ..LineOptions::default()
+        };
+        let (old, new) = (
+            lines("a\nb\nc\nd\ne\nf\ng\nh\n"),
+            lines("a\n\nb\nc\nd\ne\nf\nG\nh\n"),
+        );
+        let found = diff(&old, &new, &options);
+        for (context, old_start, new_start, count) in [(1, 5, 6, 3), (3, 3, 4, 5)] {
+            let hunks = hunks(&found, old.len(), new.len(), context);
+            assert_eq!(hunks.len(), 1);
+            assert_eq!(
+                (hunks[0].old_start, hunks[0].new_start, hunks[0].old_count),
+                (old_start, new_start, count)
+            );
+        }
+        assert!(hunks(&found[..1], old.len(), new.len(), 3).is_empty());
+    }
+}
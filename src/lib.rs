@@ -0,0 +1,49 @@
+#![allow(
+    clippy::needless_return,
+    clippy::too_many_arguments,
+    clippy::unnecessary_unwrap
+)]
+
+//! A small git implementation. [`Repository`] is the entry point: it finds a
+//! repository on disk and gives access to its objects, refs, index, diffs
+//! and remotes. The `git` binary is a command line layer over this crate,
+//! started with [`run`].
+
+pub(crate) mod arg_parse;
+pub(crate) mod cli;
+pub(crate) mod commands;
+pub(crate) mod error;
+pub(crate) mod git;
+pub(crate) mod git_attributes;
+pub(crate) mod git_config;
+pub(crate) mod git_date;
+pub(crate) mod git_diff;
+pub(crate) mod git_graph;
+pub(crate) mod git_ignore;
+pub(crate) mod git_index;
+pub(crate) mod git_merge_base;
+pub(crate) mod git_object;
+pub(crate) mod git_pack;
+pub(crate) mod git_pretty;
+pub(crate) mod git_reflog;
+pub(crate) mod git_refs;
+pub(crate) mod git_rename;
+pub(crate) mod git_revision;
+pub(crate) mod git_revwalk;
+pub(crate) mod git_status;
+pub(crate) mod git_submodule;
+pub(crate) mod git_transport;
+pub(crate) mod git_xdiff;
+pub(crate) mod lock_file;
+pub(crate) mod object_id;
+pub(crate) mod reader_utils;
+pub(crate) mod repository;
+
+pub use cli::run;
+pub use error::Error;
+pub use git_diff::{DiffSide, FilePair, Origin};
+pub use git_index::IndexEntry;
+pub use git_object::{Blob, Commit, Object, ObjectType, Signature, Tag, Tree, TreeNode};
+pub use git_refs::{Ref, RefUpdate, RefValue};
+pub use object_id::ObjectId;
+pub use repository::Repository;
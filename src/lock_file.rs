@@ -0,0 +1,88 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::error::Error;
+
+/// Git's protocol for replacing a file without readers ever seeing it half
+/// written: new contents go to `<path>.lock`, created exclusively so only one
+/// process can hold it, which is then renamed over `<path>`. Dropping the lock
+/// without committing leaves `<path>` untouched.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    /// Takes the lock for `path`, failing with [`Error::LockConflict`] when
+    /// someone else holds it.
+    pub fn acquire(path: &Path) -> Result<Self, Error> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| {
+                Error::io(
+                    format!("error creating directory {}", parent.display()),
+                    err,
+                )
+            })?;
+        }
+        let file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                return Err(Error::LockConflict { path: lock_path })
+            }
+            Err(err) => {
+                return Err(Error::io(
+                    format!("error creating {}", lock_path.display()),
+                    err,
+                ))
+            }
+        };
+        return Ok(LockFile {
+            path: path.to_path_buf(),
+            lock_path,
+            file: Some(file),
+        });
+    }
+
+    pub fn write(&mut self, contents: &[u8]) -> Result<(), Error> {
+        let file = self.file.as_mut().unwrap();
+        return file
+            .write_all(contents)
+            .map_err(|err| Error::io(format!("error writing {}", self.lock_path.display()), err));
+    }
+
+    /// Replaces the locked file with what was written and releases the lock.
+    pub fn commit(mut self) -> Result<(), Error> {
+        let file = self.file.take().unwrap();
+        let result = file
+            .sync_all()
+            .and_then(|_| fs::rename(&self.lock_path, &self.path));
+        if let Err(err) = result {
+            let _ = fs::remove_file(&self.lock_path);
+            return Err(Error::io(
+                format!("error committing {}", self.lock_path.display()),
+                err,
+            ));
+        }
+        return Ok(());
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        // still open means commit() never ran
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
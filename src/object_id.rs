@@ -0,0 +1,76 @@
+use sha1::{Digest, Sha1};
+use std::{fmt::Display, str::FromStr};
+
+use crate::error::Error;
+
+/// The SHA-1 name of an object.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId([u8; 20]);
+
+impl ObjectId {
+    /// All zeros, used by git for "no object", e.g. the old value of a newly
+    /// created ref.
+    pub const NULL: ObjectId = ObjectId([0; 20]);
+
+    /// Length of the hex form.
+    pub const HEX_LENGTH: usize = 40;
+
+    /// The name of `data`, which must include the `<type> <size>\0` header.
+    pub fn hash(data: &[u8]) -> Self {
+        let mut hasher = Sha1::new();
+        hasher.update(data);
+        return ObjectId(hasher.finalize().into());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes: [u8; 20] = bytes
+            .try_into()
+            .map_err(|_| format!("object id must be 20 bytes, got {}", bytes.len()))?;
+        return Ok(ObjectId(bytes));
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        if hex.len() != Self::HEX_LENGTH {
+            return Err(format!("invalid object id '{hex}'").into());
+        }
+        let bytes = hex::decode(hex).map_err(|_| format!("invalid object id '{hex}'"))?;
+        return ObjectId::from_bytes(&bytes);
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        return &self.0;
+    }
+
+    pub fn to_hex(&self) -> String {
+        return hex::encode(self.0);
+    }
+
+    /// The abbreviated form git shows by default, e.g. in `commit` output.
+    pub fn short(&self) -> String {
+        return self.to_hex()[..7].to_string();
+    }
+
+    pub fn is_null(&self) -> bool {
+        return self == &ObjectId::NULL;
+    }
+}
+
+impl Display for ObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl std::fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ObjectId({})", self.to_hex())
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return ObjectId::from_hex(s);
+    }
+}
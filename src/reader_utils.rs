@@ -1,7 +1,9 @@
 use core::str;
 use std::io::Read;
 
-pub fn read_to_next_null_byte(reader: &mut impl Read) -> Result<String, String> {
+use crate::error::Error;
+
+pub fn read_to_next_null_byte(reader: &mut impl Read) -> Result<String, Error> {
     let mut bytes: Vec<u8> = Vec::new();
     loop {
         let byte = read_byte(reader)?;
@@ -15,25 +17,25 @@ pub fn read_to_next_null_byte(reader: &mut impl Read) -> Result<String, String>
         .to_string());
 }
 
-pub fn read_byte(reader: &mut impl Read) -> Result<u8, String> {
+pub fn read_byte(reader: &mut impl Read) -> Result<u8, Error> {
     return Ok(read_n_bytes(1, reader)?[0]);
 }
 
-pub fn read_n_bytes(n: usize, reader: &mut impl Read) -> Result<Vec<u8>, String> {
+pub fn read_n_bytes(n: usize, reader: &mut impl Read) -> Result<Vec<u8>, Error> {
     let mut buf = vec![0u8; n];
     reader
         .read_exact(&mut buf)
-        .map_err(|err| format!("error while reading bytes: {err}"))?;
+        .map_err(|err| Error::io("error while reading bytes", err))?;
     return Ok(buf);
 }
 
-pub fn read_git_pack_line(reader: &mut impl Read) -> Result<Option<Vec<u8>>, String> {
+pub fn read_git_pack_line(reader: &mut impl Read) -> Result<Option<Vec<u8>>, Error> {
     let length = usize::from_str_radix(
         str::from_utf8(&read_n_bytes(4, reader)?)
-            .map_err(|err| format!("error converting pack line length to string: {err}"))?,
+            .map_err(|err| Error::Protocol(format!("pack line length isn't text: {err}")))?,
         16,
     )
-    .map_err(|err| format!("error reading length of pack line: {err}"))?;
+    .map_err(|err| Error::Protocol(format!("bad pack line length: {err}")))?;
     return if length <= 4 {
         Ok(None)
     } else {
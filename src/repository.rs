@@ -0,0 +1,531 @@
+use std::{
+    cell::RefCell,
+    env, fs,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    error::Error,
+    git,
+    git_attributes::Attributes,
+    git_config::{self, Config},
+    git_diff::{self, FilePair},
+    git_index::{self, IndexEntry},
+    git_object::{self, Blob, Commit, Object, ObjectType, Tag, Tree},
+    git_refs::{self, PackedRefs, Ref, RefUpdate, RefValue},
+    git_revision, git_transport,
+    object_id::ObjectId,
+};
+
+/// Locates the parts of a repository: its git directory, the working tree
+/// (absent for bare repositories) and the object store. Every path the rest
+/// of the code touches is derived from one of these.
+pub struct Repository {
+    pub git_dir: PathBuf,
+    /// Where the parts shared by all worktrees live, such as objects, refs
+    /// and the config: the `commondir` of a linked worktree's git
+    /// directory, or the git directory itself.
+    pub common_dir: PathBuf,
+    pub work_tree: Option<PathBuf>,
+    pub object_dir: PathBuf,
+    /// The directory the command was run from, relative to the working tree
+    /// root and with a trailing slash unless it is the root itself.
+    pub prefix: String,
+    /// Parsed `packed-refs`, shared by every ref lookup of a command.
+    pub(crate) packed_refs: RefCell<Option<PackedRefs>>,
+    /// The layered config, read once and shared by every lookup of a
+    /// command until it writes a config file.
+    config: RefCell<Option<Rc<Config>>>,
+    /// The attributes files, read once like the config.
+    attributes: RefCell<Option<Rc<Attributes>>>,
+}
+
+impl Repository {
+    /// Finds the repository the current directory belongs to, the way git
+    /// does: `--git-dir`/`GIT_DIR` if given, otherwise the first parent
+    /// directory containing `.git` (a directory or a `gitdir:` file) or being
+    /// a bare repository itself.
+    pub fn discover(git_dir: Option<&str>, work_tree: Option<&str>) -> Result<Self, Error> {
+        let current =
+            env::current_dir().map_err(|err| Error::io("error getting current directory", err))?;
+        let git_dir = git_dir.map(str::to_string).or_else(|| from_env("GIT_DIR"));
+        let work_tree = work_tree
+            .map(str::to_string)
+            .or_else(|| from_env("GIT_WORK_TREE"));
+
+        let (git_dir, discovered_work_tree) = match &git_dir {
+            Some(git_dir) => {
+                let git_dir = current.join(git_dir);
+                let git_dir = if git_dir.is_file() {
+                    read_gitfile(&git_dir)?
+                } else {
+                    git_dir
+                };
+                if !is_git_dir(&git_dir) {
+                    return Err(
+                        format!("fatal: not a git repository: '{}'", git_dir.display()).into(),
+                    );
+                }
+                // an explicit git directory makes the current directory the
+                // top of the working tree unless told otherwise
+                (git_dir, Some(current.clone()))
+            }
+            None => find_git_dir(&current)?,
+        };
+
+        let local_config = Config {
+            entries: git_config::read_file(common_dir(&git_dir).join("config"))?,
+        };
+        let work_tree = if let Some(work_tree) = work_tree {
+            Some(current.join(work_tree))
+        } else if let Some(work_tree) = local_config.get("core.worktree")? {
+            Some(git_dir.join(work_tree))
+        } else if local_config.get_bool("core.bare")? == Some(true) {
+            None
+        } else {
+            discovered_work_tree
+        };
+
+        let mut repository = Repository::open(&git_dir, work_tree.as_deref())?;
+        if let Some(object_dir) = from_env("GIT_OBJECT_DIRECTORY") {
+            repository.object_dir = current.join(object_dir);
+        }
+        if let Some(root) = &repository.work_tree {
+            let current = fs::canonicalize(&current).unwrap_or(current);
+            if let Ok(relative) = current.strip_prefix(root) {
+                let relative = relative.to_string_lossy();
+                if !relative.is_empty() {
+                    repository.prefix = format!("{relative}/");
+                }
+            }
+        }
+        return Ok(repository);
+    }
+
+    /// Opens the repository at `git_dir` without any discovery.
+    pub fn open(git_dir: &Path, work_tree: Option<&Path>) -> Result<Self, Error> {
+        let git_dir = fs::canonicalize(git_dir)
+            .map_err(|err| format!("error opening {}: {err}", git_dir.display()))?;
+        let work_tree = match work_tree {
+            Some(work_tree) => Some(fs::canonicalize(work_tree).map_err(|err| {
+                format!(
+                    "fatal: cannot use '{}' as a working tree: {err}",
+                    work_tree.display()
+                )
+            })?),
+            None => None,
+        };
+        let common_dir = fs::canonicalize(common_dir(&git_dir))
+            .map_err(|err| format!("error opening {}: {err}", git_dir.display()))?;
+        return Ok(Repository {
+            object_dir: common_dir.join("objects"),
+            git_dir,
+            common_dir,
+            work_tree,
+            prefix: String::new(),
+            packed_refs: RefCell::new(None),
+            config: RefCell::new(None),
+            attributes: RefCell::new(None),
+        });
+    }
+
+    /// Opens the repository whose working tree is `directory`, such as a
+    /// submodule, following a `.git` file if there is one.
+    pub fn open_work_tree(directory: &Path) -> Result<Self, Error> {
+        let dot_git = directory.join(".git");
+        let git_dir = if dot_git.is_file() {
+            read_gitfile(&dot_git)?
+        } else {
+            dot_git
+        };
+        return Repository::open(&git_dir, Some(directory));
+    }
+
+    /// Creates a new repository in `directory`. Bare repositories keep
+    /// everything in `directory` itself rather than in a `.git` inside it.
+    pub fn init(directory: &Path, bare: bool) -> Result<Self, Error> {
+        let git_dir = if bare {
+            directory.to_path_buf()
+        } else {
+            directory.join(".git")
+        };
+        fs::create_dir_all(directory)
+            .map_err(|err| format!("error creating {}: {err}", directory.display()))?;
+        if !bare {
+            fs::create_dir(&git_dir)
+                .map_err(|err| Error::io("error creating .git directory", err))?;
+        }
+        fs::create_dir(git_dir.join("objects"))
+            .map_err(|err| Error::io("error creating objects directory", err))?;
+        fs::create_dir_all(git_dir.join("refs/heads"))
+            .map_err(|err| Error::io("error creating refs directory", err))?;
+        fs::create_dir_all(git_dir.join("refs/tags"))
+            .map_err(|err| Error::io("error creating refs directory", err))?;
+
+        let default_branch = Config::load(None)?
+            .get("init.defaultbranch")?
+            .unwrap_or("main".to_string());
+        fs::write(
+            git_dir.join("HEAD"),
+            format!("ref: refs/heads/{default_branch}\n"),
+        )
+        .map_err(|err| Error::io("error writing HEAD file", err))?;
+        fs::write(
+            git_dir.join("config"),
+            format!(
+                "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n\tbare = {bare}\n{}",
+                if bare {
+                    ""
+                } else {
+                    "\tlogallrefupdates = true\n"
+                }
+            ),
+        )
+        .map_err(|err| Error::io("error writing config file", err))?;
+
+        return Repository::open(&git_dir, (!bare).then_some(directory));
+    }
+
+    /// The config of every scope that applies to the repository, read on
+    /// first use.
+    pub(crate) fn config(&self) -> Result<Rc<Config>, Error> {
+        if let Some(config) = self.config.borrow().as_ref() {
+            return Ok(config.clone());
+        }
+        let config = Rc::new(Config::load(Some(self))?);
+        *self.config.borrow_mut() = Some(config.clone());
+        return Ok(config);
+    }
+
+    /// Drops the config read so far, after writing to a config file.
+    pub(crate) fn forget_config(&self) {
+        *self.config.borrow_mut() = None;
+    }
+
+    /// The attributes of the repository's paths, read on first use.
+    pub(crate) fn attributes(&self) -> Result<Rc<Attributes>, Error> {
+        if let Some(attributes) = self.attributes.borrow().as_ref() {
+            return Ok(attributes.clone());
+        }
+        let attributes = Rc::new(Attributes::load(self)?);
+        *self.attributes.borrow_mut() = Some(attributes.clone());
+        return Ok(attributes);
+    }
+
+    /// Drops the attributes read so far, after checking out or removing a
+    /// `.gitattributes` file.
+    pub(crate) fn forget_attributes(&self) {
+        *self.attributes.borrow_mut() = None;
+    }
+
+    /// A path inside the git directory, e.g. `git_path("HEAD")`. In a
+    /// linked worktree, paths shared by all worktrees are in the common
+    /// directory instead.
+    pub fn git_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        return match is_common_path(&path.to_string_lossy()) {
+            true => self.common_dir.join(path),
+            false => self.git_dir.join(path),
+        };
+    }
+
+    /// The working tree root, failing for bare repositories.
+    pub fn work_tree(&self) -> Result<&Path, Error> {
+        return self
+            .work_tree
+            .as_deref()
+            .ok_or("fatal: this operation must be run in a work tree".into());
+    }
+
+    /// Where the file at `path`, relative to the working tree root, lives on
+    /// disk.
+    pub fn work_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let root = self.work_tree.as_deref().unwrap_or(&self.git_dir);
+        let path = path.as_ref();
+        if path.as_os_str().is_empty() {
+            return root.to_path_buf();
+        }
+        return root.join(path);
+    }
+
+    /// Turns a path given on the command line, relative to where the command
+    /// was run, into a slash separated path relative to the working tree
+    /// root. An empty result means the root itself.
+    pub fn path_from_cwd(&self, path: &str) -> Result<String, Error> {
+        let mut components: Vec<String> = Vec::new();
+        let joined = if path.starts_with('/') {
+            let root = self.work_tree()?;
+            Path::new(path)
+                .strip_prefix(root)
+                .map_err(|_| format!("fatal: {path}: '{path}' is outside repository"))?
+                .to_path_buf()
+        } else {
+            Path::new(&self.prefix).join(path)
+        };
+        for component in joined.components() {
+            match component {
+                Component::Normal(name) => components.push(name.to_string_lossy().to_string()),
+                Component::ParentDir if components.pop().is_none() => {
+                    return Err(format!("fatal: {path}: '{path}' is outside repository").into());
+                }
+                _ => {}
+            }
+        }
+        return Ok(components.join("/"));
+    }
+
+    /// Shows a path relative to the working tree root as relative to where
+    /// the command was run, like git does in human readable output.
+    pub fn path_for_display(&self, path: &str) -> String {
+        let mut prefix = self.prefix.as_str();
+        let mut path = path;
+        // drop the directories both share
+        while let Some((directory, rest)) = prefix.split_once('/') {
+            match path.strip_prefix(&format!("{directory}/")) {
+                Some(remaining) => {
+                    path = remaining;
+                    prefix = rest;
+                }
+                None => break,
+            }
+        }
+        let display = format!("{}{path}", "../".repeat(prefix.matches('/').count()));
+        if display.is_empty() {
+            return "./".to_string();
+        }
+        return display;
+    }
+
+    /// Expands an abbreviated hex object name to the full id.
+    pub fn resolve_prefix(&self, prefix: &str) -> Result<ObjectId, Error> {
+        return git_object::full_hash(self, prefix);
+    }
+
+    /// Resolves any revision syntax git accepts for a single object, such as
+    /// `HEAD~2`, `v1^{tree}` or `main:src/lib.rs`.
+    pub fn resolve_revision(&self, spec: &str) -> Result<ObjectId, Error> {
+        return git_revision::resolve(self, spec);
+    }
+
+    pub fn object_type(&self, id: &ObjectId) -> Result<ObjectType, Error> {
+        return git_object::get_type(self, id);
+    }
+
+    pub fn read_object(&self, id: &ObjectId) -> Result<Object, Error> {
+        return git_object::read_object(self, id);
+    }
+
+    pub fn read_commit(&self, id: &ObjectId) -> Result<Commit, Error> {
+        return git_object::read_commit(self, id);
+    }
+
+    pub fn read_tree(&self, id: &ObjectId) -> Result<Tree, Error> {
+        return git_object::read_tree_object(self, id);
+    }
+
+    pub fn read_blob(&self, id: &ObjectId) -> Result<Blob, Error> {
+        return git_object::read_blob(self, id);
+    }
+
+    pub fn read_tag(&self, id: &ObjectId) -> Result<Tag, Error> {
+        return git_object::read_tag(self, id);
+    }
+
+    pub fn write_blob(&self, data: &[u8]) -> Result<ObjectId, Error> {
+        return git_object::write_blob(self, &mut data.to_vec());
+    }
+
+    pub fn write_tree(&self, tree: &Tree) -> Result<ObjectId, Error> {
+        return git_object::write_tree(self, &mut tree.serialize());
+    }
+
+    pub fn write_commit(&self, commit: &Commit) -> Result<ObjectId, Error> {
+        return git_object::write_commit(self, &mut commit.serialize());
+    }
+
+    pub fn write_tag(&self, tag: &Tag) -> Result<ObjectId, Error> {
+        return git_object::write_tag(self, &mut tag.serialize());
+    }
+
+    /// The commit HEAD points at, `None` on an unborn branch.
+    pub fn head(&self) -> Result<Option<ObjectId>, Error> {
+        return git::head_commit(self);
+    }
+
+    /// The branch HEAD points at, `None` when detached.
+    pub fn current_branch(&self) -> Result<Option<String>, Error> {
+        return git::current_branch(self);
+    }
+
+    /// Resolves a branch, tag, remote-tracking branch or abbreviated hash to
+    /// a commit.
+    pub fn resolve_commit(&self, name: &str) -> Result<ObjectId, Error> {
+        return git::resolve_commit(self, name);
+    }
+
+    /// Points HEAD (or the branch it is on) at `commit`, logging `message`
+    /// in the reflogs.
+    pub fn update_head(&self, commit: &ObjectId, message: &str) -> Result<(), Error> {
+        return git::update_head(self, commit, message);
+    }
+
+    /// What the ref `name` holds, without following symbolic refs.
+    pub fn read_ref(&self, name: &str) -> Result<Option<RefValue>, Error> {
+        return git_refs::read(self, name);
+    }
+
+    /// The object the ref `name` ultimately points at, if it exists.
+    pub fn resolve_ref(&self, name: &str) -> Result<Option<ObjectId>, Error> {
+        return git_refs::resolve(self, name);
+    }
+
+    /// The refs whose names start with `prefix`, sorted by name.
+    pub fn refs(&self, prefix: &str) -> Result<Vec<Ref>, Error> {
+        return git_refs::list(self, prefix);
+    }
+
+    /// Applies `updates` as one transaction: either all of them or none.
+    pub fn update_refs(&self, updates: &[RefUpdate]) -> Result<(), Error> {
+        return git_refs::apply(self, updates);
+    }
+
+    pub fn read_index(&self) -> Result<Vec<IndexEntry>, Error> {
+        return git_index::read(self);
+    }
+
+    pub fn write_index(&self, entries: &mut [IndexEntry]) -> Result<(), Error> {
+        return git_index::write(self, entries);
+    }
+
+    /// Writes the index as trees, returning the root tree.
+    pub fn write_index_tree(&self) -> Result<ObjectId, Error> {
+        return git_index::write_tree(self, &self.read_index()?);
+    }
+
+    /// Clones the repository at `url` into `directory`.
+    /// The files that differ between two trees, `None` being the empty tree.
+    pub fn diff_trees(
+        &self,
+        old: Option<&ObjectId>,
+        new: Option<&ObjectId>,
+    ) -> Result<Vec<FilePair>, Error> {
+        return git_diff::diff_trees(self, old, new, &[], false);
+    }
+
+    /// The files that differ between a tree and the index.
+    pub fn diff_tree_to_index(&self, tree: Option<&ObjectId>) -> Result<Vec<FilePair>, Error> {
+        return git_diff::diff_tree_to_index(self, tree, &[], false);
+    }
+
+    /// The files that differ between the index and the working tree.
+    pub fn diff_index_to_worktree(&self) -> Result<Vec<FilePair>, Error> {
+        return git_diff::diff_index_to_worktree(self, &[], false);
+    }
+
+    pub fn clone(url: &str, directory: &Path) -> Result<Self, Error> {
+        return git_transport::clone(url, directory);
+    }
+
+    /// Downloads `wants` and everything reachable from them from `url`.
+    pub fn fetch(&self, url: &str, wants: &[ObjectId]) -> Result<(), Error> {
+        return git_transport::fetch(self, &reqwest::blocking::Client::new(), url, wants);
+    }
+}
+
+fn from_env(name: &str) -> Option<String> {
+    return env::var(name).ok().filter(|value| !value.is_empty());
+}
+
+/// Walks up from `start` looking for a repository, returning its git
+/// directory and working tree.
+fn find_git_dir(start: &Path) -> Result<(PathBuf, Option<PathBuf>), Error> {
+    for directory in start.ancestors() {
+        let dot_git = directory.join(".git");
+        if dot_git.is_file() {
+            return Ok((read_gitfile(&dot_git)?, Some(directory.to_path_buf())));
+        }
+        if is_git_dir(&dot_git) {
+            return Ok((dot_git, Some(directory.to_path_buf())));
+        }
+        if is_git_dir(directory) {
+            return Ok((directory.to_path_buf(), None));
+        }
+    }
+    return Err(Error::NotARepository);
+}
+
+fn is_git_dir(path: &Path) -> bool {
+    let common_dir = common_dir(path);
+    return path.join("HEAD").is_file()
+        && common_dir.join("objects").is_dir()
+        && common_dir.join("refs").is_dir();
+}
+
+/// The directory `git_dir` shares objects, refs and config with: the one
+/// its `commondir` file names, relative to `git_dir`, or `git_dir` itself.
+fn common_dir(git_dir: &Path) -> PathBuf {
+    return match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(contents) => git_dir.join(contents.trim_end()),
+        Err(_) => git_dir.to_path_buf(),
+    };
+}
+
+/// Paths below these are shared by all worktrees, except for the ones
+/// below [`PER_WORKTREE_PATHS`], following git's `common_list`.
+const COMMON_PATHS: &[&str] = &[
+    "branches",
+    "common",
+    "config",
+    "gc.pid",
+    "hooks",
+    "info",
+    "logs",
+    "lost-found",
+    "objects",
+    "packed-refs",
+    "refs",
+    "remotes",
+    "rr-cache",
+    "shallow",
+    "svn",
+    "worktrees",
+];
+
+const PER_WORKTREE_PATHS: &[&str] = &[
+    "info/sparse-checkout",
+    "logs/HEAD",
+    "logs/refs/bisect",
+    "logs/refs/rewritten",
+    "logs/refs/worktree",
+    "refs/bisect",
+    "refs/rewritten",
+    "refs/worktree",
+];
+
+/// Whether `path`, relative to the git directory, lives in the common
+/// directory.
+fn is_common_path(path: &str) -> bool {
+    let below = |prefix: &&str| {
+        path.strip_prefix(*prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    return !PER_WORKTREE_PATHS.iter().any(below) && COMMON_PATHS.iter().any(below);
+}
+
+/// Follows a `.git` file containing `gitdir: <path>`, as used by submodules
+/// and linked worktrees. Relative paths are relative to the file.
+fn read_gitfile(path: &Path) -> Result<PathBuf, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("error reading {}: {err}", path.display()))?;
+    let target = contents
+        .trim_end()
+        .strip_prefix("gitdir: ")
+        .ok_or_else(|| format!("fatal: invalid gitfile format: {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let git_dir = base.join(target);
+    if !is_git_dir(&git_dir) {
+        return Err(format!("fatal: not a git repository: {}", git_dir.display()).into());
+    }
+    return Ok(git_dir);
+}